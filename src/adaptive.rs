@@ -0,0 +1,218 @@
+//! Adaptive resolution of the thickness map by merging poorly sampled grid bins.
+
+use crate::grids::{LeafletTotals, ThicknessGrids};
+
+/// Maximal number of times the largest adaptive bins can be split into quarters.
+pub(crate) const MAX_LEVELS: u32 = 10;
+
+/// Accumulated samples of a square block of grid bins.
+#[derive(Debug, Clone, Copy, Default)]
+struct Block {
+    totals: LeafletTotals,
+    /// Number of samples in the upper and the lower leaflet.
+    counts: (usize, usize),
+}
+
+impl Block {
+    /// The block has at least `nan_limit` samples in both leaflets.
+    fn is_sampled(&self, nan_limit: usize) -> bool {
+        self.counts.0 >= nan_limit && self.counts.1 >= nan_limit
+    }
+}
+
+/// Construct a quadtree of bins over the grids. The grids are first split into square blocks of 2^`levels`
+/// x 2^`levels` grid bins, and each block is recursively split into four quarters as long as every quarter
+/// has at least `nan_limit` samples in both leaflets. The thickness of each resulting adaptive bin
+/// is calculated from all samples of its grid bins.
+///
+/// Returns `(x, y, thickness, size)` for every grid bin in the order of the grid maps, where `thickness`
+/// is the thickness of the adaptive bin containing the grid bin and `size` is the number of grid bins
+/// along each side of the adaptive bin. Thickness is NaN for adaptive bins with fewer than `nan_limit` samples
+/// in any of the leaflets.
+pub(crate) fn quadtree(
+    grids: &ThicknessGrids,
+    nan_limit: usize,
+    levels: u32,
+) -> Vec<(f32, f32, f64, usize)> {
+    let (n_x, n_y) = (grids.upper_sum.n_tiles_x(), grids.upper_sum.n_tiles_y());
+    let bins = grids
+        .leaflet_totals()
+        .into_iter()
+        .zip(grids.upper_count.extract_raw())
+        .zip(grids.lower_count.extract_raw())
+        .map(|((totals, (_, _, &upper)), (_, _, &lower))| Block {
+            totals,
+            counts: (upper, lower),
+        })
+        .collect::<Vec<_>>();
+
+    let mut cells = vec![(f64::NAN, 1); n_x * n_y];
+    let size = 1 << levels;
+    for x in (0..n_x).step_by(size) {
+        for y in (0..n_y).step_by(size) {
+            split(&bins, &mut cells, (n_x, n_y), (x, y), size, nan_limit);
+        }
+    }
+
+    grids
+        .upper_sum
+        .extract_raw()
+        .zip(cells)
+        .map(|((x, y, _), (thickness, size))| (x, y, thickness, size))
+        .collect()
+}
+
+/// Sum the samples of the grid bins in the block starting at `start` with `size` bins along each side.
+/// Parts of the block outside the grids are ignored.
+fn block(bins: &[Block], (n_x, n_y): (usize, usize), start: (usize, usize), size: usize) -> Block {
+    let mut sum = Block::default();
+    for x in start.0..(start.0 + size).min(n_x) {
+        for y in start.1..(start.1 + size).min(n_y) {
+            let bin = &bins[x * n_y + y];
+            sum.totals += bin.totals;
+            sum.counts.0 += bin.counts.0;
+            sum.counts.1 += bin.counts.1;
+        }
+    }
+
+    sum
+}
+
+/// Split the block into quarters if all its quarters are sampled well enough, otherwise assign its thickness
+/// and size to all its grid bins.
+fn split(
+    bins: &[Block],
+    cells: &mut [(f64, usize)],
+    dims: (usize, usize),
+    start: (usize, usize),
+    size: usize,
+    nan_limit: usize,
+) {
+    let half = size / 2;
+    let quarters = [(0, 0), (half, 0), (0, half), (half, half)]
+        .into_iter()
+        .map(|(dx, dy)| (start.0 + dx, start.1 + dy))
+        .filter(|&(x, y)| x < dims.0 && y < dims.1)
+        .collect::<Vec<_>>();
+
+    if size > 1
+        && quarters
+            .iter()
+            .all(|&quarter| block(bins, dims, quarter, half).is_sampled(nan_limit))
+    {
+        for quarter in quarters {
+            split(bins, cells, dims, quarter, half, nan_limit);
+        }
+        return;
+    }
+
+    let sum = block(bins, dims, start, size);
+    let thickness = if sum.is_sampled(nan_limit) {
+        sum.totals
+            .thickness_since(&LeafletTotals::default())
+            .unwrap_or(f64::NAN)
+    } else {
+        f64::NAN
+    };
+
+    for x in start.0..(start.0 + size).min(dims.0) {
+        for y in start.1..(start.1 + size).min(dims.1) {
+            cells[x * dims.1 + y] = (thickness, size);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{geometry::Geometry, normal::Normal};
+
+    /// Grids of 4 x 4 bins of 1 x 1 with a sample of thickness `thickness(x, y)` in each bin with defined thickness.
+    fn grids(thickness: impl Fn(f32, f32) -> f64) -> ThicknessGrids {
+        let mut grids = ThicknessGrids::new(
+            (0.0, 3.0),
+            (0.0, 3.0),
+            (1.0, 1.0),
+            Normal::default(),
+            Geometry::Planar,
+        )
+        .unwrap();
+
+        for x in 0..4 {
+            for y in 0..4 {
+                let (x, y) = (x as f32, y as f32);
+                let value = thickness(x, y);
+                if !value.is_finite() {
+                    continue;
+                }
+
+                *grids.upper_sum.get_mut_at(x, y).unwrap() += value / 2.0;
+                *grids.upper_count.get_mut_at(x, y).unwrap() += 1;
+                *grids.upper_weight.get_mut_at(x, y).unwrap() += 1.0;
+                *grids.lower_sum.get_mut_at(x, y).unwrap() -= value / 2.0;
+                *grids.lower_count.get_mut_at(x, y).unwrap() += 1;
+                *grids.lower_weight.get_mut_at(x, y).unwrap() += 1.0;
+            }
+        }
+
+        grids
+    }
+
+    #[test]
+    fn constant_map_unchanged() {
+        let bins = quadtree(&grids(|_, _| 4.2), 1, 2);
+        assert_eq!(bins.len(), 16);
+        for (_, _, thickness, size) in bins {
+            assert!((thickness - 4.2).abs() < 1e-12);
+            assert_eq!(size, 1);
+        }
+    }
+
+    #[test]
+    fn poorly_sampled_bins_merged() {
+        let bins = quadtree(&grids(|x, y| (x + y) as f64), 2, 2);
+        for (x, y, thickness, size) in bins {
+            assert_eq!(size, 2);
+            let expected = match (x < 2.0, y < 2.0) {
+                (true, true) => 1.0,
+                (false, false) => 5.0,
+                _ => 3.0,
+            };
+            assert!((thickness - expected).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn split_limited_by_undefined_quarter() {
+        // a single sampled bin is not enough to split the block
+        let bins = quadtree(
+            &grids(|x, y| if (x, y) == (0.0, 0.0) { 3.0 } else { f64::NAN }),
+            1,
+            2,
+        );
+        for (_, _, thickness, size) in bins {
+            assert_eq!(thickness, 3.0);
+            assert_eq!(size, 4);
+        }
+    }
+
+    #[test]
+    fn undefined_map() {
+        let bins = quadtree(&grids(|_, _| f64::NAN), 1, 2);
+        assert!(
+            bins.iter()
+                .all(|&(_, _, thickness, size)| thickness.is_nan() && size == 4)
+        );
+    }
+
+    #[test]
+    fn blocks_exceeding_grids() {
+        // blocks of 8 x 8 bins are clipped at the edges of the grids of 4 x 4 bins,
+        // so the only quarter inside the grids is always sampled well enough
+        let bins = quadtree(&grids(|_, _| 2.0), 16, 3);
+        for (_, _, thickness, size) in bins {
+            assert_eq!(thickness, 2.0);
+            assert_eq!(size, 4);
+        }
+    }
+}
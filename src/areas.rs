@@ -0,0 +1,171 @@
+//! Areas of the Voronoi cells of headgroups in the membrane plane.
+
+use groan_rs::prelude::*;
+
+use crate::{leaflets::LateralCells, normal::Normal};
+
+/// Maximal lateral distance (in nm) of the border of a Voronoi cell from its headgroup.
+/// Cells of isolated headgroups are truncated to a square of this half-width.
+const MAX_CELL_RADIUS: f32 = 2.0;
+
+/// Calculate the area (in nm^2) of the Voronoi cell of each headgroup in the membrane plane,
+/// tessellating each leaflet separately. `upper` specifies the leaflet of each headgroup.
+/// Areas of headgroups not assigned to any leaflet are NaN.
+pub(crate) fn voronoi_areas(
+    positions: &[Vector3D],
+    upper: &[Option<bool>],
+    normal: Normal,
+    simbox: &SimBox,
+) -> Vec<f64> {
+    // neighbors farther than twice the maximal cell radius cannot affect the cell
+    let mut upper_cells = LateralCells::new(simbox, 2.0 * MAX_CELL_RADIUS, normal);
+    let mut lower_cells = LateralCells::new(simbox, 2.0 * MAX_CELL_RADIUS, normal);
+    for (i, (position, &is_upper)) in positions.iter().zip(upper.iter()).enumerate() {
+        match is_upper {
+            Some(true) => upper_cells.insert(position, i),
+            Some(false) => lower_cells.insert(position, i),
+            None => (),
+        }
+    }
+
+    positions
+        .iter()
+        .zip(upper.iter())
+        .enumerate()
+        .map(|(i, (position, &is_upper))| {
+            let Some(is_upper) = is_upper else {
+                return f64::NAN;
+            };
+
+            let cells = if is_upper { &upper_cells } else { &lower_cells };
+            let mut polygon = vec![
+                (-MAX_CELL_RADIUS, -MAX_CELL_RADIUS),
+                (MAX_CELL_RADIUS, -MAX_CELL_RADIUS),
+                (MAX_CELL_RADIUS, MAX_CELL_RADIUS),
+                (-MAX_CELL_RADIUS, MAX_CELL_RADIUS),
+            ];
+
+            for (other, j) in cells.neighbors(position) {
+                if *j == i {
+                    continue;
+                }
+
+                let vector = normal.lateral(&position.vector_to(other, simbox));
+                polygon = clip(&polygon, vector);
+            }
+
+            area(&polygon) as f64
+        })
+        .collect()
+}
+
+/// Clip the convex polygon (relative to its headgroup) by the perpendicular bisector between the headgroup
+/// and its neighbor located at `neighbor` relative to the headgroup, keeping the part closer to the headgroup.
+fn clip(polygon: &[(f32, f32)], neighbor: (f32, f32)) -> Vec<(f32, f32)> {
+    let limit = (neighbor.0 * neighbor.0 + neighbor.1 * neighbor.1) / 2.0;
+    if limit <= 0.0 {
+        return polygon.to_vec();
+    }
+
+    let projection = |point: (f32, f32)| point.0 * neighbor.0 + point.1 * neighbor.1;
+
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+    for (k, &a) in polygon.iter().enumerate() {
+        let b = polygon[(k + 1) % polygon.len()];
+        let (inside_a, inside_b) = (projection(a) <= limit, projection(b) <= limit);
+
+        if inside_a {
+            clipped.push(a);
+        }
+
+        if inside_a != inside_b {
+            let t = (limit - projection(a)) / (projection(b) - projection(a));
+            clipped.push((a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1)));
+        }
+    }
+
+    clipped
+}
+
+/// Area of the polygon calculated using the shoelace formula.
+fn area(polygon: &[(f32, f32)]) -> f32 {
+    let doubled = polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .map(|(a, b)| a.0 * b.1 - b.0 * a.1)
+        .sum::<f32>();
+
+    doubled.abs() / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn area_of_polygons() {
+        let square = [(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)];
+        assert!((area(&square) - 4.0).abs() < 1e-6);
+
+        let triangle = [(0.0, 0.0), (0.0, 3.0), (4.0, 0.0)];
+        assert!((area(&triangle) - 6.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn clip_by_bisector() {
+        let square = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+
+        // the bisector with a neighbor at (1, 0) cuts the square at x = 0.5
+        let clipped = clip(&square, (1.0, 0.0));
+        assert!((area(&clipped) - 3.0).abs() < 1e-6);
+        assert!(clipped.iter().all(|&(x, _)| x <= 0.5 + 1e-6));
+
+        // distant and coincident neighbors do not clip the polygon
+        assert!((area(&clip(&square, (5.0, 0.0))) - 4.0).abs() < 1e-6);
+        assert!((area(&clip(&square, (0.0, 0.0))) - 4.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn voronoi_areas_square_lattice() {
+        // upper leaflet forming a periodic lattice of 1 x 1 nm, lower leaflet of 2 x 1 nm
+        let simbox = SimBox::from([8.0, 4.0, 10.0]);
+        let mut positions = Vec::new();
+        let mut upper = Vec::new();
+        for i in 0..8 {
+            for j in 0..4 {
+                positions.push(Vector3D::new(i as f32 + 0.5, j as f32 + 0.5, 7.0));
+                upper.push(Some(true));
+            }
+        }
+
+        for i in 0..4 {
+            for j in 0..4 {
+                positions.push(Vector3D::new(2.0 * i as f32 + 0.5, j as f32 + 0.5, 3.0));
+                upper.push(Some(false));
+            }
+        }
+
+        positions.push(Vector3D::new(1.0, 1.0, 5.0));
+        upper.push(None);
+
+        let areas = voronoi_areas(&positions, &upper, Normal::default(), &simbox);
+        assert_eq!(areas.len(), 49);
+        assert!(areas[..32].iter().all(|area| (area - 1.0).abs() < 1e-5));
+        assert!(areas[32..48].iter().all(|area| (area - 2.0).abs() < 1e-5));
+        assert!(areas[48].is_nan());
+    }
+
+    #[test]
+    fn voronoi_area_of_isolated_headgroup() {
+        let simbox = SimBox::from([20.0, 20.0, 10.0]);
+        let areas = voronoi_areas(
+            &[Vector3D::new(10.0, 10.0, 7.0)],
+            &[Some(true)],
+            Normal::default(),
+            &simbox,
+        );
+
+        let side = 2.0 * MAX_CELL_RADIUS as f64;
+        assert!((areas[0] - side * side).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,354 @@
+//! Autocorrelation of the time series of the average membrane thickness and of the thickness of the grid bins.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+
+use crate::grids::LeafletTotals;
+
+/// Average membrane thickness of the individual analyzed frames.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ThicknessSeries {
+    /// Simulation time (in ps) and average thickness (in nm) of each frame.
+    values: Vec<(f32, f64)>,
+}
+
+/// Integrated autocorrelation time of the average thickness.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CorrelationTime {
+    /// Integrated autocorrelation time (in ps).
+    pub(crate) time: f64,
+    /// Number of statistically independent samples in the time series.
+    pub(crate) n_independent: f64,
+}
+
+impl ThicknessSeries {
+    /// Add the average thickness of a frame.
+    pub(crate) fn add(&mut self, time: f32, thickness: f64) {
+        self.values.push((time, thickness));
+    }
+
+    /// Remove all frames.
+    pub(crate) fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    /// Add the frames of the other series into this series. Frames are kept ordered by their time.
+    pub(crate) fn merge(&mut self, other: &ThicknessSeries) {
+        self.values.extend_from_slice(&other.values);
+        self.values.sort_by(|a, b| a.0.total_cmp(&b.0));
+    }
+
+    /// Calculate the normalized autocorrelation function of the average thickness for lags
+    /// of up to half of the length of the series (in frames). Frames are assumed to be equally spaced in time.
+    fn autocorrelation(&self) -> Vec<f64> {
+        let values = self.values.iter().map(|&(_, x)| x).collect::<Vec<_>>();
+        autocorrelation(&values).collect()
+    }
+
+    /// Time between consecutive frames of the series (in ps).
+    fn time_step(&self) -> f64 {
+        let (first, last) = (
+            self.values[0].0 as f64,
+            self.values[self.values.len() - 1].0 as f64,
+        );
+        (last - first) / (self.values.len() - 1) as f64
+    }
+
+    /// Calculate the integrated autocorrelation time of the average thickness, i.e., the time step multiplied by
+    /// 1/2 plus the sum of the autocorrelation function over the lags preceding its first non-positive value.
+    /// Returns `None` for series shorter than three frames or with constant thickness.
+    pub(crate) fn correlation_time(&self) -> Option<CorrelationTime> {
+        if self.values.len() < 3 {
+            return None;
+        }
+
+        let values = self.values.iter().map(|&(_, x)| x).collect::<Vec<_>>();
+        let frames = correlation_frames(&values)?;
+
+        Some(CorrelationTime {
+            time: frames * self.time_step(),
+            n_independent: self.values.len() as f64 / (2.0 * frames),
+        })
+    }
+
+    /// Write the autocorrelation function of the average thickness and its integrated autocorrelation time into a file.
+    pub(crate) fn write(
+        &self,
+        filename: impl AsRef<Path>,
+        raw_arguments: &[String],
+    ) -> anyhow::Result<()> {
+        let filename = filename.as_ref();
+        let Some(correlation) = self.correlation_time() else {
+            anyhow::bail!(
+                "Autocorrelation of the average thickness requires at least three analyzed frames with varying thickness."
+            );
+        };
+
+        let file = File::create(filename).with_context(|| {
+            format!(
+                "Could not create autocorrelation file '{}'.",
+                filename.display()
+            )
+        })?;
+        let mut output = BufWriter::new(file);
+
+        writeln!(
+            &mut output,
+            "# Generated with memthick v{}.",
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(&mut output, "# Command line: {}", raw_arguments.join(" "))?;
+        writeln!(
+            &mut output,
+            "# Autocorrelation function of the average membrane thickness of the individual frames."
+        )?;
+        writeln!(
+            &mut output,
+            "# Integrated autocorrelation time: {:.3} ps",
+            correlation.time
+        )?;
+        writeln!(
+            &mut output,
+            "# Independent samples: {:.1} of {} frames",
+            correlation.n_independent,
+            self.values.len()
+        )?;
+        writeln!(&mut output, "@ xlabel lag [ps]")?;
+        writeln!(&mut output, "@ ylabel autocorrelation")?;
+        writeln!(&mut output, "# lag [ps] autocorrelation")?;
+
+        let time_step = self.time_step();
+        for (lag, value) in self.autocorrelation().iter().enumerate() {
+            writeln!(
+                &mut output,
+                "{:12.3} {:12.6}",
+                lag as f64 * time_step,
+                value
+            )?;
+        }
+
+        output.flush()?;
+        Ok(())
+    }
+}
+
+/// Instantaneous thickness of every grid bin in the individual analyzed frames.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BinSeries {
+    /// Simulation time (in ps) and the thickness of each bin (in nm, NaN for bins without samples
+    /// in both leaflets) of each frame.
+    frames: Vec<(f32, Vec<f32>)>,
+}
+
+impl BinSeries {
+    /// Add the instantaneous thickness of the bins in a frame.
+    /// `before` and `after` are the accumulated leaflet totals of each bin (see [`LeafletTotals`])
+    /// before and after adding the frame.
+    pub(crate) fn add(&mut self, time: f32, before: &[LeafletTotals], after: &[LeafletTotals]) {
+        let thickness = before
+            .iter()
+            .zip(after.iter())
+            .map(|(before, after)| {
+                after
+                    .thickness_since(before)
+                    .map_or(f32::NAN, |thickness| thickness as f32)
+            })
+            .collect();
+
+        self.frames.push((time, thickness));
+    }
+
+    /// Remove all frames.
+    pub(crate) fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Add the frames of the other series into this series. Frames are kept ordered by their time.
+    pub(crate) fn merge(&mut self, other: &BinSeries) {
+        self.frames.extend_from_slice(&other.frames);
+        self.frames.sort_by(|a, b| a.0.total_cmp(&b.0));
+    }
+
+    /// Calculate the effective number of statistically independent samples of the thickness of each bin
+    /// in the order of the grid maps, i.e., the number of frames in which the bin has samples in both leaflets
+    /// divided by twice its integrated autocorrelation time (in frames). Frames without samples in the bin are
+    /// skipped when calculating the autocorrelation. The number is NaN for bins sampled in fewer than three frames
+    /// or with constant thickness.
+    pub(crate) fn effective_samples(&self) -> Vec<f64> {
+        let n_bins = self
+            .frames
+            .first()
+            .map_or(0, |(_, thickness)| thickness.len());
+
+        (0..n_bins)
+            .map(|bin| {
+                let values = self
+                    .frames
+                    .iter()
+                    .map(|(_, thickness)| thickness[bin] as f64)
+                    .collect::<Vec<_>>();
+
+                let n_sampled = values.iter().filter(|value| value.is_finite()).count();
+                if n_sampled < 3 {
+                    return f64::NAN;
+                }
+
+                correlation_frames(&values)
+                    .map_or(f64::NAN, |frames| n_sampled as f64 / (2.0 * frames))
+            })
+            .collect()
+    }
+}
+
+/// Calculate the normalized autocorrelation function of the time series for lags of up to half of its length.
+/// Undefined (NaN) values are skipped, i.e., the covariance at each lag is averaged over the pairs
+/// of defined values. The autocorrelation at lags without any such pairs is NaN.
+fn autocorrelation(values: &[f64]) -> impl Iterator<Item = f64> + '_ {
+    let (sum, n) = values
+        .iter()
+        .filter(|value| value.is_finite())
+        .fold((0.0, 0), |(sum, n), value| (sum + value, n + 1));
+    let mean = sum / n as f64;
+    let deviations = values.iter().map(move |x| x - mean).collect::<Vec<_>>();
+    let variance = deviations
+        .iter()
+        .filter(|x| x.is_finite())
+        .map(|x| x * x)
+        .sum::<f64>()
+        / n as f64;
+
+    (0..=values.len() / 2).map(move |lag| {
+        let (covariance, n_pairs) = deviations
+            .iter()
+            .zip(deviations.iter().skip(lag))
+            .map(|(a, b)| a * b)
+            .filter(|product| product.is_finite())
+            .fold((0.0, 0), |(sum, n), product| (sum + product, n + 1));
+
+        covariance / n_pairs as f64 / variance
+    })
+}
+
+/// Calculate the integrated autocorrelation time of the time series in frames, i.e., 1/2 plus the sum
+/// of the autocorrelation function over the lags preceding its first non-positive (or undefined) value.
+/// Returns `None` for series with constant values.
+fn correlation_frames(values: &[f64]) -> Option<f64> {
+    let mut autocorrelation = autocorrelation(values);
+    if !autocorrelation.next()?.is_finite() {
+        return None;
+    }
+
+    Some(
+        0.5 + autocorrelation
+            .take_while(|&value| value > 0.0)
+            .sum::<f64>(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(values: &[f64], expected: &[f64]) {
+        assert_eq!(values.len(), expected.len());
+        for (value, expected) in values.iter().zip(expected.iter()) {
+            assert!(
+                (value.is_nan() && expected.is_nan()) || (value - expected).abs() < 1e-12,
+                "{:?} != {:?}",
+                values,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn autocorrelation_constant() {
+        let values = [2.0; 6];
+        assert!(autocorrelation(&values).all(|value| value.is_nan()));
+        assert!(correlation_frames(&values).is_none());
+    }
+
+    #[test]
+    fn autocorrelation_alternating() {
+        let values = [1.0, -1.0, 1.0, -1.0, 1.0, -1.0, 1.0, -1.0];
+        let function = autocorrelation(&values).collect::<Vec<_>>();
+        assert_close(&function, &[1.0, -1.0, 1.0, -1.0, 1.0]);
+        assert_eq!(correlation_frames(&values), Some(0.5));
+    }
+
+    #[test]
+    fn autocorrelation_gaps() {
+        // the pairs containing an undefined value are skipped
+        let values = [1.0, -1.0, f64::NAN, 1.0, -1.0, f64::NAN];
+        let function = autocorrelation(&values).collect::<Vec<_>>();
+        assert_close(&function, &[1.0, -1.0, -1.0, 1.0]);
+
+        // no pairs of defined values at the lag of one frame
+        let values = [1.0, f64::NAN, -1.0, f64::NAN];
+        let function = autocorrelation(&values).collect::<Vec<_>>();
+        assert_close(&function, &[1.0, f64::NAN, -1.0]);
+        assert_eq!(correlation_frames(&values), Some(0.5));
+    }
+
+    #[test]
+    fn correlation_frames_ar1() {
+        // AR(1) process x[t] = phi * x[t - 1] + noise with the integrated autocorrelation time
+        // of 1/2 + phi / (1 - phi) = 1.5 frames for phi = 0.5
+        let mut state = 12345u64;
+        let mut uniform = || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((state >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+        };
+
+        let mut values = Vec::with_capacity(100_000);
+        let mut x = 0.0;
+        for _ in 0..100_000 {
+            let noise =
+                (-2.0 * uniform().ln()).sqrt() * (2.0 * std::f64::consts::PI * uniform()).cos();
+            x = 0.5 * x + noise;
+            values.push(x);
+        }
+
+        let frames = correlation_frames(&values).unwrap();
+        assert!((frames - 1.5).abs() < 0.1, "{}", frames);
+    }
+
+    #[test]
+    fn correlation_time_of_series() {
+        let mut series = ThicknessSeries::default();
+        for (index, thickness) in [4.0, 3.0, 4.0, 3.0, 4.0, 3.0, 4.0, 3.0].iter().enumerate() {
+            series.add(10.0 * index as f32, *thickness);
+        }
+
+        let correlation = series.correlation_time().unwrap();
+        assert!((correlation.time - 5.0).abs() < 1e-9);
+        assert!((correlation.n_independent - 8.0).abs() < 1e-9);
+
+        let mut short = ThicknessSeries::default();
+        short.add(0.0, 4.0);
+        short.add(10.0, 3.0);
+        assert!(short.correlation_time().is_none());
+    }
+
+    #[test]
+    fn effective_samples_of_bins() {
+        // alternating bin, constant bin, and bin sampled in only two frames
+        let frames = (0..8)
+            .map(|index| {
+                let alternating = if index % 2 == 0 { 4.0 } else { 3.0 };
+                let sparse = if index < 2 { 4.0 } else { f32::NAN };
+                (index as f32, vec![alternating, 4.0, sparse])
+            })
+            .collect();
+
+        let samples = BinSeries { frames }.effective_samples();
+        assert_close(&samples, &[8.0, f64::NAN, f64::NAN]);
+    }
+}
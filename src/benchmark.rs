@@ -0,0 +1,195 @@
+//! Benchmarking the analysis on a limited number of trajectory frames.
+
+use std::time::{Duration, Instant};
+
+use groan_rs::prelude::*;
+
+use crate::{
+    Args, READ_GROUP,
+    grids::{self, ThicknessGrids},
+    leaflets::LeafletClassifier,
+    preprocess::FramePreprocessor,
+    setup_reader,
+    stream::{self, XtcStreamReader},
+    trajectory,
+};
+
+/// Time spent in the individual parts of the analysis.
+#[derive(Debug, Default)]
+struct Timings {
+    /// Number of analyzed frames.
+    n_frames: usize,
+    /// Time spent reading the trajectory.
+    reading: Duration,
+    /// Time spent preparing the frame and calculating the center of the membrane.
+    center: Duration,
+    /// Time spent assigning headgroups to membrane leaflets.
+    assignment: Duration,
+    /// Time spent assigning headgroups to the grid bins.
+    binning: Duration,
+}
+
+impl Timings {
+    /// Total time of the benchmark.
+    fn total(&self) -> Duration {
+        self.reading + self.center + self.assignment + self.binning
+    }
+
+    /// Print the time spent in one part of the analysis.
+    fn print_part(&self, name: &str, time: Duration) {
+        println!(
+            "{:<24} {:10.3} s ({:5.1} %)",
+            name,
+            time.as_secs_f64(),
+            100.0 * time.as_secs_f64() / self.total().as_secs_f64()
+        );
+    }
+}
+
+/// Analyze at most `max_frames` frames of the trajectory while measuring the time spent in each part of the analysis.
+fn benchmark_trajectory<'a>(
+    mut trajectory: impl TrajMasterRead<'a>,
+    grids: &mut ThicknessGrids,
+    classifier: &LeafletClassifier,
+    preprocessor: &FramePreprocessor,
+    max_frames: usize,
+) -> anyhow::Result<Timings> {
+    let mut timings = Timings::default();
+
+    while timings.n_frames < max_frames {
+        let start = Instant::now();
+        let Some(frame) = trajectory.next() else {
+            break;
+        };
+        let frame = frame?;
+        timings.reading += start.elapsed();
+
+        let start = Instant::now();
+        preprocessor.apply(frame)?;
+        let membrane_center = grids::membrane_center(frame, grids.center_of_mass)?;
+        timings.center += start.elapsed();
+
+        let start = Instant::now();
+        let upper = classifier.counted(&classifier.assign(frame, &membrane_center)?);
+        timings.assignment += start.elapsed();
+
+        let start = Instant::now();
+        grids.add_heads(frame, &membrane_center, &upper, 1.0)?;
+        timings.binning += start.elapsed();
+
+        timings.n_frames += 1;
+    }
+
+    Ok(timings)
+}
+
+/// Estimate the number of frames that would be analyzed in the full analysis.
+fn expected_frames(system: &System, args: &Args) -> anyhow::Result<Option<usize>> {
+    let Some(times) = trajectory::frame_times(system, &args.trajectory)? else {
+        return Ok(None);
+    };
+
+    Ok(Some(
+        trajectory::analyzed_times(&times, args.begin, args.end, args.step).len(),
+    ))
+}
+
+/// Run the benchmark and report the results.
+/// No output map is written.
+pub(crate) fn run_benchmark(
+    system: &mut System,
+    mut grids: ThicknessGrids,
+    args: &Args,
+    classifier: &LeafletClassifier,
+    max_frames: usize,
+) -> anyhow::Result<()> {
+    grids.clear();
+
+    let expected = expected_frames(system, args)?;
+    let preprocessor = FramePreprocessor::new(args, system)?;
+
+    let first = args.trajectory[0].as_str();
+    let timings = match FileType::from_name(first) {
+        _ if first == stream::STDIN => benchmark_trajectory(
+            setup_reader(XtcStreamReader::new(system), args)?,
+            &mut grids,
+            classifier,
+            &preprocessor,
+            max_frames,
+        )?,
+        FileType::XTC => benchmark_trajectory(
+            setup_reader(
+                system.group_traj_cat_iter::<GroupXtcReader>(&args.trajectory, READ_GROUP)?,
+                args,
+            )?,
+            &mut grids,
+            classifier,
+            &preprocessor,
+            max_frames,
+        )?,
+        FileType::TRR => benchmark_trajectory(
+            setup_reader(system.traj_cat_iter::<TrrReader>(&args.trajectory)?, args)?,
+            &mut grids,
+            classifier,
+            &preprocessor,
+            max_frames,
+        )?,
+        #[cfg(feature = "chemfiles")]
+        FileType::DCD | FileType::NC => benchmark_trajectory(
+            setup_reader(
+                system.traj_cat_iter::<ChemfilesReader>(&args.trajectory)?,
+                args,
+            )?,
+            &mut grids,
+            classifier,
+            &preprocessor,
+            max_frames,
+        )?,
+        _ => unreachable!(
+            "FATAL MEMTHICK ERROR | run_benchmark | Unsupported trajectory format should have been caught."
+        ),
+    };
+
+    println!("\n");
+
+    if timings.n_frames == 0 {
+        anyhow::bail!("No trajectory frames could be analyzed in the benchmark.");
+    }
+
+    let per_frame = timings.total().as_secs_f64() / timings.n_frames as f64;
+
+    println!("Benchmark of {} analyzed frames:", timings.n_frames);
+    timings.print_part("  reading", timings.reading);
+    timings.print_part("  center calculation", timings.center);
+    timings.print_part("  leaflet assignment", timings.assignment);
+    timings.print_part("  binning", timings.binning);
+    println!(
+        "{:<24} {:10.3} s ({:.1} frames/s)",
+        "  total",
+        timings.total().as_secs_f64(),
+        1.0 / per_frame
+    );
+
+    match expected {
+        Some(expected) => {
+            println!("\nFrames to analyze in the full analysis: {}", expected);
+            println!(
+                "Estimated runtime of the full analysis: {:.1} s",
+                expected as f64 * per_frame
+            );
+
+            if args.threads > 1 {
+                println!(
+                    "Estimated runtime using {} threads:   {:.1} s (assuming perfect scaling)",
+                    args.threads,
+                    expected as f64 * per_frame / args.threads as f64
+                );
+            }
+        }
+        None => println!(
+            "\nThe number of frames in the trajectory is not known. Runtime of the full analysis could not be estimated."
+        ),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,540 @@
+//! Block averaging for estimating the uncertainty of the average membrane thickness.
+
+use std::fmt::Display;
+
+use clap::ValueEnum;
+use groan_rs::prelude::*;
+
+use crate::grids::{LeafletTotals, SumMap, ThicknessGrids};
+
+/// Estimator of the errors of the thickness from the blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub(crate) enum ErrorMethod {
+    /// Standard error of the mean of the values calculated from the individual blocks.
+    #[default]
+    Blocks,
+    /// Jackknife error of the value calculated from all blocks, using the values calculated with each block left out.
+    Jackknife,
+}
+
+impl Display for ErrorMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = self.to_possible_value().expect(
+            "FATAL MEMTHICK ERROR | blocks::ErrorMethod::fmt | Error method should have a name.",
+        );
+
+        write!(f, "{}", value.get_name())
+    }
+}
+
+/// Grids accumulated separately for consecutive blocks of analyzed frames.
+#[derive(Debug)]
+pub(crate) struct BlockAverage {
+    blocks: Vec<ThicknessGrids>,
+    /// Number of analyzed frames in one block.
+    block_size: usize,
+    /// Estimator of the errors of the thickness.
+    pub(crate) method: ErrorMethod,
+}
+
+impl BlockAverage {
+    /// Prepare `n_blocks` empty blocks for the expected number of analyzed frames.
+    pub(crate) fn new(template: &ThicknessGrids, n_blocks: usize, n_frames: usize) -> Self {
+        let mut empty = template.clone();
+        empty.clear();
+
+        BlockAverage {
+            blocks: vec![empty; n_blocks],
+            block_size: n_frames.div_ceil(n_blocks).max(1),
+            method: ErrorMethod::default(),
+        }
+    }
+
+    /// Prepare empty blocks of `block_length` analyzed frames covering the expected number of analyzed frames.
+    /// The last block may contain fewer frames.
+    pub(crate) fn with_length(
+        template: &ThicknessGrids,
+        block_length: usize,
+        n_frames: usize,
+    ) -> anyhow::Result<Self> {
+        let n_blocks = n_frames.div_ceil(block_length);
+        if n_blocks < 2 {
+            anyhow::bail!(
+                "Blocks of {} frames are too long for {} analyzed frames. At least two blocks are required.",
+                block_length,
+                n_frames
+            );
+        }
+
+        let mut empty = template.clone();
+        empty.clear();
+
+        Ok(BlockAverage {
+            blocks: vec![empty; n_blocks],
+            block_size: block_length,
+            method: ErrorMethod::default(),
+        })
+    }
+
+    /// Get the grids accumulated for the individual blocks.
+    pub(crate) fn blocks(&self) -> &[ThicknessGrids] {
+        &self.blocks
+    }
+
+    /// Get the number of blocks.
+    pub(crate) fn n_blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Add headgroups of the analyzed frame with the specified index into the corresponding block.
+    /// See [`ThicknessGrids::add_heads`].
+    pub(crate) fn add_heads(
+        &mut self,
+        index: usize,
+        frame: &System,
+        membrane_center: &Vector3D,
+        upper: &[Option<bool>],
+        weight: f64,
+    ) -> anyhow::Result<()> {
+        // frames beyond the expected number of frames are added into the last block
+        let block = (index / self.block_size).min(self.blocks.len() - 1);
+        self.blocks[block].add_heads(frame, membrane_center, upper, weight)
+    }
+
+    /// Estimate the average membrane thickness and its standard error using the selected error method.
+    /// Returns `None` if fewer than two blocks contain data.
+    pub(crate) fn estimate(&self, grids: &ThicknessGrids, nan_limit: usize) -> Option<(f64, f64)> {
+        match self.method {
+            ErrorMethod::Blocks => self.block_estimate(grids, nan_limit),
+            ErrorMethod::Jackknife => self.jackknife_estimate(grids, nan_limit),
+        }
+    }
+
+    /// Calculate the standard error of the membrane thickness in each grid bin as `(x, y, error)`
+    /// using the selected error method.
+    ///
+    /// Only blocks with at least one sample in both leaflets of the bin are used. The error is NaN
+    /// for bins with undefined thickness in the full map (`grids`) and for bins with data in fewer than two blocks.
+    pub(crate) fn bin_errors(
+        &self,
+        grids: &ThicknessGrids,
+        nan_limit: usize,
+    ) -> Vec<(f32, f32, f64)> {
+        match self.method {
+            ErrorMethod::Blocks => self.block_bin_errors(grids, nan_limit),
+            ErrorMethod::Jackknife => self.jackknife_bin_errors(grids, nan_limit),
+        }
+    }
+
+    /// Calculate the mean of the average thicknesses of the individual blocks and its standard error.
+    ///
+    /// For every block, the average thickness is calculated over the bins with thickness defined
+    /// in the full map (`grids`) that contain at least one sample in both leaflets of the block.
+    /// Returns `None` if fewer than two blocks contain data.
+    fn block_estimate(&self, grids: &ThicknessGrids, nan_limit: usize) -> Option<(f64, f64)> {
+        let defined = grids
+            .thickness(nan_limit)
+            .map(|(_, _, thickness)| thickness.is_finite())
+            .collect::<Vec<_>>();
+
+        let averages = self
+            .blocks
+            .iter()
+            .filter_map(|block| {
+                let (sum, n) = block
+                    .thickness(1)
+                    .zip(defined.iter())
+                    .filter(|((_, _, thickness), defined)| **defined && thickness.is_finite())
+                    .fold((0.0, 0), |(sum, n), ((_, _, thickness), _)| {
+                        (sum + thickness, n + 1)
+                    });
+
+                (n > 0).then(|| sum / n as f64)
+            })
+            .collect::<Vec<_>>();
+
+        if averages.len() < 2 {
+            return None;
+        }
+
+        let n = averages.len() as f64;
+        let mean = averages.iter().sum::<f64>() / n;
+        let variance = averages.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+
+        Some((mean, (variance / n).sqrt()))
+    }
+
+    /// Calculate the standard error of the membrane thickness in each grid bin as `(x, y, error)`
+    /// from the thickness of the bin in the individual blocks.
+    fn block_bin_errors(&self, grids: &ThicknessGrids, nan_limit: usize) -> Vec<(f32, f32, f64)> {
+        let block_maps = self
+            .blocks
+            .iter()
+            .map(|block| {
+                block
+                    .thickness(1)
+                    .map(|(_, _, thickness)| thickness)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        grids
+            .thickness(nan_limit)
+            .enumerate()
+            .map(|(bin, (x, y, thickness))| {
+                let values = block_maps
+                    .iter()
+                    .map(|map| map[bin])
+                    .filter(|value| value.is_finite())
+                    .collect::<Vec<_>>();
+
+                if !thickness.is_finite() || values.len() < 2 {
+                    return (x, y, f64::NAN);
+                }
+
+                let n = values.len() as f64;
+                let mean = values.iter().sum::<f64>() / n;
+                let variance = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+
+                (x, y, (variance / n).sqrt())
+            })
+            .collect()
+    }
+
+    /// Sum the samples of all blocks in each grid bin and get the samples of the individual blocks
+    /// in the order of the grid maps.
+    fn totals(&self) -> (Vec<LeafletTotals>, Vec<Vec<LeafletTotals>>) {
+        let blocks = self
+            .blocks
+            .iter()
+            .map(|block| block.leaflet_totals())
+            .collect::<Vec<_>>();
+
+        let mut total = vec![LeafletTotals::default(); self.blocks[0].upper_sum.n_tiles()];
+        for block in blocks.iter() {
+            for (total, &bin) in total.iter_mut().zip(block.iter()) {
+                *total += bin;
+            }
+        }
+
+        (total, blocks)
+    }
+
+    /// Calculate the average thickness of all blocks and its jackknife standard error.
+    ///
+    /// The average thickness is calculated over the bins with thickness defined in the full map (`grids`)
+    /// from the samples of all blocks and then again with each block containing data in these bins left out.
+    /// Returns `None` if fewer than two blocks contain data.
+    fn jackknife_estimate(&self, grids: &ThicknessGrids, nan_limit: usize) -> Option<(f64, f64)> {
+        let defined = grids
+            .thickness(nan_limit)
+            .map(|(_, _, thickness)| thickness.is_finite())
+            .collect::<Vec<_>>();
+        let (total, blocks) = self.totals();
+
+        let average = |left_out: &[LeafletTotals]| {
+            let (sum, n) = total
+                .iter()
+                .zip(left_out.iter())
+                .zip(defined.iter())
+                .filter(|(_, defined)| **defined)
+                .filter_map(|((total, left_out), _)| total.thickness_since(left_out))
+                .fold((0.0, 0), |(sum, n), thickness| (sum + thickness, n + 1));
+
+            (n > 0).then(|| sum / n as f64)
+        };
+
+        let empty = vec![LeafletTotals::default(); total.len()];
+        let full = average(&empty)?;
+        let averages = blocks
+            .iter()
+            .filter(|block| {
+                block.iter().zip(defined.iter()).any(|(bin, defined)| {
+                    *defined && bin.thickness_since(&LeafletTotals::default()).is_some()
+                })
+            })
+            .filter_map(|block| average(block))
+            .collect::<Vec<_>>();
+
+        jackknife_error(&averages).map(|error| (full, error))
+    }
+
+    /// Calculate the jackknife standard error of the membrane thickness in each grid bin as `(x, y, error)`
+    /// from the thickness of the bin calculated with each block left out.
+    fn jackknife_bin_errors(
+        &self,
+        grids: &ThicknessGrids,
+        nan_limit: usize,
+    ) -> Vec<(f32, f32, f64)> {
+        let (total, blocks) = self.totals();
+
+        grids
+            .thickness(nan_limit)
+            .enumerate()
+            .map(|(bin, (x, y, thickness))| {
+                if !thickness.is_finite() {
+                    return (x, y, f64::NAN);
+                }
+
+                let values = blocks
+                    .iter()
+                    .filter(|block| {
+                        block[bin]
+                            .thickness_since(&LeafletTotals::default())
+                            .is_some()
+                    })
+                    .filter_map(|block| total[bin].thickness_since(&block[bin]))
+                    .collect::<Vec<_>>();
+
+                (x, y, jackknife_error(&values).unwrap_or(f64::NAN))
+            })
+            .collect()
+    }
+
+    /// Estimate the confidence interval of the membrane thickness in each grid bin as `(x, y, lower, upper)`
+    /// by bootstrapping over the blocks.
+    ///
+    /// In each of the `n_samples` bootstrap samples, blocks are drawn with replacement and the thickness of every bin
+    /// is calculated from the data of the drawn blocks. The bounds are the percentiles of these thicknesses enclosing
+    /// the `confidence` fraction of the samples. The bounds are NaN for bins with undefined thickness in the full map
+    /// (`grids`). Bootstrap samples without data in both leaflets of a bin are ignored for that bin.
+    pub(crate) fn bootstrap(
+        &self,
+        grids: &ThicknessGrids,
+        nan_limit: usize,
+        n_samples: usize,
+        confidence: f64,
+        seed: u64,
+    ) -> Vec<(f32, f32, f64, f64)> {
+        let raw = |map: &SumMap| {
+            map.extract_raw()
+                .map(|(_, _, value)| *value)
+                .collect::<Vec<_>>()
+        };
+        let blocks = self
+            .blocks
+            .iter()
+            .map(|block| {
+                [
+                    raw(&block.upper_sum),
+                    raw(&block.upper_weight),
+                    raw(&block.lower_sum),
+                    raw(&block.lower_weight),
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        let n_bins = grids.upper_sum.n_tiles();
+        let mut samples = vec![Vec::with_capacity(n_samples); n_bins];
+        let mut random = SplitMix64(seed);
+        let mut multiplicity = vec![0usize; blocks.len()];
+        for _ in 0..n_samples {
+            multiplicity.fill(0);
+            for _ in 0..blocks.len() {
+                multiplicity[random.below(blocks.len())] += 1;
+            }
+
+            for (bin, samples) in samples.iter_mut().enumerate() {
+                let mut totals = [0.0; 4];
+                for (block, &count) in blocks.iter().zip(multiplicity.iter()) {
+                    for (total, map) in totals.iter_mut().zip(block.iter()) {
+                        *total += count as f64 * map[bin];
+                    }
+                }
+
+                let [upper_sum, upper_weight, lower_sum, lower_weight] = totals;
+                if upper_weight > 0.0 && lower_weight > 0.0 {
+                    samples.push(upper_sum / upper_weight - lower_sum / lower_weight);
+                }
+            }
+        }
+
+        let tail = (1.0 - confidence) / 2.0;
+        grids
+            .thickness(nan_limit)
+            .zip(samples.iter_mut())
+            .map(|((x, y, thickness), samples)| {
+                if !thickness.is_finite() || samples.is_empty() {
+                    return (x, y, f64::NAN, f64::NAN);
+                }
+
+                samples.sort_by(f64::total_cmp);
+                (
+                    x,
+                    y,
+                    percentile(samples, tail),
+                    percentile(samples, 1.0 - tail),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Calculate the jackknife standard error from the values calculated with each block left out.
+/// Returns `None` for fewer than two values.
+fn jackknife_error(values: &[f64]) -> Option<f64> {
+    if values.len() < 2 {
+        return None;
+    }
+
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() * (n - 1.0) / n;
+
+    Some(variance.sqrt())
+}
+
+/// Calculate the percentile of the sorted values by linear interpolation between the closest ranks.
+fn percentile(sorted: &[f64], fraction: f64) -> f64 {
+    let rank = fraction * (sorted.len() - 1) as f64;
+    let (below, above) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[below] + (rank - below as f64) * (sorted[above] - sorted[below])
+}
+
+/// Simple pseudo-random number generator making the bootstrap reproducible for the same seed.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    /// Generate the next pseudo-random number.
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Generate a pseudo-random index smaller than `n`.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{geometry::Geometry, normal::Normal};
+
+    /// Add a sample of the given height to the bin of the upper or the lower leaflet.
+    fn add(grids: &mut ThicknessGrids, (x, y): (f32, f32), is_upper: bool, value: f64) {
+        let (sum, count, weight) = if is_upper {
+            (
+                &mut grids.upper_sum,
+                &mut grids.upper_count,
+                &mut grids.upper_weight,
+            )
+        } else {
+            (
+                &mut grids.lower_sum,
+                &mut grids.lower_count,
+                &mut grids.lower_weight,
+            )
+        };
+
+        *sum.get_mut_at(x, y).unwrap() += value;
+        *count.get_mut_at(x, y).unwrap() += 1;
+        *weight.get_mut_at(x, y).unwrap() += 1.0;
+    }
+
+    /// Prepare blocks with the specified thickness in the bin at (1.0, 1.0) and no data in the other bins.
+    fn blocks(thickness: &[f64]) -> (ThicknessGrids, BlockAverage) {
+        let grids = ThicknessGrids::new(
+            (0.0, 2.0),
+            (0.0, 2.0),
+            (1.0, 1.0),
+            Normal::default(),
+            Geometry::Planar,
+        )
+        .unwrap();
+
+        let mut average = BlockAverage::new(&grids, thickness.len(), thickness.len());
+        let mut full = grids.clone();
+        for (block, &value) in average.blocks.iter_mut().zip(thickness.iter()) {
+            add(block, (1.0, 1.0), true, value / 2.0);
+            add(block, (1.0, 1.0), false, -value / 2.0);
+            full.merge(block);
+        }
+
+        (full, average)
+    }
+
+    #[test]
+    fn jackknife_error_known_value() {
+        // mean 2, sum of squared deviations 2, error sqrt(2 * 2 / 3)
+        let error = jackknife_error(&[1.0, 2.0, 3.0]).unwrap();
+        assert!((error - (4.0f64 / 3.0).sqrt()).abs() < 1e-12);
+
+        assert_eq!(jackknife_error(&[5.0, 5.0, 5.0, 5.0]), Some(0.0));
+    }
+
+    #[test]
+    fn jackknife_error_too_few_values() {
+        assert_eq!(jackknife_error(&[]), None);
+        assert_eq!(jackknife_error(&[1.0]), None);
+    }
+
+    #[test]
+    fn percentile_edges() {
+        let sorted = [1.0, 2.0, 3.0, 4.0];
+        assert_eq!(percentile(&sorted, 0.0), 1.0);
+        assert_eq!(percentile(&sorted, 1.0), 4.0);
+        assert!((percentile(&sorted, 0.5) - 2.5).abs() < 1e-12);
+        assert!((percentile(&sorted, 1.0 / 3.0) - 2.0).abs() < 1e-12);
+        assert!((percentile(&sorted, 0.9) - 3.7).abs() < 1e-12);
+
+        assert_eq!(percentile(&[7.0], 0.0), 7.0);
+        assert_eq!(percentile(&[7.0], 0.975), 7.0);
+    }
+
+    #[test]
+    fn splitmix_reference_values() {
+        let mut random = SplitMix64(0);
+        assert_eq!(random.next(), 0xE220_A839_7B1D_CDAF);
+        assert_eq!(random.next(), 0x6E78_9E6A_A1B9_65F4);
+        assert_eq!(random.next(), 0x06C4_5D18_8009_454F);
+    }
+
+    #[test]
+    fn splitmix_below() {
+        let mut random = SplitMix64(42);
+        assert!((0..1000).all(|_| random.below(7) < 7));
+    }
+
+    #[test]
+    fn bootstrap_reproducible() {
+        let (full, average) = blocks(&[3.0, 3.5, 4.0, 4.5, 5.0]);
+
+        let first = average.bootstrap(&full, 1, 200, 0.95, 1234);
+        let second = average.bootstrap(&full, 1, 200, 0.95, 1234);
+        assert_eq!(first.len(), second.len());
+        for (a, b) in first.iter().zip(second.iter()) {
+            assert_eq!(a.0, b.0);
+            assert_eq!(a.1, b.1);
+            assert!(a.2.to_bits() == b.2.to_bits() && a.3.to_bits() == b.3.to_bits());
+        }
+
+        for &(x, y, lower, upper) in first.iter() {
+            if (x, y) == (1.0, 1.0) {
+                assert!(lower >= 3.0 && upper <= 5.0 && lower < upper);
+            } else {
+                assert!(lower.is_nan() && upper.is_nan());
+            }
+        }
+    }
+
+    #[test]
+    fn jackknife_estimate_of_mean() {
+        // the jackknife error of a mean equals the standard error of the mean
+        let (full, mut average) = blocks(&[1.0, 2.0, 3.0]);
+        average.method = ErrorMethod::Jackknife;
+
+        let (thickness, error) = average.estimate(&full, 1).unwrap();
+        assert!((thickness - 2.0).abs() < 1e-12);
+        assert!((error - (1.0f64 / 3.0).sqrt()).abs() < 1e-12);
+
+        average.method = ErrorMethod::Blocks;
+        let (thickness, error) = average.estimate(&full, 1).unwrap();
+        assert!((thickness - 2.0).abs() < 1e-12);
+        assert!((error - (1.0f64 / 3.0).sqrt()).abs() < 1e-12);
+    }
+}
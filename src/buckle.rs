@@ -0,0 +1,199 @@
+//! Description of buckled membranes by the profile of their midplane.
+
+use groan_rs::{errors::SimBoxError, prelude::*};
+
+use crate::{TAILS_GROUP, normal::Normal};
+
+/// Profile of the midplane of a membrane buckled along the first axis of the membrane plane.
+///
+/// The height of the midplane along the membrane normal is described by a Fourier series periodic
+/// in the buckling direction and fitted to the positions of lipid tail ends. For projecting positions
+/// onto the midplane, the profile is sampled in regularly spaced points connected by line segments.
+#[derive(Debug)]
+pub(crate) struct BuckleProfile {
+    normal: Normal,
+    /// Lateral coordinate and height of the sampled points of the midplane.
+    points: Vec<(f32, f32)>,
+    /// Arclength of the midplane at each sampled point, measured from the first point.
+    /// Contains one additional value, the contour length of the whole profile.
+    arclengths: Vec<f32>,
+    /// Size of the simulation box in the buckling direction.
+    period: f32,
+}
+
+/// Number of points in which the profile of the midplane is sampled.
+const N_SAMPLES: usize = 250;
+
+impl BuckleProfile {
+    /// Calculate the profile of the midplane in the frame using `n_modes` Fourier modes.
+    pub(crate) fn new(
+        frame: &System,
+        membrane_center: &Vector3D,
+        normal: Normal,
+        n_modes: usize,
+    ) -> anyhow::Result<BuckleProfile> {
+        let simbox = frame.get_box().ok_or(SimBoxError::DoesNotExist)?;
+        let (period, _) = normal.lateral_box(simbox).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Buckled membranes require the membrane normal to be oriented along an axis of the simulation box."
+            )
+        })?;
+
+        // least-squares fit of the heights of lipid tail ends using the normal equations
+        let n_terms = 2 * n_modes + 1;
+        let mut matrix = vec![vec![0.0f64; n_terms]; n_terms];
+        let mut rhs = vec![0.0f64; n_terms];
+        for atom in frame.group_iter(TAILS_GROUP)? {
+            let position = atom
+                .get_position()
+                .ok_or_else(|| anyhow::anyhow!("Atom {} has no position.", atom.get_index() + 1))?;
+            let phase = 2.0 * std::f32::consts::PI * normal.lateral(position).0 / period;
+            let height = normal.distance(position, membrane_center, simbox) as f64;
+
+            let terms = basis(phase, n_modes);
+            for i in 0..n_terms {
+                for j in 0..n_terms {
+                    matrix[i][j] += terms[i] * terms[j];
+                }
+                rhs[i] += terms[i] * height;
+            }
+        }
+
+        let coefficients = solve(matrix, rhs).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Could not fit the profile of the buckled membrane at time {} ps. Try decreasing '--buckle-modes'.",
+                frame.get_simulation_time()
+            )
+        })?;
+
+        let points = (0..N_SAMPLES)
+            .map(|i| {
+                let coordinate = period * i as f32 / N_SAMPLES as f32;
+                let phase = 2.0 * std::f32::consts::PI * coordinate / period;
+                let height = basis(phase, n_modes)
+                    .iter()
+                    .zip(coefficients.iter())
+                    .map(|(term, coefficient)| term * coefficient)
+                    .sum::<f64>();
+
+                (coordinate, height as f32)
+            })
+            .collect::<Vec<_>>();
+
+        let mut profile = BuckleProfile {
+            normal,
+            points,
+            arclengths: vec![0.0],
+            period,
+        };
+
+        for i in 0..N_SAMPLES {
+            let (dx, dh) = profile.segment(i).1;
+            let length = profile.arclengths[i] + dx.hypot(dh);
+            profile.arclengths.push(length);
+        }
+
+        Ok(profile)
+    }
+
+    /// Contour length of the whole profile.
+    pub(crate) fn length(&self) -> f32 {
+        *self.arclengths.last().expect(
+            "FATAL MEMTHICK ERROR | buckle::BuckleProfile::length | Profile should contain at least one arclength.",
+        )
+    }
+
+    /// Get the start point and the direction of the segment between the sampled point `i` and the next point.
+    fn segment(&self, i: usize) -> ((f32, f32), (f32, f32)) {
+        let start = self.points[i];
+        let end = self.points[(i + 1) % self.points.len()];
+
+        (start, (self.period / N_SAMPLES as f32, end.1 - start.1))
+    }
+
+    /// Position in the map of the buckled membrane and the signed distance from the midplane.
+    ///
+    /// The position is projected onto the closest point of the midplane. Returns the arclength
+    /// of this point, the transverse coordinate (second axis of the membrane plane), and the distance
+    /// of the position from the midplane, positive on the upper side.
+    pub(crate) fn map_position(
+        &self,
+        position: &Vector3D,
+        membrane_center: &Vector3D,
+        simbox: &SimBox,
+    ) -> (f32, f32, f32) {
+        let (coordinate, transverse) = self.normal.lateral(position);
+        let height = self.normal.distance(position, membrane_center, simbox);
+
+        let mut closest = (f32::INFINITY, 0.0, 0.0);
+        for i in 0..self.points.len() {
+            let ((x, h), (dx, dh)) = self.segment(i);
+
+            // vector from the start of the segment to the position, using minimum image in the buckling direction
+            let mut rx = (coordinate - x).rem_euclid(self.period);
+            if rx > self.period / 2.0 {
+                rx -= self.period;
+            }
+            let rh = height - h;
+
+            let length = dx.hypot(dh);
+            let t = ((rx * dx + rh * dh) / (length * length)).clamp(0.0, 1.0);
+            let (ox, oh) = (rx - t * dx, rh - t * dh);
+
+            let distance = ox.hypot(oh);
+            if distance < closest.0 {
+                let signed = (dx * oh - dh * ox) / length;
+                closest = (distance, self.arclengths[i] + t * length, signed);
+            }
+        }
+
+        (closest.1, transverse, closest.2)
+    }
+}
+
+/// Values of the terms of the Fourier series with `n_modes` modes at the specified phase.
+fn basis(phase: f32, n_modes: usize) -> Vec<f64> {
+    let phase = phase as f64;
+    let mut terms = vec![1.0];
+    for k in 1..=n_modes {
+        terms.push((k as f64 * phase).cos());
+        terms.push((k as f64 * phase).sin());
+    }
+
+    terms
+}
+
+/// Solve the system of linear equations using Gaussian elimination with partial pivoting.
+/// Returns `None` if the system is singular.
+fn solve(mut matrix: Vec<Vec<f64>>, mut rhs: Vec<f64>) -> Option<Vec<f64>> {
+    let n = rhs.len();
+    for column in 0..n {
+        let pivot = (column..n)
+            .max_by(|&a, &b| matrix[a][column].abs().total_cmp(&matrix[b][column].abs()))?;
+        if matrix[pivot][column].abs() < 1e-9 {
+            return None;
+        }
+
+        matrix.swap(column, pivot);
+        rhs.swap(column, pivot);
+
+        let pivot_row = matrix[column].clone();
+        for row in column + 1..n {
+            let factor = matrix[row][column] / pivot_row[column];
+            for (value, pivot_value) in matrix[row].iter_mut().zip(pivot_row.iter()).skip(column) {
+                *value -= factor * pivot_value;
+            }
+            rhs[row] -= factor * rhs[column];
+        }
+    }
+
+    let mut solution = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum = (row + 1..n)
+            .map(|k| matrix[row][k] * solution[k])
+            .sum::<f64>();
+        solution[row] = (rhs[row] - sum) / matrix[row][row];
+    }
+
+    Some(solution)
+}
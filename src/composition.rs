@@ -0,0 +1,166 @@
+//! Lipid composition of the grid bins in both membrane leaflets.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use groan_rs::prelude::*;
+
+use crate::{HEADS_GROUP, grids::CountMap};
+
+/// Numbers of headgroups of each lipid species detected in the grid bins of both leaflets.
+#[derive(Debug, Clone)]
+pub(crate) struct Composition {
+    /// Residue names of the lipid species, sorted alphabetically.
+    names: Vec<String>,
+    /// Index of the lipid species of each headgroup.
+    species: Vec<usize>,
+    upper: Vec<CountMap>,
+    lower: Vec<CountMap>,
+}
+
+impl Composition {
+    /// Identify the lipid species of the headgroups by their residue names and prepare empty maps
+    /// spanning the same area as the `template` map.
+    pub(crate) fn new(system: &System, template: &CountMap) -> anyhow::Result<Composition> {
+        let residues = system
+            .group_iter(HEADS_GROUP)?
+            .map(|atom| atom.get_residue_name().to_owned())
+            .collect::<Vec<_>>();
+
+        let mut names = residues.clone();
+        names.sort();
+        names.dedup();
+
+        let species = residues
+            .iter()
+            .map(|name| {
+                names.binary_search(name).expect(
+                    "FATAL MEMTHICK ERROR | composition::Composition::new | Residue name should be a known species.",
+                )
+            })
+            .collect();
+
+        let mut empty = template.clone();
+        empty.clear();
+
+        Ok(Composition {
+            upper: vec![empty.clone(); names.len()],
+            lower: vec![empty; names.len()],
+            names,
+            species,
+        })
+    }
+
+    /// Count the headgroup with the specified index in the bin of the leaflet at the map coordinates.
+    pub(crate) fn add(&mut self, head: usize, is_upper: bool, x: f32, y: f32) {
+        let maps = if is_upper {
+            &mut self.upper
+        } else {
+            &mut self.lower
+        };
+        if let Some(count) = maps[self.species[head]].get_mut_at(x, y) {
+            *count += 1;
+        }
+    }
+
+    /// Remove all counted headgroups.
+    pub(crate) fn clear(&mut self) {
+        for map in self.upper.iter_mut().chain(self.lower.iter_mut()) {
+            map.clear();
+        }
+    }
+
+    /// Add the headgroups counted in the other composition into this composition.
+    pub(crate) fn merge(&mut self, other: &Composition) {
+        for (map, other) in self
+            .upper
+            .iter_mut()
+            .chain(self.lower.iter_mut())
+            .zip(other.upper.iter().chain(other.lower.iter()))
+        {
+            for (x, y, value) in other.extract_raw() {
+                if let Some(count) = map.get_mut_at(x, y) {
+                    *count += *value;
+                }
+            }
+        }
+    }
+
+    /// Write the fraction of headgroups of each lipid species in every bin of both leaflets into a file.
+    /// Fractions are NaN for bins without any headgroups in the leaflet.
+    pub(crate) fn write(
+        &self,
+        filename: impl AsRef<Path>,
+        raw_arguments: &[String],
+    ) -> anyhow::Result<()> {
+        let filename = filename.as_ref();
+        let file = File::create(filename).with_context(|| {
+            format!(
+                "Could not create composition file '{}'.",
+                filename.display()
+            )
+        })?;
+        let mut output = BufWriter::new(file);
+
+        writeln!(
+            &mut output,
+            "# Generated with memthick v{}.",
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(&mut output, "# Command line: {}", raw_arguments.join(" "))?;
+        writeln!(
+            &mut output,
+            "# Fraction of headgroups of each lipid species detected in the grid bins of the upper and lower leaflet."
+        )?;
+
+        let columns = self
+            .names
+            .iter()
+            .map(|name| format!("upper:{}", name))
+            .chain(self.names.iter().map(|name| format!("lower:{}", name)))
+            .collect::<Vec<_>>();
+        writeln!(&mut output, "# x y {}", columns.join(" "))?;
+
+        let mut upper = self
+            .upper
+            .iter()
+            .map(|map| map.extract_raw())
+            .collect::<Vec<_>>();
+        let mut lower = self
+            .lower
+            .iter()
+            .map(|map| map.extract_raw())
+            .collect::<Vec<_>>();
+        let Some(template) = self.upper.first() else {
+            return Ok(());
+        };
+
+        for _ in template.extract_raw() {
+            let upper_counts = upper
+                .iter_mut()
+                .filter_map(Iterator::next)
+                .collect::<Vec<_>>();
+            let lower_counts = lower
+                .iter_mut()
+                .filter_map(Iterator::next)
+                .collect::<Vec<_>>();
+            let (x, y, _) = upper_counts[0];
+
+            write!(&mut output, "{:12.6} {:12.6}", x, y)?;
+            for counts in [upper_counts, lower_counts] {
+                let total = counts.iter().map(|(_, _, count)| **count).sum::<usize>();
+                for (_, _, count) in counts {
+                    write!(&mut output, " {:8.4}", *count as f64 / total as f64)?;
+                }
+            }
+            writeln!(&mut output)?;
+        }
+
+        output.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+//! Membrane thickness at the lateral positions of the individual residues of a protein.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use groan_rs::prelude::*;
+
+use crate::{grids::ThicknessGrids, normal::Normal};
+
+/// Residue whose lateral position is tracked.
+#[derive(Debug, Clone)]
+struct Residue {
+    number: usize,
+    name: String,
+    /// Indices of the selected atoms of the residue.
+    atoms: Vec<usize>,
+}
+
+/// Grid bins visited by the centers of the selected residues over the analyzed frames.
+#[derive(Debug, Clone)]
+pub(crate) struct ResidueThickness {
+    residues: Vec<Residue>,
+    n_x: usize,
+    n_y: usize,
+    tile: (f32, f32),
+    /// Center of the first grid bin.
+    start: (f32, f32),
+    /// Number of frames in which the center of each residue was located in each grid bin.
+    visits: Vec<HashMap<usize, usize>>,
+}
+
+impl ResidueThickness {
+    /// Prepare the tracking of the residues of the atoms of the group. Consecutive atoms with the same
+    /// residue number form one residue.
+    pub(crate) fn new(
+        system: &System,
+        group: &str,
+        grids: &ThicknessGrids,
+    ) -> anyhow::Result<ResidueThickness> {
+        let mut residues: Vec<Residue> = Vec::new();
+        for atom in system.group_iter(group)? {
+            match residues.last_mut() {
+                Some(residue) if residue.number == atom.get_residue_number() => {
+                    residue.atoms.push(atom.get_index())
+                }
+                _ => residues.push(Residue {
+                    number: atom.get_residue_number(),
+                    name: atom.get_residue_name().to_owned(),
+                    atoms: vec![atom.get_index()],
+                }),
+            }
+        }
+
+        if residues.is_empty() {
+            anyhow::bail!("No residues selected for the thickness at the residues.");
+        }
+
+        let n_residues = residues.len();
+        Ok(ResidueThickness {
+            residues,
+            n_x: grids.upper_sum.n_tiles_x(),
+            n_y: grids.upper_sum.n_tiles_y(),
+            tile: grids.upper_sum.tile_dim(),
+            start: (grids.upper_sum.span_x().0, grids.upper_sum.span_y().0),
+            visits: vec![HashMap::new(); n_residues],
+        })
+    }
+
+    /// Add the grid bins containing the centers of the residues in the membrane plane in the frame.
+    /// Centers are calculated using the periodic boundary conditions. Residues located outside the grids
+    /// (also after folding them into the grids using the periodicity of the simulation box) are skipped.
+    pub(crate) fn add(
+        &mut self,
+        frame: &System,
+        normal: &Normal,
+        simbox: &SimBox,
+    ) -> anyhow::Result<()> {
+        let periods = normal.lateral_box(simbox);
+        let images = |value: f32, period: Option<f32>| match period {
+            Some(period) => [value, value - period, value + period],
+            None => [value; 3],
+        };
+
+        for (residue, visits) in self.residues.iter().zip(self.visits.iter_mut()) {
+            let position = |index: usize| {
+                frame
+                    .get_atom(index)?
+                    .get_position()
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Atom {} has no position.", index + 1))
+            };
+
+            let first = position(residue.atoms[0])?;
+            let mut shift = Vector3D::new(0.0, 0.0, 0.0);
+            for &index in residue.atoms.iter().skip(1) {
+                shift = shift + first.vector_to(&position(index)?, simbox);
+            }
+            let center = first + shift / residue.atoms.len() as f32;
+            let (x, y) = normal.lateral(&center);
+
+            let bin = images(x, periods.map(|periods| periods.0))
+                .into_iter()
+                .flat_map(|x| images(y, periods.map(|periods| periods.1)).map(move |y| (x, y)))
+                .find_map(|(x, y)| {
+                    let (i, j) = (
+                        ((x - self.start.0) / self.tile.0).round(),
+                        ((y - self.start.1) / self.tile.1).round(),
+                    );
+                    (i >= 0.0 && j >= 0.0 && (i as usize) < self.n_x && (j as usize) < self.n_y)
+                        .then(|| i as usize * self.n_y + j as usize)
+                });
+
+            if let Some(bin) = bin {
+                *visits.entry(bin).or_default() += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove all visited bins.
+    pub(crate) fn clear(&mut self) {
+        for visits in self.visits.iter_mut() {
+            visits.clear();
+        }
+    }
+
+    /// Add the bins visited in the other tracking into this tracking.
+    pub(crate) fn merge(&mut self, other: &ResidueThickness) {
+        for (visits, other) in self.visits.iter_mut().zip(other.visits.iter()) {
+            for (&bin, &count) in other.iter() {
+                *visits.entry(bin).or_default() += count;
+            }
+        }
+    }
+
+    /// Write the average thickness of the final map at the positions of every residue over the analyzed frames.
+    /// Bins with undefined thickness are ignored.
+    pub(crate) fn write(
+        &self,
+        filename: impl AsRef<Path>,
+        grids: &ThicknessGrids,
+        nan_limit: usize,
+        raw_arguments: &[String],
+    ) -> anyhow::Result<()> {
+        let filename = filename.as_ref();
+        let thickness = grids
+            .thickness(nan_limit)
+            .map(|(_, _, thickness)| thickness)
+            .collect::<Vec<_>>();
+
+        let file = File::create(filename).with_context(|| {
+            format!(
+                "Could not create residue thickness file '{}'.",
+                filename.display()
+            )
+        })?;
+        let mut output = BufWriter::new(file);
+
+        writeln!(
+            &mut output,
+            "# Generated with memthick v{}.",
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(&mut output, "# Command line: {}", raw_arguments.join(" "))?;
+        writeln!(
+            &mut output,
+            "# Average membrane thickness at the lateral positions of the residues over the analyzed frames."
+        )?;
+        writeln!(&mut output, "# residue name thickness [nm] frames")?;
+
+        for (residue, visits) in self.residues.iter().zip(self.visits.iter()) {
+            let (sum, n_frames) = visits
+                .iter()
+                .filter(|&(&bin, _)| thickness[bin].is_finite())
+                .fold((0.0, 0), |(sum, n), (&bin, &count)| {
+                    (sum + thickness[bin] * count as f64, n + count)
+                });
+
+            writeln!(
+                &mut output,
+                "{:8} {:>6} {:12.4} {:8}",
+                residue.number,
+                residue.name,
+                sum / n_frames as f64,
+                n_frames
+            )?;
+        }
+
+        output.flush()?;
+        Ok(())
+    }
+}
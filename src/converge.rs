@@ -0,0 +1,211 @@
+//! Assessment of the convergence of the thickness map from the maps of consecutive trajectory segments.
+
+use std::io::Write;
+
+use clap::Parser;
+
+use crate::{
+    Args, blocks::BlockAverage, grids::ThicknessGrids, run_analysis, suffixed_name, write_values,
+};
+
+/// Arguments of the `converge` subcommand.
+#[derive(clap::Args, Debug, Clone)]
+pub(crate) struct ConvergeArgs {
+    #[arg(
+        short = 'n',
+        long = "segments",
+        help = "Number of trajectory segments.",
+        long_help = "Number of consecutive segments of equal size into which the analyzed frames are split. A separate thickness map is calculated for each segment.",
+        default_value_t = 2
+    )]
+    segments: usize,
+
+    #[arg(
+        short = 'o',
+        long = "output",
+        help = "Path to the output file.",
+        long_help = "Path to the output file where the map of the root-mean-square difference of the thickness between the segment maps will be written. The root-mean-square differences between the individual pairs of segment maps are written at the end of the file.",
+        default_value = "convergence_map.dat"
+    )]
+    output: String,
+
+    #[arg(
+        long = "segment-maps",
+        help = "Also write the thickness maps of the individual segments.",
+        long_help = "Write the thickness map of every segment into a separate file named by appending the index of the segment to the provided file name, e.g., 'segment.dat' becomes 'segment_1.dat', 'segment_2.dat', etc.",
+        value_name = "FILE"
+    )]
+    segment_maps: Option<String>,
+
+    #[arg(
+        help = "Options of the analysis",
+        long_help = "Options of the analysis of the trajectory provided after '--', e.g., '-- -s system.gro -f md.xtc'. See 'memthick --help' for all options. The thickness map of the whole trajectory is written as usual.",
+        last = true,
+        required = true
+    )]
+    analysis: Vec<String>,
+}
+
+/// Output files of the convergence assessment.
+#[derive(Debug, Clone)]
+pub(crate) struct ConvergeOutput {
+    /// Map of the root-mean-square difference between the segment maps.
+    pub(crate) output: String,
+    /// Thickness maps of the individual segments (if requested).
+    pub(crate) segment_maps: Option<String>,
+}
+
+/// Print the specified options of the `converge` subcommand.
+fn print_options(args: &ConvergeArgs) {
+    println!("[SEGMENTS]      {}", args.segments);
+    println!("[CONVERGENCE]   {}", args.output);
+    if let Some(segment_maps) = args.segment_maps.as_ref() {
+        println!("[SEGMENT MAPS]  {}", segment_maps);
+    }
+}
+
+/// Analyze the trajectory split into segments and write the differences between the thickness maps of the segments.
+pub(crate) fn run_converge(args: &ConvergeArgs, raw_arguments: &[String]) -> anyhow::Result<()> {
+    if args.segments < 2 {
+        anyhow::bail!(
+            "Number of segments must be at least 2, not {}",
+            args.segments
+        );
+    }
+
+    let mut analysis = Args::try_parse_from(
+        std::iter::once("memthick").chain(args.analysis.iter().map(String::as_str)),
+    )?;
+
+    if analysis.command.is_some() {
+        anyhow::bail!("Subcommands cannot be used in the analysis of the convergence.");
+    }
+
+    if analysis.trajectory.is_empty() {
+        anyhow::bail!("Convergence assessment requires a trajectory.");
+    }
+
+    if analysis.blocks.is_some() || analysis.block_length.is_some() {
+        anyhow::bail!(
+            "Blocks cannot be used in the analysis of the convergence. Use '--segments' instead."
+        );
+    }
+
+    print_options(args);
+
+    // the segments are the blocks of the analysis
+    analysis.blocks = Some(args.segments);
+    analysis.converge = Some(ConvergeOutput {
+        output: args.output.clone(),
+        segment_maps: args.segment_maps.clone(),
+    });
+
+    run_analysis(analysis, raw_arguments)
+}
+
+/// Write the map of the root-mean-square difference of the thickness between the maps of the segments
+/// and the maps of the individual segments (if requested). The NAN limit is applied to every segment map.
+pub(crate) fn write_convergence(
+    output: &ConvergeOutput,
+    grids: &ThicknessGrids,
+    blocks: &BlockAverage,
+    nan_limit: usize,
+    raw_arguments: &[String],
+) -> anyhow::Result<()> {
+    let maps = blocks
+        .blocks()
+        .iter()
+        .map(|block| block.thickness(nan_limit).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    if let Some(segment_maps) = &output.segment_maps {
+        for (index, map) in maps.iter().enumerate() {
+            write_values(
+                suffixed_name(segment_maps, index + 1),
+                grids,
+                "membrane thickness",
+                Some("nm"),
+                map,
+                raw_arguments,
+            )?;
+        }
+    }
+
+    let pairs = (0..maps.len())
+        .flat_map(|i| (i + 1..maps.len()).map(move |j| (i, j)))
+        .collect::<Vec<_>>();
+
+    // root-mean-square difference between the pairs of segments over the bins defined in both segments
+    let rms = |differences: &mut dyn Iterator<Item = f64>| {
+        let (sum, n) = differences
+            .filter(|difference| difference.is_finite())
+            .fold((0.0, 0), |(sum, n), difference| {
+                (sum + difference * difference, n + 1)
+            });
+        ((sum / n as f64).sqrt(), n)
+    };
+
+    let values = grids
+        .upper_sum
+        .extract_raw()
+        .enumerate()
+        .map(|(bin, (x, y, _))| {
+            let mut differences = pairs.iter().map(|&(i, j)| maps[i][bin].2 - maps[j][bin].2);
+            (x, y, rms(&mut differences).0)
+        })
+        .collect::<Vec<_>>();
+
+    let mut file = write_values(
+        &output.output,
+        grids,
+        "root-mean-square difference of the membrane thickness between segments",
+        Some("nm"),
+        &values,
+        raw_arguments,
+    )?;
+
+    println!(
+        "\nConvergence of the thickness map ({} segments):",
+        maps.len()
+    );
+    for (index, map) in maps.iter().enumerate() {
+        let (sum, n) = map
+            .iter()
+            .filter(|(_, _, thickness)| thickness.is_finite())
+            .fold((0.0, 0), |(sum, n), (_, _, thickness)| {
+                (sum + thickness, n + 1)
+            });
+        println!(
+            "Segment {:4}: average thickness {:8.4} nm ({} bins)",
+            index + 1,
+            sum / n as f64,
+            n
+        );
+    }
+
+    let mut total = 0.0;
+    for &(i, j) in pairs.iter() {
+        let mut differences = maps[i].iter().zip(maps[j].iter()).map(|(a, b)| a.2 - b.2);
+        let (rmsd, n) = rms(&mut differences);
+        total += rmsd;
+
+        let line = format!(
+            "RMSD between segments {} and {}: {:12.4} nm ({} bins)",
+            i + 1,
+            j + 1,
+            rmsd,
+            n
+        );
+        println!("{}", line);
+        writeln!(&mut file, "# {}", line)?;
+    }
+
+    let line = format!(
+        "Average RMSD between segments: {:12.4} nm",
+        total / pairs.len() as f64
+    );
+    println!("{}", line);
+    writeln!(&mut file, "# {}", line)?;
+
+    Ok(())
+}
@@ -0,0 +1,87 @@
+//! Writing the cumulative average membrane thickness as a function of the number of analyzed frames.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+
+use crate::grids::ThicknessGrids;
+
+/// Writer of the convergence file.
+pub(crate) struct ConvergenceWriter {
+    output: BufWriter<File>,
+}
+
+impl ConvergenceWriter {
+    /// Create a new convergence file and write its header.
+    pub(crate) fn create(
+        filename: impl AsRef<Path>,
+        raw_arguments: &[String],
+    ) -> anyhow::Result<()> {
+        let filename = filename.as_ref();
+        let file = File::create(filename).with_context(|| {
+            format!(
+                "Could not create convergence file '{}'.",
+                filename.display()
+            )
+        })?;
+        let mut output = BufWriter::new(file);
+
+        writeln!(
+            &mut output,
+            "# Generated with memthick v{}.",
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(&mut output, "# Command line: {}", raw_arguments.join(" "))?;
+        writeln!(
+            &mut output,
+            "# Cumulative average membrane thickness calculated from all frames analyzed so far."
+        )?;
+        writeln!(&mut output, "@ xlabel number of analyzed frames")?;
+        writeln!(&mut output, "@ ylabel average membrane thickness [nm]")?;
+        writeln!(
+            &mut output,
+            "# frames time [ps] thickness [nm] defined bins"
+        )?;
+
+        output.flush()?;
+        Ok(())
+    }
+
+    /// Open an existing convergence file for appending.
+    pub(crate) fn open(filename: impl AsRef<Path>) -> anyhow::Result<ConvergenceWriter> {
+        let filename = filename.as_ref();
+        let file = OpenOptions::new()
+            .append(true)
+            .open(filename)
+            .with_context(|| {
+                format!("Could not open convergence file '{}'.", filename.display())
+            })?;
+
+        Ok(ConvergenceWriter {
+            output: BufWriter::new(file),
+        })
+    }
+
+    /// Write the current average membrane thickness.
+    pub(crate) fn write(&mut self, grids: &ThicknessGrids, nan_limit: usize) -> anyhow::Result<()> {
+        let (thickness, n_bins) = grids.average_thickness(nan_limit);
+
+        writeln!(
+            &mut self.output,
+            "{:8} {:12.3} {:12.4} {:8}",
+            grids.n_frames, grids.last_time, thickness, n_bins
+        )?;
+
+        Ok(())
+    }
+
+    /// Flush all the written data into the file.
+    pub(crate) fn finish(mut self) -> anyhow::Result<()> {
+        self.output.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+//! Curvature of the membrane midplane calculated from the map of its height.
+
+/// Derivatives of the height field in a grid bin calculated by central finite differences.
+struct Derivatives {
+    x: f64,
+    y: f64,
+    xx: f64,
+    yy: f64,
+    xy: f64,
+}
+
+impl Derivatives {
+    /// Calculate the derivatives of the height field in the bin `(i, j)` of a grid with `n_y` bins along y
+    /// stored in row-major order. Returns `None` for bins at the edges of the grid and for bins
+    /// neighboring any bin with undefined height.
+    fn new(
+        heights: &[f64],
+        n_x: usize,
+        n_y: usize,
+        tile: (f32, f32),
+        i: usize,
+        j: usize,
+    ) -> Option<Derivatives> {
+        if i == 0 || j == 0 || i + 1 >= n_x || j + 1 >= n_y {
+            return None;
+        }
+
+        let h = |di: isize, dj: isize| {
+            heights[(i as isize + di) as usize * n_y + (j as isize + dj) as usize]
+        };
+
+        if (-1..=1)
+            .flat_map(|di| (-1..=1).map(move |dj| (di, dj)))
+            .any(|(di, dj)| !h(di, dj).is_finite())
+        {
+            return None;
+        }
+
+        let (dx, dy) = (tile.0 as f64, tile.1 as f64);
+        Some(Derivatives {
+            x: (h(1, 0) - h(-1, 0)) / (2.0 * dx),
+            y: (h(0, 1) - h(0, -1)) / (2.0 * dy),
+            xx: (h(1, 0) - 2.0 * h(0, 0) + h(-1, 0)) / (dx * dx),
+            yy: (h(0, 1) - 2.0 * h(0, 0) + h(0, -1)) / (dy * dy),
+            xy: (h(1, 1) - h(1, -1) - h(-1, 1) + h(-1, -1)) / (4.0 * dx * dy),
+        })
+    }
+
+    /// Mean curvature of the height field. Negative for bulges toward higher values of the height.
+    fn mean(&self) -> f64 {
+        let numerator = (1.0 + self.x * self.x) * self.yy - 2.0 * self.x * self.y * self.xy
+            + (1.0 + self.y * self.y) * self.xx;
+
+        numerator / (2.0 * (1.0 + self.x * self.x + self.y * self.y).powf(1.5))
+    }
+
+    /// Gaussian curvature of the height field.
+    fn gaussian(&self) -> f64 {
+        (self.xx * self.yy - self.xy * self.xy) / (1.0 + self.x * self.x + self.y * self.y).powi(2)
+    }
+}
+
+/// Calculate the mean curvature of the height field given as `(x, y, height)` for every bin of a grid
+/// with `n_x` x `n_y` bins of size `tile` in row-major order. Returns `(x, y, curvature)`.
+/// Curvature is NaN for bins at the edges of the grid and for bins neighboring any bin with undefined height.
+pub(crate) fn mean_curvature(
+    heights: &[(f32, f32, f64)],
+    n_x: usize,
+    n_y: usize,
+    tile: (f32, f32),
+) -> Vec<(f32, f32, f64)> {
+    curvature(heights, n_x, n_y, tile, Derivatives::mean)
+}
+
+/// Calculate the Gaussian curvature of the height field. See [`mean_curvature`] for the details.
+pub(crate) fn gaussian_curvature(
+    heights: &[(f32, f32, f64)],
+    n_x: usize,
+    n_y: usize,
+    tile: (f32, f32),
+) -> Vec<(f32, f32, f64)> {
+    curvature(heights, n_x, n_y, tile, Derivatives::gaussian)
+}
+
+/// Calculate the curvature of the height field in every bin using the provided function of the derivatives.
+fn curvature(
+    heights: &[(f32, f32, f64)],
+    n_x: usize,
+    n_y: usize,
+    tile: (f32, f32),
+    curvature: fn(&Derivatives) -> f64,
+) -> Vec<(f32, f32, f64)> {
+    let values = heights
+        .iter()
+        .map(|&(_, _, height)| height)
+        .collect::<Vec<_>>();
+
+    heights
+        .iter()
+        .enumerate()
+        .map(|(index, &(x, y, _))| {
+            let curvature = Derivatives::new(&values, n_x, n_y, tile, index / n_y, index % n_y)
+                .map(|derivatives| curvature(&derivatives))
+                .unwrap_or(f64::NAN);
+
+            (x, y, curvature)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const N: usize = 7;
+    const TILE: (f32, f32) = (0.5, 0.5);
+
+    /// Height field sampled on a grid of N x N bins centered at the origin.
+    fn height_field(height: impl Fn(f64, f64) -> f64) -> Vec<(f32, f32, f64)> {
+        (0..N * N)
+            .map(|index| {
+                let x = (index / N) as f32 * TILE.0 - 1.5;
+                let y = (index % N) as f32 * TILE.1 - 1.5;
+                (x, y, height(x as f64, y as f64))
+            })
+            .collect()
+    }
+
+    fn is_edge(index: usize) -> bool {
+        let (i, j) = (index / N, index % N);
+        i == 0 || j == 0 || i == N - 1 || j == N - 1
+    }
+
+    #[test]
+    fn mean_curvature_paraboloid() {
+        // h = a (x^2 + y^2), H = a (2 + |grad h|^2) / (1 + |grad h|^2)^(3/2)
+        let a = 0.2;
+        let heights = height_field(|x, y| a * (x * x + y * y));
+        let curvature = mean_curvature(&heights, N, N, TILE);
+
+        for (index, &(x, y, value)) in curvature.iter().enumerate() {
+            if is_edge(index) {
+                assert!(value.is_nan());
+                continue;
+            }
+
+            let gradient = 4.0 * a * a * (x as f64 * x as f64 + y as f64 * y as f64);
+            let expected = a * (2.0 + gradient) / (1.0 + gradient).powf(1.5);
+            assert!((value - expected).abs() < 1e-6);
+        }
+
+        // curvature 1/R = 2a at the apex, negative for the inverted paraboloid
+        assert!((curvature[N * N / 2].2 - 2.0 * a).abs() < 1e-9);
+        let inverted = mean_curvature(&height_field(|x, y| -a * (x * x + y * y)), N, N, TILE);
+        assert!((inverted[N * N / 2].2 + 2.0 * a).abs() < 1e-9);
+    }
+
+    #[test]
+    fn mean_curvature_plane() {
+        let heights = height_field(|x, y| 0.3 * x - 0.1 * y + 2.0);
+        let curvature = mean_curvature(&heights, N, N, TILE);
+        assert!(
+            curvature
+                .iter()
+                .enumerate()
+                .all(|(index, &(_, _, value))| is_edge(index) || value.abs() < 1e-9)
+        );
+    }
+
+    #[test]
+    fn mean_curvature_undefined_neighbors() {
+        let mut heights = height_field(|x, y| 0.2 * (x * x + y * y));
+        heights[2 * N + 2].2 = f64::NAN;
+        let curvature = mean_curvature(&heights, N, N, TILE);
+
+        for (index, &(_, _, value)) in curvature.iter().enumerate() {
+            let (i, j) = (index / N, index % N);
+            let near = i.abs_diff(2) <= 1 && j.abs_diff(2) <= 1;
+            assert_eq!(value.is_nan(), is_edge(index) || near);
+        }
+    }
+
+    #[test]
+    fn gaussian_curvature_paraboloid() {
+        // h = a (x^2 + y^2), K = 4 a^2 / (1 + |grad h|^2)^2
+        let a = 0.2;
+        let heights = height_field(|x, y| a * (x * x + y * y));
+        let curvature = gaussian_curvature(&heights, N, N, TILE);
+
+        for (index, &(x, y, value)) in curvature.iter().enumerate() {
+            if is_edge(index) {
+                assert!(value.is_nan());
+                continue;
+            }
+
+            let gradient = 4.0 * a * a * (x as f64 * x as f64 + y as f64 * y as f64);
+            let expected = 4.0 * a * a / (1.0 + gradient).powi(2);
+            assert!((value - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn gaussian_curvature_saddle() {
+        // h = c x y has K = -c^2 at the origin and zero mean curvature there
+        let c = 0.4;
+        let heights = height_field(|x, y| c * x * y);
+        let gaussian = gaussian_curvature(&heights, N, N, TILE);
+        let mean = mean_curvature(&heights, N, N, TILE);
+
+        assert!((gaussian[N * N / 2].2 + c * c).abs() < 1e-9);
+        assert!(mean[N * N / 2].2.abs() < 1e-9);
+    }
+
+    #[test]
+    fn gaussian_curvature_undefined_neighbors() {
+        let mut heights = height_field(|x, y| 0.2 * (x * x + y * y));
+        heights[N * N / 2].2 = f64::NAN;
+        let curvature = gaussian_curvature(&heights, N, N, TILE);
+
+        for (index, &(_, _, value)) in curvature.iter().enumerate() {
+            let (i, j) = (index / N, index % N);
+            let near = i.abs_diff(N / 2) <= 1 && j.abs_diff(N / 2) <= 1;
+            assert_eq!(value.is_nan(), is_edge(index) || near);
+        }
+    }
+}
@@ -0,0 +1,292 @@
+//! Relative deviation and deformation of the thickness map from the thickness of the unperturbed membrane.
+
+use groan_rs::prelude::*;
+
+use crate::{geometry, grids::ThicknessGrids};
+
+/// Thickness from which the relative deviation of the map is calculated.
+#[derive(Debug, Clone)]
+pub(crate) enum Reference {
+    /// Thickness provided by the user (in nm).
+    Value(f64),
+    /// Average thickness of the grid bins flagged as the bulk membrane in the order of the grid maps.
+    Bulk(Vec<bool>),
+    /// Average thickness of all grid bins.
+    Average,
+}
+
+impl Reference {
+    /// Flag the grid bins farther than `cutoff` (in nm) from all atoms of the group in the membrane plane
+    /// of the input structure as the bulk membrane. Distances are calculated using the periodic boundary conditions.
+    pub(crate) fn bulk(
+        system: &System,
+        grids: &ThicknessGrids,
+        group: &str,
+        cutoff: f32,
+    ) -> anyhow::Result<Reference> {
+        let bulk = bin_distances(system, grids, group)?
+            .into_iter()
+            .map(|distance| distance > cutoff)
+            .collect::<Vec<_>>();
+
+        Ok(Reference::Bulk(bulk))
+    }
+
+    /// Calculate the reference thickness from the thickness map given as `(x, y, thickness)` for every grid bin.
+    /// Returns the reference thickness and the number of bins it was calculated from
+    /// (`None` if no bins of the bulk membrane have defined thickness).
+    pub(crate) fn thickness(&self, values: &[(f32, f32, f64)]) -> Option<(f64, usize)> {
+        let defined = |included: &dyn Fn(usize) -> bool| {
+            let (sum, n) = values
+                .iter()
+                .enumerate()
+                .filter(|&(index, (_, _, value))| value.is_finite() && included(index))
+                .fold((0.0, 0), |(sum, n), (_, (_, _, value))| {
+                    (sum + value, n + 1)
+                });
+            (n > 0).then(|| (sum / n as f64, n))
+        };
+
+        match self {
+            Reference::Value(thickness) => Some((*thickness, 0)),
+            Reference::Bulk(bulk) => defined(&|index| bulk[index]),
+            Reference::Average => defined(&|_| true),
+        }
+    }
+}
+
+/// Calculate the minimal lateral distance of the center of every grid bin from the atoms of the group
+/// in the membrane plane of the input structure. Distances are calculated using the periodic boundary conditions.
+fn bin_distances(system: &System, grids: &ThicknessGrids, group: &str) -> anyhow::Result<Vec<f32>> {
+    let simbox = system.get_box().ok_or_else(|| {
+        anyhow::anyhow!("Bulk membrane can only be identified with a simulation box.")
+    })?;
+    let periods = grids.normal.lateral_box(simbox);
+
+    let atoms = system
+        .group_iter(group)?
+        .map(|atom| {
+            atom.get_position()
+                .map(|position| grids.normal.lateral(position))
+                .ok_or_else(|| anyhow::anyhow!("Atom {} has no position.", atom.get_index() + 1))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    if atoms.is_empty() {
+        anyhow::bail!("No atoms selected to identify the bulk membrane.");
+    }
+
+    Ok(grids
+        .upper_sum
+        .extract_raw()
+        .map(|(x, y, _)| {
+            atoms
+                .iter()
+                .map(|&atom| geometry::polar_coordinates((x, y), atom, periods).0)
+                .fold(f32::INFINITY, f32::min)
+        })
+        .collect())
+}
+
+/// Calculate the deviation of the thickness map given as `(x, y, thickness)` from the reference thickness
+/// in percent of the reference thickness.
+pub(crate) fn relative_deviation(
+    values: &[(f32, f32, f64)],
+    reference: f64,
+) -> Vec<(f32, f32, f64)> {
+    values
+        .iter()
+        .map(|&(x, y, thickness)| (x, y, 100.0 * (thickness - reference) / reference))
+        .collect()
+}
+
+/// Deformation of the membrane thickness from the far-field thickness of the bulk membrane around an inclusion.
+#[derive(Debug, Clone)]
+pub(crate) struct Deformation {
+    /// Lateral distance of every grid bin from the closest atom of the inclusion in the order of the grid maps.
+    distances: Vec<f32>,
+    /// Bins farther than this distance from the inclusion form the bulk membrane (in nm).
+    cutoff: f32,
+    /// Width of the shells of the distance used to fit the decay of the deformation (in nm).
+    shell: f32,
+}
+
+/// Exponential decay `amplitude * exp(-distance / length)` of the deformation with the distance from the inclusion.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Decay {
+    /// Deformation extrapolated to the distance of 0 nm (in nm).
+    pub(crate) amplitude: f64,
+    /// Decay length of the deformation (in nm).
+    pub(crate) length: f64,
+    /// Number of shells used for the fit.
+    pub(crate) n_shells: usize,
+}
+
+impl Deformation {
+    /// Prepare the deformation around the atoms of the group. See [`Reference::bulk`].
+    pub(crate) fn new(
+        system: &System,
+        grids: &ThicknessGrids,
+        group: &str,
+        cutoff: f32,
+        shell: f32,
+    ) -> anyhow::Result<Deformation> {
+        Ok(Deformation {
+            distances: bin_distances(system, grids, group)?,
+            cutoff,
+            shell,
+        })
+    }
+
+    /// Calculate the far-field thickness as the average thickness of the bins of the bulk membrane.
+    /// Returns the thickness and the number of bins it was calculated from
+    /// (`None` if no bins of the bulk membrane have defined thickness).
+    pub(crate) fn far_field(&self, values: &[(f32, f32, f64)]) -> Option<(f64, usize)> {
+        Reference::Bulk(
+            self.distances
+                .iter()
+                .map(|&distance| distance > self.cutoff)
+                .collect(),
+        )
+        .thickness(values)
+    }
+
+    /// Calculate the deformation of the thickness map given as `(x, y, thickness)` from the far-field thickness (in nm).
+    pub(crate) fn map(values: &[(f32, f32, f64)], far_field: f64) -> Vec<(f32, f32, f64)> {
+        values
+            .iter()
+            .map(|&(x, y, thickness)| (x, y, thickness - far_field))
+            .collect()
+    }
+
+    /// Fit the exponential decay of the deformation map with the distance from the inclusion.
+    /// The deformation is averaged in shells of the distance up to the cutoff of the bulk membrane
+    /// and the logarithm of the absolute average deformation is fitted linearly. Only the shells
+    /// with deformation of the same sign as the innermost shell are used.
+    /// Returns `None` if fewer than two shells can be used or the deformation does not decay.
+    pub(crate) fn decay(&self, deformation: &[(f32, f32, f64)]) -> Option<Decay> {
+        let n_shells = (self.cutoff / self.shell).ceil() as usize;
+        let mut shells = vec![(0.0, 0); n_shells];
+        for (&(_, _, value), &distance) in deformation.iter().zip(self.distances.iter()) {
+            let shell = (distance / self.shell) as usize;
+            if value.is_finite() && distance <= self.cutoff && shell < n_shells {
+                shells[shell].0 += value;
+                shells[shell].1 += 1;
+            }
+        }
+
+        let averages = shells
+            .iter()
+            .enumerate()
+            .filter(|&(_, &(_, n))| n > 0)
+            .map(|(index, &(sum, n))| ((index as f64 + 0.5) * self.shell as f64, sum / n as f64))
+            .filter(|&(_, average)| average != 0.0)
+            .collect::<Vec<_>>();
+
+        let sign = averages.first()?.1.signum();
+        let points = averages
+            .iter()
+            .filter(|&&(_, average)| average.signum() == sign)
+            .map(|&(distance, average)| (distance, average.abs().ln()))
+            .collect::<Vec<_>>();
+
+        if points.len() < 2 {
+            return None;
+        }
+
+        let n = points.len() as f64;
+        let mean_x = points.iter().map(|&(x, _)| x).sum::<f64>() / n;
+        let mean_y = points.iter().map(|&(_, y)| y).sum::<f64>() / n;
+        let covariance = points
+            .iter()
+            .map(|&(x, y)| (x - mean_x) * (y - mean_y))
+            .sum::<f64>();
+        let variance = points
+            .iter()
+            .map(|&(x, _)| (x - mean_x).powi(2))
+            .sum::<f64>();
+
+        let slope = covariance / variance;
+        if slope >= 0.0 {
+            return None;
+        }
+
+        Some(Decay {
+            amplitude: sign * (mean_y - slope * mean_x).exp(),
+            length: -1.0 / slope,
+            n_shells: points.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deformation with bins at the centers of the shells of 0.5 nm up to the cutoff of 3 nm and two bulk bins.
+    fn deformation() -> Deformation {
+        Deformation {
+            distances: vec![0.25, 0.75, 1.25, 1.75, 2.25, 2.75, 4.0, 5.0],
+            cutoff: 3.0,
+            shell: 0.5,
+        }
+    }
+
+    fn values(deformation: &Deformation, value: impl Fn(f64) -> f64) -> Vec<(f32, f32, f64)> {
+        deformation
+            .distances
+            .iter()
+            .map(|&distance| (0.0, 0.0, value(distance as f64)))
+            .collect()
+    }
+
+    #[test]
+    fn far_field_of_bulk_bins() {
+        let deformation = deformation();
+        let mut values = values(&deformation, |distance| distance);
+        assert_eq!(deformation.far_field(&values), Some((4.5, 2)));
+
+        values[7].2 = f64::NAN;
+        assert_eq!(deformation.far_field(&values), Some((4.0, 1)));
+    }
+
+    #[test]
+    fn decay_exponential() {
+        let deformation = deformation();
+        let values = values(&deformation, |distance| -0.4 * (-distance / 1.5).exp());
+
+        let decay = deformation.decay(&values).unwrap();
+        assert!((decay.amplitude + 0.4).abs() < 1e-6);
+        assert!((decay.length - 1.5).abs() < 1e-6);
+        assert_eq!(decay.n_shells, 6);
+    }
+
+    #[test]
+    fn decay_ignores_shells_of_opposite_sign() {
+        let deformation = deformation();
+        let mut values = values(&deformation, |distance| 0.3 * (-distance / 0.8).exp());
+        values[5].2 = -0.1;
+
+        let decay = deformation.decay(&values).unwrap();
+        assert!((decay.amplitude - 0.3).abs() < 1e-6);
+        assert!((decay.length - 0.8).abs() < 1e-6);
+        assert_eq!(decay.n_shells, 5);
+    }
+
+    #[test]
+    fn decay_undefined() {
+        let deformation = deformation();
+
+        // growing deformation
+        let values = values(&deformation, |distance| 0.1 * distance);
+        assert!(deformation.decay(&values).is_none());
+
+        // single defined shell
+        let mut values = values.clone();
+        values
+            .iter_mut()
+            .skip(1)
+            .for_each(|value| value.2 = f64::NAN);
+        assert!(deformation.decay(&values).is_none());
+    }
+}
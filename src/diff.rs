@@ -0,0 +1,419 @@
+//! Differences between the maps of separate analyses.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+
+use crate::{grids::ThicknessGrids, significance};
+
+/// Maximal difference between the coordinates of the corresponding bins of two maps on the same grid.
+const COORDINATE_TOLERANCE: f32 = 1e-4;
+
+/// Arguments of the `diff` subcommand.
+#[derive(clap::Args, Debug, Clone)]
+pub(crate) struct DiffArgs {
+    #[arg(
+        help = "Map to subtract from",
+        long_help = "Path to a map written by memthick (e.g., of the mutant membrane) from which the other map is subtracted. With '--states', path to a state file written using the '--state' option."
+    )]
+    map_a: String,
+
+    #[arg(
+        help = "Map to subtract",
+        long_help = "Path to a map written by memthick on the same grid as the first map (e.g., of the wild-type membrane) which is subtracted from the first map. With '--states', path to a state file written using the '--state' option."
+    )]
+    map_b: String,
+
+    #[arg(
+        long = "states",
+        help = "Compare state files instead of maps.",
+        long_help = "Read the raw accumulated grids of both analyses from state files written using the '--state' option instead of reading the written maps. The thickness maps are then calculated from the grids using the NAN limit ('--nan'). Required for '--significance'."
+    )]
+    states: bool,
+
+    #[arg(
+        short = 'a',
+        long = "nan",
+        help = "Minimal required number of samples in a grid bin.",
+        long_help = "How many phosphates must be detected in a grid bin of a state file to calculate membrane thickness for this bin. Only used with '--states'.",
+        default_value = "30"
+    )]
+    nan_limit: usize,
+
+    #[arg(
+        short = 'o',
+        long = "output",
+        help = "Path to the output file.",
+        long_help = "Path to the output file where the difference between the first and the second map will be written. Bins undefined in any of the maps are undefined in the difference.",
+        default_value = "thickness_difference.dat"
+    )]
+    output: String,
+
+    #[arg(
+        long = "relative",
+        help = "Also write the relative difference of the maps into this file.",
+        long_help = "Path to a file where the difference between the first and the second map divided by the second map will be written. Bins undefined in any of the maps or with zero value in the second map are undefined in the relative difference.",
+        value_name = "FILE"
+    )]
+    relative: Option<String>,
+
+    #[arg(
+        long = "significance",
+        help = "Also write the map of the statistical significance of the difference into this file.",
+        long_help = "Compare the thickness of every bin in the two analyses using Welch's t-test and write the map of the two-sided p-values of the null hypothesis that the thickness is the same into this file. The variance of the thickness of each bin is calculated from the fluctuations of the individual headgroup samples, which are assumed to be independent. Since consecutive frames are correlated, the p-values are too small for densely sampled trajectories; analyze frames separated by at least the correlation time (see '--step' and '--autocorrelation') for reliable p-values. Requires '--states'.",
+        value_name = "FILE"
+    )]
+    significance: Option<String>,
+
+    #[arg(
+        long = "significance-level",
+        help = "Significance level for reporting the bins with different thickness.",
+        long_help = "Bins with p-values (see '--significance') below this level are reported as significantly different.",
+        default_value_t = 0.05
+    )]
+    significance_level: f64,
+}
+
+/// Print the specified options of the `diff` subcommand.
+fn print_options(args: &DiffArgs) {
+    println!("[MAPS]          {} {}", args.map_a, args.map_b);
+    if args.states {
+        println!("[NAN LIMIT]     {}", args.nan_limit);
+    }
+    println!("[OUTPUT]        {}", args.output);
+    if let Some(relative) = args.relative.as_ref() {
+        println!("[RELATIVE]      {}", relative);
+    }
+    if let Some(significance) = args.significance.as_ref() {
+        println!(
+            "[SIGNIFICANCE]  {} (level {})",
+            significance, args.significance_level
+        );
+    }
+    println!("\n");
+}
+
+/// Map read from a file written by memthick.
+#[derive(Debug, Clone)]
+struct Map {
+    /// Labels of the axes of the map.
+    labels: (String, String),
+    /// Name of the mapped quantity.
+    quantity: String,
+    /// Unit of the mapped quantity (`None` for dimensionless quantities).
+    unit: Option<String>,
+    /// Coordinates and value of every bin of the map.
+    values: Vec<(f32, f32, f64)>,
+}
+
+impl Map {
+    /// Read the map from a file. Lines starting with '#', '@', or '$' are only used for the labels of the map.
+    fn read(filename: impl AsRef<Path>) -> anyhow::Result<Map> {
+        let filename = filename.as_ref();
+        let file = File::open(filename)
+            .with_context(|| format!("Could not open map file '{}'.", filename.display()))?;
+
+        let mut map = Map {
+            labels: (String::from("x"), String::from("y")),
+            quantity: String::from("value"),
+            unit: None,
+            values: Vec::new(),
+        };
+
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let line = line.trim();
+            if let Some(label) = line.strip_prefix("@ xlabel ") {
+                map.labels.0 = label.to_owned();
+                continue;
+            }
+
+            if let Some(label) = line.strip_prefix("@ ylabel ") {
+                map.labels.1 = label.to_owned();
+                continue;
+            }
+
+            if let Some(label) = line.strip_prefix("@ zlabel ") {
+                match label
+                    .strip_suffix(']')
+                    .and_then(|label| label.rsplit_once(" ["))
+                {
+                    Some((quantity, unit)) => {
+                        map.quantity = quantity.to_owned();
+                        map.unit = Some(unit.to_owned());
+                    }
+                    None => map.quantity = label.to_owned(),
+                }
+                continue;
+            }
+
+            if line.is_empty()
+                || line.starts_with('#')
+                || line.starts_with('@')
+                || line.starts_with('$')
+            {
+                continue;
+            }
+
+            let parse_error = || {
+                anyhow::anyhow!(
+                    "Could not parse line '{}' in map file '{}'.",
+                    line,
+                    filename.display()
+                )
+            };
+
+            let mut columns = line.split_whitespace();
+            let (Some(x), Some(y), Some(value), None) = (
+                columns.next(),
+                columns.next(),
+                columns.next(),
+                columns.next(),
+            ) else {
+                return Err(parse_error());
+            };
+
+            map.values.push((
+                x.parse().map_err(|_| parse_error())?,
+                y.parse().map_err(|_| parse_error())?,
+                value.parse().map_err(|_| parse_error())?,
+            ));
+        }
+
+        if map.values.is_empty() {
+            anyhow::bail!("Map file '{}' contains no bins.", filename.display());
+        }
+
+        Ok(map)
+    }
+
+    /// Calculate the thickness map from the accumulated grids.
+    fn from_grids(grids: &ThicknessGrids, nan_limit: usize) -> Map {
+        let (label_x, label_y) = grids.axis_labels();
+        Map {
+            labels: (label_x, label_y),
+            quantity: grids.quantity.to_owned(),
+            unit: Some(String::from("nm")),
+            values: grids.thickness(nan_limit).collect(),
+        }
+    }
+
+    /// Check that the other map is defined on the same grid as this map.
+    fn is_compatible(&self, other: &Map) -> bool {
+        self.values.len() == other.values.len()
+            && self
+                .values
+                .iter()
+                .zip(other.values.iter())
+                .all(|(&(x1, y1, _), &(x2, y2, _))| {
+                    (x1 - x2).abs() <= COORDINATE_TOLERANCE
+                        && (y1 - y2).abs() <= COORDINATE_TOLERANCE
+                })
+    }
+
+    /// Write the map with the values calculated from the values of the corresponding bins of this map
+    /// and the other map into a file.
+    fn write_combined(
+        &self,
+        other: &Map,
+        filename: impl AsRef<Path>,
+        quantity: &str,
+        unit: Option<&str>,
+        combine: impl Fn(f64, f64) -> f64,
+        raw_arguments: &[String],
+    ) -> anyhow::Result<()> {
+        let values = self
+            .values
+            .iter()
+            .zip(other.values.iter())
+            .map(|(&(_, _, a), &(_, _, b))| combine(a, b))
+            .collect::<Vec<_>>();
+
+        self.write_values(filename, quantity, unit, &values, raw_arguments)
+    }
+
+    /// Write the provided values of the bins of this map into a file.
+    fn write_values(
+        &self,
+        filename: impl AsRef<Path>,
+        quantity: &str,
+        unit: Option<&str>,
+        values: &[f64],
+        raw_arguments: &[String],
+    ) -> anyhow::Result<()> {
+        let filename = filename.as_ref();
+        let file = File::create(filename)
+            .with_context(|| format!("Could not create map file '{}'.", filename.display()))?;
+        let mut output = BufWriter::new(file);
+
+        writeln!(
+            &mut output,
+            "# Generated with memthick v{}.",
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(&mut output, "# Command line: {}", raw_arguments.join(" "))?;
+        writeln!(
+            &mut output,
+            "# See the average {} at the end of this file.",
+            quantity
+        )?;
+        writeln!(&mut output, "@ xlabel {}", self.labels.0)?;
+        writeln!(&mut output, "@ ylabel {}", self.labels.1)?;
+        match unit {
+            Some(unit) => writeln!(&mut output, "@ zlabel {} [{}]", quantity, unit)?,
+            None => writeln!(&mut output, "@ zlabel {}", quantity)?,
+        }
+        writeln!(&mut output, "@ grid --")?;
+        writeln!(&mut output, "$ type colorbar")?;
+        writeln!(&mut output, "$ colormap rainbow")?;
+
+        let (mut sum, mut n_bins) = (0.0, 0);
+        for (&(x, y, _), &value) in self.values.iter().zip(values.iter()) {
+            writeln!(&mut output, "{:12.6} {:12.6} {:12.4}", x, y, value)?;
+
+            if value.is_finite() {
+                sum += value;
+                n_bins += 1;
+            }
+        }
+
+        writeln!(
+            &mut output,
+            "# Average {}: {:12.4}{}",
+            quantity,
+            sum / n_bins as f64,
+            unit.map(|unit| format!(" {}", unit)).unwrap_or_default()
+        )?;
+
+        output.flush()?;
+        Ok(())
+    }
+}
+
+/// Subtract the second map from the first map and write the difference (and the relative difference
+/// and the statistical significance of the difference).
+pub(crate) fn run_diff(args: &DiffArgs, raw_arguments: &[String]) -> anyhow::Result<()> {
+    if args.states && args.nan_limit == 0 {
+        anyhow::bail!("NAN limit must be larger than 0, not {}", args.nan_limit);
+    }
+
+    if args.significance.is_some() && !args.states {
+        anyhow::bail!(
+            "Statistical significance can only be calculated from state files (use '--states')."
+        );
+    }
+
+    if args.significance_level <= 0.0 || args.significance_level >= 1.0 {
+        anyhow::bail!(
+            "Significance level must be between 0 and 1, not {}.",
+            args.significance_level
+        );
+    }
+
+    print_options(args);
+
+    let grids = if args.states {
+        let grids_a = ThicknessGrids::read_state(&args.map_a)?;
+        let grids_b = ThicknessGrids::read_state(&args.map_b)?;
+        if !grids_a.is_compatible(&grids_b) {
+            anyhow::bail!(
+                "The grid stored in the state file '{}' does not match the grid stored in the state file '{}'.",
+                args.map_b,
+                args.map_a
+            );
+        }
+
+        Some((grids_a, grids_b))
+    } else {
+        None
+    };
+
+    let (map_a, map_b) = match &grids {
+        Some((grids_a, grids_b)) => (
+            Map::from_grids(grids_a, args.nan_limit),
+            Map::from_grids(grids_b, args.nan_limit),
+        ),
+        None => (Map::read(&args.map_a)?, Map::read(&args.map_b)?),
+    };
+
+    if !map_a.is_compatible(&map_b) {
+        anyhow::bail!(
+            "The map '{}' is not defined on the same grid as the map '{}'.",
+            args.map_b,
+            args.map_a
+        );
+    }
+
+    if map_a.quantity != map_b.quantity || map_a.unit != map_b.unit {
+        println!(
+            "WARNING: Map '{}' contains {} while map '{}' contains {}.",
+            args.map_a, map_a.quantity, args.map_b, map_b.quantity
+        );
+    }
+
+    let defined = map_a
+        .values
+        .iter()
+        .zip(map_b.values.iter())
+        .filter(|((_, _, a), (_, _, b))| a.is_finite() && b.is_finite())
+        .count();
+    println!(
+        "Read {} bins, {} of them defined in both maps.",
+        map_a.values.len(),
+        defined
+    );
+
+    map_a.write_combined(
+        &map_b,
+        &args.output,
+        &format!("difference of {}", map_a.quantity),
+        map_a.unit.as_deref(),
+        |a, b| a - b,
+        raw_arguments,
+    )?;
+
+    if let Some(relative) = &args.relative {
+        map_a.write_combined(
+            &map_b,
+            relative,
+            &format!("relative difference of {}", map_a.quantity),
+            None,
+            |a, b| if b == 0.0 { f64::NAN } else { (a - b) / b },
+            raw_arguments,
+        )?;
+    }
+
+    if let (Some(significance), Some((grids_a, grids_b))) = (&args.significance, &grids) {
+        let p_values = significance::estimates(grids_a, args.nan_limit)
+            .into_iter()
+            .zip(significance::estimates(grids_b, args.nan_limit))
+            .map(|estimates| match estimates {
+                (Some(a), Some(b)) => significance::welch_test(&a, &b),
+                _ => f64::NAN,
+            })
+            .collect::<Vec<_>>();
+
+        let n_significant = p_values
+            .iter()
+            .filter(|&&p| p < args.significance_level)
+            .count();
+        println!(
+            "Thickness differs significantly (p < {}) in {} of {} bins defined in both maps.",
+            args.significance_level, n_significant, defined
+        );
+
+        map_a.write_values(
+            significance,
+            "p-value of the difference of membrane thickness",
+            None,
+            &p_values,
+            raw_arguments,
+        )?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,199 @@
+//! Segmentation of the thickness map into connected domains of thick and thin membrane.
+
+/// Number of histogram bins used to determine the thickness threshold by Otsu's method.
+const OTSU_BINS: usize = 256;
+
+/// Connected region of grid bins lying on the same side of the thickness threshold.
+#[derive(Debug, Clone)]
+pub(crate) struct Domain {
+    /// The domain is thicker than the threshold.
+    pub(crate) thick: bool,
+    /// Number of grid bins forming the domain.
+    pub(crate) n_bins: usize,
+    /// Area of the domain in the map coordinates.
+    pub(crate) area: f64,
+    /// Sum of the thickness in the bins of the domain weighted by their areas.
+    weighted_thickness: f64,
+}
+
+impl Domain {
+    /// Average thickness of the domain weighted by the areas of its bins.
+    pub(crate) fn thickness(&self) -> f64 {
+        self.weighted_thickness / self.area
+    }
+}
+
+/// Determine the threshold separating the values into two classes with the highest between-class variance
+/// (Otsu's method). Undefined values are ignored. Returns `None` if there are no two different defined values.
+pub(crate) fn otsu_threshold(values: &[f64]) -> Option<f64> {
+    let finite = values.iter().copied().filter(|value| value.is_finite());
+    let (min, max) = finite
+        .clone()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+            (min.min(value), max.max(value))
+        });
+
+    if max <= min {
+        return None;
+    }
+
+    let width = (max - min) / OTSU_BINS as f64;
+    let mut histogram = [0usize; OTSU_BINS];
+    for value in finite {
+        histogram[(((value - min) / width) as usize).min(OTSU_BINS - 1)] += 1;
+    }
+
+    let center = |bin: usize| min + (bin as f64 + 0.5) * width;
+    let total = histogram.iter().sum::<usize>() as f64;
+    let total_sum = histogram
+        .iter()
+        .enumerate()
+        .map(|(bin, &count)| count as f64 * center(bin))
+        .sum::<f64>();
+
+    let (mut below, mut below_sum) = (0.0, 0.0);
+    let (mut best, mut best_variance) = (1, f64::NEG_INFINITY);
+    for (bin, &count) in histogram.iter().enumerate().take(OTSU_BINS - 1) {
+        below += count as f64;
+        below_sum += count as f64 * center(bin);
+
+        let above = total - below;
+        if below == 0.0 || above == 0.0 {
+            continue;
+        }
+
+        let difference = below_sum / below - (total_sum - below_sum) / above;
+        let variance = below * above * difference * difference;
+        if variance > best_variance {
+            best_variance = variance;
+            best = bin + 1;
+        }
+    }
+
+    Some(min + best as f64 * width)
+}
+
+/// Label connected domains of bins with thickness above (thick) or below (thin) the threshold.
+/// `thickness` and `areas` are given for every bin of a grid with `n_x` x `n_y` bins in row-major order.
+/// Bins are connected through their sides. Returns the index of the domain of each bin
+/// (`None` for bins with undefined thickness) and the list of the domains.
+pub(crate) fn segment(
+    thickness: &[f64],
+    areas: &[f64],
+    n_x: usize,
+    n_y: usize,
+    threshold: f64,
+) -> (Vec<Option<usize>>, Vec<Domain>) {
+    let mut labels = vec![None; thickness.len()];
+    let mut domains = Vec::new();
+
+    for start in 0..thickness.len() {
+        if labels[start].is_some() || !thickness[start].is_finite() {
+            continue;
+        }
+
+        let thick = thickness[start] >= threshold;
+        let mut domain = Domain {
+            thick,
+            n_bins: 0,
+            area: 0.0,
+            weighted_thickness: 0.0,
+        };
+
+        labels[start] = Some(domains.len());
+        let mut stack = vec![start];
+        while let Some(bin) = stack.pop() {
+            domain.n_bins += 1;
+            domain.area += areas[bin];
+            domain.weighted_thickness += areas[bin] * thickness[bin];
+
+            let (i, j) = (bin / n_y, bin % n_y);
+            let neighbors = [
+                (i > 0).then(|| bin - n_y),
+                (i + 1 < n_x).then(|| bin + n_y),
+                (j > 0).then(|| bin - 1),
+                (j + 1 < n_y).then(|| bin + 1),
+            ];
+
+            for neighbor in neighbors.into_iter().flatten() {
+                if labels[neighbor].is_none()
+                    && thickness[neighbor].is_finite()
+                    && (thickness[neighbor] >= threshold) == thick
+                {
+                    labels[neighbor] = Some(domains.len());
+                    stack.push(neighbor);
+                }
+            }
+        }
+
+        domains.push(domain);
+    }
+
+    (labels, domains)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn otsu_bimodal() {
+        let values = [3.0, 3.1, 2.9, 3.05, f64::NAN, 4.0, 4.1, 3.9, 4.05, 3.95];
+        let threshold = otsu_threshold(&values).unwrap();
+        assert!(threshold > 3.1 && threshold < 3.9);
+    }
+
+    #[test]
+    fn otsu_two_values() {
+        let threshold = otsu_threshold(&[1.0, 1.0, 2.0, 2.0]).unwrap();
+        assert!(threshold > 1.0 && threshold <= 2.0);
+    }
+
+    #[test]
+    fn otsu_undefined() {
+        assert_eq!(otsu_threshold(&[]), None);
+        assert_eq!(otsu_threshold(&[f64::NAN, f64::NAN]), None);
+        assert_eq!(otsu_threshold(&[2.0, 2.0, f64::NAN]), None);
+    }
+
+    #[test]
+    fn segment_domains() {
+        // 3 x 3 grid with a thick column, a thin column, and a column split by an undefined bin
+        #[rustfmt::skip]
+        let thickness = [
+            4.0, 4.0, 4.0,
+            3.0, 3.0, 3.0,
+            4.0, f64::NAN, 3.0,
+        ];
+        let areas = [1.0, 1.0, 1.0, 2.0, 2.0, 2.0, 1.0, 1.0, 1.0];
+
+        let (labels, domains) = segment(&thickness, &areas, 3, 3, 3.5);
+        assert_eq!(
+            labels,
+            [
+                Some(0),
+                Some(0),
+                Some(0),
+                Some(1),
+                Some(1),
+                Some(1),
+                Some(2),
+                None,
+                Some(1)
+            ]
+        );
+
+        assert_eq!(domains.len(), 3);
+        assert!(domains[0].thick && !domains[1].thick && domains[2].thick);
+        assert_eq!(
+            domains
+                .iter()
+                .map(|domain| domain.n_bins)
+                .collect::<Vec<_>>(),
+            [3, 4, 1]
+        );
+        assert_eq!(domains[1].area, 7.0);
+        assert!((domains[1].thickness() - 3.0).abs() < 1e-12);
+        assert!((domains[0].thickness() - 4.0).abs() < 1e-12);
+    }
+}
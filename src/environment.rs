@@ -0,0 +1,193 @@
+//! Distances of headgroups from the membrane center classified by the number of selected lipid atoms
+//! (e.g., cholesterol hydroxyl groups) in their surroundings.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use groan_rs::{errors::SimBoxError, prelude::*};
+
+use crate::{ENVIRONMENT_GROUP, HEADS_GROUP, leaflets::LateralCells, normal::Normal};
+
+/// Accumulated distances of headgroups of one leaflet with the same number of neighbors.
+#[derive(Debug, Clone, Default)]
+struct Samples {
+    sum: f64,
+    weight: f64,
+    count: usize,
+}
+
+impl Samples {
+    /// Weighted average distance of the headgroups from the membrane center.
+    fn average(&self) -> f64 {
+        self.sum / self.weight
+    }
+}
+
+/// Distances of headgroups from the membrane center classified by the number of environment atoms
+/// located within the cutoff from the headgroup.
+#[derive(Debug, Clone)]
+pub(crate) struct Environment {
+    cutoff: f32,
+    /// Residue number of the lipid of each headgroup. Environment atoms of the same lipid are not counted.
+    residues: Vec<usize>,
+    /// Samples of the upper leaflet indexed by the number of environment atoms around the headgroup.
+    upper: Vec<Samples>,
+    /// Samples of the lower leaflet indexed by the number of environment atoms around the headgroup.
+    lower: Vec<Samples>,
+}
+
+impl Environment {
+    /// Prepare empty classes for the headgroups of the system.
+    pub(crate) fn new(system: &System, cutoff: f32) -> anyhow::Result<Environment> {
+        let residues = system
+            .group_iter(HEADS_GROUP)?
+            .map(|atom| atom.get_residue_number())
+            .collect();
+
+        Ok(Environment {
+            cutoff,
+            residues,
+            upper: Vec::new(),
+            lower: Vec::new(),
+        })
+    }
+
+    /// Distribute the environment atoms of the frame into cells for counting them around the headgroups.
+    pub(crate) fn cells(
+        &self,
+        frame: &System,
+        normal: Normal,
+    ) -> anyhow::Result<LateralCells<usize>> {
+        let simbox = frame.get_box().ok_or(SimBoxError::DoesNotExist)?;
+        let mut cells = LateralCells::new(simbox, self.cutoff, normal);
+        for atom in frame.group_iter(ENVIRONMENT_GROUP)? {
+            let position = atom
+                .get_position()
+                .ok_or_else(|| anyhow::anyhow!("Atom {} has no position.", atom.get_index() + 1))?;
+            cells.insert(position, atom.get_residue_number());
+        }
+
+        Ok(cells)
+    }
+
+    /// Add the distance of the headgroup with the specified index to the class given by the number of environment atoms
+    /// of other lipids located within the cutoff from the headgroup.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn add(
+        &mut self,
+        cells: &LateralCells<usize>,
+        head: usize,
+        position: &Vector3D,
+        is_upper: bool,
+        distance: f64,
+        weight: f64,
+        simbox: &SimBox,
+    ) {
+        let residue = self.residues[head];
+        let n_neighbors = cells
+            .neighbors(position)
+            .filter(|&(other, other_residue)| {
+                *other_residue != residue
+                    && position.distance(other, Dimension::XYZ, simbox) <= self.cutoff
+            })
+            .count();
+
+        let classes = if is_upper {
+            &mut self.upper
+        } else {
+            &mut self.lower
+        };
+        if classes.len() <= n_neighbors {
+            classes.resize(n_neighbors + 1, Samples::default());
+        }
+
+        let samples = &mut classes[n_neighbors];
+        samples.sum += distance * weight;
+        samples.weight += weight;
+        samples.count += 1;
+    }
+
+    /// Remove all accumulated samples.
+    pub(crate) fn clear(&mut self) {
+        self.upper.clear();
+        self.lower.clear();
+    }
+
+    /// Add the samples accumulated in the other environment into this environment.
+    pub(crate) fn merge(&mut self, other: &Environment) {
+        for (classes, other) in [
+            (&mut self.upper, &other.upper),
+            (&mut self.lower, &other.lower),
+        ] {
+            if classes.len() < other.len() {
+                classes.resize(other.len(), Samples::default());
+            }
+
+            for (samples, other) in classes.iter_mut().zip(other.iter()) {
+                samples.sum += other.sum;
+                samples.weight += other.weight;
+                samples.count += other.count;
+            }
+        }
+    }
+
+    /// Write the average distances of the headgroups from the membrane center and the resulting thickness
+    /// for each number of environment atoms around the headgroups into a file.
+    /// `leaflets` are the names of the upper and lower leaflet.
+    pub(crate) fn write(
+        &self,
+        filename: impl AsRef<Path>,
+        leaflets: (&str, &str),
+        raw_arguments: &[String],
+    ) -> anyhow::Result<()> {
+        let filename = filename.as_ref();
+        let file = File::create(filename).with_context(|| {
+            format!(
+                "Could not create environment file '{}'.",
+                filename.display()
+            )
+        })?;
+        let mut output = BufWriter::new(file);
+
+        writeln!(
+            &mut output,
+            "# Generated with memthick v{}.",
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(&mut output, "# Command line: {}", raw_arguments.join(" "))?;
+        writeln!(
+            &mut output,
+            "# Average distances of headgroups from the membrane center and membrane thickness [nm] by the number of neighboring environment atoms (within {} nm).",
+            self.cutoff
+        )?;
+        writeln!(
+            &mut output,
+            "# neighbors {0} {1} thickness samples_{0} samples_{1}",
+            leaflets.0, leaflets.1
+        )?;
+
+        let empty = Samples::default();
+        for n_neighbors in 0..self.upper.len().max(self.lower.len()) {
+            let upper = self.upper.get(n_neighbors).unwrap_or(&empty);
+            let lower = self.lower.get(n_neighbors).unwrap_or(&empty);
+
+            writeln!(
+                &mut output,
+                "{:9} {:12.6} {:12.6} {:12.6} {:10} {:10}",
+                n_neighbors,
+                upper.average(),
+                lower.average(),
+                upper.average() - lower.average(),
+                upper.count,
+                lower.count,
+            )?;
+        }
+
+        output.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+//! Extremes of the instantaneous membrane thickness in the individual grid bins.
+
+use crate::grids::LeafletTotals;
+
+/// Minimal and maximal instantaneous thickness of each grid bin over the analyzed frames.
+#[derive(Debug, Clone)]
+pub(crate) struct Extremes {
+    min: Vec<f64>,
+    max: Vec<f64>,
+}
+
+impl Extremes {
+    /// Prepare empty extremes for the specified number of grid bins.
+    pub(crate) fn new(n_bins: usize) -> Extremes {
+        Extremes {
+            min: vec![f64::INFINITY; n_bins],
+            max: vec![f64::NEG_INFINITY; n_bins],
+        }
+    }
+
+    /// Update the extremes with the instantaneous thickness of a frame.
+    /// `before` and `after` are the accumulated leaflet totals of each bin (see [`LeafletTotals`])
+    /// before and after adding the frame. Bins without samples in both leaflets in the frame are not updated.
+    pub(crate) fn update(&mut self, before: &[LeafletTotals], after: &[LeafletTotals]) {
+        for (bin, (before, after)) in before.iter().zip(after.iter()).enumerate() {
+            let Some(thickness) = after.thickness_since(before) else {
+                continue;
+            };
+
+            self.min[bin] = self.min[bin].min(thickness);
+            self.max[bin] = self.max[bin].max(thickness);
+        }
+    }
+
+    /// Remove all recorded extremes.
+    pub(crate) fn clear(&mut self) {
+        self.min.fill(f64::INFINITY);
+        self.max.fill(f64::NEG_INFINITY);
+    }
+
+    /// Add the extremes recorded in the other extremes into these extremes.
+    pub(crate) fn merge(&mut self, other: &Extremes) {
+        for (value, other) in self.min.iter_mut().zip(other.min.iter()) {
+            *value = value.min(*other);
+        }
+
+        for (value, other) in self.max.iter_mut().zip(other.max.iter()) {
+            *value = value.max(*other);
+        }
+    }
+
+    /// Get the minimal and maximal thickness of each bin in the order of the grid maps as `(min, max)`.
+    /// Both are NaN for bins that never had samples in both leaflets in the same frame.
+    pub(crate) fn values(&self) -> impl Iterator<Item = (f64, f64)> + '_ {
+        self.min.iter().zip(self.max.iter()).map(|(&min, &max)| {
+            if min > max {
+                (f64::NAN, f64::NAN)
+            } else {
+                (min, max)
+            }
+        })
+    }
+}
@@ -0,0 +1,151 @@
+//! Detection of lipids changing membrane leaflet during the analysis.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use groan_rs::prelude::*;
+
+use crate::HEADS_GROUP;
+
+/// Lipid identified by its headgroup atom.
+#[derive(Debug)]
+struct Lipid {
+    /// Number of the headgroup atom (starting from 1).
+    atom: usize,
+    residue_number: usize,
+    residue_name: String,
+}
+
+/// Lipid that changed leaflet.
+#[derive(Debug)]
+struct FlipFlop {
+    /// Index of the lipid in `FlipFlopTracker::lipids`.
+    lipid: usize,
+    /// Time of the first frame in which the lipid was detected in the new leaflet (in ps).
+    time: f32,
+    /// `true` if the lipid moved from the lower to the upper leaflet.
+    to_upper: bool,
+}
+
+/// Tracks the leaflet assignment of each lipid over time.
+#[derive(Debug)]
+pub(crate) struct FlipFlopTracker {
+    lipids: Vec<Lipid>,
+    /// Leaflet in which each lipid currently resides.
+    current: Vec<bool>,
+    /// Time and number of consecutive frames since the lipid has been detected in the opposite leaflet.
+    pending: Vec<Option<(f32, usize)>>,
+    /// Number of consecutive frames a lipid must spend in the opposite leaflet to count as a flip-flop.
+    min_frames: usize,
+    events: Vec<FlipFlop>,
+}
+
+impl FlipFlopTracker {
+    /// Prepare tracking of the headgroups of the system.
+    pub(crate) fn new(system: &System, min_frames: usize) -> anyhow::Result<Self> {
+        let lipids = system
+            .group_iter(HEADS_GROUP)?
+            .map(|head| Lipid {
+                atom: head.get_index() + 1,
+                residue_number: head.get_residue_number(),
+                residue_name: head.get_residue_name().to_owned(),
+            })
+            .collect::<Vec<_>>();
+
+        Ok(FlipFlopTracker {
+            pending: vec![None; lipids.len()],
+            lipids,
+            current: Vec::new(),
+            min_frames,
+            events: Vec::new(),
+        })
+    }
+
+    /// Update the tracker with the leaflet assignment of the next analyzed frame.
+    pub(crate) fn update(&mut self, time: f32, upper: &[bool]) {
+        // the first frame defines the initial leaflets
+        if self.current.is_empty() {
+            self.current = upper.to_vec();
+            return;
+        }
+
+        for (i, &is_upper) in upper.iter().enumerate() {
+            if is_upper == self.current[i] {
+                self.pending[i] = None;
+                continue;
+            }
+
+            let (since, n_frames) = self.pending[i].get_or_insert((time, 0));
+            *n_frames += 1;
+
+            if *n_frames >= self.min_frames {
+                self.events.push(FlipFlop {
+                    lipid: i,
+                    time: *since,
+                    to_upper: is_upper,
+                });
+                self.current[i] = is_upper;
+                self.pending[i] = None;
+            }
+        }
+    }
+
+    /// Get the number of detected flip-flops.
+    pub(crate) fn n_events(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Write the detected flip-flops into a file.
+    pub(crate) fn write(
+        &self,
+        filename: impl AsRef<Path>,
+        raw_arguments: &[String],
+    ) -> anyhow::Result<()> {
+        let filename = filename.as_ref();
+        let file = File::create(filename).with_context(|| {
+            format!("Could not create flip-flop file '{}'.", filename.display())
+        })?;
+        let mut output = BufWriter::new(file);
+
+        writeln!(
+            &mut output,
+            "# Generated with memthick v{}.",
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(&mut output, "# Command line: {}", raw_arguments.join(" "))?;
+        writeln!(
+            &mut output,
+            "# Lipids that moved to the opposite leaflet for at least {} consecutive analyzed frames.",
+            self.min_frames
+        )?;
+        writeln!(&mut output, "# time [ps] resid resname atom from to")?;
+
+        for event in self.events.iter() {
+            let lipid = &self.lipids[event.lipid];
+            let (from, to) = if event.to_upper {
+                ("lower", "upper")
+            } else {
+                ("upper", "lower")
+            };
+
+            writeln!(
+                &mut output,
+                "{:12.3} {:8} {:>6} {:8} {} {}",
+                event.time, lipid.residue_number, lipid.residue_name, lipid.atom, from, to
+            )?;
+        }
+
+        writeln!(
+            &mut output,
+            "# Total number of flip-flops: {}",
+            self.events.len()
+        )?;
+
+        output.flush()?;
+        Ok(())
+    }
+}
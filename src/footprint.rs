@@ -0,0 +1,173 @@
+//! Masking of the grid bins covered by a protein in the membrane plane and exclusion of the headgroups around it.
+
+use groan_rs::{errors::SimBoxError, prelude::*};
+
+use crate::{
+    PROTEIN_GROUP,
+    grids::{self, ThicknessGrids},
+    normal::Normal,
+};
+
+/// Number of analyzed frames in which each grid bin was covered by the selected atoms.
+#[derive(Debug, Clone)]
+pub(crate) struct Footprint {
+    /// Group of atoms covering the bins.
+    group: &'static str,
+    /// Lateral distance (in nm) from the atoms within which the bins are covered.
+    radius: f32,
+    /// Fraction of the analyzed frames above which the covered bins are masked.
+    threshold: f64,
+    n_x: usize,
+    n_y: usize,
+    tile: (f32, f32),
+    /// Center of the first grid bin.
+    start: (f32, f32),
+    /// Number of frames in which each bin was covered in the order of the grid maps.
+    covered: Vec<usize>,
+    n_frames: usize,
+}
+
+impl Footprint {
+    /// Prepare an empty footprint of the atoms of the group for the bins of the grids.
+    pub(crate) fn new(
+        grids: &ThicknessGrids,
+        group: &'static str,
+        radius: f32,
+        threshold: f64,
+    ) -> Footprint {
+        let (n_x, n_y) = (grids.upper_sum.n_tiles_x(), grids.upper_sum.n_tiles_y());
+
+        Footprint {
+            group,
+            radius,
+            threshold,
+            n_x,
+            n_y,
+            tile: grids.upper_sum.tile_dim(),
+            start: (grids.upper_sum.span_x().0, grids.upper_sum.span_y().0),
+            covered: vec![0; n_x * n_y],
+            n_frames: 0,
+        }
+    }
+
+    /// Add the bins covered by the atoms of the group in the frame. A bin is covered if its center lies
+    /// within the radius from any atom in the membrane plane. Distances are calculated using the periodic
+    /// boundary conditions if the membrane normal is oriented along an axis of the simulation box.
+    pub(crate) fn add(
+        &mut self,
+        frame: &System,
+        normal: &Normal,
+        simbox: &SimBox,
+    ) -> anyhow::Result<()> {
+        let images = |value: f32, period: Option<f32>| match period {
+            Some(period) => [value, value - period, value + period],
+            None => [value; 3],
+        };
+        let periods = normal.lateral_box(simbox);
+
+        let mut covered = vec![false; self.covered.len()];
+        for atom in frame.group_iter(self.group)? {
+            let position = atom
+                .get_position()
+                .ok_or_else(|| anyhow::anyhow!("Atom {} has no position.", atom.get_index() + 1))?;
+            let (x, y) = normal.lateral(position);
+
+            for x in images(x, periods.map(|periods| periods.0)) {
+                for y in images(y, periods.map(|periods| periods.1)) {
+                    self.cover(&mut covered, (x, y));
+                }
+            }
+        }
+
+        for (count, covered) in self.covered.iter_mut().zip(covered) {
+            *count += covered as usize;
+        }
+        self.n_frames += 1;
+
+        Ok(())
+    }
+
+    /// Flag the bins with centers within the radius from the point.
+    fn cover(&self, covered: &mut [bool], (x, y): (f32, f32)) {
+        // range of bin indices along one axis that may lie within the radius
+        let range = |value: f32, start: f32, tile: f32, n: usize| {
+            let first = ((value - self.radius - start) / tile).ceil().max(0.0) as usize;
+            let last = ((value + self.radius - start) / tile).floor();
+            if last < 0.0 {
+                (0, 0)
+            } else {
+                (first, (last as usize + 1).min(n))
+            }
+        };
+
+        let (first_x, last_x) = range(x, self.start.0, self.tile.0, self.n_x);
+        let (first_y, last_y) = range(y, self.start.1, self.tile.1, self.n_y);
+
+        for i in first_x..last_x {
+            for j in first_y..last_y {
+                let (dx, dy) = (
+                    self.start.0 + i as f32 * self.tile.0 - x,
+                    self.start.1 + j as f32 * self.tile.1 - y,
+                );
+                if dx * dx + dy * dy <= self.radius * self.radius {
+                    covered[i * self.n_y + j] = true;
+                }
+            }
+        }
+    }
+
+    /// Remove all covered bins.
+    pub(crate) fn clear(&mut self) {
+        self.covered.fill(0);
+        self.n_frames = 0;
+    }
+
+    /// Add the bins covered in the other footprint into this footprint.
+    pub(crate) fn merge(&mut self, other: &Footprint) {
+        for (count, other) in self.covered.iter_mut().zip(other.covered.iter()) {
+            *count += other;
+        }
+        self.n_frames += other.n_frames;
+    }
+
+    /// Calculate the fraction of the analyzed frames in which each bin of the grids was covered as `(x, y, fraction)`.
+    pub(crate) fn occupancy(&self, grids: &ThicknessGrids) -> Vec<(f32, f32, f64)> {
+        grids
+            .upper_sum
+            .extract_raw()
+            .zip(self.covered.iter())
+            .map(|((x, y, _), &count)| (x, y, count as f64 / self.n_frames as f64))
+            .collect()
+    }
+
+    /// Get the bins covered in a larger fraction of the analyzed frames than the threshold in the order of the grid maps.
+    pub(crate) fn masked(&self) -> Vec<bool> {
+        self.covered
+            .iter()
+            .map(|&count| self.n_frames > 0 && count as f64 / self.n_frames as f64 > self.threshold)
+            .collect()
+    }
+}
+
+/// Remove the headgroups located within `cutoff` (in nm) from any protein atom in the membrane plane
+/// from the leaflets specified by `upper` (see [`ThicknessGrids::add_heads`]).
+pub(crate) fn exclude_shell(
+    frame: &System,
+    normal: &Normal,
+    upper: &[Option<bool>],
+    cutoff: f32,
+) -> anyhow::Result<Vec<Option<bool>>> {
+    let simbox = frame.get_box().ok_or(SimBoxError::DoesNotExist)?;
+    let protein = grids::atom_positions(frame, PROTEIN_GROUP)?;
+
+    Ok(grids::head_positions(frame)?
+        .iter()
+        .zip(upper.iter())
+        .map(|(head, &upper)| {
+            let annular = protein
+                .iter()
+                .any(|atom| normal.lateral_distance(head, atom, simbox) <= cutoff);
+            if annular { None } else { upper }
+        })
+        .collect())
+}
@@ -0,0 +1,212 @@
+//! Shape of the analyzed membrane.
+
+use std::fmt::Display;
+
+use clap::ValueEnum;
+use groan_rs::prelude::*;
+
+use crate::normal::{self, Normal};
+
+/// Shape of the membrane determining the coordinates of the thickness map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub(crate) enum Geometry {
+    /// Flat membrane. Thickness is mapped in the membrane plane.
+    #[default]
+    Planar,
+    /// Spherical vesicle. Radial thickness is mapped in the polar and azimuthal angle around the vesicle center.
+    Vesicle,
+    /// Cylindrical membrane tube with its axis along the membrane normal. Radial thickness is mapped
+    /// in the axial coordinate and the azimuthal angle around the tube axis.
+    Tube,
+    /// Membrane buckled along the first axis of the membrane plane. Thickness is mapped in the arclength
+    /// along the membrane midplane and the transverse coordinate.
+    Buckle,
+}
+
+impl Geometry {
+    /// Parse the geometry from its name.
+    pub(crate) fn parse(name: &str) -> anyhow::Result<Geometry> {
+        Geometry::from_str(name, true)
+            .map_err(|_| anyhow::anyhow!("Invalid membrane geometry '{}'.", name))
+    }
+
+    /// Range of the map coordinates covering the whole membrane.
+    pub(crate) fn map_range(&self, normal: &Normal, simbox: &SimBox) -> ((f32, f32), (f32, f32)) {
+        match self {
+            Geometry::Planar | Geometry::Buckle => normal.lateral_range(simbox),
+            Geometry::Vesicle => ((0.0, 180.0), (0.0, 360.0)),
+            Geometry::Tube => (normal.axial_range(simbox), (0.0, 360.0)),
+        }
+    }
+
+    /// Periods of the map coordinates imposed by the periodic boundary conditions.
+    /// `None` is returned for coordinates that are not periodic or that are always calculated inside their range.
+    pub(crate) fn map_periods(
+        &self,
+        normal: &Normal,
+        simbox: &SimBox,
+    ) -> (Option<f32>, Option<f32>) {
+        let lateral = normal.lateral_box(simbox);
+        match self {
+            Geometry::Planar => (lateral.map(|(x, _)| x), lateral.map(|(_, y)| y)),
+            Geometry::Vesicle => (None, None),
+            Geometry::Tube => (lateral.map(|_| normal.axial_range(simbox).1), None),
+            Geometry::Buckle => (None, lateral.map(|(_, y)| y)),
+        }
+    }
+
+    /// Labels of the axes of the thickness map.
+    pub(crate) fn axis_labels(&self, normal: &Normal) -> (String, String) {
+        match self {
+            Geometry::Planar => {
+                let (axis_x, axis_y) = normal.plane_axes();
+                (
+                    format!("{}-coordinate [nm]", axis_x),
+                    format!("{}-coordinate [nm]", axis_y),
+                )
+            }
+            Geometry::Vesicle => (
+                "polar angle [deg]".to_owned(),
+                "azimuthal angle [deg]".to_owned(),
+            ),
+            Geometry::Tube => (
+                "axial coordinate [nm]".to_owned(),
+                "azimuthal angle [deg]".to_owned(),
+            ),
+            Geometry::Buckle => (
+                "arclength [nm]".to_owned(),
+                format!("{}-coordinate [nm]", normal.plane_axes().1),
+            ),
+        }
+    }
+
+    /// Names of the leaflets stored in the upper and lower grid maps, respectively.
+    pub(crate) fn leaflet_names(&self) -> (&'static str, &'static str) {
+        match self {
+            Geometry::Planar | Geometry::Buckle => ("upper", "lower"),
+            Geometry::Vesicle | Geometry::Tube => ("outer", "inner"),
+        }
+    }
+
+    /// Distance of the position from the center of a curved membrane.
+    ///
+    /// For vesicles, this is the distance from the vesicle center.
+    /// For tubes, this is the distance from the tube axis passing through the center.
+    /// For planar membranes, this is the signed distance from the membrane center along the normal.
+    pub(crate) fn radial_distance(
+        &self,
+        normal: &Normal,
+        position: &Vector3D,
+        center: &Vector3D,
+        simbox: &SimBox,
+    ) -> f32 {
+        match self {
+            Geometry::Planar => normal.distance(position, center, simbox),
+            Geometry::Vesicle => center.vector_to(position, simbox).len(),
+            Geometry::Tube => {
+                let vector = center.vector_to(position, simbox);
+                let axial = normal.axial(&vector);
+                (vector.len().powi(2) - axial.powi(2)).max(0.0).sqrt()
+            }
+            Geometry::Buckle => unreachable!(
+                "FATAL MEMTHICK ERROR | geometry::Geometry::radial_distance | Distances in buckled membranes should be calculated from the membrane profile."
+            ),
+        }
+    }
+
+    /// Coordinates of the position in a map of a curved membrane.
+    ///
+    /// For vesicles, these are the polar angle measured from the membrane normal (0-180°)
+    /// and the azimuthal angle around it (0-360°).
+    /// For tubes, these are the coordinate along the tube axis and the azimuthal angle around it (0-360°).
+    /// For planar membranes, these are the coordinates in the membrane plane.
+    pub(crate) fn map_coordinates(
+        &self,
+        normal: &Normal,
+        position: &Vector3D,
+        center: &Vector3D,
+        simbox: &SimBox,
+    ) -> (f32, f32) {
+        match self {
+            Geometry::Planar => normal.lateral(position),
+            Geometry::Vesicle => {
+                let (axis, u, v) = normal.basis();
+                let vector = center.vector_to(position, simbox);
+                let vector = [vector.x, vector.y, vector.z];
+
+                let length = normal::dot(vector, vector).sqrt();
+                let polar = (normal::dot(axis, vector) / length)
+                    .clamp(-1.0, 1.0)
+                    .acos()
+                    .to_degrees();
+                let azimuthal = normal::dot(v, vector)
+                    .atan2(normal::dot(u, vector))
+                    .to_degrees()
+                    .rem_euclid(360.0);
+
+                (polar, azimuthal)
+            }
+            Geometry::Tube => {
+                let (_, u, v) = normal.basis();
+                let vector = center.vector_to(position, simbox);
+                let vector = [vector.x, vector.y, vector.z];
+
+                let azimuthal = normal::dot(v, vector)
+                    .atan2(normal::dot(u, vector))
+                    .to_degrees()
+                    .rem_euclid(360.0);
+
+                (normal.axial(position), azimuthal)
+            }
+            Geometry::Buckle => unreachable!(
+                "FATAL MEMTHICK ERROR | geometry::Geometry::map_coordinates | Coordinates in buckled membranes should be calculated from the membrane profile."
+            ),
+        }
+    }
+}
+
+/// Coordinate system of the thickness map of a planar membrane.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub(crate) enum Grid {
+    /// Coordinates in the membrane plane.
+    #[default]
+    Cartesian,
+    /// Distance from an origin in the membrane plane and the azimuthal angle around it.
+    Polar,
+}
+
+/// Convert coordinates in the membrane plane into the distance from the origin and the azimuthal angle
+/// around it (0-360°). With `periods`, the point is first replaced by its periodic image closest to the origin.
+pub(crate) fn polar_coordinates(
+    (x, y): (f32, f32),
+    origin: (f32, f32),
+    periods: Option<(f32, f32)>,
+) -> (f32, f32) {
+    let (mut dx, mut dy) = (x - origin.0, y - origin.1);
+    if let Some((period_x, period_y)) = periods {
+        dx -= period_x * (dx / period_x).round();
+        dy -= period_y * (dy / period_y).round();
+    }
+
+    (dx.hypot(dy), dy.atan2(dx).to_degrees().rem_euclid(360.0))
+}
+
+impl Display for Grid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = self
+            .to_possible_value()
+            .expect("FATAL MEMTHICK ERROR | geometry::Grid::fmt | Grid should have a name.");
+
+        write!(f, "{}", value.get_name())
+    }
+}
+
+impl Display for Geometry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = self.to_possible_value().expect(
+            "FATAL MEMTHICK ERROR | geometry::Geometry::fmt | Geometry should have a name.",
+        );
+
+        write!(f, "{}", value.get_name())
+    }
+}
@@ -0,0 +1,1656 @@
+//! Grid maps used to accumulate headgroup positions.
+
+use std::{collections::HashMap, fmt::Display, ops::AddAssign};
+
+use clap::ValueEnum;
+use groan_rs::{
+    errors::{GridMapError, SimBoxError},
+    prelude::*,
+};
+
+use crate::{
+    CENTER_GROUP, HEAD_ATOMS_GROUP, HEADS_GROUP, LIPIDS_GROUP, WATER_GROUP, areas,
+    autocorrelation::{BinSeries, ThicknessSeries},
+    buckle::BuckleProfile,
+    composition::Composition,
+    contacts::ResidueThickness,
+    deviation::{Deformation, Reference},
+    environment::Environment,
+    extremes::Extremes,
+    footprint::{self, Footprint},
+    geometry::{self, Geometry},
+    interdigitation::Distributions,
+    leaflets::{LateralCells, LeafletClassifier},
+    molecules::Molecules,
+    normal::Normal,
+    profile::RadialProfile,
+    region::Region,
+    roi::RegionOfInterest,
+    smoothing::KERNEL_CUTOFF,
+    spectrum::Spectrum,
+    tilt::TiltMaps,
+};
+
+pub(crate) type SumMap = GridMap<f64, f64, fn(&f64) -> f64>;
+pub(crate) type CountMap = GridMap<usize, usize, fn(&usize) -> usize>;
+
+/// Lateral distance (in nm) within which the closest headgroup of each grid bin is searched first
+/// when rasterizing the Voronoi cells of the headgroups.
+const VORONOI_RADIUS: f32 = 2.0;
+
+/// Definition of the membrane thickness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub(crate) enum ThicknessMethod {
+    /// Distance between the average positions of headgroups in the two leaflets.
+    #[default]
+    Heads,
+    /// Luzzati thickness, i.e., the length of the grid column not occupied by water.
+    Luzzati,
+    /// Distance between each headgroup and the laterally closest headgroup of the opposite leaflet.
+    Pairwise,
+    /// Every bin is assigned to the Voronoi cell of the laterally closest headgroup in each leaflet.
+    Voronoi,
+    /// Leaflet surfaces are estimated in every frame by kernel smoothing of the headgroup distances.
+    Kde,
+}
+
+impl ThicknessMethod {
+    /// Parse the thickness definition from its name.
+    pub(crate) fn parse(name: &str) -> anyhow::Result<ThicknessMethod> {
+        ThicknessMethod::from_str(name, true)
+            .map_err(|_| anyhow::anyhow!("Invalid thickness method '{}'.", name))
+    }
+}
+
+impl Display for ThicknessMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = self.to_possible_value().expect(
+            "FATAL MEMTHICK ERROR | grids::ThicknessMethod::fmt | Thickness method should have a name.",
+        );
+
+        write!(f, "{}", value.get_name())
+    }
+}
+
+/// Weighted sums of the samples and the weights of both leaflets accumulated in a grid bin.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct LeafletTotals {
+    pub(crate) upper_sum: f64,
+    pub(crate) upper_weight: f64,
+    pub(crate) lower_sum: f64,
+    pub(crate) lower_weight: f64,
+}
+
+impl LeafletTotals {
+    /// Calculate the thickness from the samples accumulated since the `before` totals.
+    /// Returns `None` if no samples were added to any of the leaflets.
+    pub(crate) fn thickness_since(&self, before: &LeafletTotals) -> Option<f64> {
+        let upper_weight = self.upper_weight - before.upper_weight;
+        let lower_weight = self.lower_weight - before.lower_weight;
+        if upper_weight <= 0.0 || lower_weight <= 0.0 {
+            return None;
+        }
+
+        Some(
+            (self.upper_sum - before.upper_sum) / upper_weight
+                - (self.lower_sum - before.lower_sum) / lower_weight,
+        )
+    }
+}
+
+impl AddAssign for LeafletTotals {
+    fn add_assign(&mut self, other: LeafletTotals) {
+        self.upper_sum += other.upper_sum;
+        self.upper_weight += other.upper_weight;
+        self.lower_sum += other.lower_sum;
+        self.lower_weight += other.lower_weight;
+    }
+}
+
+/// Value of each headgroup accumulated in the grids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum Sample {
+    /// Distance of the headgroup from the membrane center.
+    #[default]
+    Distance,
+    /// Area of the Voronoi cell of the headgroup in the membrane plane.
+    Area,
+}
+
+/// Grid maps accumulating the positions of headgroups in both membrane leaflets.
+#[derive(Debug, Clone)]
+pub(crate) struct ThicknessGrids {
+    pub(crate) upper_sum: SumMap,
+    pub(crate) upper_count: CountMap,
+    /// Sum of the weights of the frames in which a headgroup was detected in a bin.
+    pub(crate) upper_weight: SumMap,
+    /// Sum of the squared samples multiplied by the weights of their frames.
+    pub(crate) upper_squares: SumMap,
+    pub(crate) lower_sum: SumMap,
+    pub(crate) lower_count: CountMap,
+    pub(crate) lower_weight: SumMap,
+    pub(crate) lower_squares: SumMap,
+    /// Number of frames added to the grids.
+    pub(crate) n_frames: usize,
+    /// Simulation time of the last frame added to the grids (in ps).
+    pub(crate) last_time: f32,
+    /// Orientation of the membrane normal.
+    pub(crate) normal: Normal,
+    /// Shape of the membrane.
+    pub(crate) geometry: Geometry,
+    /// Definition of the membrane thickness.
+    pub(crate) method: ThicknessMethod,
+    /// Maximal lateral distance between paired headgroups for the pairwise thickness (in nm).
+    pub(crate) pair_cutoff: f32,
+    /// Width of the Gaussian kernel estimating the leaflet surfaces for the kernel thickness (in nm).
+    pub(crate) kde_bandwidth: f32,
+    /// Headgroups within this lateral distance from the protein are not added to the grids (in nm).
+    pub(crate) exclude_shell: Option<f32>,
+    /// Headgroups outside this region of the simulation box are not added to the grids.
+    pub(crate) region: Option<Region>,
+    /// Number of Fourier modes describing the profile of buckled membranes.
+    pub(crate) buckle_modes: usize,
+    /// Measure the distances of headgroups perpendicular to a plane fitted to the headgroups in every frame.
+    pub(crate) fit_plane: bool,
+    /// Map coordinates in the membrane plane are scaled by the size of the simulation box in every frame.
+    pub(crate) scaled: bool,
+    /// Origin of the polar map coordinates in the membrane plane (`None` for Cartesian map coordinates).
+    pub(crate) origin: Option<(f32, f32)>,
+    /// The membrane center is the center of mass of the lipids instead of their center of geometry.
+    pub(crate) center_of_mass: bool,
+    /// Description of the selected headgroups, stored in state files to check their compatibility.
+    pub(crate) heads: String,
+    /// Description of the assignment of the headgroups to leaflets, stored in state files to check their compatibility.
+    pub(crate) leaflets: String,
+    /// Grids accumulating the positions of other atoms than the headgroups for separate maps
+    /// (e.g., of the hydrophobic thickness).
+    pub(crate) secondary: Vec<ThicknessGrids>,
+    /// For secondary grids, the group of atoms whose positions are accumulated and the index of the headgroup
+    /// of the lipid each of these atoms belongs to (`None` for atoms of lipids without a headgroup).
+    pub(crate) atoms: Option<(&'static str, Vec<Option<usize>>)>,
+    /// Name of the quantity mapped by the grids.
+    pub(crate) quantity: &'static str,
+    /// Value of each headgroup accumulated in the grids.
+    pub(crate) sample: Sample,
+    /// Width of the Gaussian kernel depositing each headgroup over the neighboring bins
+    /// (`None` to add each headgroup to a single bin).
+    pub(crate) kernel: Option<f32>,
+    /// Lipid composition of the grid bins (if requested).
+    pub(crate) composition: Option<Composition>,
+    /// Distances of headgroups classified by their lipid environment (if requested).
+    pub(crate) environment: Option<Environment>,
+    /// Grid bins visited by the headgroups of the individual lipids (if requested).
+    pub(crate) molecules: Option<Molecules>,
+    /// Distributions of the accumulated atoms along the normal for mapping the leaflet interdigitation (if requested).
+    pub(crate) distributions: Option<Distributions>,
+    /// Undulation spectrum of the membrane midplane (if requested).
+    pub(crate) spectrum: Option<Spectrum>,
+    /// Profiles of the thickness around points or selected atoms in the membrane plane (if requested).
+    pub(crate) profiles: Vec<RadialProfile>,
+    /// Reference thickness of the map of the relative deviation of the thickness (if requested).
+    pub(crate) reference: Option<Reference>,
+    /// Region of interest whose thickness is compared with the rest of the map (if requested).
+    pub(crate) roi: Option<RegionOfInterest>,
+    /// Distances of the bins from the inclusion for the map of the deformation of the thickness (if requested).
+    pub(crate) deformation: Option<Deformation>,
+    /// Minimal and maximal instantaneous thickness of the grid bins (if requested).
+    pub(crate) extremes: Option<Extremes>,
+    /// Time series of the average thickness of the individual frames (if requested).
+    pub(crate) series: Option<ThicknessSeries>,
+    /// Time series of the thickness of the individual grid bins (if requested).
+    pub(crate) bin_series: Option<BinSeries>,
+    /// Bins covered by the protein which are masked in the maps (if requested).
+    pub(crate) footprint: Option<Footprint>,
+    /// Grids conditioned on the direction of the tilt of a transmembrane helix (if requested).
+    pub(crate) tilt: Option<TiltMaps>,
+    /// Grid bins visited by the selected protein residues (if requested).
+    pub(crate) residues: Option<ResidueThickness>,
+}
+
+impl ThicknessGrids {
+    /// Create empty grid maps spanning the specified area of the map coordinates.
+    pub(crate) fn new(
+        span_x: (f32, f32),
+        span_y: (f32, f32),
+        tile: (f32, f32),
+        normal: Normal,
+        geometry: Geometry,
+    ) -> Result<Self, GridMapError> {
+        Ok(ThicknessGrids {
+            upper_sum: GridMap::new(span_x, span_y, tile, f64::clone as fn(&f64) -> f64)?,
+            upper_count: GridMap::new(span_x, span_y, tile, usize::clone as fn(&usize) -> usize)?,
+            upper_weight: GridMap::new(span_x, span_y, tile, f64::clone as fn(&f64) -> f64)?,
+            upper_squares: GridMap::new(span_x, span_y, tile, f64::clone as fn(&f64) -> f64)?,
+            lower_sum: GridMap::new(span_x, span_y, tile, f64::clone as fn(&f64) -> f64)?,
+            lower_count: GridMap::new(span_x, span_y, tile, usize::clone as fn(&usize) -> usize)?,
+            lower_weight: GridMap::new(span_x, span_y, tile, f64::clone as fn(&f64) -> f64)?,
+            lower_squares: GridMap::new(span_x, span_y, tile, f64::clone as fn(&f64) -> f64)?,
+            n_frames: 0,
+            last_time: 0.0,
+            normal,
+            geometry,
+            method: ThicknessMethod::default(),
+            pair_cutoff: 1.5,
+            kde_bandwidth: 1.0,
+            exclude_shell: None,
+            region: None,
+            buckle_modes: 2,
+            fit_plane: false,
+            scaled: false,
+            origin: None,
+            center_of_mass: false,
+            heads: String::new(),
+            leaflets: String::new(),
+            secondary: Vec::new(),
+            atoms: None,
+            quantity: "membrane thickness",
+            sample: Sample::default(),
+            kernel: None,
+            composition: None,
+            environment: None,
+            molecules: None,
+            distributions: None,
+            spectrum: None,
+            profiles: Vec::new(),
+            reference: None,
+            roi: None,
+            deformation: None,
+            extremes: None,
+            series: None,
+            bin_series: None,
+            footprint: None,
+            tilt: None,
+            residues: None,
+        })
+    }
+
+    /// Assign headgroups to leaflets and add their positions relative to the membrane center to the grids.
+    /// Each contribution is multiplied by the weight of the frame.
+    pub(crate) fn add_frame(
+        &mut self,
+        frame: &System,
+        classifier: &LeafletClassifier,
+        weight: f64,
+    ) -> anyhow::Result<()> {
+        let membrane_center = membrane_center(frame, self.center_of_mass)?;
+        let upper = classifier.assign(frame, &membrane_center)?;
+        self.add_heads(frame, &membrane_center, &classifier.counted(&upper), weight)
+    }
+
+    /// Add positions of headgroups relative to the provided membrane center to the grids.
+    /// `upper` specifies for each headgroup whether it is counted in the upper leaflet (`Some(true)`),
+    /// in the lower leaflet (`Some(false)`), or not at all (`None`).
+    pub(crate) fn add_heads(
+        &mut self,
+        frame: &System,
+        membrane_center: &Vector3D,
+        upper: &[Option<bool>],
+        weight: f64,
+    ) -> anyhow::Result<()> {
+        let restricted;
+        let upper = match &self.region {
+            Some(region) => {
+                restricted = region.restrict(frame, upper)?;
+                &restricted
+            }
+            None => upper,
+        };
+
+        let excluded;
+        let upper = match self.exclude_shell {
+            Some(cutoff) => {
+                excluded = footprint::exclude_shell(frame, &self.normal, upper, cutoff)?;
+                &excluded
+            }
+            None => upper,
+        };
+
+        if let Some(tilt) = self.tilt.as_mut() {
+            tilt.add_heads(frame, &self.normal, membrane_center, upper, weight)?;
+        }
+
+        // the instantaneous thickness is obtained from the data added by this frame
+        let before =
+            (self.extremes.is_some() || self.series.is_some() || self.bin_series.is_some())
+                .then(|| self.leaflet_totals());
+        self.add_samples(frame, membrane_center, upper, weight)?;
+
+        if let Some(footprint) = self.footprint.as_mut() {
+            let simbox = frame.get_box().ok_or(SimBoxError::DoesNotExist)?;
+            footprint.add(frame, &self.normal, simbox)?;
+        }
+
+        if let Some(residues) = self.residues.as_mut() {
+            let simbox = frame.get_box().ok_or(SimBoxError::DoesNotExist)?;
+            residues.add(frame, &self.normal, simbox)?;
+        }
+
+        if let Some(before) = before {
+            let after = self.leaflet_totals();
+            if let Some(extremes) = self.extremes.as_mut() {
+                extremes.update(&before, &after);
+            }
+
+            if let Some(bin_series) = self.bin_series.as_mut() {
+                bin_series.add(frame.get_simulation_time(), &before, &after);
+            }
+
+            if let Some(series) = self.series.as_mut() {
+                let total = |totals: &[LeafletTotals]| {
+                    totals
+                        .iter()
+                        .fold(LeafletTotals::default(), |mut total, &bin| {
+                            total += bin;
+                            total
+                        })
+                };
+
+                if let Some(thickness) = total(&after).thickness_since(&total(&before)) {
+                    series.add(frame.get_simulation_time(), thickness);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add the samples of the frame to the grids using the selected thickness method. See [`ThicknessGrids::add_heads`].
+    fn add_samples(
+        &mut self,
+        frame: &System,
+        membrane_center: &Vector3D,
+        upper: &[Option<bool>],
+        weight: f64,
+    ) -> anyhow::Result<()> {
+        for grids in self.secondary.iter_mut() {
+            grids.add_heads(frame, membrane_center, upper, weight)?;
+        }
+
+        let simbox = frame.get_box().ok_or(SimBoxError::DoesNotExist)?;
+
+        // distances are measured perpendicular to the plane fitted in this frame,
+        // while the positions in the map are still given in the membrane plane
+        let distance_normal = if self.fit_plane {
+            self.normal.fit(frame, membrane_center, upper)?
+        } else {
+            self.normal
+        };
+
+        // buckled membranes are described by the profile of their midplane in this frame
+        let profile = if self.geometry == Geometry::Buckle {
+            Some(BuckleProfile::new(
+                frame,
+                membrane_center,
+                self.normal,
+                self.buckle_modes,
+            )?)
+        } else {
+            None
+        };
+
+        let periods = if self.scaled {
+            (Some(1.0), Some(1.0))
+        } else if self.origin.is_some() {
+            (None, None)
+        } else {
+            self.geometry.map_periods(&self.normal, simbox)
+        };
+        let scale = if self.scaled {
+            self.normal.lateral_box(simbox).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Scaled grid coordinates require the membrane normal to be oriented along an axis of the simulation box."
+                )
+            })?
+        } else {
+            (1.0, 1.0)
+        };
+
+        // atoms other than the headgroups are counted in the leaflet of their lipids
+        let (positions, upper) = match &self.atoms {
+            Some((group, heads)) => (
+                atom_positions(frame, group)?,
+                heads
+                    .iter()
+                    .map(|head| head.and_then(|head| upper[head]))
+                    .collect::<Vec<_>>(),
+            ),
+            None => (head_positions(frame)?, upper.to_vec()),
+        };
+
+        if let Some(spectrum) = self.spectrum.as_mut() {
+            spectrum.add(
+                &positions,
+                membrane_center,
+                &upper,
+                &self.normal,
+                simbox,
+                weight,
+            );
+        }
+
+        for profile in self.profiles.iter_mut() {
+            profile.add(
+                frame,
+                &positions,
+                membrane_center,
+                &upper,
+                &self.normal,
+                simbox,
+                weight,
+            )?;
+        }
+
+        match self.method {
+            ThicknessMethod::Heads => (),
+            ThicknessMethod::Luzzati => {
+                return self.add_water(
+                    frame,
+                    membrane_center,
+                    &positions,
+                    &upper,
+                    weight,
+                    periods,
+                    scale,
+                );
+            }
+            ThicknessMethod::Voronoi => {
+                return self.add_cells(
+                    frame,
+                    membrane_center,
+                    &positions,
+                    &upper,
+                    weight,
+                    distance_normal,
+                    periods,
+                    scale,
+                );
+            }
+            ThicknessMethod::Kde => {
+                return self.add_surfaces(
+                    frame,
+                    membrane_center,
+                    &positions,
+                    &upper,
+                    weight,
+                    distance_normal,
+                    periods,
+                    scale,
+                );
+            }
+            ThicknessMethod::Pairwise => {
+                return self.add_pairs(
+                    frame,
+                    membrane_center,
+                    &positions,
+                    &upper,
+                    weight,
+                    distance_normal,
+                    periods,
+                    scale,
+                );
+            }
+        }
+
+        let areas = if self.sample == Sample::Area {
+            Some(areas::voronoi_areas(
+                &positions,
+                &upper,
+                self.normal,
+                simbox,
+            ))
+        } else {
+            None
+        };
+
+        let environment_cells = match &self.environment {
+            Some(environment) => Some(environment.cells(frame, self.normal)?),
+            None => None,
+        };
+
+        for (i, (position, &is_upper)) in positions.iter().zip(upper.iter()).enumerate() {
+            let Some(is_upper) = is_upper else {
+                continue;
+            };
+
+            let (x, y, zdist) = match &profile {
+                Some(profile) => profile.map_position(position, membrane_center, simbox),
+                None => {
+                    let (x, y) = self.geometry.map_coordinates(
+                        &self.normal,
+                        position,
+                        membrane_center,
+                        simbox,
+                    );
+                    let zdist = self.geometry.radial_distance(
+                        &distance_normal,
+                        position,
+                        membrane_center,
+                        simbox,
+                    );
+                    match self.origin {
+                        Some(origin) => {
+                            let (r, angle) = geometry::polar_coordinates(
+                                (x, y),
+                                origin,
+                                self.normal.lateral_box(simbox),
+                            );
+                            (r, angle, zdist)
+                        }
+                        None => (x / scale.0, y / scale.1, zdist),
+                    }
+                }
+            };
+            let (x, y) = self.wrap_into_grid(x, y, periods);
+            let value = match (self.sample, &areas) {
+                (Sample::Area, Some(areas)) => areas[i],
+                _ => zdist as f64,
+            };
+            match self.kernel {
+                Some(sigma) => self.deposit_sample(x, y, is_upper, value, weight, sigma, periods),
+                None => self.add_sample(x, y, is_upper, value, weight),
+            }
+            if let Some(composition) = self.composition.as_mut() {
+                composition.add(i, is_upper, x, y);
+            }
+            if let (Some(environment), Some(cells)) =
+                (self.environment.as_mut(), &environment_cells)
+            {
+                environment.add(cells, i, position, is_upper, zdist as f64, weight, simbox);
+            }
+            if let Some(molecules) = self.molecules.as_mut() {
+                molecules.add(i, is_upper, x, y, weight);
+            }
+            if let Some(distributions) = self.distributions.as_mut() {
+                distributions.add(x, y, is_upper, zdist, weight);
+            }
+        }
+
+        self.n_frames += 1;
+        self.last_time = frame.get_simulation_time();
+
+        Ok(())
+    }
+
+    /// Add the Luzzati thickness of each grid column in the frame to the grids.
+    ///
+    /// The volume fraction of water in a column is the number of water atoms in the column divided by
+    /// the number expected for bulk water filling the whole column. The bulk density of water is estimated
+    /// from the water atoms farther from the membrane center than all headgroups of the corresponding leaflet.
+    /// Half of the column length not occupied by water on the upper side of the membrane center is added
+    /// to the upper grids and the negative value for the lower side to the lower grids, so that the thickness
+    /// calculated from the grids is the Luzzati thickness.
+    #[allow(clippy::too_many_arguments)]
+    fn add_water(
+        &mut self,
+        frame: &System,
+        membrane_center: &Vector3D,
+        positions: &[Vector3D],
+        upper: &[Option<bool>],
+        weight: f64,
+        periods: (Option<f32>, Option<f32>),
+        scale: (f32, f32),
+    ) -> anyhow::Result<()> {
+        let simbox = frame.get_box().ok_or(SimBoxError::DoesNotExist)?;
+        let (box_x, box_y) = self.normal.lateral_box(simbox).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Luzzati thickness requires the membrane normal to be oriented along an axis of the simulation box."
+            )
+        })?;
+        let length = self.normal.axial_range(simbox).1;
+
+        // the headgroups farthest from the membrane center delimit the bulk water
+        let (mut upper_limit, mut lower_limit) = (0.0f32, 0.0f32);
+        for (position, &is_upper) in positions.iter().zip(upper.iter()) {
+            let distance = self.normal.distance(position, membrane_center, simbox);
+            match is_upper {
+                Some(true) => upper_limit = upper_limit.max(distance),
+                Some(false) => lower_limit = lower_limit.min(distance),
+                None => (),
+            }
+        }
+
+        let mut upper_water = self.upper_count.clone();
+        upper_water.clear();
+        let mut lower_water = upper_water.clone();
+
+        let images = |value: f32, period: Option<f32>| match period {
+            Some(period) => vec![value, value - period, value + period],
+            None => vec![value],
+        };
+
+        let mut n_bulk = 0;
+        for atom in frame.group_iter(WATER_GROUP)? {
+            let position = atom
+                .get_position()
+                .ok_or_else(|| anyhow::anyhow!("Atom {} has no position.", atom.get_index() + 1))?;
+
+            let distance = self.normal.distance(position, membrane_center, simbox);
+            if distance > upper_limit || distance < lower_limit {
+                n_bulk += 1;
+            }
+
+            let water = if distance >= 0.0 {
+                &mut upper_water
+            } else {
+                &mut lower_water
+            };
+
+            // bins at the opposite edges of the grid may cover the same part of the membrane
+            let (x, y) = self.normal.lateral(position);
+            for x_image in images(x / scale.0, periods.0) {
+                for y_image in images(y / scale.1, periods.1) {
+                    if let Some(count) = water.get_mut_at(x_image, y_image) {
+                        *count += 1;
+                    }
+                }
+            }
+        }
+
+        let bulk_volume = box_x * box_y * (length - upper_limit + lower_limit);
+        if n_bulk == 0 || bulk_volume <= 0.0 {
+            anyhow::bail!(
+                "Could not estimate the density of bulk water at time {} ps. No water atoms are located farther from the membrane center than the headgroups.",
+                frame.get_simulation_time()
+            );
+        }
+
+        let tile = self.upper_sum.tile_dim();
+        let expected = n_bulk as f64 / bulk_volume as f64
+            * (tile.0 * scale.0 * tile.1 * scale.1) as f64
+            * (length / 2.0) as f64;
+
+        for ((x, y, &n_upper), (_, _, &n_lower)) in
+            upper_water.extract_raw().zip(lower_water.extract_raw())
+        {
+            let upper_half = (length / 2.0) as f64 * (1.0 - n_upper as f64 / expected);
+            let lower_half = (length / 2.0) as f64 * (1.0 - n_lower as f64 / expected);
+
+            self.add_sample(x, y, true, upper_half, weight);
+            self.add_sample(x, y, false, -lower_half, weight);
+        }
+
+        self.n_frames += 1;
+        self.last_time = frame.get_simulation_time();
+
+        Ok(())
+    }
+
+    /// Add the distances of paired headgroups from the membrane center to the grids.
+    ///
+    /// Each headgroup is paired with the laterally closest headgroup of the opposite leaflet located
+    /// within `pair_cutoff`. The distances of both headgroups of the pair are added at the position
+    /// of the midpoint between them, so that the thickness calculated from the grids is the average
+    /// distance between the paired headgroups. Headgroups without a pair are not counted.
+    #[allow(clippy::too_many_arguments)]
+    fn add_pairs(
+        &mut self,
+        frame: &System,
+        membrane_center: &Vector3D,
+        positions: &[Vector3D],
+        upper: &[Option<bool>],
+        weight: f64,
+        distance_normal: Normal,
+        periods: (Option<f32>, Option<f32>),
+        scale: (f32, f32),
+    ) -> anyhow::Result<()> {
+        let simbox = frame.get_box().ok_or(SimBoxError::DoesNotExist)?;
+
+        let mut upper_cells = LateralCells::new(simbox, self.pair_cutoff, self.normal);
+        let mut lower_cells = LateralCells::new(simbox, self.pair_cutoff, self.normal);
+        for (position, &is_upper) in positions.iter().zip(upper.iter()) {
+            match is_upper {
+                Some(true) => upper_cells.insert(position, ()),
+                Some(false) => lower_cells.insert(position, ()),
+                None => (),
+            }
+        }
+
+        for (position, &is_upper) in positions.iter().zip(upper.iter()) {
+            let Some(is_upper) = is_upper else {
+                continue;
+            };
+
+            let opposite = if is_upper { &lower_cells } else { &upper_cells };
+            let Some(partner) = opposite
+                .neighbors(position)
+                .map(|(other, _)| (other, self.normal.lateral_distance(position, other, simbox)))
+                .filter(|&(_, distance)| distance <= self.pair_cutoff)
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(other, _)| other)
+            else {
+                continue;
+            };
+
+            let vector = position.vector_to(partner, simbox);
+            let midpoint = Vector3D::new(
+                position.x + vector.x / 2.0,
+                position.y + vector.y / 2.0,
+                position.z + vector.z / 2.0,
+            );
+            let (x, y) = self.normal.lateral(&midpoint);
+            let (x, y) = self.wrap_into_grid(x / scale.0, y / scale.1, periods);
+
+            let distance = distance_normal.distance(position, membrane_center, simbox) as f64;
+            let partner_distance =
+                distance_normal.distance(partner, membrane_center, simbox) as f64;
+            self.add_sample(x, y, is_upper, distance, weight);
+            self.add_sample(x, y, !is_upper, partner_distance, weight);
+        }
+
+        self.n_frames += 1;
+        self.last_time = frame.get_simulation_time();
+
+        Ok(())
+    }
+
+    /// Add the distances of headgroups from the membrane center to the grids using their Voronoi cells.
+    ///
+    /// In each leaflet, every bin of the grids is assigned to the headgroup laterally closest to the center
+    /// of the bin, i.e., to the Voronoi cell of this headgroup, and the distance of this headgroup is added
+    /// to the bin. Every bin thus obtains one sample per frame in each leaflet containing any headgroups.
+    #[allow(clippy::too_many_arguments)]
+    fn add_cells(
+        &mut self,
+        frame: &System,
+        membrane_center: &Vector3D,
+        positions: &[Vector3D],
+        upper: &[Option<bool>],
+        weight: f64,
+        distance_normal: Normal,
+        periods: (Option<f32>, Option<f32>),
+        scale: (f32, f32),
+    ) -> anyhow::Result<()> {
+        let simbox = frame.get_box().ok_or(SimBoxError::DoesNotExist)?;
+        let (span_x, span_y) = (self.upper_sum.span_x(), self.upper_sum.span_y());
+        let tile = self.upper_sum.tile_dim();
+        let (n_x, n_y) = (self.upper_sum.n_tiles_x(), self.upper_sum.n_tiles_y());
+        let bin =
+            |ix: usize, iy: usize| (span_x.0 + ix as f32 * tile.0, span_y.0 + iy as f32 * tile.1);
+
+        // squared lateral distance (in nm^2) between two points in map coordinates
+        let distance2 = |(ax, ay): (f32, f32), (bx, by): (f32, f32), minimum_image: bool| {
+            let mut dx = bx - ax;
+            let mut dy = by - ay;
+            if minimum_image {
+                if let Some(period) = periods.0 {
+                    dx -= period * (dx / period).round();
+                }
+                if let Some(period) = periods.1 {
+                    dy -= period * (dy / period).round();
+                }
+            }
+
+            (dx * scale.0).powi(2) + (dy * scale.1).powi(2)
+        };
+
+        let images = |value: f32, period: Option<f32>| match period {
+            Some(period) => vec![value, value - period, value + period],
+            None => vec![value],
+        };
+
+        for leaflet in [true, false] {
+            let heads = positions
+                .iter()
+                .zip(upper.iter())
+                .filter(|&(_, &is_upper)| is_upper == Some(leaflet))
+                .map(|(position, _)| {
+                    let (x, y) = self.normal.lateral(position);
+                    let distance = distance_normal.distance(position, membrane_center, simbox);
+                    ((x / scale.0, y / scale.1), distance as f64)
+                })
+                .collect::<Vec<_>>();
+
+            if heads.is_empty() {
+                continue;
+            }
+
+            // closest headgroup of each bin as (squared distance, distance from the membrane center)
+            let mut closest = vec![(f32::INFINITY, 0.0); n_x * n_y];
+            let window = (
+                VORONOI_RADIUS / scale.0 / tile.0,
+                VORONOI_RADIUS / scale.1 / tile.1,
+            );
+            for &(head, distance) in heads.iter() {
+                for x in images(head.0, periods.0) {
+                    for y in images(head.1, periods.1) {
+                        let center = ((x - span_x.0) / tile.0, (y - span_y.0) / tile.1);
+                        let min_x = (center.0 - window.0).ceil().max(0.0) as usize;
+                        let max_x = (center.0 + window.0).floor().min(n_x as f32 - 1.0);
+                        let min_y = (center.1 - window.1).ceil().max(0.0) as usize;
+                        let max_y = (center.1 + window.1).floor().min(n_y as f32 - 1.0);
+                        if max_x < 0.0 || max_y < 0.0 {
+                            continue;
+                        }
+
+                        for ix in min_x..=max_x as usize {
+                            for iy in min_y..=max_y as usize {
+                                let d2 = distance2((x, y), bin(ix, iy), false);
+                                let best = &mut closest[ix * n_y + iy];
+                                if d2 < best.0 {
+                                    *best = (d2, distance);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            for ix in 0..n_x {
+                for iy in 0..n_y {
+                    let (mut d2, mut distance) = closest[ix * n_y + iy];
+
+                    // bins far from all headgroups are assigned by searching through all headgroups
+                    if d2 > VORONOI_RADIUS * VORONOI_RADIUS {
+                        for &(head, head_distance) in heads.iter() {
+                            let candidate = distance2(head, bin(ix, iy), true);
+                            if candidate < d2 {
+                                (d2, distance) = (candidate, head_distance);
+                            }
+                        }
+                    }
+
+                    let (x, y) = bin(ix, iy);
+                    self.add_sample(x, y, leaflet, distance, weight);
+                }
+            }
+        }
+
+        self.n_frames += 1;
+        self.last_time = frame.get_simulation_time();
+
+        Ok(())
+    }
+
+    /// Add the distances of headgroups from the membrane center to the grids using kernel-smoothed leaflet surfaces.
+    ///
+    /// In each leaflet, the height of the surface at the center of every bin is the average distance of the headgroups
+    /// weighted by a Gaussian kernel of the lateral distance of each headgroup from the bin (of width `kde_bandwidth`,
+    /// truncated at [`KERNEL_CUTOFF`] widths), and this height is added to the bin. Every bin thus obtains one sample
+    /// per frame in each leaflet with any headgroups within the truncation distance.
+    #[allow(clippy::too_many_arguments)]
+    fn add_surfaces(
+        &mut self,
+        frame: &System,
+        membrane_center: &Vector3D,
+        positions: &[Vector3D],
+        upper: &[Option<bool>],
+        weight: f64,
+        distance_normal: Normal,
+        periods: (Option<f32>, Option<f32>),
+        scale: (f32, f32),
+    ) -> anyhow::Result<()> {
+        let simbox = frame.get_box().ok_or(SimBoxError::DoesNotExist)?;
+        let (span_x, span_y) = (self.upper_sum.span_x(), self.upper_sum.span_y());
+        let tile = self.upper_sum.tile_dim();
+        let (n_x, n_y) = (self.upper_sum.n_tiles_x(), self.upper_sum.n_tiles_y());
+        let bin =
+            |ix: usize, iy: usize| (span_x.0 + ix as f32 * tile.0, span_y.0 + iy as f32 * tile.1);
+
+        let sigma = self.kde_bandwidth;
+        let cutoff = KERNEL_CUTOFF * sigma;
+        let window = (cutoff / scale.0 / tile.0, cutoff / scale.1 / tile.1);
+
+        let images = |value: f32, period: Option<f32>| match period {
+            Some(period) => vec![value, value - period, value + period],
+            None => vec![value],
+        };
+
+        for leaflet in [true, false] {
+            // kernel-weighted sums of the distances and of the kernel weights in every bin
+            let mut surface = vec![(0.0, 0.0); n_x * n_y];
+            for (position, _) in positions
+                .iter()
+                .zip(upper.iter())
+                .filter(|&(_, &is_upper)| is_upper == Some(leaflet))
+            {
+                let (x, y) = self.normal.lateral(position);
+                let (x, y) = (x / scale.0, y / scale.1);
+                let distance = distance_normal.distance(position, membrane_center, simbox) as f64;
+
+                for x in images(x, periods.0) {
+                    for y in images(y, periods.1) {
+                        let center = ((x - span_x.0) / tile.0, (y - span_y.0) / tile.1);
+                        let min_x = (center.0 - window.0).ceil().max(0.0) as usize;
+                        let max_x = (center.0 + window.0).floor().min(n_x as f32 - 1.0);
+                        let min_y = (center.1 - window.1).ceil().max(0.0) as usize;
+                        let max_y = (center.1 + window.1).floor().min(n_y as f32 - 1.0);
+                        if max_x < 0.0 || max_y < 0.0 {
+                            continue;
+                        }
+
+                        for ix in min_x..=max_x as usize {
+                            for iy in min_y..=max_y as usize {
+                                let (bin_x, bin_y) = bin(ix, iy);
+                                let d2 = ((bin_x - x) * scale.0).powi(2)
+                                    + ((bin_y - y) * scale.1).powi(2);
+                                if d2 > cutoff * cutoff {
+                                    continue;
+                                }
+
+                                let kernel = (-d2 / (2.0 * sigma * sigma)).exp() as f64;
+                                let (sum, total) = &mut surface[ix * n_y + iy];
+                                *sum += kernel * distance;
+                                *total += kernel;
+                            }
+                        }
+                    }
+                }
+            }
+
+            for ix in 0..n_x {
+                for iy in 0..n_y {
+                    let (sum, total) = surface[ix * n_y + iy];
+                    if total > 0.0 {
+                        let (x, y) = bin(ix, iy);
+                        self.add_sample(x, y, leaflet, sum / total, weight);
+                    }
+                }
+            }
+        }
+
+        self.n_frames += 1;
+        self.last_time = frame.get_simulation_time();
+
+        Ok(())
+    }
+
+    /// Add a weighted value to the bin of the upper or the lower grids at the specified map coordinates.
+    /// Values outside the grids are ignored.
+    fn add_sample(&mut self, x: f32, y: f32, is_upper: bool, value: f64, weight: f64) {
+        self.add_weighted(x, y, is_upper, value, weight);
+        self.add_count(x, y, is_upper);
+    }
+
+    /// Count a sample in the bin of the upper or the lower grids at the specified map coordinates.
+    fn add_count(&mut self, x: f32, y: f32, is_upper: bool) {
+        let count = if is_upper {
+            &mut self.upper_count
+        } else {
+            &mut self.lower_count
+        };
+
+        if let Some(count) = count.get_mut_at(x, y) {
+            *count += 1;
+        }
+    }
+
+    /// Add a weighted value to the bin of the upper or the lower grids at the specified map coordinates
+    /// without counting it as a sample.
+    fn add_weighted(&mut self, x: f32, y: f32, is_upper: bool, value: f64, weight: f64) {
+        let (sum, total, squares) = if is_upper {
+            (
+                &mut self.upper_sum,
+                &mut self.upper_weight,
+                &mut self.upper_squares,
+            )
+        } else {
+            (
+                &mut self.lower_sum,
+                &mut self.lower_weight,
+                &mut self.lower_squares,
+            )
+        };
+
+        if let Some(sum) = sum.get_mut_at(x, y) {
+            *sum += weight * value;
+        }
+
+        if let Some(total) = total.get_mut_at(x, y) {
+            *total += weight;
+        }
+
+        if let Some(squares) = squares.get_mut_at(x, y) {
+            *squares += weight * value * value;
+        }
+    }
+
+    /// Distribute a weighted value over the bins of the upper or the lower grids around the specified map coordinates
+    /// using a normalized Gaussian kernel of width `sigma` truncated at [`KERNEL_CUTOFF`] widths.
+    /// Bins beyond the edges of the grids are folded back using the periods of the coordinates or ignored.
+    /// The value is counted as a single sample of the bin at the specified map coordinates.
+    #[allow(clippy::too_many_arguments)]
+    fn deposit_sample(
+        &mut self,
+        x: f32,
+        y: f32,
+        is_upper: bool,
+        value: f64,
+        weight: f64,
+        sigma: f32,
+        periods: (Option<f32>, Option<f32>),
+    ) {
+        let Some((center_x, center_y)) = self.upper_sum.get_tile(x, y) else {
+            return;
+        };
+
+        let tile = self.upper_sum.tile_dim();
+        let reach_x = (KERNEL_CUTOFF * sigma / tile.0).ceil() as isize;
+        let reach_y = (KERNEL_CUTOFF * sigma / tile.1).ceil() as isize;
+        let cutoff = KERNEL_CUTOFF * sigma;
+
+        let mut shares = Vec::new();
+        for i in -reach_x..=reach_x {
+            for j in -reach_y..=reach_y {
+                let (bin_x, bin_y) = (center_x + i as f32 * tile.0, center_y + j as f32 * tile.1);
+                let (dx, dy) = (bin_x - x, bin_y - y);
+                let squared = dx * dx + dy * dy;
+                if squared <= cutoff * cutoff {
+                    shares.push((
+                        bin_x,
+                        bin_y,
+                        (-squared / (2.0 * sigma * sigma)).exp() as f64,
+                    ));
+                }
+            }
+        }
+
+        // kernels narrower than the bins fall back to adding the value into a single bin
+        let total = shares.iter().map(|&(_, _, share)| share).sum::<f64>();
+        if total <= 0.0 {
+            self.add_sample(x, y, is_upper, value, weight);
+            return;
+        }
+
+        for (bin_x, bin_y, share) in shares {
+            let (bin_x, bin_y) = self.wrap_into_grid(bin_x, bin_y, periods);
+            self.add_weighted(bin_x, bin_y, is_upper, value, weight * share / total);
+        }
+
+        self.add_count(x, y, is_upper);
+    }
+
+    /// Labels of the axes of the thickness map.
+    pub(crate) fn axis_labels(&self) -> (String, String) {
+        if self.origin.is_some() {
+            return (
+                "radial distance [nm]".to_owned(),
+                "azimuthal angle [deg]".to_owned(),
+            );
+        }
+
+        if self.scaled {
+            let (axis_x, axis_y) = self.normal.plane_axes();
+            return (
+                format!("scaled {}-coordinate", axis_x),
+                format!("scaled {}-coordinate", axis_y),
+            );
+        }
+
+        self.geometry.axis_labels(&self.normal)
+    }
+
+    /// Fold the map coordinates lying outside the grids back into the grids using the periods of the coordinates.
+    /// Coordinates inside the grids, and coordinates that can not be folded into the grids, are returned unchanged.
+    fn wrap_into_grid(&self, x: f32, y: f32, periods: (Option<f32>, Option<f32>)) -> (f32, f32) {
+        if self.upper_sum.is_inside(x, y) {
+            return (x, y);
+        }
+
+        let images = |value: f32, period: Option<f32>| match period {
+            Some(period) => [value, value - period, value + period],
+            None => [value; 3],
+        };
+
+        for x_image in images(x, periods.0) {
+            for y_image in images(y, periods.1) {
+                if self.upper_sum.is_inside(x_image, y_image) {
+                    return (x_image, y_image);
+                }
+            }
+        }
+
+        (x, y)
+    }
+
+    /// Calculate membrane thickness in each grid bin as `(x, y, thickness)`.
+    /// Thickness is NaN for bins with fewer than `nan_limit` samples in any of the leaflets.
+    pub(crate) fn thickness(&self, nan_limit: usize) -> impl Iterator<Item = (f32, f32, f64)> + '_ {
+        self.leaflet_heights(nan_limit)
+            .map(|(x, y, upper, lower)| (x, y, upper - lower))
+    }
+
+    /// Calculate the average distance of headgroups from the membrane center in each grid bin
+    /// as `(x, y, upper, lower)`. Distances are NaN for bins with fewer than `nan_limit` samples in the leaflet
+    /// and for bins masked by the protein footprint.
+    pub(crate) fn leaflet_heights(
+        &self,
+        nan_limit: usize,
+    ) -> impl Iterator<Item = (f32, f32, f64, f64)> + '_ {
+        let average = move |sum: f64, count: usize, weight: f64| {
+            if count < nan_limit {
+                f64::NAN
+            } else {
+                sum / weight
+            }
+        };
+        let masked = self.footprint.as_ref().map(Footprint::masked);
+
+        self.upper_sum
+            .extract_raw()
+            .zip(self.upper_count.extract_raw())
+            .zip(self.lower_sum.extract_raw())
+            .zip(self.lower_count.extract_raw())
+            .zip(self.upper_weight.extract_raw())
+            .zip(self.lower_weight.extract_raw())
+            .enumerate()
+            .map(
+                move |(
+                    bin,
+                    (
+                        ((((upper_sum, upper_count), lower_sum), lower_count), upper_weight),
+                        lower_weight,
+                    ),
+                )| {
+                    if masked.as_ref().is_some_and(|masked| masked[bin]) {
+                        return (upper_sum.0, upper_sum.1, f64::NAN, f64::NAN);
+                    }
+
+                    (
+                        upper_sum.0,
+                        upper_sum.1,
+                        average(*upper_sum.2, *upper_count.2, *upper_weight.2),
+                        average(*lower_sum.2, *lower_count.2, *lower_weight.2),
+                    )
+                },
+            )
+    }
+
+    /// Get the weighted sums of the samples and the weights of both leaflets in each grid bin
+    /// in the order of the grid maps.
+    pub(crate) fn leaflet_totals(&self) -> Vec<LeafletTotals> {
+        self.upper_sum
+            .extract_raw()
+            .zip(self.upper_weight.extract_raw())
+            .zip(self.lower_sum.extract_raw())
+            .zip(self.lower_weight.extract_raw())
+            .map(
+                |(
+                    (((_, _, upper_sum), (_, _, upper_weight)), (_, _, lower_sum)),
+                    (_, _, lower_weight),
+                )| {
+                    LeafletTotals {
+                        upper_sum: *upper_sum,
+                        upper_weight: *upper_weight,
+                        lower_sum: *lower_sum,
+                        lower_weight: *lower_weight,
+                    }
+                },
+            )
+            .collect()
+    }
+
+    /// Calculate the area of each grid bin in the map coordinates in the order of the grid maps.
+    /// Bins at the edges of the grids only cover the part of their area inside the span of the grids.
+    pub(crate) fn bin_areas(&self) -> impl Iterator<Item = f64> + '_ {
+        let tile = self.upper_count.tile_dim();
+        let (span_x, span_y) = (self.upper_count.span_x(), self.upper_count.span_y());
+        let width = |center: f32, span: (f32, f32), size: f32| {
+            ((center + size / 2.0).min(span.1) - (center - size / 2.0).max(span.0)).max(0.0) as f64
+        };
+
+        self.upper_count
+            .extract_raw()
+            .map(move |(x, y, _)| width(x, span_x, tile.0) * width(y, span_y, tile.1))
+    }
+
+    /// Calculate the number density of headgroups (number per nm^2 per frame) in each grid bin
+    /// as `(x, y, upper, lower)`.
+    pub(crate) fn leaflet_densities(&self) -> impl Iterator<Item = (f32, f32, f64, f64)> + '_ {
+        self.upper_count
+            .extract_raw()
+            .zip(self.lower_count.extract_raw())
+            .zip(self.bin_areas())
+            .map(move |(((x, y, upper), (_, _, lower)), area)| {
+                let norm = self.n_frames as f64 * area;
+                (x, y, *upper as f64 / norm, *lower as f64 / norm)
+            })
+    }
+
+    /// Calculate the asymmetry of the number of samples between the leaflets, (upper - lower) / (upper + lower),
+    /// in each grid bin. The asymmetry is NaN for bins without any samples.
+    pub(crate) fn count_asymmetry(&self) -> impl Iterator<Item = (f32, f32, f64)> + '_ {
+        self.upper_count
+            .extract_raw()
+            .zip(self.lower_count.extract_raw())
+            .map(|((x, y, &upper), (_, _, &lower))| {
+                let total = upper + lower;
+                if total == 0 {
+                    (x, y, f64::NAN)
+                } else {
+                    (x, y, (upper as f64 - lower as f64) / total as f64)
+                }
+            })
+    }
+
+    /// Calculate the standard deviation of the samples of each leaflet in each grid bin as `(x, y, upper, lower)`.
+    /// For headgroups, this is the roughness of the leaflet, i.e., the root-mean-square fluctuation of the distances
+    /// of headgroups from the membrane center around their average.
+    /// Deviations are NaN for bins with fewer than `nan_limit` samples in the leaflet.
+    pub(crate) fn leaflet_deviations(
+        &self,
+        nan_limit: usize,
+    ) -> impl Iterator<Item = (f32, f32, f64, f64)> + '_ {
+        let mean_square = move |squares: f64, count: usize, weight: f64| {
+            if count < nan_limit {
+                f64::NAN
+            } else {
+                squares / weight
+            }
+        };
+        let rms = |average: f64, squared: f64| {
+            (squared - average * average)
+                .clamp(0.0, f64::INFINITY)
+                .sqrt()
+        };
+
+        self.leaflet_heights(nan_limit)
+            .zip(self.upper_squares.extract_raw())
+            .zip(self.upper_count.extract_raw())
+            .zip(self.upper_weight.extract_raw())
+            .zip(self.lower_squares.extract_raw())
+            .zip(self.lower_count.extract_raw())
+            .zip(self.lower_weight.extract_raw())
+            .map(
+                move |(
+                    (
+                        ((((heights, upper_squares), upper_count), upper_weight), lower_squares),
+                        lower_count,
+                    ),
+                    lower_weight,
+                )| {
+                    let (x, y, upper, lower) = heights;
+                    let upper_squared =
+                        mean_square(*upper_squares.2, *upper_count.2, *upper_weight.2);
+                    let lower_squared =
+                        mean_square(*lower_squares.2, *lower_count.2, *lower_weight.2);
+                    (x, y, rms(upper, upper_squared), rms(lower, lower_squared))
+                },
+            )
+    }
+
+    /// Calculate the roughness of the leaflets over all samples of the headgroups as `(upper, lower)`.
+    /// The fluctuations of the headgroups are measured relative to the average of their grid bin.
+    pub(crate) fn global_roughness(&self) -> (f64, f64) {
+        let rms = |sum: &SumMap, weight: &SumMap, squared: &SumMap| {
+            let (variance, total) = sum
+                .extract_raw()
+                .zip(weight.extract_raw())
+                .zip(squared.extract_raw())
+                .filter(|((_, (_, _, weight)), _)| **weight > 0.0)
+                .fold(
+                    (0.0, 0.0),
+                    |(variance, total), (((_, _, sum), (_, _, weight)), (_, _, squared))| {
+                        (variance + squared - sum * sum / weight, total + weight)
+                    },
+                );
+
+            (variance / total).clamp(0.0, f64::INFINITY).sqrt()
+        };
+
+        (
+            rms(&self.upper_sum, &self.upper_weight, &self.upper_squares),
+            rms(&self.lower_sum, &self.lower_weight, &self.lower_squares),
+        )
+    }
+
+    /// Calculate the standard deviation of the membrane thickness in each grid bin as `(x, y, deviation)`.
+    /// The samples of both leaflets are independent, except for the Luzzati thickness, where each sample
+    /// contributes half of the same thickness to both leaflets.
+    /// Deviation is NaN for bins with fewer than `nan_limit` samples in any of the leaflets.
+    pub(crate) fn thickness_deviation(
+        &self,
+        nan_limit: usize,
+    ) -> impl Iterator<Item = (f32, f32, f64)> + '_ {
+        self.leaflet_deviations(nan_limit)
+            .map(|(x, y, upper, lower)| {
+                let deviation = match self.method {
+                    ThicknessMethod::Luzzati => upper + lower,
+                    _ => upper.hypot(lower),
+                };
+
+                (x, y, deviation)
+            })
+    }
+
+    /// Calculate the mapped quantity in each grid bin as `(x, y, value)`. This is the interdigitation of the leaflets
+    /// for grids accumulating distributions and the membrane thickness otherwise.
+    /// Values are NaN for bins with fewer than `nan_limit` samples in any of the leaflets.
+    pub(crate) fn values(&self, nan_limit: usize) -> Vec<(f32, f32, f64)> {
+        match &self.distributions {
+            Some(distributions) => self
+                .thickness(nan_limit)
+                .zip(distributions.overlap())
+                .map(|((x, y, thickness), overlap)| {
+                    (
+                        x,
+                        y,
+                        if thickness.is_nan() {
+                            f64::NAN
+                        } else {
+                            overlap
+                        },
+                    )
+                })
+                .collect(),
+            None => self.thickness(nan_limit).collect(),
+        }
+    }
+
+    /// Calculate the average membrane thickness over all bins with defined thickness.
+    /// Returns the average thickness and the number of bins it was calculated from.
+    pub(crate) fn average_thickness(&self, nan_limit: usize) -> (f64, usize) {
+        let (sum, n_bins) = self
+            .thickness(nan_limit)
+            .filter(|(_, _, thickness)| thickness.is_finite())
+            .fold((0.0, 0), |(sum, n), (_, _, thickness)| {
+                (sum + thickness, n + 1)
+            });
+
+        (sum / n_bins as f64, n_bins)
+    }
+
+    /// Calculate the average distance of headgroups from the membrane center in each leaflet
+    /// over the whole map as `(upper, lower)`. For vesicles, these are the radii of the leaflets.
+    pub(crate) fn leaflet_distances(&self) -> (f64, f64) {
+        let total = |map: &SumMap| map.extract_raw().map(|(_, _, value)| *value).sum::<f64>();
+
+        (
+            total(&self.upper_sum) / total(&self.upper_weight),
+            total(&self.lower_sum) / total(&self.lower_weight),
+        )
+    }
+
+    /// Remove all accumulated data from the grids.
+    pub(crate) fn clear(&mut self) {
+        self.upper_sum.clear();
+        self.upper_count.clear();
+        self.upper_weight.clear();
+        self.upper_squares.clear();
+        self.lower_sum.clear();
+        self.lower_count.clear();
+        self.lower_weight.clear();
+        self.lower_squares.clear();
+
+        self.n_frames = 0;
+        self.last_time = 0.0;
+
+        for grids in self.secondary.iter_mut() {
+            grids.clear();
+        }
+
+        if let Some(composition) = self.composition.as_mut() {
+            composition.clear();
+        }
+
+        if let Some(environment) = self.environment.as_mut() {
+            environment.clear();
+        }
+
+        if let Some(molecules) = self.molecules.as_mut() {
+            molecules.clear();
+        }
+
+        if let Some(distributions) = self.distributions.as_mut() {
+            distributions.clear();
+        }
+
+        if let Some(spectrum) = self.spectrum.as_mut() {
+            spectrum.clear();
+        }
+
+        for profile in self.profiles.iter_mut() {
+            profile.clear();
+        }
+
+        if let Some(extremes) = self.extremes.as_mut() {
+            extremes.clear();
+        }
+
+        if let Some(series) = self.series.as_mut() {
+            series.clear();
+        }
+
+        if let Some(bin_series) = self.bin_series.as_mut() {
+            bin_series.clear();
+        }
+
+        if let Some(footprint) = self.footprint.as_mut() {
+            footprint.clear();
+        }
+
+        if let Some(tilt) = self.tilt.as_mut() {
+            tilt.clear();
+        }
+
+        if let Some(residues) = self.residues.as_mut() {
+            residues.clear();
+        }
+    }
+
+    /// Add the data accumulated in other grids into these grids.
+    /// Both grids must span the same area with the same bin size.
+    pub(crate) fn merge(&mut self, other: &ThicknessGrids) {
+        add_map(&mut self.upper_sum, &other.upper_sum);
+        add_map(&mut self.upper_count, &other.upper_count);
+        add_map(&mut self.upper_weight, &other.upper_weight);
+        add_map(&mut self.upper_squares, &other.upper_squares);
+        add_map(&mut self.lower_sum, &other.lower_sum);
+        add_map(&mut self.lower_count, &other.lower_count);
+        add_map(&mut self.lower_weight, &other.lower_weight);
+        add_map(&mut self.lower_squares, &other.lower_squares);
+
+        self.n_frames += other.n_frames;
+        self.last_time = self.last_time.max(other.last_time);
+
+        for (grids, other) in self.secondary.iter_mut().zip(other.secondary.iter()) {
+            grids.merge(other);
+        }
+
+        if let (Some(composition), Some(other)) =
+            (self.composition.as_mut(), other.composition.as_ref())
+        {
+            composition.merge(other);
+        }
+
+        if let (Some(environment), Some(other)) =
+            (self.environment.as_mut(), other.environment.as_ref())
+        {
+            environment.merge(other);
+        }
+
+        if let (Some(molecules), Some(other)) = (self.molecules.as_mut(), other.molecules.as_ref())
+        {
+            molecules.merge(other);
+        }
+
+        if let (Some(distributions), Some(other)) =
+            (self.distributions.as_mut(), other.distributions.as_ref())
+        {
+            distributions.merge(other);
+        }
+
+        if let (Some(spectrum), Some(other)) = (self.spectrum.as_mut(), other.spectrum.as_ref()) {
+            spectrum.merge(other);
+        }
+
+        for (profile, other) in self.profiles.iter_mut().zip(other.profiles.iter()) {
+            profile.merge(other);
+        }
+
+        if let (Some(extremes), Some(other)) = (self.extremes.as_mut(), other.extremes.as_ref()) {
+            extremes.merge(other);
+        }
+
+        if let (Some(series), Some(other)) = (self.series.as_mut(), other.series.as_ref()) {
+            series.merge(other);
+        }
+
+        if let (Some(bin_series), Some(other)) =
+            (self.bin_series.as_mut(), other.bin_series.as_ref())
+        {
+            bin_series.merge(other);
+        }
+
+        if let (Some(footprint), Some(other)) = (self.footprint.as_mut(), other.footprint.as_ref())
+        {
+            footprint.merge(other);
+        }
+
+        if let (Some(tilt), Some(other)) = (self.tilt.as_mut(), other.tilt.as_ref()) {
+            tilt.merge(other);
+        }
+
+        if let (Some(residues), Some(other)) = (self.residues.as_mut(), other.residues.as_ref()) {
+            residues.merge(other);
+        }
+    }
+}
+
+/// Calculate the center of the membrane, i.e., the center of geometry of all lipid atoms
+/// or their center of mass if `center_of_mass` is set.
+/// If the user selected specific atoms defining the membrane center, only these atoms are used.
+pub(crate) fn membrane_center(frame: &System, center_of_mass: bool) -> anyhow::Result<Vector3D> {
+    let group = if frame.group_exists(CENTER_GROUP) {
+        CENTER_GROUP
+    } else {
+        LIPIDS_GROUP
+    };
+
+    if center_of_mass {
+        Ok(frame.group_get_com(group)?)
+    } else {
+        Ok(frame.group_get_center(group)?)
+    }
+}
+
+/// Calculate the positions of all headgroups, in the order of the headgroups in the system.
+///
+/// If each headgroup is formed by several atoms, the position of a headgroup is the center of mass
+/// of the headgroup atoms of the lipid (identified by its residue number). If the masses of the atoms
+/// are not known, the center of geometry is used instead.
+pub(crate) fn head_positions(frame: &System) -> anyhow::Result<Vec<Vector3D>> {
+    let position_of = |atom: &Atom| {
+        atom.get_position()
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Atom {} has no position.", atom.get_index() + 1))
+    };
+
+    // the group of headgroup atoms only exists if the headgroups are formed by several atoms
+    if !frame.group_exists(HEAD_ATOMS_GROUP) {
+        return atom_positions(frame, HEADS_GROUP);
+    }
+
+    let simbox = frame.get_box().ok_or(SimBoxError::DoesNotExist)?;
+    let center = |first: &Vector3D, sum: &Vector3D, total: f32| {
+        Vector3D::new(
+            first.x + sum.x / total,
+            first.y + sum.y / total,
+            first.z + sum.z / total,
+        )
+    };
+
+    let mut positions = Vec::new();
+    let mut current: Option<(usize, Vector3D, Vector3D, f32)> = None;
+    for atom in frame.group_iter(HEAD_ATOMS_GROUP)? {
+        let position = position_of(atom)?;
+        let mass = atom.get_mass().unwrap_or(1.0);
+
+        match &mut current {
+            Some((residue, first, sum, total)) if *residue == atom.get_residue_number() => {
+                let vector = first.vector_to(&position, simbox);
+                *sum = Vector3D::new(
+                    sum.x + mass * vector.x,
+                    sum.y + mass * vector.y,
+                    sum.z + mass * vector.z,
+                );
+                *total += mass;
+            }
+            _ => {
+                if let Some((_, first, sum, total)) = current.take() {
+                    positions.push(center(&first, &sum, total));
+                }
+
+                current = Some((
+                    atom.get_residue_number(),
+                    position,
+                    Vector3D::default(),
+                    mass,
+                ));
+            }
+        }
+    }
+
+    if let Some((_, first, sum, total)) = current {
+        positions.push(center(&first, &sum, total));
+    }
+
+    Ok(positions)
+}
+
+/// Get the positions of all atoms of the group.
+pub(crate) fn atom_positions(frame: &System, group: &str) -> anyhow::Result<Vec<Vector3D>> {
+    frame
+        .group_iter(group)?
+        .map(|atom| {
+            atom.get_position()
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("Atom {} has no position.", atom.get_index() + 1))
+        })
+        .collect()
+}
+
+/// For each atom of the group, find the index of the headgroup of the same lipid (identified by its residue number).
+pub(crate) fn lipid_heads(
+    system: &System,
+    group: &str,
+    query: &str,
+) -> anyhow::Result<Vec<Option<usize>>> {
+    let heads = system
+        .group_iter(HEADS_GROUP)?
+        .enumerate()
+        .map(|(i, atom)| (atom.get_residue_number(), i))
+        .collect::<HashMap<_, _>>();
+
+    let atoms = system
+        .group_iter(group)?
+        .map(|atom| heads.get(&atom.get_residue_number()).copied())
+        .collect::<Vec<_>>();
+
+    if atoms.iter().all(Option::is_none) {
+        anyhow::bail!(
+            "None of the atoms selected by '{}' belongs to a lipid with a headgroup.",
+            query
+        );
+    }
+
+    Ok(atoms)
+}
+
+/// Create a map of the same grid bins as the `template` map with each bin holding its index
+/// in the order of the grid maps.
+pub(crate) fn tile_indices(template: &CountMap) -> CountMap {
+    let mut indices = template.clone();
+    let tiles = template
+        .extract_raw()
+        .map(|(x, y, _)| (x, y))
+        .collect::<Vec<_>>();
+    for (i, (x, y)) in tiles.into_iter().enumerate() {
+        if let Some(index) = indices.get_mut_at(x, y) {
+            *index = i;
+        }
+    }
+
+    indices
+}
+
+/// Add values of the source map to the corresponding values of the target map.
+fn add_map<T>(target: &mut GridMap<T, T, fn(&T) -> T>, source: &GridMap<T, T, fn(&T) -> T>)
+where
+    T: Default + Clone + Copy + std::fmt::Debug + Display + AddAssign,
+{
+    for (x, y, value) in source.extract_raw() {
+        *target.get_mut_at(x, y).expect(
+            "FATAL MEMTHICK ERROR | grids::add_map | Grid maps should have the same dimensions.",
+        ) += *value;
+    }
+}
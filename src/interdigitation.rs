@@ -0,0 +1,96 @@
+//! Interdigitation of the membrane leaflets calculated from the distributions of lipid tail ends.
+
+use crate::grids::{CountMap, tile_indices};
+
+/// Distances from the membrane center (in nm) covered by the distributions of the tail ends.
+const RANGE: f32 = 3.0;
+/// Width of the histogram bins of the distributions (in nm).
+const BIN: f32 = 0.1;
+
+/// Distributions of the distances of the tail ends from the membrane center along the normal
+/// in every grid bin of both leaflets.
+#[derive(Debug, Clone)]
+pub(crate) struct Distributions {
+    /// Index of each grid bin in the order of the grid maps.
+    indices: CountMap,
+    /// Weighted histograms of the upper leaflet for each grid bin.
+    upper: Vec<Vec<f64>>,
+    /// Weighted histograms of the lower leaflet for each grid bin.
+    lower: Vec<Vec<f64>>,
+}
+
+impl Distributions {
+    /// Prepare empty distributions for the grid bins of the `template` map.
+    pub(crate) fn new(template: &CountMap) -> Distributions {
+        let n_histogram = (2.0 * RANGE / BIN).round() as usize;
+        let empty = vec![vec![0.0; n_histogram]; template.n_tiles()];
+
+        Distributions {
+            indices: tile_indices(template),
+            upper: empty.clone(),
+            lower: empty,
+        }
+    }
+
+    /// Add the distance of a tail end from the membrane center to the distribution of the leaflet in the grid bin
+    /// at the map coordinates. Distances outside the covered range are ignored.
+    pub(crate) fn add(&mut self, x: f32, y: f32, is_upper: bool, distance: f32, weight: f64) {
+        let Some(&bin) = self.indices.get_at(x, y) else {
+            return;
+        };
+
+        let index = ((distance + RANGE) / BIN).floor();
+        let histograms = if is_upper {
+            &mut self.upper
+        } else {
+            &mut self.lower
+        };
+        let histogram = &mut histograms[bin];
+        if index >= 0.0 && (index as usize) < histogram.len() {
+            histogram[index as usize] += weight;
+        }
+    }
+
+    /// Remove all accumulated distances.
+    pub(crate) fn clear(&mut self) {
+        for histogram in self.upper.iter_mut().chain(self.lower.iter_mut()) {
+            histogram.fill(0.0);
+        }
+    }
+
+    /// Add the distances accumulated in the other distributions into these distributions.
+    pub(crate) fn merge(&mut self, other: &Distributions) {
+        for (histogram, other) in self
+            .upper
+            .iter_mut()
+            .chain(self.lower.iter_mut())
+            .zip(other.upper.iter().chain(other.lower.iter()))
+        {
+            for (value, other) in histogram.iter_mut().zip(other.iter()) {
+                *value += other;
+            }
+        }
+    }
+
+    /// Calculate the overlap of the normalized distributions of both leaflets in each grid bin
+    /// in the order of the grid maps. The overlap is 0 for fully separated leaflets and 1 for identical distributions.
+    /// Overlap is NaN for bins without any tail ends in one of the leaflets.
+    pub(crate) fn overlap(&self) -> impl Iterator<Item = f64> + '_ {
+        self.upper
+            .iter()
+            .zip(self.lower.iter())
+            .map(|(upper, lower)| {
+                let (upper_total, lower_total) =
+                    (upper.iter().sum::<f64>(), lower.iter().sum::<f64>());
+                if upper_total <= 0.0 || lower_total <= 0.0 {
+                    return f64::NAN;
+                }
+
+                upper
+                    .iter()
+                    .zip(lower.iter())
+                    .map(|(u, l)| (u / upper_total).min(l / lower_total))
+                    .sum()
+            })
+    }
+}
@@ -0,0 +1,125 @@
+//! Filling of undefined bins of the maps by interpolation from the neighboring bins.
+
+/// Fill the undefined bins of the map given as `(x, y, value)` for every bin of a grid with `n_x` x `n_y` bins
+/// of size `tile` in row-major order. The value of each undefined bin is interpolated from the defined bins
+/// within `radius` (in the units of the map coordinates) using inverse-distance weighting with weights 1/d^2.
+/// Bins without any defined bins within the radius remain undefined.
+///
+/// Returns the filled map and, for each bin, whether its value was interpolated.
+pub(crate) fn fill_undefined(
+    values: &[(f32, f32, f64)],
+    n_x: usize,
+    n_y: usize,
+    tile: (f32, f32),
+    radius: f32,
+) -> (Vec<(f32, f32, f64)>, Vec<bool>) {
+    let reach_x = (radius / tile.0).floor() as isize;
+    let reach_y = (radius / tile.1).floor() as isize;
+
+    values
+        .iter()
+        .enumerate()
+        .map(|(index, &(x, y, value))| {
+            if value.is_finite() {
+                return ((x, y, value), false);
+            }
+
+            let (i, j) = ((index / n_y) as isize, (index % n_y) as isize);
+            let (mut sum, mut total) = (0.0, 0.0);
+            for di in -reach_x..=reach_x {
+                for dj in -reach_y..=reach_y {
+                    let (ni, nj) = (i + di, j + dj);
+                    if ni < 0 || nj < 0 || ni >= n_x as isize || nj >= n_y as isize {
+                        continue;
+                    }
+
+                    let (dx, dy) = (di as f32 * tile.0, dj as f32 * tile.1);
+                    let squared = dx * dx + dy * dy;
+                    let neighbor = values[ni as usize * n_y + nj as usize].2;
+                    if squared <= radius * radius && neighbor.is_finite() {
+                        let weight = 1.0 / squared as f64;
+                        sum += weight * neighbor;
+                        total += weight;
+                    }
+                }
+            }
+
+            if total > 0.0 {
+                ((x, y, sum / total), true)
+            } else {
+                ((x, y, value), false)
+            }
+        })
+        .unzip()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Map of `n_x` x `n_y` bins of 1 x 1 with the specified values of the bins `(i, j)`.
+    fn map(n_x: usize, n_y: usize, value: impl Fn(usize, usize) -> f64) -> Vec<(f32, f32, f64)> {
+        (0..n_x * n_y)
+            .map(|index| {
+                let (i, j) = (index / n_y, index % n_y);
+                (i as f32, j as f32, value(i, j))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn defined_map_unchanged() {
+        let values = map(4, 3, |i, j| (i * 3 + j) as f64);
+        let (filled, interpolated) = fill_undefined(&values, 4, 3, (1.0, 1.0), 2.0);
+        assert_eq!(filled, values);
+        assert!(interpolated.iter().all(|&x| !x));
+    }
+
+    #[test]
+    fn constant_map_filled() {
+        let mut values = map(3, 3, |_, _| 2.5);
+        values[4].2 = f64::NAN;
+
+        let (filled, interpolated) = fill_undefined(&values, 3, 3, (1.0, 1.0), 1.5);
+        assert!((filled[4].2 - 2.5).abs() < 1e-12);
+        assert_eq!(interpolated, (0..9).map(|i| i == 4).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn inverse_distance_weighting() {
+        let values = vec![
+            (0.0, 0.0, 1.0),
+            (1.0, 0.0, f64::NAN),
+            (2.0, 0.0, f64::NAN),
+            (3.0, 0.0, 7.0),
+        ];
+        let (filled, _) = fill_undefined(&values, 4, 1, (1.0, 1.0), 2.0);
+
+        // weights 1 and 1/4 for the neighbors at distances 1 and 2
+        assert!((filled[1].2 - (1.0 + 7.0 / 4.0) / 1.25).abs() < 1e-12);
+        assert!((filled[2].2 - (1.0 / 4.0 + 7.0) / 1.25).abs() < 1e-12);
+    }
+
+    #[test]
+    fn out_of_radius_remains_undefined() {
+        let values = map(5, 1, |i, _| if i == 0 { 1.0 } else { f64::NAN });
+        let (filled, interpolated) = fill_undefined(&values, 5, 1, (1.0, 1.0), 1.0);
+
+        // undefined neighbors are not used for the interpolation, even if they are filled
+        assert_eq!(filled[1].2, 1.0);
+        assert!(filled[2..].iter().all(|&(_, _, value)| value.is_nan()));
+        assert_eq!(interpolated, vec![false, true, false, false, false]);
+    }
+
+    #[test]
+    fn edges_not_wrapped() {
+        let values = vec![
+            (0.0, 0.0, f64::NAN),
+            (1.0, 0.0, 5.0),
+            (2.0, 0.0, 7.0),
+            (3.0, 0.0, 100.0),
+        ];
+        let (filled, _) = fill_undefined(&values, 4, 1, (1.0, 1.0), 1.0);
+        assert_eq!(filled[0].2, 5.0);
+    }
+}
@@ -0,0 +1,452 @@
+//! Assignment of lipid headgroups to membrane leaflets.
+
+use std::sync::Mutex;
+
+use clap::ValueEnum;
+use groan_rs::{errors::SimBoxError, prelude::*};
+
+use crate::{
+    Args, HEADS_GROUP, LIPIDS_GROUP, LOWER_GROUP, TAILS_GROUP, UPPER_GROUP, buckle::BuckleProfile,
+    geometry::Geometry, grids, normal::Normal,
+};
+
+/// Method used to assign headgroups to membrane leaflets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum LeafletMethod {
+    /// Compare the position of each headgroup along the membrane normal with the center of the whole membrane.
+    Global,
+    /// Compare the position of each headgroup along the membrane normal with the center of the lipids around it.
+    Local,
+    /// Use the direction of the vector from the headgroup to the tail end of each lipid.
+    Orientation,
+    /// Identify the leaflets as the two largest clusters of headgroups.
+    Clustering,
+}
+
+/// Assigns headgroups to membrane leaflets.
+#[derive(Debug)]
+pub(crate) struct LeafletClassifier {
+    method: LeafletMethod,
+    normal: Normal,
+    geometry: Geometry,
+    /// Number of Fourier modes describing the profile of buckled membranes.
+    buckle_modes: usize,
+    /// Check in every frame that the membrane normal is still oriented along the detected axis.
+    check_normal: bool,
+    /// The membrane center is the center of mass of the lipids.
+    center_of_mass: bool,
+    /// Lateral radius of the cylinder used to calculate the local membrane center (in nm).
+    radius: f32,
+    /// Maximal distance between two headgroups of the same cluster (in nm).
+    cutoff: f32,
+    /// Number of frames for which an assignment is used before the headgroups are assigned again.
+    assign_every: usize,
+    /// The last assignment and the number of frames it has been used for.
+    cached: Mutex<Option<(usize, Vec<bool>)>>,
+    /// For each headgroup, whether it can be counted in the upper and in the lower leaflet.
+    /// `None` if all headgroups can be counted in both leaflets.
+    selected: Option<Vec<(bool, bool)>>,
+}
+
+impl LeafletClassifier {
+    /// Create the classifier from the provided options.
+    /// If the user specified the leaflets, the headgroups are assigned based on the groups in the system.
+    pub(crate) fn new(args: &Args, system: &System) -> anyhow::Result<LeafletClassifier> {
+        let mut cached = None;
+        if args.upper.is_some() {
+            let upper = system
+                .group_iter(HEADS_GROUP)?
+                .map(|head| system.group_isin(UPPER_GROUP, head.get_index()))
+                .collect::<Result<Vec<bool>, _>>()?;
+
+            cached = Some((0, upper));
+        }
+
+        // user-defined leaflets or the leaflets assigned in the first frame are used for the whole trajectory
+        let assign_every = if args.upper.is_some() || args.assign_once {
+            usize::MAX
+        } else {
+            args.assign_every
+        };
+
+        // different headgroup atoms for each leaflet
+        let selected = if args.phosphates_upper.is_some() {
+            Some(
+                system
+                    .group_iter(HEADS_GROUP)?
+                    .map(|head| {
+                        Ok((
+                            system.group_isin(UPPER_GROUP, head.get_index())?,
+                            system.group_isin(LOWER_GROUP, head.get_index())?,
+                        ))
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?,
+            )
+        } else {
+            None
+        };
+
+        Ok(LeafletClassifier {
+            method: args.leaflet_method,
+            normal: args.normal(),
+            geometry: args.geometry,
+            buckle_modes: args.buckle_modes,
+            check_normal: args.auto_normal,
+            center_of_mass: args.center_of_mass,
+            radius: args.leaflet_radius,
+            cutoff: args.cluster_cutoff,
+            assign_every,
+            cached: Mutex::new(cached),
+            selected,
+        })
+    }
+
+    /// Determine the leaflet in which each headgroup is counted based on the leaflet assignment.
+    /// Headgroups that are not selected for the leaflet they are assigned to are not counted (`None`).
+    pub(crate) fn counted(&self, upper: &[bool]) -> Vec<Option<bool>> {
+        match &self.selected {
+            None => upper.iter().map(|&is_upper| Some(is_upper)).collect(),
+            Some(selected) => upper
+                .iter()
+                .zip(selected.iter())
+                .map(|(&is_upper, &(in_upper, in_lower))| {
+                    ((is_upper && in_upper) || (!is_upper && in_lower)).then_some(is_upper)
+                })
+                .collect(),
+        }
+    }
+
+    /// Assign headgroups to leaflets. Returns `true` for every headgroup of the upper leaflet
+    /// and `false` for every headgroup of the lower leaflet, in the order of the headgroups in the system.
+    ///
+    /// The headgroups are only assigned again once the previous assignment has been used
+    /// for the requested number of frames. Otherwise, the previous assignment is returned.
+    pub(crate) fn assign(
+        &self,
+        frame: &System,
+        membrane_center: &Vector3D,
+    ) -> anyhow::Result<Vec<bool>> {
+        if self.check_normal {
+            let detected = Normal::guess(frame, self.center_of_mass)?;
+            if detected != self.normal.dominant_axis() {
+                anyhow::bail!(
+                    "The membrane normal was detected along the {}-axis, but at time {} ps it seems to be oriented along the {}-axis. The orientation of the membrane must not change during the analysis.",
+                    self.normal,
+                    frame.get_simulation_time(),
+                    detected
+                );
+            }
+        }
+
+        let mut cached = self.cached.lock().expect(
+            "FATAL MEMTHICK ERROR | leaflets::LeafletClassifier::assign | Cached assignment is poisoned.",
+        );
+
+        if let Some((n_frames, assignment)) = cached.as_mut()
+            && *n_frames < self.assign_every
+        {
+            *n_frames += 1;
+            return Ok(assignment.clone());
+        }
+
+        let assignment = self.classify(frame, membrane_center)?;
+        *cached = Some((1, assignment.clone()));
+
+        Ok(assignment)
+    }
+
+    /// Assign headgroups of the frame to leaflets using the selected method.
+    fn classify(&self, frame: &System, membrane_center: &Vector3D) -> anyhow::Result<Vec<bool>> {
+        match self.geometry {
+            Geometry::Planar => (),
+            Geometry::Vesicle | Geometry::Tube => {
+                return assign_radial(frame, membrane_center, self.geometry, self.normal);
+            }
+            Geometry::Buckle => {
+                return assign_buckle(frame, membrane_center, self.buckle_modes, self.normal);
+            }
+        }
+
+        match self.method {
+            LeafletMethod::Global => assign_global(frame, membrane_center, self.normal),
+            LeafletMethod::Local => assign_local(frame, membrane_center, self.radius, self.normal),
+            LeafletMethod::Orientation => assign_orientation(frame, self.normal),
+            LeafletMethod::Clustering => {
+                assign_clustering(frame, membrane_center, self.cutoff, self.normal)
+            }
+        }
+    }
+}
+
+/// Assign headgroups to leaflets based on their position relative to the global membrane center.
+fn assign_global(
+    frame: &System,
+    membrane_center: &Vector3D,
+    normal: Normal,
+) -> anyhow::Result<Vec<bool>> {
+    let simbox = frame.get_box().ok_or(SimBoxError::DoesNotExist)?;
+
+    Ok(grids::head_positions(frame)?
+        .iter()
+        .map(|position| normal.distance(position, membrane_center, simbox) > 0.0)
+        .collect())
+}
+
+/// Assign headgroups of a curved membrane to leaflets based on their distance from the membrane center.
+/// Headgroups further from the center than the average headgroup are assigned to the outer (upper) leaflet.
+fn assign_radial(
+    frame: &System,
+    membrane_center: &Vector3D,
+    geometry: Geometry,
+    normal: Normal,
+) -> anyhow::Result<Vec<bool>> {
+    let simbox = frame.get_box().ok_or(SimBoxError::DoesNotExist)?;
+
+    let distances = grids::head_positions(frame)?
+        .iter()
+        .map(|position| geometry.radial_distance(&normal, position, membrane_center, simbox))
+        .collect::<Vec<f32>>();
+
+    let average = distances.iter().sum::<f32>() / distances.len() as f32;
+    Ok(distances
+        .into_iter()
+        .map(|distance| distance > average)
+        .collect())
+}
+
+/// Assign headgroups of a buckled membrane to leaflets based on their side of the membrane midplane.
+fn assign_buckle(
+    frame: &System,
+    membrane_center: &Vector3D,
+    n_modes: usize,
+    normal: Normal,
+) -> anyhow::Result<Vec<bool>> {
+    let simbox = frame.get_box().ok_or(SimBoxError::DoesNotExist)?;
+    let profile = BuckleProfile::new(frame, membrane_center, normal, n_modes)?;
+
+    Ok(grids::head_positions(frame)?
+        .iter()
+        .map(|position| profile.map_position(position, membrane_center, simbox).2 > 0.0)
+        .collect())
+}
+
+/// Assign headgroups to leaflets based on their position relative to the center of lipid atoms
+/// located laterally within `radius` from the headgroup.
+fn assign_local(
+    frame: &System,
+    membrane_center: &Vector3D,
+    radius: f32,
+    normal: Normal,
+) -> anyhow::Result<Vec<bool>> {
+    let simbox = frame.get_box().ok_or(SimBoxError::DoesNotExist)?;
+
+    // for every lipid atom, store its distance from the global membrane center along the normal
+    let mut cells = LateralCells::new(simbox, radius, normal);
+    for atom in frame.group_iter(LIPIDS_GROUP)? {
+        let position = position_of(atom)?;
+        cells.insert(position, normal.distance(position, membrane_center, simbox));
+    }
+
+    let mut assignment = Vec::new();
+    for position in grids::head_positions(frame)?.iter() {
+        let zdist = normal.distance(position, membrane_center, simbox);
+
+        let (sum, count) = cells
+            .neighbors(position)
+            .filter(|(lipid, _)| normal.lateral_distance(lipid, position, simbox) <= radius)
+            .fold((0.0, 0usize), |(sum, count), (_, lipid_zdist)| {
+                (sum + lipid_zdist, count + 1)
+            });
+
+        // without lipids around the headgroup, fall back to the global membrane center
+        let local_center = if count > 0 { sum / count as f32 } else { 0.0 };
+        assignment.push(zdist > local_center);
+    }
+
+    Ok(assignment)
+}
+
+/// Assign headgroups to leaflets by clustering them. Headgroups closer than `cutoff` belong to the same cluster.
+/// The two largest clusters form the leaflets, the leaflet located higher relative to the membrane center
+/// being the upper leaflet. Headgroups from the remaining clusters are assigned to the leaflet
+/// of the closest headgroup.
+fn assign_clustering(
+    frame: &System,
+    membrane_center: &Vector3D,
+    cutoff: f32,
+    normal: Normal,
+) -> anyhow::Result<Vec<bool>> {
+    let simbox = frame.get_box().ok_or(SimBoxError::DoesNotExist)?;
+
+    let positions = grids::head_positions(frame)?;
+
+    let mut cells = LateralCells::new(simbox, cutoff, normal);
+    for (i, position) in positions.iter().enumerate() {
+        cells.insert(position, i);
+    }
+
+    // connected components of the headgroups
+    let mut clusters = (0..positions.len()).collect::<Vec<_>>();
+    for (i, position) in positions.iter().enumerate() {
+        for &(ref other, j) in cells.neighbors(position) {
+            if j > i && position.distance(other, Dimension::XYZ, simbox) <= cutoff {
+                let (root_i, root_j) = (find_root(&mut clusters, i), find_root(&mut clusters, j));
+                clusters[root_i.max(root_j)] = root_i.min(root_j);
+            }
+        }
+    }
+
+    let roots = (0..positions.len())
+        .map(|i| find_root(&mut clusters, i))
+        .collect::<Vec<_>>();
+
+    let mut sizes = vec![0usize; positions.len()];
+    for &root in roots.iter() {
+        sizes[root] += 1;
+    }
+
+    let mut largest = (0..positions.len())
+        .filter(|&root| sizes[root] > 0)
+        .collect::<Vec<_>>();
+    largest.sort_by(|&a, &b| sizes[b].cmp(&sizes[a]));
+
+    let (first, second) = match largest.as_slice() {
+        [first, second, ..] => (*first, *second),
+        _ => anyhow::bail!(
+            "Could not identify two membrane leaflets by clustering headgroups at time {} ps (found {} cluster(s)). Try decreasing '--cluster-cutoff'.",
+            frame.get_simulation_time(),
+            largest.len()
+        ),
+    };
+
+    // the leaflet located higher relative to the membrane center is the upper leaflet
+    let mean_zdist = |root: usize| {
+        positions
+            .iter()
+            .zip(roots.iter())
+            .filter(|(_, r)| **r == root)
+            .map(|(position, _)| normal.distance(position, membrane_center, simbox))
+            .sum::<f32>()
+            / sizes[root] as f32
+    };
+    let upper_root = if mean_zdist(first) >= mean_zdist(second) {
+        first
+    } else {
+        second
+    };
+
+    let mut assignment = Vec::with_capacity(positions.len());
+    for (position, &root) in positions.iter().zip(roots.iter()) {
+        if root == first || root == second {
+            assignment.push(root == upper_root);
+            continue;
+        }
+
+        // headgroups outside the leaflets are assigned to the leaflet of the closest headgroup
+        let closest = positions
+            .iter()
+            .zip(roots.iter())
+            .filter(|(_, r)| **r == first || **r == second)
+            .map(|(other, r)| (position.distance(other, Dimension::XYZ, simbox), *r))
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, r)| r)
+            .expect("FATAL MEMTHICK ERROR | leaflets::assign_clustering | Leaflets should not be empty.");
+
+        assignment.push(closest == upper_root);
+    }
+
+    Ok(assignment)
+}
+
+/// Find the root of the cluster containing the item, compressing the path to the root.
+pub(crate) fn find_root(clusters: &mut [usize], mut item: usize) -> usize {
+    while clusters[item] != item {
+        clusters[item] = clusters[clusters[item]];
+        item = clusters[item];
+    }
+
+    item
+}
+
+/// Get the position of an atom or return an error if the atom has no position.
+fn position_of(atom: &Atom) -> anyhow::Result<&Vector3D> {
+    atom.get_position()
+        .ok_or_else(|| anyhow::anyhow!("Atom {} has no position.", atom.get_index() + 1))
+}
+
+/// Items distributed into lateral cells of the simulation box for fast neighbor searching.
+pub(crate) struct LateralCells<T> {
+    cells: Vec<Vec<(Vector3D, T)>>,
+    n_x: usize,
+    n_y: usize,
+    /// Size of the simulation box in the membrane plane.
+    size: (f32, f32),
+    normal: Normal,
+}
+
+impl<T> LateralCells<T> {
+    /// Create empty cells with size of at least `size` in both dimensions of the membrane plane.
+    /// If the membrane plane is not periodic, all items are placed into a single cell.
+    pub(crate) fn new(simbox: &SimBox, size: f32, normal: Normal) -> Self {
+        let (box_x, box_y) = normal.lateral_box(simbox).unwrap_or((size, size));
+        let n_x = ((box_x / size).floor() as usize).max(1);
+        let n_y = ((box_y / size).floor() as usize).max(1);
+
+        LateralCells {
+            cells: (0..n_x * n_y).map(|_| Vec::new()).collect(),
+            n_x,
+            n_y,
+            size: (box_x, box_y),
+            normal,
+        }
+    }
+
+    /// Get the indices of the cell containing the position.
+    fn cell_of(&self, position: &Vector3D) -> (usize, usize) {
+        let (px, py) = self.normal.lateral(position);
+        let (box_x, box_y) = self.size;
+        let x = (px.rem_euclid(box_x) / box_x * self.n_x as f32) as usize;
+        let y = (py.rem_euclid(box_y) / box_y * self.n_y as f32) as usize;
+        (x.min(self.n_x - 1), y.min(self.n_y - 1))
+    }
+
+    /// Add an item located at the position.
+    pub(crate) fn insert(&mut self, position: &Vector3D, item: T) {
+        let (x, y) = self.cell_of(position);
+        self.cells[y * self.n_x + x].push((position.clone(), item));
+    }
+
+    /// Iterate over the items in the cell containing the position and in the neighboring cells.
+    /// Items located laterally within the cell size from the position are guaranteed to be included.
+    pub(crate) fn neighbors(&self, position: &Vector3D) -> impl Iterator<Item = &(Vector3D, T)> {
+        let (x, y) = self.cell_of(position);
+
+        // collect the neighboring cells (each only once, even for very small grids)
+        let mut neighbors = Vec::with_capacity(9);
+        for dx in [self.n_x - 1, 0, 1] {
+            for dy in [self.n_y - 1, 0, 1] {
+                let index = ((y + dy) % self.n_y) * self.n_x + (x + dx) % self.n_x;
+                if !neighbors.contains(&index) {
+                    neighbors.push(index);
+                }
+            }
+        }
+
+        neighbors
+            .into_iter()
+            .flat_map(move |index| self.cells[index].iter())
+    }
+}
+
+/// Assign headgroups to leaflets based on the orientation of the lipids.
+/// A lipid belongs to the upper leaflet if its headgroup is located above its tail end.
+/// The i-th headgroup is paired with the i-th tail atom.
+fn assign_orientation(frame: &System, normal: Normal) -> anyhow::Result<Vec<bool>> {
+    let simbox = frame.get_box().ok_or(SimBoxError::DoesNotExist)?;
+
+    grids::head_positions(frame)?
+        .iter()
+        .zip(frame.group_iter(TAILS_GROUP)?)
+        .map(|(head, tail)| Ok(normal.distance(head, position_of(tail)?, simbox) > 0.0))
+        .collect()
+}
@@ -1,37 +1,51 @@
-use std::{fs::File, io::BufWriter, path::Path, process};
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use groan_rs::{
-    errors::{GroupError, SimBoxError},
+    errors::{ElementError, GroupError, ReadTrajError, SimBoxError},
     prelude::*,
 };
 
 use std::io::Write;
 
 // Calculate membrane thickness.
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(
     author,
     version,
     about,
-    long_about = "Calculate a 2D map of membrane thickness."
+    long_about = "Calculate a 2D map of membrane thickness.",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
 )]
 pub struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(
         short = 's',
         long = "structure",
         help = "Input structure file",
-        long_help = "Path to a gro, pdb, or tpr file containing the system structure."
+        long_help = "Path to a gro, pdb, or tpr file containing the system structure.",
+        required = true
     )]
-    structure: String,
+    // not required when a subcommand is used
+    structure: Option<String>,
 
     #[arg(
         short = 'f',
         long = "trajectory",
-        help = "Input trajectory file",
-        long_help = "Path to an xtc file containing the trajectory to analyze."
+        help = "Input trajectory file(s)",
+        long_help = "Path to an xtc, trr, dcd, or Amber nc file containing the trajectory to analyze. Reading dcd and nc files requires the 'chemfiles' feature. Multiple trajectory files of the same format can be provided; these are analyzed in the specified order as one continuous trajectory. Patterns such as 'md.part*.xtc' can also be used; matching files are then sorted by the time of their first frames. Use '-' to read an xtc trajectory from the standard input. If no trajectory is provided, membrane thickness is calculated from the structure file.",
+        num_args = 1..
     )]
-    trajectory: String,
+    trajectory: Vec<String>,
 
     #[arg(
         short = 'o',
@@ -50,6 +64,32 @@ pub struct Args {
     )]
     index: Option<String>,
 
+    #[arg(
+        short = 'b',
+        long = "begin",
+        help = "Time of the first frame to read (in ps).",
+        long_help = "Time of the first trajectory frame to analyze (in ps). Frames with simulation time lower than this value are skipped.",
+        default_value_t = 0.0
+    )]
+    begin: f32,
+
+    #[arg(
+        short = 'e',
+        long = "end",
+        help = "Time of the last frame to read (in ps).",
+        long_help = "Time of the last trajectory frame to analyze (in ps). Frames with simulation time higher than this value are skipped.",
+        default_value_t = f32::INFINITY
+    )]
+    end: f32,
+
+    #[arg(
+        long = "step",
+        help = "Analyze every Nth frame.",
+        long_help = "Analyze only every Nth frame of the trajectory. Frames that are skipped over are not read.",
+        default_value_t = 1
+    )]
+    step: usize,
+
     #[arg(
         short = 'l',
         long = "lipids",
@@ -63,7 +103,7 @@ pub struct Args {
         short = 'p',
         long = "phosphates",
         help = "Specification of atoms identifying lipid headgroups.",
-        long_help = "Specify atoms identifying lipid headgroups. Use only one atom per lipid molecule!",
+        long_help = "Specify atoms identifying lipid headgroups. Use only one atom per lipid molecule, unless '--head-com' is used!",
         default_value = "name PO4 P"
     )]
     phosphates: String,
@@ -112,248 +152,4976 @@ pub struct Args {
         default_value_t = 0.1
     )]
     bin_size: f32,
-}
 
-/// Print the specified options.
-fn print_options(args: &Args, simbox: &SimBox) {
-    println!("[STRUCTURE]     {}", args.structure);
-    println!("[TRAJECTORY]    {}", args.trajectory);
-    println!("[OUTPUT]        {}", args.output);
+    #[arg(
+        short = 't',
+        long = "threads",
+        help = "Number of threads to use for the analysis.",
+        long_help = "Number of threads to use for the analysis. Trajectory frames are distributed among the threads and the results are combined at the end of the analysis.",
+        default_value_t = 1
+    )]
+    threads: usize,
 
-    if let Some(ndx) = args.index.as_ref() {
-        println!("[INDEX]        {}", ndx);
-    }
+    #[arg(
+        long = "chunk",
+        help = "Analyze only the i-th of N equal parts of the trajectory.",
+        long_help = "Analyze only the i-th of N equally sized parts of the trajectory, specified as 'i/N' (counting from 1). The frames selected using the time range and the step are split into N contiguous parts. Intended for distributing the analysis of a long trajectory over several jobs; requires '--state'. The state files from the individual chunks can then be combined using 'memthick merge'.",
+        value_name = "i/N",
+        help_heading = "Trajectory reading and restarts"
+    )]
+    chunk: Option<Chunk>,
 
-    println!("[LIPIDS]        {}", args.lipids);
-    println!("[PHOSPHATES]    {}", args.phosphates);
-    println!("[NAN LIMIT]     {}", args.nan_limit);
+    #[arg(
+        long = "skip-broken",
+        help = "Skip trajectory frames that could not be read.",
+        long_help = "Skip trajectory frames that could not be read (e.g., in a truncated trajectory from a crashed simulation) instead of terminating the analysis with an error. Not supported for parallel analysis.",
+        help_heading = "Trajectory reading and restarts"
+    )]
+    skip_broken: bool,
 
-    println!(
-        "[X-RANGE]       {}-{} nm",
-        args.xmin.unwrap_or(0.0),
-        args.xmax.unwrap_or(simbox.x)
-    );
-    println!(
-        "[Y-RANGE]       {}-{} nm",
-        args.ymin.unwrap_or(0.0),
-        args.ymax.unwrap_or(simbox.y)
-    );
+    #[arg(
+        long = "weights",
+        help = "File with weights of the analyzed frames.",
+        long_help = "Path to a file containing one weight per line for each analyzed trajectory frame (i.e., after applying the time range and the step). Contributions of each frame to the membrane thickness are multiplied by its weight. Useful for reweighting biased simulations. Note that the minimal number of samples in a grid bin ('-a') still refers to the number of detected phosphates, not to their total weight. When analyzing a trajectory chunk (see '--chunk'), the file contains the weights of the frames of all chunks together. Not supported for parallel analysis.",
+        help_heading = "Trajectory reading and restarts"
+    )]
+    weights: Option<String>,
 
-    println!("[BIN SIZE]      {} nm", args.bin_size);
-    println!("\n");
-}
+    #[arg(
+        long = "follow",
+        help = "Wait for new frames appended to the trajectory.",
+        long_help = "Keep analyzing the trajectory while it is being written by a running simulation. memthick periodically checks the trajectory for new frames and rewrites the output map whenever new frames have been analyzed. An incomplete last frame is read again once it has been written, while any other error in reading the trajectory stops the analysis (see '--skip-broken'). Stop the analysis using Ctrl+C. Requires a single xtc or trr trajectory file and is not supported for parallel analysis.",
+        help_heading = "Trajectory reading and restarts"
+    )]
+    follow: bool,
 
-fn sanity_check_options(args: &Args) -> anyhow::Result<()> {
-    if args.nan_limit == 0 {
-        anyhow::bail!("NAN limit must be larger than 0, not {}", args.nan_limit);
-    }
+    #[arg(
+        long = "follow-interval",
+        help = "How often (in seconds) to check the trajectory for new frames.",
+        long_help = "How often (in seconds) to check the followed trajectory for new frames. Only used with the '--follow' option.",
+        default_value_t = 30,
+        help_heading = "Trajectory reading and restarts"
+    )]
+    follow_interval: u64,
 
-    if args.xmin > args.xmax {
-        anyhow::bail!("Minimum grid x-value cannot be higher than the maximum grid x-value.");
-    }
+    #[arg(
+        long = "state",
+        help = "Periodically write the state of the analysis into this file.",
+        long_help = "Path to a file where the state of the analysis (the raw accumulated grids) will be periodically written. The analysis can be continued from this file using the '--restart' option.",
+        help_heading = "Trajectory reading and restarts"
+    )]
+    state: Option<String>,
 
-    if args.ymin > args.ymax {
-        anyhow::bail!("Minimum grid y-value cannot be higher than the maximum grid y-value.");
-    }
+    #[arg(
+        long = "state-freq",
+        help = "How often (in analyzed frames) the state file should be written.",
+        long_help = "How often (in the number of analyzed frames) the state of the analysis should be written into the state file. The state is always written at the end of the analysis.",
+        default_value_t = 1000,
+        help_heading = "Trajectory reading and restarts"
+    )]
+    state_freq: usize,
 
-    Ok(())
-}
+    #[arg(
+        long = "restart",
+        help = "Continue the analysis from a state file.",
+        long_help = "Path to a state file written by a previous run of memthick. The analysis continues from the last frame recorded in the state file. The grid must be defined in the same way as in the previous run.",
+        help_heading = "Trajectory reading and restarts"
+    )]
+    restart: Option<String>,
 
-fn write_map(
-    output_name: impl AsRef<Path>,
-    grid_upper: &GridMap<f64, f64, impl Fn(&f64) -> f64>,
-    count_upper: &GridMap<usize, usize, impl Fn(&usize) -> usize>,
-    grid_lower: &GridMap<f64, f64, impl Fn(&f64) -> f64>,
-    count_lower: &GridMap<usize, usize, impl Fn(&usize) -> usize>,
-    nan_limit: usize,
-    raw_arguments: &Vec<String>,
-) -> anyhow::Result<()> {
-    let file = File::create(&output_name)?;
-    let mut output = BufWriter::new(file);
+    #[arg(
+        long = "benchmark",
+        help = "Only benchmark the analysis on N frames (default: 100).",
+        long_help = "Analyze only the first N frames of the trajectory (100 if not specified) and report the analysis speed, the time spent reading the trajectory, calculating the membrane center, and assigning the phosphates to the grid bins, as well as the estimated runtime of the full analysis. The benchmark always runs on a single thread. No output map is written.",
+        num_args = 0..=1,
+        default_missing_value = "100",
+        value_name = "N",
+        help_heading = "Trajectory reading and restarts"
+    )]
+    benchmark: Option<usize>,
 
-    writeln!(
-        &mut output,
-        "# Generated with memthick v{}.",
-        env!("CARGO_PKG_VERSION")
-    )?;
-    writeln!(&mut output, "# Command line: {}", raw_arguments.join(" "))?;
+    #[arg(
+        long = "exclude",
+        help = "Specification of atoms excluded from the analysis.",
+        long_help = "Specify atoms that are removed from the lipids, the headgroups, and the tail ends after these are selected. Useful for excluding, e.g., a patch of restrained lipids or a specific lipid species without rewriting the other selections.",
+        help_heading = "Headgroup selection"
+    )]
+    exclude: Option<String>,
 
-    writeln!(
-        &mut output,
-        "# See the average membrane thickness at the end of this file."
-    )?;
+    #[arg(
+        long = "sterol-heads",
+        help = "Specification of atoms identifying sterol headgroups.",
+        long_help = "Specify atoms identifying the headgroups of sterols (e.g., 'name ROH O3'), which are added to the headgroups selected by '--phosphates' (or '--phosphates-upper' and '--phosphates-lower'). Sterols are assigned to leaflets in the same way as the other lipids and contribute to the thickness map. Use only one atom per sterol molecule, unless '--head-com' is used.",
+        conflicts_with = "upper",
+        help_heading = "Headgroup selection"
+    )]
+    sterol_heads: Option<String>,
 
-    writeln!(&mut output, "@ xlabel x-coordinate [nm]")?;
-    writeln!(&mut output, "@ ylabel y-coordinate [nm]")?;
+    #[arg(
+        long = "head-com",
+        help = "Use the center of mass of all headgroup atoms of each lipid.",
+        long_help = "Allow the headgroup selections ('--phosphates', '--phosphates-upper', '--phosphates-lower', '--upper', '--lower') to select several atoms per lipid and use the center of mass of the selected atoms of each lipid (identified by its residue number) as the position of its headgroup. The center of geometry is used if the masses of the atoms are not known. Useful for atomistic force fields, where the phosphate group is better represented by its center of mass than by a single phosphorus atom.",
+        help_heading = "Headgroup selection"
+    )]
+    head_com: bool,
 
-    writeln!(&mut output, "@ zlabel membrane thickness [nm]")?;
-    writeln!(&mut output, "@ grid --")?;
-    writeln!(&mut output, "$ type colorbar")?;
-    writeln!(&mut output, "$ colormap rainbow")?;
+    #[arg(
+        long = "allow-multiple",
+        help = "Allow several headgroup atoms per lipid.",
+        long_help = "Allow the headgroup selections to select several atoms of the same lipid (identified by its residue number) and count each of them as a separate headgroup. By default, the analysis stops with an error in this case, since such selections (e.g., 'name PO4 P' matching both atoms in the same lipid) silently count lipids several times. With this option, only a warning is printed.",
+        conflicts_with = "head_com",
+        help_heading = "Headgroup selection"
+    )]
+    allow_multiple: bool,
 
-    let mut average_thickness = Vec::new();
-    for (((upper_sum, upper_count), lower_sum), lower_count) in grid_upper
-        .extract_raw()
-        .zip(count_upper.extract_raw())
-        .zip(grid_lower.extract_raw())
-        .zip(count_lower.extract_raw())
-    {
-        let thickness = if *upper_count.2 < nan_limit || *lower_count.2 < nan_limit {
-            f64::NAN
-        } else {
-            let upper_av = upper_sum.2 / (*upper_count.2 as f64);
-            let lower_av = lower_sum.2 / (*lower_count.2 as f64);
-            upper_av - lower_av
-        };
+    #[arg(
+        long = "upper",
+        help = "Specification of headgroups of the upper leaflet.",
+        long_help = "Specify atoms identifying lipid headgroups of the upper membrane leaflet (e.g., using a group from an ndx file). Together with '--lower', this replaces '--phosphates' and the automatic assignment of lipids to leaflets. Use only one atom per lipid molecule!",
+        requires = "lower",
+        conflicts_with_all = ["phosphates", "leaflet_method", "tails", "assign_once", "assign_every"],
+        help_heading = "Headgroup selection"
+    )]
+    upper: Option<String>,
 
-        writeln!(
-            &mut output,
-            "{:12.6} {:12.6} {:12.4}",
-            upper_sum.0, upper_sum.1, thickness
-        )?;
+    #[arg(
+        long = "lower",
+        help = "Specification of headgroups of the lower leaflet.",
+        long_help = "Specify atoms identifying lipid headgroups of the lower membrane leaflet (e.g., using a group from an ndx file). Together with '--upper', this replaces '--phosphates' and the automatic assignment of lipids to leaflets. Use only one atom per lipid molecule!",
+        requires = "upper",
+        help_heading = "Headgroup selection"
+    )]
+    lower: Option<String>,
 
-        if thickness.is_finite() {
-            average_thickness.push(thickness);
-        }
-    }
+    #[arg(
+        long = "phosphates-upper",
+        help = "Specification of atoms identifying lipid headgroups in the upper leaflet.",
+        long_help = "Specify atoms identifying lipid headgroups used for the upper membrane leaflet. Together with '--phosphates-lower', this replaces '--phosphates' for asymmetric membranes with different headgroup atoms in each leaflet. Lipids are still assigned to leaflets automatically, but only the atoms selected for the leaflet a lipid is assigned to are used. Use only one atom per lipid molecule!",
+        requires = "phosphates_lower",
+        conflicts_with_all = ["phosphates", "upper"],
+        help_heading = "Headgroup selection"
+    )]
+    phosphates_upper: Option<String>,
 
-    writeln!(
-        &mut output,
-        "# Average membrane thickness: {:12.4} nm",
-        average_thickness.iter().sum::<f64>() / average_thickness.len() as f64
-    )?;
+    #[arg(
+        long = "phosphates-lower",
+        help = "Specification of atoms identifying lipid headgroups in the lower leaflet.",
+        long_help = "Specify atoms identifying lipid headgroups used for the lower membrane leaflet. Together with '--phosphates-upper', this replaces '--phosphates' for asymmetric membranes with different headgroup atoms in each leaflet. Lipids are still assigned to leaflets automatically, but only the atoms selected for the leaflet a lipid is assigned to are used. Use only one atom per lipid molecule!",
+        requires = "phosphates_upper",
+        conflicts_with_all = ["phosphates", "lower"],
+        help_heading = "Headgroup selection"
+    )]
+    phosphates_lower: Option<String>,
 
-    Ok(())
-}
+    #[arg(
+        long = "region",
+        help = "Analyze only the headgroups located in this region of the simulation box.",
+        long_help = "Analyze only the headgroups located inside the cuboid region of the simulation box specified as 'xmin:xmax,ymin:ymax[,zmin:zmax]' (in nm) in every frame. The coordinates refer to the axes of the simulation box regardless of the membrane normal, and bounds that are not provided are unlimited, e.g., '5:,:' selects the headgroups with the x-coordinate of at least 5 nm. Unlike '--xmin', '--xmax', '--ymin', and '--ymax', the region does not change the extent of the grid, and unlike the selection of the headgroups, it is applied to the instantaneous positions of the headgroups. The membrane center is still calculated from all lipids.",
+        value_name = "REGION",
+        help_heading = "Headgroup selection"
+    )]
+    region: Option<Region>,
 
-fn run() -> anyhow::Result<()> {
-    let raw_arguments = std::env::args().collect::<Vec<_>>();
+    #[arg(
+        long = "membranes",
+        help = "Number of membranes stacked along the membrane normal.",
+        long_help = "Number of membranes stacked along the membrane normal in the system (e.g., in double-bilayer setups). The lipids are split into the individual membranes at the largest gaps between them along the normal in the input structure, and a separate thickness map is calculated for each membrane. The maps are written into files named after the output file with the index of the membrane appended (e.g., 'thickness_1.dat', 'thickness_2.dat'), with the membranes ordered along the normal.",
+        default_value_t = 1,
+        help_heading = "Headgroup selection"
+    )]
+    membranes: usize,
 
-    let args = Args::parse();
-    println!("\n>> memthick {} <<\n", env!("CARGO_PKG_VERSION"));
-    sanity_check_options(&args)?;
+    #[arg(
+        long = "largest-aggregate",
+        help = "Analyze only the largest aggregate of lipids.",
+        long_help = "Detect aggregates of lipids connected by atoms closer than '--aggregate-cutoff' in the input structure and analyze only the lipids of the largest aggregate. This excludes stray micelles, monolayer patches, or lipids bound to a protein away from the membrane, which would otherwise distort the membrane center and the thickness map.",
+        help_heading = "Headgroup selection"
+    )]
+    largest_aggregate: bool,
 
-    let mut system = System::from_file(&args.structure).map_err(anyhow::Error::from_boxed)?;
-    if let Some(ndx) = &args.index {
-        system.read_ndx(ndx)?;
-    }
+    #[arg(
+        long = "aggregate-cutoff",
+        help = "Distance cutoff for detecting lipid aggregates and inclusions (in nm).",
+        long_help = "Maximal distance (in nm) between two lipid atoms belonging to the same aggregate when using '--largest-aggregate' and between two atoms belonging to the same inclusion when using '--inclusions'. It should be larger than the typical distance between neighboring atoms but smaller than the distance between separate aggregates.",
+        default_value_t = 0.8,
+        help_heading = "Headgroup selection"
+    )]
+    aggregate_cutoff: f32,
 
-    let simbox = system.get_box().ok_or(SimBoxError::DoesNotExist)?;
-    if !simbox.is_orthogonal() || simbox.is_zero() {
-        return Err(SimBoxError::NotOrthogonal.into());
-    }
+    #[arg(
+        long = "leaflet-method",
+        help = "Method used to assign lipids to membrane leaflets.",
+        long_help = "Method used to assign lipid headgroups to membrane leaflets. 'global' compares the z-coordinate of each headgroup with the center of the whole membrane. 'local' compares it with the center of the lipid atoms located within '--leaflet-radius' from the headgroup in the xy-plane, which is more robust for curved or undulating membranes. 'orientation' compares the z-coordinate of each headgroup with the z-coordinate of the corresponding tail end (see '--tails'). 'clustering' identifies the leaflets as the two largest clusters of headgroups (see '--cluster-cutoff'), which also handles highly undulating membranes and membranes with pores.",
+        value_enum,
+        default_value_t = LeafletMethod::Global,
+        help_heading = "Leaflet assignment"
+    )]
+    leaflet_method: LeafletMethod,
 
-    print_options(&args, simbox);
+    #[arg(
+        long = "leaflet-radius",
+        help = "Radius for the local leaflet assignment (in nm).",
+        long_help = "Lateral radius (in nm) of the cylinder around each headgroup used to calculate the local membrane center when using '--leaflet-method local'.",
+        default_value_t = 2.0,
+        help_heading = "Leaflet assignment"
+    )]
+    leaflet_radius: f32,
 
-    let xmin = args.xmin.unwrap_or(0.0);
-    let xmax = args.xmax.unwrap_or(simbox.x);
-    let ymin = args.ymin.unwrap_or(0.0);
-    let ymax = args.ymax.unwrap_or(simbox.y);
+    #[arg(
+        long = "cluster-cutoff",
+        help = "Distance cutoff for clustering headgroups (in nm).",
+        long_help = "Maximal distance (in nm) between two headgroups belonging to the same cluster when using '--leaflet-method clustering'. It should be larger than the typical distance between neighboring headgroups in a leaflet but smaller than the distance between the leaflets.",
+        default_value_t = 2.0,
+        help_heading = "Leaflet assignment"
+    )]
+    cluster_cutoff: f32,
 
-    match system.group_create("xxxMemthickReservedxxx-Lipids", &args.lipids) {
-        Ok(_) | Err(GroupError::AlreadyExistsWarning(_)) => (),
-        Err(e) => return Err(e.into()),
-    }
+    #[arg(
+        long = "tails",
+        help = "Specification of atoms identifying lipid tail ends.",
+        long_help = "Specify atoms identifying the ends of lipid tails. Required when using '--leaflet-method orientation'. Use exactly one atom per lipid molecule and select the lipids in the same order as with '--phosphates'!",
+        help_heading = "Leaflet assignment"
+    )]
+    tails: Option<String>,
 
-    if system
-        .group_get_n_atoms("xxxMemthickReservedxxx-Lipids")
-        .unwrap()
-        == 0
-    {
-        anyhow::bail!("The query '{}' selects no atoms.", &args.lipids);
-    }
+    #[arg(
+        long = "assign-once",
+        help = "Assign lipids to leaflets only in the first analyzed frame.",
+        long_help = "Assign lipid headgroups to membrane leaflets only in the first analyzed frame and keep this assignment for the whole trajectory. This prevents headgroups transiently moving toward the membrane center from being counted in the opposite leaflet. Only use this option if lipids do not flip-flop between the leaflets. Not supported for parallel analysis.",
+        help_heading = "Leaflet assignment"
+    )]
+    assign_once: bool,
 
-    match system.group_create("xxxMemthickReservedxxx-Heads", &args.phosphates) {
-        Ok(_) | Err(GroupError::AlreadyExistsWarning(_)) => (),
-        Err(e) => return Err(e.into()),
-    }
+    #[arg(
+        long = "assign-every",
+        help = "Assign lipids to leaflets only every N analyzed frames.",
+        long_help = "Assign lipid headgroups to membrane leaflets only every N analyzed frames and reuse the last assignment for the frames in between. This speeds up the analysis, especially with the more expensive leaflet assignment methods, if lipids rarely flip-flop between the leaflets. Not supported for parallel analysis.",
+        default_value_t = 1,
+        conflicts_with = "assign_once",
+        help_heading = "Leaflet assignment"
+    )]
+    assign_every: usize,
 
-    if system
-        .group_get_n_atoms("xxxMemthickReservedxxx-Heads")
-        .unwrap()
-        == 0
-    {
-        anyhow::bail!("The query '{}' selects no atoms.", &args.phosphates);
-    }
+    #[arg(
+        long = "flip-flops",
+        help = "Write lipids changing leaflet into this file.",
+        long_help = "Track the leaflet assignment of each lipid during the analysis and write the lipids that moved to the opposite leaflet (flip-flop events) into this file together with the time of the event. Not supported for parallel analysis and when following the trajectory.",
+        help_heading = "Leaflet assignment"
+    )]
+    flip_flops: Option<String>,
 
-    let mut grid_upper = GridMap::new(
-        (xmin, xmax),
-        (ymin, ymax),
-        (args.bin_size, args.bin_size),
-        f64::clone,
-    )?;
+    #[arg(
+        long = "flip-flop-frames",
+        help = "Number of frames a lipid must spend in the opposite leaflet to count as a flip-flop.",
+        long_help = "Number of consecutive analyzed frames a lipid must spend in the opposite leaflet for its move to be reported as a flip-flop event. This prevents lipids transiently assigned to the opposite leaflet from being reported.",
+        default_value_t = 10,
+        help_heading = "Leaflet assignment"
+    )]
+    flip_flop_frames: usize,
 
-    let mut grid_lower = GridMap::new(
-        (xmin, xmax),
-        (ymin, ymax),
-        (args.bin_size, args.bin_size),
-        f64::clone,
-    )?;
+    #[arg(
+        long = "normal",
+        help = "Orientation of the membrane normal.",
+        long_help = "Orientation of the membrane normal. Either an axis of the simulation box ('x', 'y', or 'z'), three components of an arbitrary vector (e.g., '--normal 0.2 0 0.98'), or 'auto'. With 'auto', the axis along which the membrane normal is oriented is detected from the input structure and checked in every analyzed frame. For an axis, the thickness map is constructed in the plane formed by the two remaining axes: y-z for 'x', x-z for 'y', and x-y for 'z'. For a vector, the thickness map is constructed in the plane spanned by two vectors (u, v) perpendicular to the normal. The '--xmin', '--xmax', '--ymin', and '--ymax' options refer to the first and the second axis of this plane, respectively.",
+        num_args = 1..=3,
+        allow_negative_numbers = true,
+        default_value = "z",
+        help_heading = "Membrane normal, centering, and grid"
+    )]
+    normal: Vec<String>,
 
-    let mut count_upper = GridMap::new(
-        (xmin, xmax),
-        (ymin, ymax),
-        (args.bin_size, args.bin_size),
-        usize::clone,
-    )?;
+    #[arg(
+        long = "fit-plane",
+        help = "Measure distances perpendicular to a plane fitted in every frame.",
+        long_help = "Fit a plane to the positions of all headgroups in every analyzed frame and measure the distances of headgroups from the membrane center perpendicular to this plane instead of along the membrane normal. This corrects for small collective tilts of the membrane during the simulation. The thickness map is still constructed in the plane perpendicular to the membrane normal.",
+        help_heading = "Membrane normal, centering, and grid"
+    )]
+    fit_plane: bool,
 
-    let mut count_lower = GridMap::new(
-        (xmin, xmax),
-        (ymin, ymax),
-        (args.bin_size, args.bin_size),
-        usize::clone,
-    )?;
+    #[arg(
+        long = "center-membrane",
+        help = "Center the membrane in the simulation box along the membrane normal in every frame.",
+        long_help = "Translate the lipids and all other atoms used in the analysis in every analyzed frame along the membrane normal so that the center of geometry of the lipids is placed in the center of the simulation box, and wrap them into the box. This is done before the lipids are assigned to leaflets and prevents problems with membranes drifting toward the periodic boundary.",
+        help_heading = "Membrane normal, centering, and grid"
+    )]
+    center_membrane: bool,
 
-    for frame in system
-        .group_xtc_iter(&args.trajectory, "xxxMemthickReservedxxx-Lipids")?
-        .print_progress(ProgressPrinter::default())
-    {
-        let frame = frame?;
+    #[arg(
+        long = "center",
+        help = "Center the frames laterally on the specified atoms.",
+        long_help = "Translate the lipids and all other atoms used in the analysis (e.g., the protein selected by '--protein') in every analyzed frame in the membrane plane so that the center of geometry of the specified atoms (e.g., a transmembrane protein) is placed in the center of the simulation box. Without this, the thickness footprint of a diffusing protein is smeared over the whole map.",
+        help_heading = "Membrane normal, centering, and grid"
+    )]
+    center: Option<String>,
 
-        let membrane_center = frame
-            .group_get_center("xxxMemthickReservedxxx-Lipids")
-            .unwrap();
+    #[arg(
+        long = "fit",
+        help = "Fit the frames onto the input structure using the specified atoms.",
+        long_help = "Fit every analyzed frame onto the input structure using the specified atoms (e.g., a transmembrane protein or a scaffold). The lipids and all other atoms used in the analysis are rotated around the membrane normal and translated in the membrane plane so that the specified atoms overlap with their positions in the input structure in the least-squares sense, and then wrapped into the simulation box. This keeps the thickness map registered to atoms that both diffuse and rotate in the membrane plane.",
+        conflicts_with = "center",
+        help_heading = "Membrane normal, centering, and grid"
+    )]
+    fit: Option<String>,
 
-        for head in frame.group_iter("xxxMemthickReservedxxx-Heads").unwrap() {
-            let zdist = head
-                .distance_from_point(&membrane_center, Dimension::Z, frame.get_box().unwrap())
-                .unwrap();
+    #[arg(
+        long = "center-selection",
+        help = "Atoms defining the membrane center.",
+        long_help = "Specify atoms used to calculate the membrane center instead of all lipid atoms (e.g., the terminal atoms of lipid tails). The membrane center is used to assign the headgroups to leaflets and as the reference for their distances. For asymmetric membranes, the center of all lipid atoms may be biased toward the denser leaflet.",
+        help_heading = "Membrane normal, centering, and grid"
+    )]
+    center_selection: Option<String>,
 
-            let position = head.get_position().unwrap();
+    #[arg(
+        long = "center-of-mass",
+        help = "Use the center of mass of the lipids as the membrane center.",
+        long_help = "Calculate the membrane center as the center of mass of all lipid atoms instead of their center of geometry. Masses are read from the structure file (tpr) or guessed from the names of the atoms. Note that masses guessed for coarse-grained beads are unreliable.",
+        help_heading = "Membrane normal, centering, and grid"
+    )]
+    center_of_mass: bool,
 
-            let tile_wrapped = if zdist > 0.0 {
-                grid_upper.get_mut_at(position.x, position.y)
-            } else {
-                grid_lower.get_mut_at(position.x, position.y)
-            };
+    #[arg(
+        long = "make-whole",
+        help = "Make lipids broken across periodic boundaries whole in every frame.",
+        long_help = "Make lipid molecules broken across periodic boundaries whole in every analyzed frame before the membrane center is calculated and the lipids are assigned to leaflets. Lipids are identified by their residue numbers and all their atoms are placed close to the first atom of the lipid, so no information about bonds is required.",
+        help_heading = "Membrane normal, centering, and grid"
+    )]
+    make_whole: bool,
 
-            if let Some(tile) = tile_wrapped {
-                *tile += zdist as f64;
-            }
+    #[arg(
+        long = "geometry",
+        help = "Shape of the membrane.",
+        long_help = "Shape of the analyzed membrane. For 'planar' membranes, thickness is mapped in the membrane plane. For 'vesicle', the center of the vesicle is calculated in every frame and the radial thickness is mapped in the polar angle (measured from the membrane normal) and the azimuthal angle around the vesicle center. For 'tube', the axis of the tube is oriented along the membrane normal and passes through the center of the lipids calculated in every frame. The radial thickness is then mapped in the coordinate along the axis (set the range using '--xmin' and '--xmax') and the azimuthal angle around it. For vesicles and tubes, lipids are assigned to the outer and inner leaflet based on their distance from the center or the axis. For 'buckle', the membrane is assumed to be buckled along the first axis of the membrane plane. The profile of the membrane midplane is fitted to the lipid tail ends (requires '--tails') in every frame and the thickness is mapped in the arclength along this profile and the second axis of the membrane plane. Lipids are assigned to leaflets based on their side of the midplane.",
+        value_enum,
+        default_value_t = Geometry::Planar,
+        help_heading = "Membrane normal, centering, and grid"
+    )]
+    geometry: Geometry,
 
-            let count_wrapped = if zdist > 0.0 {
-                count_upper.get_mut_at(position.x, position.y)
-            } else {
-                count_lower.get_mut_at(position.x, position.y)
-            };
+    #[arg(
+        long = "angle-bin",
+        help = "Size of a grid bin in angular coordinates (in degrees).",
+        long_help = "Size of a grid bin in angular coordinates (in degrees). Used instead of '--bin' for the polar and azimuthal angle with '--geometry vesicle' and for the azimuthal angle with '--geometry tube' and '--grid polar'. Also used for the sectors of '--azimuthal' and '--azimuthal-map'.",
+        default_value_t = 5.0,
+        help_heading = "Membrane normal, centering, and grid"
+    )]
+    angle_bin: f32,
 
-            if let Some(count) = count_wrapped {
-                *count += 1;
-            }
-        }
-    }
+    #[arg(
+        long = "grid",
+        help = "Coordinate system of the thickness map of a planar membrane.",
+        long_help = "Coordinate system of the thickness map of a planar membrane. 'cartesian' maps the thickness in the coordinates of the membrane plane. 'polar' maps the thickness in the lateral distance from the origin (see '--origin') and the azimuthal angle around it, which is useful for radially symmetric deformations (e.g., around a protein). The radial distance is binned using '--bin' and ranges from 0 to half of the shorter side of the simulation box in the membrane plane (set the range using '--xmin' and '--xmax'), the azimuthal angle is binned using '--angle-bin'. The periodic image of each headgroup closest to the origin is used. Only supported with '--thickness-method heads' for planar membranes with the normal oriented along an axis of the simulation box.",
+        value_enum,
+        default_value_t = Grid::Cartesian,
+        help_heading = "Membrane normal, centering, and grid"
+    )]
+    grid: Grid,
 
-    write_map(
-        &args.output,
-        &grid_upper,
-        &count_upper,
-        &grid_lower,
-        &count_lower,
-        args.nan_limit,
-        &raw_arguments,
-    )?;
+    #[arg(
+        long = "origin",
+        help = "Origin of the polar grid in the membrane plane (in nm).",
+        long_help = "Coordinates (in nm) of the origin of the polar grid (see '--grid polar'), of the radial and azimuthal profiles (see '--radial', '--azimuthal', and '--azimuthal-map'), and of the symmetry center of the thickness map (see '--symmetrize') in the membrane plane. If not provided, the center of the simulation box in the membrane plane is used (the center of the map for '--symmetrize'), which is where a protein ends up when the frames are centered on it using '--center'.",
+        num_args = 2,
+        value_names = ["X", "Y"],
+        allow_negative_numbers = true,
+        help_heading = "Membrane normal, centering, and grid"
+    )]
+    origin: Option<Vec<f32>>,
 
-    Ok(())
+    #[arg(
+        long = "buckle-modes",
+        help = "Number of Fourier modes describing the profile of buckled membranes.",
+        long_help = "Number of Fourier modes used to describe the profile of the membrane midplane with '--geometry buckle'. The profile is fitted to the positions of lipid tail ends (see '--tails') in every analyzed frame. Use more modes for membranes with multiple or irregular buckles.",
+        default_value_t = 2,
+        help_heading = "Membrane normal, centering, and grid"
+    )]
+    buckle_modes: usize,
+
+    #[arg(
+        long = "scaled",
+        help = "Construct the grid in coordinates scaled by the box size.",
+        long_help = "Construct the thickness map in the membrane plane in coordinates scaled by the size of the simulation box in every frame, i.e., from 0 to 1 along both axes. This prevents fluctuations of the box size (e.g., with semi-isotropic pressure coupling) from blurring the map near its edges. The grid range ('--xmin', '--xmax', '--ymin', '--ymax') is then specified in box fractions, while the bin size ('--bin') is still specified in nm and converted to box fractions using the simulation box of the input structure.",
+        help_heading = "Membrane normal, centering, and grid"
+    )]
+    scaled: bool,
+
+    #[arg(
+        long = "thickness-method",
+        help = "Definition of the membrane thickness.",
+        long_help = "Definition of the membrane thickness. 'heads' calculates the thickness as the distance between the average positions of headgroups in the two leaflets. 'pairwise' pairs each headgroup with the laterally closest headgroup of the opposite leaflet (within '--pair-cutoff') and bins the distance between them along the membrane normal at the midpoint of the pair, which resolves local deformations (e.g., around proteins) more sharply. 'voronoi' assigns every grid bin in each leaflet to the Voronoi cell of the laterally closest headgroup and uses the distance of this headgroup, so every analyzed frame contributes one sample to each grid bin and no bins remain empty even for small systems. 'luzzati' calculates the Luzzati thickness from the number of water atoms (see '--water') in each grid column, i.e., the length of the column minus the length it would take the water in it at the density of bulk water. The density of bulk water is estimated in every frame from the water located farther from the membrane center than all headgroups. For 'luzzati', every analyzed frame contributes exactly one sample to each grid bin. 'kde' estimates continuous surfaces of both leaflets in every frame by averaging the distances of the headgroups weighted by a Gaussian kernel of their lateral distance (see '--kde-bandwidth') and samples the surfaces at the centers of the grid bins, so the resolution of the map does not depend on the number of headgroups in the bins.",
+        value_enum,
+        default_value_t = ThicknessMethod::Heads,
+        help_heading = "Thickness calculation"
+    )]
+    thickness_method: ThicknessMethod,
+
+    #[arg(
+        short = 'w',
+        long = "water",
+        help = "Specification of water atoms.",
+        long_help = "Specify water atoms used to calculate the Luzzati thickness with '--thickness-method luzzati'. Use one atom per water molecule (e.g., the oxygen atoms).",
+        help_heading = "Thickness calculation"
+    )]
+    water: Option<String>,
+
+    #[arg(
+        long = "pair-cutoff",
+        help = "Maximal lateral distance between paired headgroups (in nm).",
+        long_help = "Maximal lateral distance (in nm) between a headgroup and the closest headgroup of the opposite leaflet for the pair to be counted with '--thickness-method pairwise'. Headgroups without any headgroup of the opposite leaflet within this distance are not counted.",
+        default_value_t = 1.5,
+        help_heading = "Thickness calculation"
+    )]
+    pair_cutoff: f32,
+
+    #[arg(
+        long = "kde-bandwidth",
+        help = "Width of the kernel estimating the leaflet surfaces (in nm).",
+        long_help = "Width (standard deviation, in nm) of the Gaussian kernel used to estimate the surfaces of the leaflets from the distances of the headgroups with '--thickness-method kde'. The kernel is truncated at three widths from each headgroup, so bins without any headgroup of a leaflet within this distance obtain no sample for this leaflet in the frame. Smaller widths resolve finer features of the surfaces but produce noisier maps. Cannot be combined with '--scaled'.",
+        default_value_t = 1.0,
+        help_heading = "Thickness calculation"
+    )]
+    kde_bandwidth: f32,
+
+    #[arg(
+        long = "kernel",
+        help = "Deposit each headgroup as a Gaussian of the specified width instead of into a single bin.",
+        long_help = "Deposit each headgroup sample as a normalized two-dimensional Gaussian of the specified width (standard deviation, in nm) centered on the headgroup instead of adding the sample to the single grid bin the headgroup is located in. Each bin within three widths from the headgroup receives the fraction of the sample given by the Gaussian, while the headgroup is counted as a sample (e.g., towards the NAN limit '--nan' or the headgroup density) only in the bin it is located in. This produces smooth maps at fine grid resolutions without post-processing the map. Only supported with '--thickness-method heads' for planar membranes in unscaled coordinates.",
+        value_name = "SIGMA",
+        help_heading = "Thickness calculation"
+    )]
+    kernel: Option<f32>,
+
+    #[arg(
+        long = "smooth",
+        help = "Smooth the thickness map using a Gaussian filter of this width.",
+        long_help = "Smooth the final thickness map written into the output file using a Gaussian filter of the specified width (standard deviation, in the units of the map coordinates, i.e., nm for planar membranes). Bins with undefined thickness are ignored when smoothing their neighbors and remain undefined. The average thickness at the end of the file is calculated from the smoothed map. Other maps are not smoothed.",
+        value_name = "SIGMA",
+        help_heading = "Processing of the thickness map"
+    )]
+    smooth: Option<f32>,
+
+    #[arg(
+        long = "fill-radius",
+        help = "Fill undefined bins of the thickness map by interpolation.",
+        long_help = "Fill the bins of the final thickness map with undefined thickness (e.g., bins not passing the NAN limit) by inverse-distance interpolation from the bins with defined thickness within the specified radius (in the units of the map coordinates, i.e., nm for planar membranes). Bins without any defined bins within the radius remain undefined. A mask flagging the interpolated bins with 1 and the other defined bins with 0 is written into a companion file named by appending 'mask' to the name of the output file, e.g., 'membrane_thickness.dat' becomes 'membrane_thickness_mask.dat'. Bins are filled before smoothing (see '--smooth') and other maps are not filled.",
+        value_name = "RADIUS",
+        help_heading = "Processing of the thickness map"
+    )]
+    fill_radius: Option<f32>,
+
+    #[arg(
+        long = "symmetrize",
+        help = "Impose a symmetry on the thickness map.",
+        long_help = "Symmetrize the final thickness map written into the output file before it is filled (see '--fill-radius') and smoothed (see '--smooth'). 'mirror-x' and 'mirror-y' average every bin with its mirror image with respect to the line through the symmetry center parallel to the second and the first axis of the membrane plane, respectively. 'rotation' averages every bin with its images rotated around the symmetry center by multiples of 360 degrees divided by '--symmetry-order' (e.g., 4 for tetrameric channels). The symmetry center is given by '--origin' and defaults to the center of the map. Images outside the map are ignored and bins with undefined thickness are filled from their defined images. The average thickness at the end of the file is calculated from the symmetrized map. Only supported for planar membranes with Cartesian grids. Other maps are not symmetrized.",
+        value_enum,
+        value_name = "SYMMETRY",
+        help_heading = "Processing of the thickness map"
+    )]
+    symmetrize: Option<Symmetry>,
+
+    #[arg(
+        long = "symmetry-order",
+        help = "Order of the rotational symmetry of the thickness map.",
+        long_help = "Number of symmetry-equivalent orientations of the thickness map symmetrized using '--symmetrize rotation'.",
+        default_value_t = 4,
+        help_heading = "Processing of the thickness map"
+    )]
+    symmetry_order: usize,
+
+    #[arg(
+        long = "frame-maps",
+        help = "Also write the thickness map for every analyzed frame.",
+        long_help = "Write the instantaneous membrane thickness map for every analyzed trajectory frame. For each frame, a separate file is created by appending the frame number to the provided file name, e.g., 'frames/map.dat' becomes 'frames/map_000000.dat', 'frames/map_000001.dat', etc. These maps are not subject to the NAN limit and the thickness is calculated for every bin containing at least one phosphate of each leaflet. Not supported for parallel analysis.",
+        help_heading = "Additional maps"
+    )]
+    frame_maps: Option<String>,
+
+    #[arg(
+        long = "stdev",
+        help = "Also write the map of the standard deviation of the thickness.",
+        long_help = "Write the map of the standard deviation of the membrane thickness samples in each bin into this file. The deviation is calculated from the spreads of the samples of both leaflets, which are treated as independent (for '--thickness-method luzzati', both leaflets are given halves of the same samples). Bins with large deviations and few samples should be interpreted with care.",
+        help_heading = "Additional maps"
+    )]
+    stdev: Option<String>,
+
+    #[arg(
+        long = "extremes",
+        help = "Also write the maps of the minimal and maximal instantaneous thickness.",
+        long_help = "Calculate the instantaneous thickness of every bin in every analyzed frame from the samples of both leaflets added by the frame and write the maps of its minimal and maximal value over the trajectory. Two files are created by appending 'min' and 'max' to the provided file name, e.g., 'extremes.dat' becomes 'extremes_min.dat' and 'extremes_max.dat'. The extremes of bins with undefined thickness in the final map are undefined. Cannot be combined with '--state' and '--restart'.",
+        help_heading = "Additional maps"
+    )]
+    extremes: Option<String>,
+
+    #[arg(
+        long = "leaflet-maps",
+        help = "Also write the maps of the individual leaflets.",
+        long_help = "Write the maps of the average distance of the headgroups of each leaflet from the membrane center along the normal (for vesicles and tubes, the radius of the leaflet). Two files are created by appending the name of the leaflet to the provided file name, e.g., 'leaflets.dat' becomes 'leaflets_upper.dat' and 'leaflets_lower.dat'. The distances of the lower (inner) leaflet are negative for planar membranes. Not supported with '--thickness-method luzzati'.",
+        help_heading = "Additional maps"
+    )]
+    leaflet_maps: Option<String>,
+
+    #[arg(
+        long = "midplane",
+        help = "Also write the map of the membrane midplane.",
+        long_help = "Write the map of the position of the membrane midplane into this file. The midplane is calculated in each bin as the mean of the average distances of the headgroups of both leaflets from the membrane center along the normal (for vesicles and tubes, the mean of the radii of both leaflets). Not supported with '--thickness-method luzzati'.",
+        help_heading = "Additional maps"
+    )]
+    midplane: Option<String>,
+
+    #[arg(
+        long = "curvature",
+        help = "Also write the map of the mean curvature of the membrane midplane.",
+        long_help = "Write the map of the mean curvature of the membrane midplane (see '--midplane') into this file. The curvature is calculated from the time-averaged height of the midplane using central finite differences, so it is undefined (NaN) for bins at the edges of the map and for bins neighboring any bin with undefined height. The curvature is negative where the midplane bulges toward the upper leaflet. Only supported for planar membranes and not with '--scaled' and '--thickness-method luzzati'.",
+        help_heading = "Additional maps"
+    )]
+    curvature: Option<String>,
+
+    #[arg(
+        long = "gaussian-curvature",
+        help = "Also write the map of the Gaussian curvature of the membrane midplane.",
+        long_help = "Write the map of the Gaussian curvature of the membrane midplane (see '--midplane') into this file. The curvature is calculated in the same way as the mean curvature (see '--curvature') and has the same limitations.",
+        help_heading = "Additional maps"
+    )]
+    gaussian_curvature: Option<String>,
+
+    #[arg(
+        long = "spectrum",
+        help = "Also write the undulation spectrum of the membrane midplane.",
+        long_help = "Calculate the two-dimensional Fourier spectrum of the instantaneous height of the membrane midplane in every analyzed frame and write the radially averaged spectrum A<|h(q)|^2> into this file. In each frame, the heights of the headgroups of both leaflets are averaged in bins of the size given by '--bin' covering the whole membrane plane of the simulation box, bins without any headgroups are assigned the average height of their leaflet, and the midplane is the mean of both leaflets. The bending rigidity can be estimated by fitting kT / (kappa q^4) to the spectrum at small q. Only supported for planar membranes with the normal oriented along an axis of the simulation box. Cannot be combined with '--state' and '--restart'.",
+        help_heading = "Additional maps"
+    )]
+    spectrum: Option<String>,
+
+    #[arg(
+        long = "domains",
+        help = "Also write the map of thick and thin membrane domains.",
+        long_help = "Segment the final thickness map into connected domains of bins thicker and thinner than a threshold (see '--domain-threshold') and write the map of the domain labels into this file. Bins are connected through their sides, not across periodic boundaries, and bins with undefined thickness do not belong to any domain. The type, area, and average thickness of every domain are written at the end of the file. Only supported for planar and buckled membranes and not with '--scaled'.",
+        help_heading = "Additional maps"
+    )]
+    domains: Option<String>,
+
+    #[arg(
+        long = "domain-threshold",
+        help = "Thickness separating thick and thin domains.",
+        long_help = "Thickness (in nm) separating the thick and thin domains of the membrane (see '--domains'). If not provided, the threshold is determined from the thickness map using Otsu's method, i.e., as the value that maximizes the variance between the thickness of the thick and thin bins.",
+        help_heading = "Additional maps"
+    )]
+    domain_threshold: Option<f32>,
+
+    #[arg(
+        long = "projection",
+        help = "Also write the thickness map averaged along each of its axes.",
+        long_help = "Collapse the final thickness map into one-dimensional profiles of the thickness along the first and the second map coordinate and write them into two files named by appending 'x' and 'y' to the provided file name, e.g., 'projection.dat' becomes 'projection_x.dat' and 'projection_y.dat'. Each point of a profile is the average thickness of the bins sharing the coordinate (bins with undefined thickness are ignored) together with its standard error and the number of averaged bins. This is useful for membranes varying along one direction only (e.g., buckled membranes).",
+        value_name = "FILE",
+        help_heading = "Additional maps"
+    )]
+    projection: Option<String>,
+
+    #[arg(
+        long = "adaptive",
+        help = "Also write the thickness map with adaptive resolution.",
+        long_help = "Write the thickness map with adaptive resolution into this file. The grid is first split into square blocks of 2^N x 2^N bins (see '--adaptive-levels') and every block is recursively split into four quarters as long as each quarter contains at least the required number of samples (see '--nan') in both leaflets. Every bin of the grid is then assigned the thickness calculated from all samples of the adaptive bin it belongs to, so the map is fine in well-sampled regions and coarse elsewhere. The sizes of the adaptive bins (number of grid bins along each side) are written into a companion file named by appending 'size' to the provided file name, e.g., 'adaptive.dat' becomes 'adaptive_size.dat'.",
+        value_name = "FILE",
+        help_heading = "Additional maps"
+    )]
+    adaptive: Option<String>,
+
+    #[arg(
+        long = "adaptive-levels",
+        help = "Number of levels of the adaptive map.",
+        long_help = "Number of times the largest adaptive bins of '--adaptive' can be split into quarters. The largest adaptive bins contain 2^N x 2^N grid bins and the smallest adaptive bins correspond to the bins of the grid.",
+        value_name = "N",
+        default_value_t = 3,
+        help_heading = "Additional maps"
+    )]
+    adaptive_levels: u32,
+
+    #[arg(
+        long = "area-per-lipid",
+        help = "Also write the maps of the area per lipid in both leaflets.",
+        long_help = "Calculate the area of the Voronoi cell of every headgroup in the membrane plane (tessellating each leaflet separately) in every analyzed frame and write the time-averaged maps of the area per lipid in both leaflets. The area of each cell is assigned to the grid bin of its headgroup. Two files are created by appending the name of the leaflet to the provided file name, e.g., 'apl.dat' becomes 'apl_upper.dat' and 'apl_lower.dat'. Voronoi cells are limited to 2 nm from their headgroups in each direction. Only supported for planar membranes. Cannot be combined with '--state' and '--restart'.",
+        help_heading = "Additional maps"
+    )]
+    area_per_lipid: Option<String>,
+
+    #[arg(
+        long = "density",
+        help = "Also write the maps of the number density of headgroups in both leaflets.",
+        long_help = "Write the maps of the number density of headgroups (number per nm² per frame) in both leaflets. Two files are created by appending the name of the leaflet to the provided file name, e.g., 'density.dat' becomes 'density_upper.dat' and 'density_lower.dat'. Frame weights (see '--weights') are not applied to the densities. Only supported with '--thickness-method heads' for planar and buckled membranes and not with '--scaled'.",
+        help_heading = "Additional maps"
+    )]
+    density: Option<String>,
+
+    #[arg(
+        long = "asymmetry",
+        help = "Also write the map of the asymmetry of the number of headgroups between the leaflets.",
+        long_help = "Write the map of the asymmetry of the number of headgroups between the leaflets, i.e., (N_upper - N_lower) / (N_upper + N_lower), where N is the number of headgroups of the leaflet sampled in the bin over the trajectory. Values close to 1 or -1 flag regions where one of the leaflets is depleted (e.g., lipids extracted by a protein) and the thickness is unreliable. The asymmetry is undefined for bins without any headgroups. The asymmetry of all headgroups is written at the end of the file. Only supported with '--thickness-method heads'.",
+        help_heading = "Additional maps"
+    )]
+    asymmetry: Option<String>,
+
+    #[arg(
+        long = "roughness",
+        help = "Also write the maps of the roughness of both leaflets.",
+        long_help = "Write the maps of the roughness of both leaflets, i.e., the root-mean-square fluctuation of the distances of the headgroups from the membrane center around their time-averaged value in each grid bin. Two files are created by appending the name of the leaflet to the provided file name, e.g., 'roughness.dat' becomes 'roughness_upper.dat' and 'roughness_lower.dat'. The roughness of each leaflet over all its headgroups is written at the end of each file. Only supported with '--thickness-method heads'. Cannot be combined with '--state' and '--restart'.",
+        help_heading = "Additional maps"
+    )]
+    roughness: Option<String>,
+
+    #[arg(
+        long = "hydrophobic",
+        help = "Specification of atoms delimiting the hydrophobic core.",
+        long_help = "Specify atoms delimiting the hydrophobic core of the membrane (e.g., the first carbon atoms of lipid tails or the first tail beads). A second map of the hydrophobic thickness is then calculated from the positions of these atoms in the same pass through the trajectory and written into '--hydrophobic-output'. The atoms are assigned to the leaflet of the headgroup of their lipid (identified by its residue number). Several atoms per lipid can be selected. Cannot be combined with '--state' and '--restart'.",
+        help_heading = "Additional maps"
+    )]
+    hydrophobic: Option<String>,
+
+    #[arg(
+        long = "hydrophobic-output",
+        help = "Path to the output file for the hydrophobic thickness map.",
+        long_help = "Path to the output file where the map of the hydrophobic thickness will be written when using '--hydrophobic'.",
+        default_value = "hydrophobic.dat",
+        help_heading = "Additional maps"
+    )]
+    hydrophobic_output: String,
+
+    #[arg(
+        long = "interdigitation",
+        help = "Specification of lipid tail ends for mapping the interdigitation of the leaflets.",
+        long_help = "Specify the terminal atoms of lipid tails (e.g., 'name C4A C4B' in Martini). In every grid bin, the distributions of the distances of these atoms from the membrane center along the normal are collected separately for both leaflets and their overlap is written into '--interdigitation-output'. The overlap is 0 for fully separated leaflets and 1 for completely interdigitated leaflets. The atoms are assigned to the leaflet of the headgroup of their lipid (identified by its residue number). Several atoms per lipid can be selected. Only supported with '--thickness-method heads' for planar and buckled membranes. Cannot be combined with '--state' and '--restart'.",
+        help_heading = "Additional maps"
+    )]
+    interdigitation: Option<String>,
+
+    #[arg(
+        long = "interdigitation-output",
+        help = "Path to the output file for the interdigitation map.",
+        long_help = "Path to the output file where the map of the leaflet interdigitation will be written when using '--interdigitation'.",
+        default_value = "interdigitation.dat",
+        help_heading = "Additional maps"
+    )]
+    interdigitation_output: String,
+
+    #[arg(
+        long = "sterol-output",
+        help = "Path to the output file for the thickness map calculated from sterols.",
+        long_help = "Write the thickness map calculated only from the sterol headgroups (see '--sterol-heads') into this file, in addition to the thickness map calculated from all headgroups. Cannot be combined with '--state' and '--restart'.",
+        requires = "sterol_heads",
+        help_heading = "Additional maps"
+    )]
+    sterol_output: Option<String>,
+
+    #[arg(
+        long = "composition",
+        help = "Path to the output file for the lipid composition map.",
+        long_help = "Write the lipid composition of the grid bins into this file. For each grid bin and leaflet, the file contains the fraction of headgroup samples contributed by each lipid species (identified by its residue name). Only supported with '--thickness-method heads'. Cannot be combined with '--state' and '--restart'.",
+        help_heading = "Additional maps"
+    )]
+    composition: Option<String>,
+
+    #[arg(
+        long = "environment",
+        help = "Specification of lipid atoms classifying the local environment of headgroups.",
+        long_help = "Specify lipid atoms (e.g., 'name ROH' for the hydroxyl beads of cholesterol) whose number within '--environment-cutoff' from each headgroup classifies the headgroup. The average distances of the headgroups from the membrane center and the resulting membrane thickness are then calculated separately for each number of neighboring environment atoms and written into '--environment-output'. Atoms of the lipid of the headgroup itself are not counted. Only supported with '--thickness-method heads'. Cannot be combined with '--state' and '--restart'.",
+        help_heading = "Additional maps"
+    )]
+    environment: Option<String>,
+
+    #[arg(
+        long = "environment-cutoff",
+        help = "Distance within which the environment atoms are counted (in nm).",
+        long_help = "Distance (in nm) from a headgroup within which the environment atoms (see '--environment') are counted.",
+        default_value_t = 1.0,
+        help_heading = "Additional maps"
+    )]
+    environment_cutoff: f32,
+
+    #[arg(
+        long = "environment-output",
+        help = "Path to the output file for the thickness classified by the lipid environment.",
+        long_help = "Path to the output file where the membrane thickness classified by the number of neighboring environment atoms will be written when using '--environment'.",
+        default_value = "environment.dat",
+        help_heading = "Additional maps"
+    )]
+    environment_output: String,
+
+    #[arg(
+        long = "lipid-table",
+        help = "Path to the output file for the thickness experienced by each lipid.",
+        long_help = "Write a table listing, for every headgroup, the residue number and name of its lipid, its leaflet, the average local membrane thickness experienced by the lipid over the trajectory, and the number of samples of the headgroup into this file. The local thickness is taken from the final thickness map in the grid bins visited by the headgroup. Only supported with '--thickness-method heads'. Cannot be combined with '--state' and '--restart'.",
+        help_heading = "Additional maps"
+    )]
+    lipid_table: Option<String>,
+
+    #[arg(
+        long = "roi",
+        help = "Compare the thickness inside and outside a region of interest.",
+        long_help = "Read a region of interest in the membrane plane from this file and write the average thickness and the histograms of the thickness of the bins inside and outside the region into the file specified by '--roi-output'. The file contains either the vertices of a polygon in the coordinates of the thickness map, each on a separate line as 'x y' (bins with centers on the edges of the polygon are inside the region), or a mask map with the same bins as the thickness map in the format written by memthick (e.g., the map written by '--occupancy'), in which bins with defined non-zero values are inside the region. Unlike '--xmin', '--xmax', '--ymin', and '--ymax', the region can have any shape, e.g., follow the curved boundary of a domain.",
+        value_name = "FILE",
+        help_heading = "Profiles and regions of interest"
+    )]
+    roi: Option<String>,
+
+    #[arg(
+        long = "roi-output",
+        help = "Path to the output file for the thickness inside and outside the region of interest.",
+        long_help = "Path to the output file where the histograms of the thickness of the bins inside and outside the region of interest read using '--roi' will be written. The average thickness of both parts is written at the end of the file.",
+        default_value = "roi.dat",
+        help_heading = "Profiles and regions of interest"
+    )]
+    roi_output: String,
+
+    #[arg(
+        long = "radial",
+        help = "Also write the radial profile of the thickness.",
+        long_help = "Write the profile of the membrane thickness as a function of the lateral distance from a point in the membrane plane into this file. The point is either fixed (see '--origin') or the center of the atoms selected using '--radial-center' in every analyzed frame. The headgroups are binned into concentric shells of the width given by '--radial-width' reaching to half of the shorter side of the simulation box, using the periodic image of each headgroup closest to the point. For every shell, the file contains the thickness calculated from all samples of the shell and its standard error estimated from the thickness of the shell in the individual frames (assuming independent frames). Only supported with '--thickness-method heads' for planar membranes with the normal oriented along an axis of the simulation box. Cannot be combined with '--state' and '--restart'.",
+        value_name = "FILE",
+        help_heading = "Profiles and regions of interest"
+    )]
+    radial: Option<String>,
+
+    #[arg(
+        long = "radial-center",
+        help = "Selection of atoms at the center of the radial profile.",
+        long_help = "Specify atoms (e.g., a transmembrane protein) whose center in the membrane plane is the center of the radial profile (see '--radial'), the azimuthal profile (see '--azimuthal'), and the azimuthal map (see '--azimuthal-map') in every analyzed frame. If not provided, the profiles are calculated around the point given by '--origin'.",
+        conflicts_with = "origin",
+        help_heading = "Profiles and regions of interest"
+    )]
+    radial_center: Option<String>,
+
+    #[arg(
+        long = "radial-width",
+        help = "Width of the shells of the radial profiles (in nm).",
+        long_help = "Width (in nm) of the shells of the radial profiles (see '--radial' and '--around').",
+        default_value_t = 0.2,
+        help_heading = "Profiles and regions of interest"
+    )]
+    radial_width: f32,
+
+    #[arg(
+        long = "azimuthal",
+        help = "Also write the azimuthal profile of the thickness in an annulus.",
+        long_help = "Write the profile of the membrane thickness as a function of the azimuthal angle around the center of the radial profile (see '--radial-center' and '--origin') into this file. Only the headgroups within the annulus given by '--annulus' are used and they are binned into sectors of the angle given by '--angle-bin'. The angle is measured in the membrane plane of the simulation box, so the frames should be fitted onto the input structure (see '--fit') for a rotating protein. The file has the same format as the radial profile (see '--radial'). The same restrictions as for '--radial' apply.",
+        value_name = "FILE",
+        help_heading = "Profiles and regions of interest"
+    )]
+    azimuthal: Option<String>,
+
+    #[arg(
+        long = "annulus",
+        help = "Inner and outer radius of the annulus of the azimuthal profile (in nm).",
+        long_help = "Inner and outer radius (in nm) of the annulus around the center of the radial profile whose headgroups are used for the azimuthal profile (see '--azimuthal'). Required with '--azimuthal'.",
+        num_args = 2,
+        value_names = ["RMIN", "RMAX"],
+        help_heading = "Profiles and regions of interest"
+    )]
+    annulus: Option<Vec<f32>>,
+
+    #[arg(
+        long = "azimuthal-map",
+        help = "Also write the thickness map in the distance and the angle around the center of the radial profile.",
+        long_help = "Write the map of the membrane thickness in the lateral distance from the center of the radial profile (see '--radial-center' and '--origin'), binned using '--radial-width', and the azimuthal angle around it, binned using '--angle-bin', into this file. Unlike '--grid polar', the map follows the atoms selected by '--radial-center'. The same restrictions as for '--radial' apply.",
+        value_name = "FILE",
+        help_heading = "Profiles and regions of interest"
+    )]
+    azimuthal_map: Option<String>,
+
+    #[arg(
+        long = "around",
+        help = "Selection of atoms for the thickness profile by the distance from them.",
+        long_help = "Specify atoms (e.g., a transmembrane protein) and write the profile of the membrane thickness as a function of the lateral distance from the closest of these atoms into '--around-output'. In every analyzed frame, each headgroup is binned into a shell (see '--radial-width') by its minimal lateral distance from the selected atoms calculated using the periodic boundary conditions, so the profile follows the selected atoms as they diffuse and adapts to their shape. The file has the same format as the radial profile (see '--radial'). Only supported with '--thickness-method heads' for planar membranes with the normal oriented along an axis of the simulation box. Cannot be combined with '--state' and '--restart'.",
+        help_heading = "Profiles and regions of interest"
+    )]
+    around: Option<String>,
+
+    #[arg(
+        long = "around-output",
+        help = "Path to the output file for the thickness profile by the distance from the selected atoms.",
+        long_help = "Path to the output file where the profile of the membrane thickness by the lateral distance from the atoms selected using '--around' will be written.",
+        default_value = "around.dat",
+        help_heading = "Profiles and regions of interest"
+    )]
+    around_output: String,
+
+    #[arg(
+        long = "protein",
+        help = "Mask the bins covered by the selected protein atoms.",
+        long_help = "Specify protein atoms whose footprint in the membrane plane is masked in the maps. A bin is covered by the protein in a frame if its center lies within '--protein-radius' from any of the selected atoms in the membrane plane. Bins covered in a larger fraction of the analyzed frames than '--protein-threshold' have undefined thickness in all maps and are excluded from the average thickness. The number of masked bins is written at the end of the output file. Only supported for planar membranes with Cartesian grids in unscaled coordinates. Cannot be combined with '--state' and '--restart'.",
+        value_name = "SELECTION",
+        help_heading = "Proteins and inclusions"
+    )]
+    protein: Option<String>,
+
+    #[arg(
+        long = "protein-radius",
+        help = "Radius of the protein atoms covering the bins (in nm).",
+        long_help = "Maximal lateral distance (in nm) of the center of a bin from a protein atom (see '--protein') for the bin to be covered by the protein.",
+        default_value_t = 0.5,
+        help_heading = "Proteins and inclusions"
+    )]
+    protein_radius: f32,
+
+    #[arg(
+        long = "protein-threshold",
+        help = "Fraction of frames above which the bins covered by the protein are masked.",
+        long_help = "Bins covered by the protein (see '--protein') in a larger fraction of the analyzed frames than this threshold are masked. Use 1 to only map the occupancy of the bins by the protein (see '--occupancy') without masking any bins.",
+        default_value_t = 0.5,
+        help_heading = "Proteins and inclusions"
+    )]
+    protein_threshold: f64,
+
+    #[arg(
+        long = "exclude-shell",
+        help = "Exclude the headgroups within this distance from the protein (in nm).",
+        long_help = "Exclude the headgroups located within the specified lateral distance (in nm) from any protein atom (see '--protein') from the analysis in every frame. This removes the annular lipids perturbed by the protein, so the map describes the unperturbed membrane only and can be subtracted from the map of the membrane with the protein (see 'memthick diff'). Requires '--protein'.",
+        value_name = "CUTOFF",
+        help_heading = "Proteins and inclusions"
+    )]
+    exclude_shell: Option<f32>,
+
+    #[arg(
+        long = "occupancy",
+        help = "Also write the map of the occupancy of the bins by the protein.",
+        long_help = "Write the map of the fraction of the analyzed frames in which each bin is covered by the protein (see '--protein') into this file. The map can be used to overlay the silhouette of the protein on the thickness map. Requires '--protein'.",
+        help_heading = "Proteins and inclusions"
+    )]
+    occupancy: Option<String>,
+
+    #[arg(
+        long = "residues",
+        help = "Calculate the membrane thickness at the positions of the selected protein residues.",
+        long_help = "Specify protein atoms and calculate the average membrane thickness at the lateral position of each of their residues over the trajectory. In every analyzed frame, the center of the selected atoms of each residue is assigned to a bin of the map. The thickness of a residue is the average thickness of the final map over the bins visited by the residue, weighted by the number of frames spent in each bin and ignoring bins with undefined thickness. Select interfacial residues, as bins located inside the protein typically have undefined thickness. The number, name, thickness, and the number of contributing frames of every residue are written into the file specified by '--residues-output'. Only supported for planar membranes with Cartesian grids in unscaled coordinates. Cannot be combined with '--state' and '--restart'.",
+        value_name = "SELECTION",
+        help_heading = "Proteins and inclusions"
+    )]
+    residues: Option<String>,
+
+    #[arg(
+        long = "residues-output",
+        help = "Path to the output file for the membrane thickness at the residues.",
+        long_help = "Path to the output file where the average membrane thickness at the positions of the residues selected using '--residues' will be written.",
+        default_value = "residues.dat",
+        help_heading = "Proteins and inclusions"
+    )]
+    residues_output: String,
+
+    #[arg(
+        long = "helix",
+        help = "Calculate the thickness maps conditioned on the tilt direction of the selected helix.",
+        long_help = "Specify the atoms of a transmembrane helix and calculate separate thickness maps for the frames with different directions of the tilt of the helix. The axis of the helix connects the centers of the first and the second half of the selected atoms (in the order of their indices) and is oriented towards the upper leaflet. The azimuthal angle of its projection onto the membrane plane is divided into '--tilt-sectors' sectors, the first one centered at 0°, and every frame contributes to the map of the sector containing the tilt of the helix in this frame. The maps are written into the files specified by '--tilt-output'. Center the frames on the helix (see '--center') to align the maps with the helix. Only supported for planar membranes. Cannot be combined with '--state' and '--restart'.",
+        value_name = "SELECTION",
+        help_heading = "Proteins and inclusions"
+    )]
+    helix: Option<String>,
+
+    #[arg(
+        long = "tilt-output",
+        help = "Path to the output files for the thickness maps conditioned on the helix tilt.",
+        long_help = "Path to the output files where the thickness maps conditioned on the tilt direction of the helix selected using '--helix' will be written. One file is created for every sector by appending the index of the sector to the provided file name, e.g., 'tilt.dat' becomes 'tilt_1.dat', 'tilt_2.dat', etc. The range of the azimuthal angle of the sector and the number of frames it contains are written at the end of each file.",
+        default_value = "tilt.dat",
+        help_heading = "Proteins and inclusions"
+    )]
+    tilt_output: String,
+
+    #[arg(
+        long = "tilt-sectors",
+        help = "Number of sectors of the tilt direction of the helix.",
+        long_help = "Number of sectors of equal width into which the azimuthal angle of the tilt of the helix (see '--helix') is divided.",
+        default_value_t = 4,
+        help_heading = "Proteins and inclusions"
+    )]
+    tilt_sectors: usize,
+
+    #[arg(
+        long = "inclusions",
+        help = "Calculate the radial profile of the thickness around each of the selected inclusions.",
+        long_help = "Specify the atoms of several separate inclusions (e.g., copies of a protein) and calculate the radial profile of the membrane thickness around the center of each inclusion (see '--radial-width'). The selected atoms are split into inclusions by their connectivity in the input structure: atoms closer than '--aggregate-cutoff' and atoms of the same residue belong to the same inclusion. The profile of every inclusion is written into a separate file named by appending the index of the inclusion to the file name specified by '--inclusions-output'. Only supported with '--thickness-method heads' for planar membranes. Cannot be combined with '--state' and '--restart'.",
+        value_name = "SELECTION",
+        help_heading = "Proteins and inclusions"
+    )]
+    inclusions: Option<String>,
+
+    #[arg(
+        long = "inclusions-output",
+        help = "Path to the output files for the radial profiles around the inclusions.",
+        long_help = "Path to the output files where the radial profiles of the thickness around the inclusions selected using '--inclusions' will be written, e.g., 'inclusions.dat' becomes 'inclusions_1.dat', 'inclusions_2.dat', etc.",
+        default_value = "inclusions.dat",
+        help_heading = "Proteins and inclusions"
+    )]
+    inclusions_output: String,
+
+    #[arg(
+        long = "inclusions-average",
+        action,
+        help = "Also write the radial profile averaged over all inclusions.",
+        long_help = "Also write the radial profile of the thickness calculated from the samples around all inclusions selected using '--inclusions' together, i.e., averaged over equivalent copies of the inclusion. The profile is written into the file named by appending 'average' to the file name specified by '--inclusions-output'.",
+        help_heading = "Proteins and inclusions"
+    )]
+    inclusions_average: bool,
+
+    #[arg(
+        long = "inclusion",
+        help = "Specify atoms perturbing the membrane to calculate the reference thickness of the deviation map.",
+        long_help = "Specify atoms perturbing the membrane (e.g., a transmembrane protein). The reference thickness of the deviation map (see '--deviation') and the far-field thickness of the deformation map (see '--deformation') is then the average thickness of the bins farther than '--bulk-cutoff' from all these atoms in the membrane plane. The positions of the atoms are taken from the input structure, so the frames should be centered on or fitted onto the inclusion (see '--center' and '--fit') if it moves. Only supported for planar membranes with Cartesian grids in unscaled coordinates.",
+        help_heading = "Proteins and inclusions"
+    )]
+    inclusion: Option<String>,
+
+    #[arg(
+        long = "bulk-cutoff",
+        help = "Minimal distance of the bulk membrane from the inclusion (in nm).",
+        long_help = "Minimal lateral distance (in nm) of the centers of the bins used to calculate the reference thickness of the deviation map and the far-field thickness of the deformation map from the atoms selected using '--inclusion'.",
+        default_value_t = 2.0,
+        help_heading = "Proteins and inclusions"
+    )]
+    bulk_cutoff: f32,
+
+    #[arg(
+        long = "deviation",
+        help = "Also write the map of the relative deviation of the thickness from the bulk membrane.",
+        long_help = "Write the map of the deviation of the membrane thickness from a reference thickness in percent of the reference thickness into this file. The reference thickness is given by '--reference-thickness' or calculated as the average thickness of the bins farther than '--bulk-cutoff' from the atoms selected using '--inclusion'. If neither is provided, the average thickness of the whole map is used. The reference thickness is written at the end of the file.",
+        value_name = "FILE",
+        help_heading = "Proteins and inclusions"
+    )]
+    deviation: Option<String>,
+
+    #[arg(
+        long = "reference-thickness",
+        help = "Reference thickness of the deviation map (in nm).",
+        long_help = "Thickness of the unperturbed membrane (in nm) from which the relative deviation of the thickness is calculated (see '--deviation').",
+        conflicts_with = "inclusion",
+        help_heading = "Proteins and inclusions"
+    )]
+    reference_thickness: Option<f64>,
+
+    #[arg(
+        long = "deformation",
+        help = "Also write the map of the deformation of the thickness from the far-field thickness.",
+        long_help = "Write the map of the deformation of the membrane thickness, i.e., the local thickness minus the far-field thickness of the unperturbed membrane (in nm), into this file. The far-field thickness is the average thickness of the bins farther than '--bulk-cutoff' from the atoms selected using '--inclusion'. The decay length of the deformation is obtained by fitting an exponential decay to the deformation averaged in shells of the distance from the closest atom of the inclusion (see '--radial-width') up to '--bulk-cutoff'. Only the shells with deformation of the same sign as the innermost shell are used. The far-field thickness, the decay length, and the extrapolated deformation at the inclusion are written at the end of the file. Requires '--inclusion'.",
+        value_name = "FILE",
+        help_heading = "Proteins and inclusions"
+    )]
+    deformation: Option<String>,
+
+    #[arg(
+        long = "convergence",
+        help = "Write the cumulative average thickness into this file.",
+        long_help = "Path to a file where the average membrane thickness calculated from all frames analyzed so far will be written as a function of the number of analyzed frames. Useful for judging whether the membrane thickness map has converged. When restarting the analysis, new values are appended to the file. Not supported for parallel analysis.",
+        help_heading = "Errors and convergence"
+    )]
+    convergence: Option<String>,
+
+    #[arg(
+        long = "convergence-freq",
+        help = "How often (in analyzed frames) the average thickness should be written.",
+        long_help = "How often (in the number of analyzed frames) the cumulative average membrane thickness should be written into the convergence file. The average thickness is also written after the last analyzed frame.",
+        default_value_t = 10,
+        help_heading = "Errors and convergence"
+    )]
+    convergence_freq: usize,
+
+    #[arg(
+        long = "autocorrelation",
+        help = "Write the autocorrelation of the average thickness into this file.",
+        long_help = "Calculate the average membrane thickness of every analyzed frame and write the autocorrelation function of this time series into the specified file. The integrated autocorrelation time and the corresponding number of statistically independent samples are reported in the file and at the end of the analysis. Use the correlation time to choose the length of the blocks for error estimation (see '--block-length'). Frames are assumed to be equally spaced in time and frame weights are not applied. Cannot be combined with '--state' and '--restart'.",
+        help_heading = "Errors and convergence"
+    )]
+    autocorrelation: Option<String>,
+
+    #[arg(
+        long = "effective-samples",
+        help = "Also write the map of the effective number of independent samples.",
+        long_help = "Calculate the instantaneous thickness of every bin in every analyzed frame and write the map of the effective number of statistically independent samples of the thickness of each bin, i.e., the number of frames in which the bin has samples in both leaflets divided by twice the integrated autocorrelation time (in frames) of its thickness. Unlike the raw number of samples (see '--nan'), this reveals bins whose thickness is dominated by a few slowly fluctuating configurations. The effective number of samples is undefined for bins with undefined thickness in the final map or sampled in fewer than three frames. The thickness of all bins in all frames is kept in memory. Frames are assumed to be equally spaced in time. Cannot be combined with '--state' and '--restart'.",
+        help_heading = "Errors and convergence"
+    )]
+    effective_samples: Option<String>,
+
+    #[arg(
+        long = "blocks",
+        help = "Estimate the error of the average thickness using N blocks.",
+        long_help = "Split the analyzed frames into N blocks of equal size and calculate the average membrane thickness separately for each block. The mean of the block averages and its standard error are then reported at the end of the output file. Not supported for parallel analysis, restarted analysis, trajectories read from the standard input, and when following the trajectory.",
+        value_name = "N",
+        help_heading = "Errors and convergence"
+    )]
+    blocks: Option<usize>,
+
+    #[arg(
+        long = "block-length",
+        help = "Estimate the errors using blocks of N frames.",
+        long_help = "Split the analyzed frames into consecutive blocks of N analyzed frames each (the last block may be shorter) instead of a fixed number of blocks (see '--blocks'). Choose N larger than the correlation time of the thickness so that the block averages are uncorrelated. Has the same limitations as '--blocks'.",
+        value_name = "N",
+        conflicts_with = "blocks",
+        help_heading = "Errors and convergence"
+    )]
+    block_length: Option<usize>,
+
+    #[arg(
+        long = "error-method",
+        help = "Method for estimating the errors from the blocks.",
+        long_help = "Method for estimating the errors of the average thickness and of the thickness of each bin (see '--block-errors') from the blocks of frames (see '--blocks' and '--block-length'). 'blocks' uses the standard error of the mean of the values calculated from the individual blocks. 'jackknife' recalculates the values with each block left out and reports the value calculated from all blocks with its jackknife standard error, which is more robust for blocks with very different amounts of data in a bin.",
+        value_enum,
+        default_value_t = ErrorMethod::Blocks,
+        help_heading = "Errors and convergence"
+    )]
+    error_method: ErrorMethod,
+
+    #[arg(
+        long = "block-errors",
+        help = "Also write the map of the standard errors of the thickness.",
+        long_help = "Write the map of the standard error of the membrane thickness in each bin estimated by block averaging into this file. The thickness of each bin is calculated separately for every block (see '--blocks' and '--block-length') that contains at least one sample in both leaflets of the bin, and the standard error of the mean of these block values is written (see '--error-method' for the jackknife estimate). The error is undefined (NaN) for bins with undefined thickness in the final map and for bins with data in fewer than two blocks. Requires '--blocks' or '--block-length'.",
+        help_heading = "Errors and convergence"
+    )]
+    block_errors: Option<String>,
+
+    #[arg(
+        long = "bootstrap",
+        help = "Also write the maps of the confidence bounds of the thickness.",
+        long_help = "Estimate the confidence interval of the membrane thickness in each bin by bootstrapping over the blocks of frames (see '--blocks' and '--block-length') and write the maps of its lower and upper bounds. Two files are created by appending 'lower_bound' and 'upper_bound' to the provided file name, e.g., 'bootstrap.dat' becomes 'bootstrap_lower_bound.dat' and 'bootstrap_upper_bound.dat'. Use '--block-length 1' to bootstrap over the individual frames, although the interval is then too narrow for correlated frames. Requires '--blocks' or '--block-length'.",
+        help_heading = "Errors and convergence"
+    )]
+    bootstrap: Option<String>,
+
+    #[arg(
+        long = "bootstrap-samples",
+        help = "Number of bootstrap samples.",
+        long_help = "Number of bootstrap samples drawn to estimate the confidence interval of the thickness (see '--bootstrap').",
+        default_value_t = 1000,
+        help_heading = "Errors and convergence"
+    )]
+    bootstrap_samples: usize,
+
+    #[arg(
+        long = "confidence",
+        help = "Confidence level of the bootstrapped interval.",
+        long_help = "Fraction of the bootstrap samples enclosed by the confidence interval of the thickness (see '--bootstrap').",
+        default_value_t = 0.95,
+        help_heading = "Errors and convergence"
+    )]
+    confidence: f64,
+
+    #[arg(
+        long = "seed",
+        help = "Seed for the bootstrap.",
+        long_help = "Seed of the pseudo-random number generator used to draw the bootstrap samples (see '--bootstrap'). The same seed gives the same confidence intervals.",
+        default_value_t = 42,
+        help_heading = "Errors and convergence"
+    )]
+    seed: u64,
+
+    /// Index of the analyzed membrane if the system contains several membranes.
+    #[arg(skip)]
+    membrane: Option<usize>,
+
+    /// Output files of the convergence assessment if the analysis is run by the `converge` subcommand.
+    #[arg(skip)]
+    converge: Option<ConvergeOutput>,
+
+    /// Set if the membrane normal was detected automatically.
+    #[arg(skip)]
+    auto_normal: bool,
+}
+
+impl Args {
+    /// Check whether the membrane normal should be detected automatically.
+    fn normal_is_auto(&self) -> bool {
+        matches!(self.normal.as_slice(), [value] if value.eq_ignore_ascii_case("auto"))
+    }
+
+    /// Get the orientation of the membrane normal.
+    fn normal(&self) -> Normal {
+        Normal::parse(&self.normal).expect(
+            "FATAL MEMTHICK ERROR | Args::normal | Membrane normal should have been validated.",
+        )
+    }
+
+    /// Get the origin of the polar grid and the radial profile in the membrane plane.
+    fn origin(&self, simbox: &SimBox) -> (f32, f32) {
+        match self.origin.as_deref() {
+            Some(&[x, y]) => (x, y),
+            _ => {
+                let (range_x, range_y) = self.normal().lateral_range(simbox);
+                ((range_x.0 + range_x.1) / 2.0, (range_y.0 + range_y.1) / 2.0)
+            }
+        }
+    }
+
+    /// Check whether any profile around the center of the radial profile is requested.
+    fn centered_profiles(&self) -> bool {
+        self.radial.is_some() || self.azimuthal.is_some() || self.azimuthal_map.is_some()
+    }
+
+    /// Get the origin of the polar grid in the membrane plane (`None` for Cartesian grids).
+    fn polar_origin(&self, simbox: &SimBox) -> Option<(f32, f32)> {
+        (self.grid == Grid::Polar).then(|| self.origin(simbox))
+    }
+
+    /// Get the names of all output files written for each membrane, i.e., the outputs with default names
+    /// and the requested optional outputs.
+    fn outputs_mut(&mut self) -> impl Iterator<Item = &mut String> {
+        let converge = self.converge.as_mut().into_iter().flat_map(|converge| {
+            std::iter::once(&mut converge.output).chain(converge.segment_maps.as_mut())
+        });
+
+        [
+            &mut self.output,
+            &mut self.roi_output,
+            &mut self.around_output,
+            &mut self.tilt_output,
+            &mut self.inclusions_output,
+            &mut self.hydrophobic_output,
+            &mut self.interdigitation_output,
+            &mut self.environment_output,
+            &mut self.residues_output,
+        ]
+        .into_iter()
+        .chain(
+            [
+                &mut self.stdev,
+                &mut self.deviation,
+                &mut self.deformation,
+                &mut self.extremes,
+                &mut self.leaflet_maps,
+                &mut self.midplane,
+                &mut self.curvature,
+                &mut self.gaussian_curvature,
+                &mut self.spectrum,
+                &mut self.radial,
+                &mut self.azimuthal,
+                &mut self.azimuthal_map,
+                &mut self.domains,
+                &mut self.projection,
+                &mut self.adaptive,
+                &mut self.area_per_lipid,
+                &mut self.density,
+                &mut self.asymmetry,
+                &mut self.roughness,
+                &mut self.autocorrelation,
+                &mut self.effective_samples,
+                &mut self.sterol_output,
+                &mut self.composition,
+                &mut self.lipid_table,
+                &mut self.occupancy,
+                &mut self.block_errors,
+                &mut self.bootstrap,
+            ]
+            .into_iter()
+            .flatten(),
+        )
+        .chain(converge)
+    }
+
+    /// Describe the selection of the headgroups.
+    fn heads(&self) -> String {
+        let description = match (
+            &self.upper,
+            &self.lower,
+            &self.phosphates_upper,
+            &self.phosphates_lower,
+        ) {
+            (Some(upper), Some(lower), _, _) | (_, _, Some(upper), Some(lower)) => {
+                format!("upper: {}, lower: {}", upper, lower)
+            }
+            _ => self.phosphates.clone(),
+        };
+
+        // whitespace is normalized so that the description can be stored on a single line of a state file
+        description.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    /// Describe the method and the frequency of the assignment of the headgroups to leaflets.
+    fn leaflets(&self) -> String {
+        if self.upper.is_some() && self.lower.is_some() {
+            return String::from("provided by '--upper' and '--lower'");
+        }
+
+        let leaflets = match self.leaflet_method {
+            LeafletMethod::Global => String::from("global"),
+            LeafletMethod::Local => format!("local ({} nm)", self.leaflet_radius),
+            LeafletMethod::Clustering => format!("clustering ({} nm)", self.cluster_cutoff),
+            LeafletMethod::Orientation => format!(
+                "orientation ({})",
+                self.tails.as_deref().unwrap_or_default()
+            ),
+        };
+
+        let description = if self.assign_once {
+            format!("{}, assigned in the first frame", leaflets)
+        } else if self.assign_every > 1 {
+            format!("{}, assigned every {} frames", leaflets, self.assign_every)
+        } else {
+            leaflets
+        };
+
+        description.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    /// Get the path to the structure file.
+    fn structure(&self) -> &str {
+        self.structure.as_deref().expect(
+            "FATAL MEMTHICK ERROR | Args::structure | Structure file should be required if no subcommand is used.",
+        )
+    }
+}
+
+/// Additional operations on the results of previous analyses.
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    #[command(
+        about = "Merge state files from separate runs into one map.",
+        long_about = "Combine the raw accumulated grids stored in state files (written using the '--state' option) from separate runs of memthick, e.g., analyses of different trajectory chunks or simulation replicas, into one membrane thickness map. All state files must contain grids spanning the same area with the same bin size, accumulated using the same analysis settings (e.g., the same headgroups and leaflet assignment)."
+    )]
+    Merge(merge::MergeArgs),
+    #[command(
+        about = "Subtract two maps written on the same grid.",
+        long_about = "Read two maps previously written by memthick on the same grid (e.g., thickness maps of a mutant and a wild-type membrane) and write the difference between the first and the second map and optionally their relative difference. Bins undefined in any of the maps are undefined in the difference. When comparing state files, the statistical significance of the difference in every bin can also be calculated."
+    )]
+    Diff(diff::DiffArgs),
+    #[command(
+        about = "Compare the thickness maps of consecutive trajectory segments.",
+        long_about = "Analyze the trajectory split into N consecutive segments of equal size (halves by default), calculate a separate thickness map for each segment, and report the root-mean-square differences between the segment maps for every bin and for every pair of segments. Small differences compared to the features of the map indicate that the map has converged. The analysis options are provided after '--', e.g., 'memthick converge -n 4 -- -s system.gro -f md.xtc'. Has the same limitations as '--blocks'."
+    )]
+    Converge(converge::ConvergeArgs),
+}
+
+mod adaptive;
+mod areas;
+mod autocorrelation;
+mod benchmark;
+mod blocks;
+mod buckle;
+mod composition;
+mod contacts;
+mod converge;
+mod convergence;
+mod curvature;
+mod deviation;
+mod diff;
+mod domains;
+mod environment;
+mod extremes;
+mod flipflops;
+mod footprint;
+mod geometry;
+mod grids;
+mod interdigitation;
+mod interpolation;
+mod leaflets;
+mod membranes;
+mod merge;
+mod molecules;
+mod normal;
+mod parallel;
+mod preprocess;
+mod profile;
+mod projection;
+mod region;
+mod roi;
+mod significance;
+mod smoothing;
+mod spectrum;
+mod state;
+mod stream;
+mod symmetry;
+mod tilt;
+mod trajectory;
+mod weights;
+
+use autocorrelation::{BinSeries, ThicknessSeries};
+use blocks::{BlockAverage, ErrorMethod};
+use buckle::BuckleProfile;
+use composition::Composition;
+use contacts::ResidueThickness;
+use converge::ConvergeOutput;
+use convergence::ConvergenceWriter;
+use deviation::{Deformation, Reference};
+use environment::Environment;
+use extremes::Extremes;
+use flipflops::FlipFlopTracker;
+use footprint::Footprint;
+use geometry::{Geometry, Grid};
+use grids::{Sample, ThicknessGrids, ThicknessMethod};
+use interdigitation::Distributions;
+use leaflets::{LeafletClassifier, LeafletMethod};
+use molecules::Molecules;
+use normal::Normal;
+use preprocess::FramePreprocessor;
+use profile::{ProfileCenter, RadialProfile};
+use region::Region;
+use roi::RegionOfInterest;
+use spectrum::Spectrum;
+use stream::XtcStreamReader;
+use symmetry::Symmetry;
+use tilt::TiltMaps;
+use trajectory::{Chunk, TimeContinuity};
+use weights::FrameWeights;
+
+/// Name of the group containing all lipid atoms.
+pub(crate) const LIPIDS_GROUP: &str = "xxxMemthickReservedxxx-Lipids";
+/// Name of the group containing the atoms used to center the frames laterally.
+pub(crate) const REFERENCE_GROUP: &str = "xxxMemthickReservedxxx-Reference";
+/// Name of the group containing the atoms used to fit the frames onto the input structure.
+pub(crate) const FIT_GROUP: &str = "xxxMemthickReservedxxx-Fit";
+/// Name of the group containing the atoms defining the membrane center.
+pub(crate) const CENTER_GROUP: &str = "xxxMemthickReservedxxx-Center";
+/// Name of the group containing the atoms excluded from the analysis.
+pub(crate) const EXCLUDE_GROUP: &str = "xxxMemthickReservedxxx-Exclude";
+/// Name of the group containing the headgroup atoms of sterols.
+pub(crate) const STEROL_GROUP: &str = "xxxMemthickReservedxxx-Sterols";
+/// Name of the group containing the atoms delimiting the hydrophobic core.
+pub(crate) const HYDROPHOBIC_GROUP: &str = "xxxMemthickReservedxxx-Hydrophobic";
+/// Name of the group containing the water atoms.
+pub(crate) const WATER_GROUP: &str = "xxxMemthickReservedxxx-Water";
+/// Name of the group containing the terminal atoms of lipid tails used to map the interdigitation of the leaflets.
+pub(crate) const INTERDIGITATION_GROUP: &str = "xxxMemthickReservedxxx-Interdigitation";
+/// Name of the group containing the atoms counted around the headgroups to classify their environment.
+pub(crate) const ENVIRONMENT_GROUP: &str = "xxxMemthickReservedxxx-Environment";
+/// Name of the group containing the atoms at the center of the radial profile.
+pub(crate) const PROFILE_GROUP: &str = "xxxMemthickReservedxxx-Profile";
+/// Name of the group containing the atoms from which the distances of the headgroups are profiled.
+pub(crate) const AROUND_GROUP: &str = "xxxMemthickReservedxxx-Around";
+/// Name of the group containing the atoms perturbing the membrane for the reference thickness of the deviation map.
+pub(crate) const INCLUSION_GROUP: &str = "xxxMemthickReservedxxx-Inclusion";
+/// Name of the group containing the protein atoms whose footprint is masked in the maps.
+pub(crate) const PROTEIN_GROUP: &str = "xxxMemthickReservedxxx-Protein";
+/// Name of the group containing the atoms of the helix whose tilt conditions the thickness maps.
+pub(crate) const HELIX_GROUP: &str = "xxxMemthickReservedxxx-Helix";
+/// Name of the group containing the first half of the atoms of the helix.
+pub(crate) const HELIX_START_GROUP: &str = "xxxMemthickReservedxxx-HelixStart";
+/// Name of the group containing the second half of the atoms of the helix.
+pub(crate) const HELIX_END_GROUP: &str = "xxxMemthickReservedxxx-HelixEnd";
+/// Name of the group containing the protein atoms at which the thickness is calculated for each residue.
+pub(crate) const RESIDUES_GROUP: &str = "xxxMemthickReservedxxx-Residues";
+/// Name of the group containing the atoms of all inclusions with individual radial profiles.
+pub(crate) const INCLUSIONS_GROUP: &str = "xxxMemthickReservedxxx-Inclusions";
+/// Name of the group containing all atoms read from xtc trajectories.
+pub(crate) const READ_GROUP: &str = "xxxMemthickReservedxxx-Read";
+/// Name of the group containing the lipid headgroup atoms.
+pub(crate) const HEADS_GROUP: &str = "xxxMemthickReservedxxx-Heads";
+/// Name of the group containing all headgroup atoms if the headgroups are formed by several atoms.
+pub(crate) const HEAD_ATOMS_GROUP: &str = "xxxMemthickReservedxxx-HeadAtoms";
+/// Name of the group containing the ends of lipid tails.
+pub(crate) const TAILS_GROUP: &str = "xxxMemthickReservedxxx-Tails";
+/// Name of the group containing the user-defined headgroups of the upper leaflet.
+pub(crate) const UPPER_GROUP: &str = "xxxMemthickReservedxxx-Upper";
+/// Name of the group containing the user-defined headgroups of the lower leaflet.
+pub(crate) const LOWER_GROUP: &str = "xxxMemthickReservedxxx-Lower";
+
+/// Name of the group containing the atoms of the inclusion with the specified index (see '--inclusions').
+pub(crate) fn inclusion_group(index: usize) -> String {
+    format!("{}-{}", INCLUSIONS_GROUP, index + 1)
+}
+
+/// Print the specified options.
+fn print_options(args: &Args, simbox: &SimBox) {
+    println!("[STRUCTURE]     {}", args.structure());
+    if args.trajectory.is_empty() {
+        println!("[TRAJECTORY]    none (analyzing the structure)");
+    } else if args.trajectory[0] == stream::STDIN {
+        println!("[TRAJECTORY]    standard input (xtc)");
+    } else {
+        println!("[TRAJECTORY]    {}", args.trajectory.join(" "));
+    }
+    println!("[OUTPUT]        {}", args.output);
+    if let Some(ndx) = args.index.as_ref() {
+        println!("[INDEX]        {}", ndx);
+    }
+    if let Some(chunk) = args.chunk {
+        println!("[CHUNK]         {}", chunk);
+    }
+    if !args.trajectory.is_empty() {
+        println!("[TIME RANGE]    {}-{} ps", args.begin, args.end);
+        println!("[STEP]          {}", args.step);
+    }
+    println!("[LIPIDS]        {}", args.lipids);
+    if let Some(exclude) = &args.exclude {
+        println!("[EXCLUDE]       {}", exclude);
+    }
+    match (&args.sterol_heads, &args.sterol_output) {
+        (Some(sterols), Some(output)) => {
+            println!("[STEROLS]       {} (written into {})", sterols, output)
+        }
+        (Some(sterols), None) => println!("[STEROLS]       {}", sterols),
+        _ => (),
+    }
+    if let (Some(upper), Some(lower)) = (&args.upper, &args.lower) {
+        println!("[UPPER]         {}", upper);
+        println!("[LOWER]         {}", lower);
+    } else {
+        if let (Some(upper), Some(lower)) = (&args.phosphates_upper, &args.phosphates_lower) {
+            println!("[PHOSPHATES]    upper: {}", upper);
+            println!("                lower: {}", lower);
+        } else {
+            println!("[PHOSPHATES]    {}", args.phosphates);
+        }
+
+        println!("[LEAFLETS]      {}", args.leaflets());
+    }
+    if let Some(membrane) = args.membrane {
+        println!("[MEMBRANE]      {} of {}", membrane + 1, args.membranes);
+    }
+    if args.largest_aggregate {
+        println!("[AGGREGATE]     largest ({} nm)", args.aggregate_cutoff);
+    }
+    println!("[NAN LIMIT]     {}", args.nan_limit);
+
+    match args.thickness_method {
+        ThicknessMethod::Heads => (),
+        ThicknessMethod::Luzzati => println!(
+            "[THICKNESS]     luzzati ({})",
+            args.water.as_deref().unwrap_or_default()
+        ),
+        ThicknessMethod::Pairwise => println!("[THICKNESS]     pairwise ({} nm)", args.pair_cutoff),
+        ThicknessMethod::Voronoi => println!("[THICKNESS]     voronoi"),
+        ThicknessMethod::Kde => println!("[THICKNESS]     kde ({} nm)", args.kde_bandwidth),
+    }
+
+    let normal = args.normal();
+    if args.geometry == Geometry::Buckle {
+        let (_, range_y) = normal.lateral_range(simbox);
+        let (_, axis_y) = normal.plane_axes();
+        println!(
+            "[GEOMETRY]      buckle ({} Fourier modes)",
+            args.buckle_modes
+        );
+        println!(
+            "[ARCLENGTH]     from {} to {}",
+            args.xmin.map_or("0 nm".to_owned(), |x| format!("{} nm", x)),
+            args.xmax.map_or(
+                "the contour length of the input structure".to_owned(),
+                |x| format!("{} nm", x)
+            )
+        );
+        println!(
+            "[{}-RANGE]       {}-{} nm",
+            axis_y.to_uppercase(),
+            args.ymin.unwrap_or(range_y.0),
+            args.ymax.unwrap_or(range_y.1)
+        );
+    } else if args.geometry == Geometry::Planar && args.scaled {
+        let (axis_x, axis_y) = normal.plane_axes();
+        println!(
+            "[{}-RANGE]       {}-{} (box fraction)",
+            axis_x.to_uppercase(),
+            args.xmin.unwrap_or(0.0),
+            args.xmax.unwrap_or(1.0)
+        );
+        println!(
+            "[{}-RANGE]       {}-{} (box fraction)",
+            axis_y.to_uppercase(),
+            args.ymin.unwrap_or(0.0),
+            args.ymax.unwrap_or(1.0)
+        );
+    } else if let Some((x, y)) = args.polar_origin(simbox) {
+        let (box_x, box_y) = normal.lateral_box(simbox).expect(
+            "FATAL MEMTHICK ERROR | print_options | Polar grids should only be used with normals along box axes.",
+        );
+        println!("[GRID]          polar around {} {} nm", x, y);
+        println!(
+            "[RADIAL RANGE]  {}-{} nm",
+            args.xmin.unwrap_or(0.0),
+            args.xmax.unwrap_or(box_x.min(box_y) / 2.0)
+        );
+    } else if args.geometry == Geometry::Planar {
+        let (axis_x, axis_y) = normal.plane_axes();
+        let (range_x, range_y) = normal.lateral_range(simbox);
+        println!(
+            "[{}-RANGE]       {}-{} nm",
+            axis_x.to_uppercase(),
+            args.xmin.unwrap_or(range_x.0),
+            args.xmax.unwrap_or(range_x.1)
+        );
+        println!(
+            "[{}-RANGE]       {}-{} nm",
+            axis_y.to_uppercase(),
+            args.ymin.unwrap_or(range_y.0),
+            args.ymax.unwrap_or(range_y.1)
+        );
+    } else {
+        println!("[GEOMETRY]      {}", args.geometry);
+    }
+
+    if let Some(region) = args.region {
+        println!("[REGION]        {} nm", region);
+    }
+
+    if args.geometry == Geometry::Tube {
+        let range = normal.axial_range(simbox);
+        println!(
+            "[AXIAL RANGE]   {}-{} nm",
+            args.xmin.unwrap_or(range.0),
+            args.xmax.unwrap_or(range.1)
+        );
+    }
+
+    if args.auto_normal {
+        println!("[NORMAL]        {} (detected automatically)", normal);
+    } else {
+        println!("[NORMAL]        {}", normal);
+    }
+    if args.fit_plane {
+        println!("[PLANE]         fitted to the headgroups in every frame");
+    }
+    if args.center_membrane {
+        println!("[CENTERING]     membrane along the normal");
+    }
+    if let Some(center) = &args.center {
+        println!("[CENTER]        {} (in the membrane plane)", center);
+    }
+    if let Some(fit) = &args.fit {
+        println!("[FIT]           {} (in the membrane plane)", fit);
+    }
+    match (&args.center_selection, args.center_of_mass) {
+        (Some(selection), true) => println!("[MIDPLANE]      center of mass of {}", selection),
+        (Some(selection), false) => println!("[MIDPLANE]      center of geometry of {}", selection),
+        (None, true) => println!("[MIDPLANE]      center of mass of lipids"),
+        (None, false) => (),
+    }
+    if args.make_whole {
+        println!("[MAKE WHOLE]    lipids");
+    }
+    match args.geometry {
+        Geometry::Planar if args.grid == Grid::Polar => {
+            println!(
+                "[BIN SIZE]      {} nm x {} deg",
+                args.bin_size, args.angle_bin
+            )
+        }
+        Geometry::Planar | Geometry::Buckle => println!("[BIN SIZE]      {} nm", args.bin_size),
+        Geometry::Vesicle => println!("[BIN SIZE]      {} deg", args.angle_bin),
+        Geometry::Tube => println!(
+            "[BIN SIZE]      {} nm x {} deg",
+            args.bin_size, args.angle_bin
+        ),
+    }
+    if let Some(kernel) = args.kernel {
+        println!("[KERNEL]        Gaussian ({})", kernel);
+    }
+
+    if let Some(fill_radius) = args.fill_radius {
+        println!("[FILL RADIUS]   {}", fill_radius);
+    }
+    if let Some(smooth) = args.smooth {
+        println!("[SMOOTHING]     {}", smooth);
+    }
+    match args.symmetrize {
+        Some(Symmetry::Rotation) => {
+            println!("[SYMMETRIZE]    rotation (order {})", args.symmetry_order)
+        }
+        Some(symmetry) => println!("[SYMMETRIZE]    {}", symmetry),
+        None => (),
+    }
+
+    if let Some(frame_maps) = args.frame_maps.as_ref() {
+        println!("[FRAME MAPS]    {}", frame_maps);
+    }
+    if let Some(stdev) = &args.stdev {
+        println!("[STDEV MAP]     {}", stdev);
+    }
+    if let Some(extremes) = &args.extremes {
+        println!("[EXTREMES]      {}", extremes);
+    }
+    if let Some(leaflet_maps) = &args.leaflet_maps {
+        println!("[LEAFLET MAPS]  {}", leaflet_maps);
+    }
+    if let Some(midplane) = &args.midplane {
+        println!("[MIDPLANE MAP]  {}", midplane);
+    }
+    if let Some(curvature) = &args.curvature {
+        println!("[CURVATURE]     {}", curvature);
+    }
+    if let Some(gaussian) = &args.gaussian_curvature {
+        println!("[GAUSSIAN]      {}", gaussian);
+    }
+    if let Some(spectrum) = &args.spectrum {
+        println!("[SPECTRUM]      {}", spectrum);
+    }
+    if let Some(domains) = &args.domains {
+        println!("[DOMAINS]       {}", domains);
+    }
+    if let Some(projection) = &args.projection {
+        println!("[PROJECTION]    {}", projection);
+    }
+    if let Some(adaptive) = &args.adaptive {
+        println!(
+            "[ADAPTIVE]      {} ({} levels)",
+            adaptive, args.adaptive_levels
+        );
+    }
+    if let Some(area_per_lipid) = &args.area_per_lipid {
+        println!("[AREA/LIPID]    {}", area_per_lipid);
+    }
+    if let Some(density) = &args.density {
+        println!("[DENSITY]       {}", density);
+    }
+    if let Some(asymmetry) = &args.asymmetry {
+        println!("[ASYMMETRY]     {}", asymmetry);
+    }
+    if let Some(roughness) = &args.roughness {
+        println!("[ROUGHNESS]     {}", roughness);
+    }
+    if let Some(hydrophobic) = &args.hydrophobic {
+        println!(
+            "[HYDROPHOBIC]   {} (written into {})",
+            hydrophobic, args.hydrophobic_output
+        );
+    }
+    if let Some(interdigitation) = &args.interdigitation {
+        println!(
+            "[INTERDIGIT]    {} (written into {})",
+            interdigitation, args.interdigitation_output
+        );
+    }
+    if let Some(composition) = &args.composition {
+        println!("[COMPOSITION]   {}", composition);
+    }
+    if let Some(environment) = &args.environment {
+        println!(
+            "[ENVIRONMENT]   {} within {} nm (written into {})",
+            environment, args.environment_cutoff, args.environment_output
+        );
+    }
+    if let Some(lipid_table) = &args.lipid_table {
+        println!("[LIPID TABLE]   {}", lipid_table);
+    }
+
+    if let Some(roi) = &args.roi {
+        println!("[ROI]           {} -> {}", roi, args.roi_output);
+    }
+    if let Some(radial) = &args.radial {
+        match &args.radial_center {
+            Some(center) => println!(
+                "[RADIAL]        {} (around {}, shells of {} nm)",
+                radial, center, args.radial_width
+            ),
+            None => {
+                let (x, y) = args.origin(simbox);
+                println!(
+                    "[RADIAL]        {} (around {} {} nm, shells of {} nm)",
+                    radial, x, y, args.radial_width
+                )
+            }
+        }
+    }
+    if let Some(azimuthal) = &args.azimuthal {
+        let annulus = args.annulus.as_deref().unwrap_or_default();
+        println!(
+            "[AZIMUTHAL]     {} (annulus {}-{} nm, sectors of {} deg)",
+            azimuthal,
+            annulus.first().unwrap_or(&0.0),
+            annulus.last().unwrap_or(&0.0),
+            args.angle_bin
+        );
+    }
+    if let Some(azimuthal_map) = &args.azimuthal_map {
+        println!("[AZIMUTHAL MAP] {}", azimuthal_map);
+    }
+    if let Some(around) = &args.around {
+        println!(
+            "[AROUND]        {} -> {} (shells of {} nm)",
+            around, args.around_output, args.radial_width
+        );
+    }
+
+    if let Some(protein) = &args.protein {
+        println!(
+            "[PROTEIN]       {} (within {} nm in over {} of frames)",
+            protein, args.protein_radius, args.protein_threshold
+        );
+    }
+    if let Some(occupancy) = &args.occupancy {
+        println!("[OCCUPANCY]     {}", occupancy);
+    }
+    if let Some(cutoff) = args.exclude_shell {
+        println!("[EXCLUDE SHELL] {} nm", cutoff);
+    }
+    if let Some(residues) = &args.residues {
+        println!("[RESIDUES]      {} -> {}", residues, args.residues_output);
+    }
+    if let Some(helix) = &args.helix {
+        println!(
+            "[HELIX TILT]    {} -> {} ({} sectors)",
+            helix, args.tilt_output, args.tilt_sectors
+        );
+    }
+    if let Some(inclusions) = &args.inclusions {
+        println!(
+            "[INCLUSIONS]    {} -> {} (shells of {} nm)",
+            inclusions, args.inclusions_output, args.radial_width
+        );
+    }
+    if let Some(deviation) = &args.deviation {
+        match (args.reference_thickness, &args.inclusion) {
+            (Some(reference), _) => {
+                println!("[DEVIATION]     {} (reference {} nm)", deviation, reference)
+            }
+            (None, Some(inclusion)) => println!(
+                "[DEVIATION]     {} (bulk farther than {} nm from '{}')",
+                deviation, args.bulk_cutoff, inclusion
+            ),
+            (None, None) => println!(
+                "[DEVIATION]     {} (reference average thickness)",
+                deviation
+            ),
+        }
+    }
+    if let (Some(deformation), Some(inclusion)) = (&args.deformation, &args.inclusion) {
+        println!(
+            "[DEFORMATION]   {} (bulk farther than {} nm from '{}')",
+            deformation, args.bulk_cutoff, inclusion
+        );
+    }
+
+    if let Some(convergence) = args.convergence.as_ref() {
+        println!(
+            "[CONVERGENCE]   {} (every {} frames)",
+            convergence, args.convergence_freq
+        );
+    }
+    if let Some(autocorrelation) = args.autocorrelation.as_ref() {
+        println!("[AUTOCORR]      {}", autocorrelation);
+    }
+    if let Some(effective_samples) = args.effective_samples.as_ref() {
+        println!("[EFF. SAMPLES]  {}", effective_samples);
+    }
+    if let Some(blocks) = args.blocks {
+        println!("[BLOCKS]        {}", blocks);
+    }
+
+    if let Some(block_length) = args.block_length {
+        println!("[BLOCK LENGTH]  {} frames", block_length);
+    }
+
+    if (args.blocks.is_some() || args.block_length.is_some())
+        && args.error_method != ErrorMethod::default()
+    {
+        println!("[ERROR METHOD]  {}", args.error_method);
+    }
+
+    if let Some(block_errors) = &args.block_errors {
+        println!("[BLOCK ERRORS]  {}", block_errors);
+    }
+
+    if let Some(bootstrap) = &args.bootstrap {
+        println!(
+            "[BOOTSTRAP]     {} ({} samples, {}% confidence)",
+            bootstrap,
+            args.bootstrap_samples,
+            100.0 * args.confidence
+        );
+    }
+
+    if let Some(benchmark) = args.benchmark {
+        println!("[BENCHMARK]     {} frames", benchmark);
+    }
+    if let Some(flip_flops) = args.flip_flops.as_ref() {
+        println!(
+            "[FLIP-FLOPS]    {} (at least {} frames)",
+            flip_flops, args.flip_flop_frames
+        );
+    }
+
+    if let Some(weights) = args.weights.as_ref() {
+        println!("[WEIGHTS]       {}", weights);
+    }
+
+    if let Some(state) = args.state.as_ref() {
+        println!(
+            "[STATE]         {} (every {} frames)",
+            state, args.state_freq
+        );
+    }
+
+    if let Some(restart) = args.restart.as_ref() {
+        println!("[RESTART]       {}", restart);
+    }
+
+    if args.follow {
+        println!("[FOLLOW]        every {} s", args.follow_interval);
+    }
+    println!("\n");
+}
+
+fn sanity_check_options(args: &Args) -> anyhow::Result<()> {
+    if args.nan_limit == 0 {
+        anyhow::bail!("NAN limit must be larger than 0, not {}", args.nan_limit);
+    }
+
+    if args.begin < 0.0 || args.end < 0.0 {
+        anyhow::bail!("Time range cannot be negative.");
+    }
+
+    if args.begin > args.end {
+        anyhow::bail!(
+            "Start of the time range ({} ps) cannot be higher than its end ({} ps).",
+            args.begin,
+            args.end
+        );
+    }
+
+    if !args.normal_is_auto() {
+        Normal::parse(&args.normal)?;
+    }
+
+    if args.leaflet_radius <= 0.0 {
+        anyhow::bail!(
+            "Leaflet radius must be positive, not {} nm",
+            args.leaflet_radius
+        );
+    }
+
+    if args.cluster_cutoff <= 0.0 {
+        anyhow::bail!(
+            "Clustering cutoff must be positive, not {} nm",
+            args.cluster_cutoff
+        );
+    }
+
+    if args.membranes == 0 {
+        anyhow::bail!(
+            "Number of membranes must be larger than 0, not {}",
+            args.membranes
+        );
+    }
+
+    if args.aggregate_cutoff <= 0.0 {
+        anyhow::bail!(
+            "Aggregate cutoff must be positive, not {} nm",
+            args.aggregate_cutoff
+        );
+    }
+
+    if args.membranes > 1 {
+        if args.largest_aggregate {
+            anyhow::bail!(
+                "Analyzing several membranes cannot be combined with '--largest-aggregate'."
+            );
+        }
+
+        if args.geometry != Geometry::Planar {
+            anyhow::bail!(
+                "Analyzing several membranes is not supported for '--geometry {}'.",
+                args.geometry
+            );
+        }
+
+        let unsupported = [
+            ("'--state'", args.state.is_some()),
+            ("'--restart'", args.restart.is_some()),
+            ("'--convergence'", args.convergence.is_some()),
+            ("'--frame-maps'", args.frame_maps.is_some()),
+            ("'--flip-flops'", args.flip_flops.is_some()),
+            ("'--follow'", args.follow),
+            ("'--benchmark'", args.benchmark.is_some()),
+        ];
+        if let Some((option, _)) = unsupported.iter().find(|(_, used)| *used) {
+            anyhow::bail!(
+                "Analyzing several membranes cannot be combined with {}.",
+                option
+            );
+        }
+
+        if args
+            .trajectory
+            .first()
+            .is_some_and(|file| file == stream::STDIN)
+        {
+            anyhow::bail!(
+                "Several membranes cannot be analyzed from a trajectory read from the standard input."
+            );
+        }
+    }
+
+    if args.scaled {
+        if args.geometry != Geometry::Planar {
+            anyhow::bail!(
+                "Scaled grid coordinates are not supported for '--geometry {}'.",
+                args.geometry
+            );
+        }
+
+        if !args.normal_is_auto() && !matches!(Normal::parse(&args.normal)?, Normal::Axis(_)) {
+            anyhow::bail!(
+                "Scaled grid coordinates require the membrane normal to be oriented along an axis of the simulation box."
+            );
+        }
+    }
+
+    if args.thickness_method == ThicknessMethod::Luzzati {
+        if args.water.is_none() {
+            anyhow::bail!("Luzzati thickness requires the '--water' option.");
+        }
+
+        if args.geometry != Geometry::Planar {
+            anyhow::bail!(
+                "Luzzati thickness is not supported for '--geometry {}'.",
+                args.geometry
+            );
+        }
+
+        if !args.normal_is_auto() && !matches!(Normal::parse(&args.normal)?, Normal::Axis(_)) {
+            anyhow::bail!(
+                "Luzzati thickness requires the membrane normal to be oriented along an axis of the simulation box."
+            );
+        }
+
+        if args.fit_plane {
+            anyhow::bail!(
+                "Fitting a plane to the headgroups cannot be combined with Luzzati thickness."
+            );
+        }
+    } else if args.water.is_some() {
+        anyhow::bail!("Water atoms are only used with '--thickness-method luzzati'.");
+    }
+
+    if args.hydrophobic.is_some() || args.interdigitation.is_some() || args.sterol_output.is_some()
+    {
+        if args.thickness_method == ThicknessMethod::Luzzati {
+            anyhow::bail!(
+                "Additional thickness maps cannot be calculated with '--thickness-method luzzati'."
+            );
+        }
+
+        if args.state.is_some() || args.restart.is_some() {
+            anyhow::bail!(
+                "Calculating additional thickness maps cannot be combined with state files."
+            );
+        }
+    }
+
+    if (args.leaflet_maps.is_some() || args.midplane.is_some())
+        && args.thickness_method == ThicknessMethod::Luzzati
+    {
+        anyhow::bail!(
+            "Maps of the individual leaflets and the midplane cannot be calculated with '--thickness-method luzzati'."
+        );
+    }
+
+    if args.curvature.is_some() || args.gaussian_curvature.is_some() {
+        if args.thickness_method == ThicknessMethod::Luzzati {
+            anyhow::bail!("Curvature cannot be calculated with '--thickness-method luzzati'.");
+        }
+
+        if args.geometry != Geometry::Planar {
+            anyhow::bail!(
+                "Curvature is not supported for '--geometry {}'.",
+                args.geometry
+            );
+        }
+
+        if args.scaled {
+            anyhow::bail!("Curvature cannot be calculated from scaled grid coordinates.");
+        }
+    }
+
+    if let Some(fill_radius) = args.fill_radius
+        && fill_radius <= 0.0
+    {
+        anyhow::bail!(
+            "Radius for filling undefined bins must be positive, not {}.",
+            fill_radius
+        );
+    }
+
+    if let Some(smooth) = args.smooth
+        && smooth <= 0.0
+    {
+        anyhow::bail!(
+            "Width of the smoothing filter must be positive, not {}.",
+            smooth
+        );
+    }
+
+    if args.extremes.is_some() && (args.state.is_some() || args.restart.is_some()) {
+        anyhow::bail!("Extremes of the thickness cannot be calculated with state files.");
+    }
+
+    if args.domains.is_some() {
+        if !matches!(args.geometry, Geometry::Planar | Geometry::Buckle) {
+            anyhow::bail!(
+                "Domains are not supported for '--geometry {}'.",
+                args.geometry
+            );
+        }
+
+        if args.scaled {
+            anyhow::bail!("Domains cannot be identified in scaled grid coordinates.");
+        }
+    }
+
+    if args.adaptive.is_some() && !(1..=adaptive::MAX_LEVELS).contains(&args.adaptive_levels) {
+        anyhow::bail!(
+            "Number of levels of the adaptive map must be between 1 and {}, not {}.",
+            adaptive::MAX_LEVELS,
+            args.adaptive_levels
+        );
+    }
+
+    if args.centered_profiles() || args.around.is_some() || args.inclusions.is_some() {
+        if args.thickness_method != ThicknessMethod::Heads {
+            anyhow::bail!(
+                "Radial profiles can only be calculated with '--thickness-method heads'."
+            );
+        }
+
+        if args.geometry != Geometry::Planar {
+            anyhow::bail!(
+                "Radial profiles are not supported for '--geometry {}'.",
+                args.geometry
+            );
+        }
+
+        if !args.normal_is_auto() && !matches!(Normal::parse(&args.normal)?, Normal::Axis(_)) {
+            anyhow::bail!(
+                "Radial profiles require the membrane normal to be oriented along an axis of the simulation box."
+            );
+        }
+
+        if args.radial_width <= 0.0 {
+            anyhow::bail!(
+                "Width of the shells of the radial profiles must be positive, not {} nm",
+                args.radial_width
+            );
+        }
+
+        if args.state.is_some() || args.restart.is_some() {
+            anyhow::bail!("Calculating radial profiles cannot be combined with state files.");
+        }
+    }
+
+    if args.inclusions_average && args.inclusions.is_none() {
+        anyhow::bail!("Average profile of the inclusions requires '--inclusions'.");
+    }
+
+    if !args.centered_profiles() && args.radial_center.is_some() {
+        anyhow::bail!(
+            "Center of the radial profile is only used with '--radial', '--azimuthal', and '--azimuthal-map'."
+        );
+    }
+
+    match (&args.azimuthal, args.annulus.as_deref()) {
+        (Some(_), Some(&[inner, outer])) => {
+            if inner < 0.0 || outer <= inner {
+                anyhow::bail!(
+                    "Outer radius of the annulus must be larger than its non-negative inner radius, not {}-{} nm",
+                    inner,
+                    outer
+                );
+            }
+        }
+        (Some(_), _) => anyhow::bail!("Azimuthal profile requires the '--annulus' option."),
+        (None, Some(_)) => anyhow::bail!("Annulus is only used with '--azimuthal'."),
+        (None, None) => (),
+    }
+
+    if (args.azimuthal.is_some() || args.azimuthal_map.is_some())
+        && (args.angle_bin <= 0.0 || args.angle_bin > 360.0)
+    {
+        anyhow::bail!(
+            "Angular bin size must be positive and at most 360 degrees, not {}",
+            args.angle_bin
+        );
+    }
+
+    if args.spectrum.is_some() {
+        if args.geometry != Geometry::Planar {
+            anyhow::bail!(
+                "Undulation spectrum is not supported for '--geometry {}'.",
+                args.geometry
+            );
+        }
+
+        if args.state.is_some() || args.restart.is_some() {
+            anyhow::bail!(
+                "Calculating the undulation spectrum cannot be combined with state files."
+            );
+        }
+    }
+
+    if args.roughness.is_some() {
+        if args.thickness_method != ThicknessMethod::Heads {
+            anyhow::bail!(
+                "Roughness of the leaflets can only be calculated with '--thickness-method heads'."
+            );
+        }
+
+        if args.state.is_some() || args.restart.is_some() {
+            anyhow::bail!(
+                "Calculating the roughness of the leaflets cannot be combined with state files."
+            );
+        }
+    }
+
+    if args.grid == Grid::Polar {
+        if args.geometry != Geometry::Planar {
+            anyhow::bail!(
+                "Polar grids are not supported for '--geometry {}'.",
+                args.geometry
+            );
+        }
+
+        if args.thickness_method != ThicknessMethod::Heads {
+            anyhow::bail!("Polar grids are only supported with '--thickness-method heads'.");
+        }
+
+        if !args.normal_is_auto() && !matches!(Normal::parse(&args.normal)?, Normal::Axis(_)) {
+            anyhow::bail!(
+                "Polar grids require the membrane normal to be oriented along an axis of the simulation box."
+            );
+        }
+
+        if args.scaled {
+            anyhow::bail!("Polar grids cannot be combined with scaled grid coordinates.");
+        }
+
+        if args.kernel.is_some() {
+            anyhow::bail!("Gaussian deposition of headgroups is not supported for polar grids.");
+        }
+
+        if args.density.is_some()
+            || args.curvature.is_some()
+            || args.gaussian_curvature.is_some()
+            || args.domains.is_some()
+        {
+            anyhow::bail!(
+                "Densities, curvature, and domains cannot be calculated for polar grids."
+            );
+        }
+
+        if args.state.is_some() || args.restart.is_some() {
+            anyhow::bail!("Polar grids cannot be combined with state files.");
+        }
+    } else if args.origin.is_some() && !args.centered_profiles() && args.symmetrize.is_none() {
+        anyhow::bail!(
+            "Origin is only used with '--grid polar', the radial and azimuthal profiles, and '--symmetrize'."
+        );
+    }
+
+    if args.symmetrize.is_some()
+        && (args.geometry != Geometry::Planar || args.grid == Grid::Polar || args.scaled)
+    {
+        anyhow::bail!(
+            "Symmetrization is only supported for planar membranes with Cartesian grids in unscaled coordinates."
+        );
+    }
+
+    if args.deviation.is_none() && args.reference_thickness.is_some() {
+        anyhow::bail!("Reference thickness is only used with '--deviation'.");
+    }
+
+    if args.deviation.is_none() && args.deformation.is_none() && args.inclusion.is_some() {
+        anyhow::bail!("Inclusion is only used with '--deviation' and '--deformation'.");
+    }
+
+    if args.deformation.is_some() && args.inclusion.is_none() {
+        anyhow::bail!(
+            "Deformation map requires '--inclusion' to determine the far-field thickness."
+        );
+    }
+
+    if let Some(reference) = args.reference_thickness
+        && reference <= 0.0
+    {
+        anyhow::bail!("Reference thickness must be positive, not {}.", reference);
+    }
+
+    if args.inclusion.is_some()
+        && (args.geometry != Geometry::Planar || args.grid == Grid::Polar || args.scaled)
+    {
+        anyhow::bail!(
+            "Bulk membrane can only be identified for planar membranes with Cartesian grids in unscaled coordinates."
+        );
+    }
+
+    if args.protein.is_some() {
+        if args.geometry != Geometry::Planar || args.grid == Grid::Polar || args.scaled {
+            anyhow::bail!(
+                "Protein footprint can only be masked for planar membranes with Cartesian grids in unscaled coordinates."
+            );
+        }
+
+        if args.state.is_some() || args.restart.is_some() {
+            anyhow::bail!("Protein footprint cannot be masked with state files.");
+        }
+
+        if args.protein_radius <= 0.0 {
+            anyhow::bail!(
+                "Protein radius must be positive, not {}.",
+                args.protein_radius
+            );
+        }
+
+        if !(0.0..=1.0).contains(&args.protein_threshold) {
+            anyhow::bail!(
+                "Protein threshold must be between 0 and 1, not {}.",
+                args.protein_threshold
+            );
+        }
+    }
+
+    if args.helix.is_some() {
+        if args.geometry != Geometry::Planar {
+            anyhow::bail!(
+                "Thickness maps conditioned on the helix tilt are only supported for planar membranes."
+            );
+        }
+
+        if args.state.is_some() || args.restart.is_some() {
+            anyhow::bail!(
+                "Thickness maps conditioned on the helix tilt cannot be calculated with state files."
+            );
+        }
+
+        if args.tilt_sectors < 2 {
+            anyhow::bail!(
+                "Number of tilt sectors must be at least 2, not {}.",
+                args.tilt_sectors
+            );
+        }
+    }
+
+    if args.occupancy.is_some() && args.protein.is_none() {
+        anyhow::bail!("Occupancy of the bins by the protein requires '--protein'.");
+    }
+
+    if args.residues.is_some() {
+        if args.geometry != Geometry::Planar || args.grid == Grid::Polar || args.scaled {
+            anyhow::bail!(
+                "Thickness at the residues is only supported for planar membranes with Cartesian grids in unscaled coordinates."
+            );
+        }
+
+        if args.state.is_some() || args.restart.is_some() {
+            anyhow::bail!("Thickness at the residues cannot be calculated with state files.");
+        }
+    }
+
+    if let Some(cutoff) = args.exclude_shell {
+        if args.protein.is_none() {
+            anyhow::bail!("Excluding the headgroups around the protein requires '--protein'.");
+        }
+
+        if cutoff <= 0.0 {
+            anyhow::bail!(
+                "Cutoff of the excluded shell must be positive, not {}.",
+                cutoff
+            );
+        }
+    }
+
+    if args.inclusion.is_some() && args.bulk_cutoff <= 0.0 {
+        anyhow::bail!("Bulk cutoff must be positive, not {}.", args.bulk_cutoff);
+    }
+
+    if args.symmetrize == Some(Symmetry::Rotation) && args.symmetry_order < 2 {
+        anyhow::bail!(
+            "Order of the rotational symmetry must be at least 2, not {}.",
+            args.symmetry_order
+        );
+    }
+
+    if args.asymmetry.is_some() && args.thickness_method != ThicknessMethod::Heads {
+        anyhow::bail!(
+            "Asymmetry of the leaflets can only be mapped with '--thickness-method heads'."
+        );
+    }
+
+    if args.density.is_some() {
+        if args.thickness_method != ThicknessMethod::Heads {
+            anyhow::bail!(
+                "Densities of headgroups can only be mapped with '--thickness-method heads'."
+            );
+        }
+
+        if !matches!(args.geometry, Geometry::Planar | Geometry::Buckle) {
+            anyhow::bail!(
+                "Densities of headgroups are not supported for '--geometry {}'.",
+                args.geometry
+            );
+        }
+
+        if args.scaled {
+            anyhow::bail!(
+                "Densities of headgroups cannot be calculated from scaled grid coordinates."
+            );
+        }
+    }
+
+    if args.area_per_lipid.is_some() {
+        if args.geometry != Geometry::Planar {
+            anyhow::bail!(
+                "Area per lipid is not supported for '--geometry {}'.",
+                args.geometry
+            );
+        }
+
+        if args.state.is_some() || args.restart.is_some() {
+            anyhow::bail!("Calculating the area per lipid cannot be combined with state files.");
+        }
+    }
+
+    if args.interdigitation.is_some() {
+        if args.thickness_method != ThicknessMethod::Heads {
+            anyhow::bail!(
+                "Leaflet interdigitation can only be mapped with '--thickness-method heads'."
+            );
+        }
+
+        if !matches!(args.geometry, Geometry::Planar | Geometry::Buckle) {
+            anyhow::bail!(
+                "Leaflet interdigitation is not supported for '--geometry {}'.",
+                args.geometry
+            );
+        }
+    }
+
+    if args.composition.is_some() {
+        if args.thickness_method != ThicknessMethod::Heads {
+            anyhow::bail!("Lipid composition can only be mapped with '--thickness-method heads'.");
+        }
+
+        if args.state.is_some() || args.restart.is_some() {
+            anyhow::bail!("Mapping the lipid composition cannot be combined with state files.");
+        }
+    }
+
+    if args.lipid_table.is_some() {
+        if args.thickness_method != ThicknessMethod::Heads {
+            anyhow::bail!(
+                "Thickness experienced by lipids can only be calculated with '--thickness-method heads'."
+            );
+        }
+
+        if args.state.is_some() || args.restart.is_some() {
+            anyhow::bail!("Writing the lipid table cannot be combined with state files.");
+        }
+    }
+
+    if args.environment.is_some() {
+        if args.thickness_method != ThicknessMethod::Heads {
+            anyhow::bail!(
+                "Lipid environment can only be analyzed with '--thickness-method heads'."
+            );
+        }
+
+        if args.state.is_some() || args.restart.is_some() {
+            anyhow::bail!("Analyzing the lipid environment cannot be combined with state files.");
+        }
+
+        if args.environment_cutoff <= 0.0 {
+            anyhow::bail!(
+                "Environment cutoff must be positive, not {} nm",
+                args.environment_cutoff
+            );
+        }
+    }
+
+    if args.thickness_method == ThicknessMethod::Voronoi && args.geometry != Geometry::Planar {
+        anyhow::bail!(
+            "Voronoi thickness is not supported for '--geometry {}'.",
+            args.geometry
+        );
+    }
+
+    if args.thickness_method == ThicknessMethod::Pairwise {
+        if args.geometry != Geometry::Planar {
+            anyhow::bail!(
+                "Pairwise thickness is not supported for '--geometry {}'.",
+                args.geometry
+            );
+        }
+
+        if args.pair_cutoff <= 0.0 {
+            anyhow::bail!(
+                "Pairing cutoff must be positive, not {} nm",
+                args.pair_cutoff
+            );
+        }
+    }
+
+    if args.thickness_method == ThicknessMethod::Kde {
+        if args.geometry != Geometry::Planar {
+            anyhow::bail!(
+                "Kernel thickness is not supported for '--geometry {}'.",
+                args.geometry
+            );
+        }
+
+        if args.scaled {
+            anyhow::bail!("Kernel thickness cannot be combined with scaled grid coordinates.");
+        }
+
+        if args.kde_bandwidth <= 0.0 {
+            anyhow::bail!(
+                "Kernel bandwidth must be positive, not {} nm",
+                args.kde_bandwidth
+            );
+        }
+    }
+
+    if args.geometry != Geometry::Planar {
+        if args.leaflet_method != LeafletMethod::Global {
+            anyhow::bail!(
+                "Only the 'global' leaflet assignment method is supported for '--geometry {}'.",
+                args.geometry
+            );
+        }
+
+        if args.fit_plane {
+            anyhow::bail!(
+                "Fitting a plane to the headgroups is not supported for '--geometry {}'.",
+                args.geometry
+            );
+        }
+
+        if args.normal_is_auto() {
+            anyhow::bail!(
+                "Membrane normal cannot be detected automatically for '--geometry {}'.",
+                args.geometry
+            );
+        }
+
+        if args.geometry != Geometry::Buckle && (args.ymin.is_some() || args.ymax.is_some()) {
+            anyhow::bail!(
+                "Angular grid range cannot be specified for '--geometry {}'.",
+                args.geometry
+            );
+        }
+
+        if args.geometry == Geometry::Vesicle && (args.xmin.is_some() || args.xmax.is_some()) {
+            anyhow::bail!("Grid range cannot be specified for '--geometry vesicle'.");
+        }
+
+        if args.geometry == Geometry::Buckle {
+            if args.tails.is_none() {
+                anyhow::bail!(
+                    "Buckled membranes require the '--tails' option to locate the membrane midplane."
+                );
+            }
+
+            if !matches!(Normal::parse(&args.normal)?, Normal::Axis(_)) {
+                anyhow::bail!(
+                    "Buckled membranes require the membrane normal to be oriented along an axis of the simulation box."
+                );
+            }
+
+            if args.buckle_modes == 0 {
+                anyhow::bail!(
+                    "Number of Fourier modes must be larger than 0, not {}",
+                    args.buckle_modes
+                );
+            }
+        }
+
+        if args.angle_bin <= 0.0 || args.angle_bin > 180.0 {
+            anyhow::bail!(
+                "Angular bin size must be positive and at most 180 degrees, not {}",
+                args.angle_bin
+            );
+        }
+    }
+
+    if args.leaflet_method == LeafletMethod::Orientation && args.tails.is_none() {
+        anyhow::bail!("Leaflet assignment by lipid orientation requires the '--tails' option.");
+    }
+
+    if let Some(kernel) = args.kernel {
+        if kernel <= 0.0 {
+            anyhow::bail!(
+                "Width of the deposition kernel must be positive, not {}.",
+                kernel
+            );
+        }
+
+        if args.thickness_method != ThicknessMethod::Heads {
+            anyhow::bail!(
+                "Gaussian deposition of headgroups is only supported with '--thickness-method heads'."
+            );
+        }
+
+        // the width is given in nm and compared with the lateral distances in the membrane plane
+        if args.geometry != Geometry::Planar || args.scaled {
+            anyhow::bail!(
+                "Gaussian deposition of headgroups is only supported for planar membranes in unscaled coordinates."
+            );
+        }
+    }
+
+    sanity_check_trajectories(args)?;
+
+    if args.threads == 0 {
+        anyhow::bail!(
+            "Number of threads must be larger than 0, not {}",
+            args.threads
+        );
+    }
+
+    if args.skip_broken && args.threads > 1 {
+        anyhow::bail!("Skipping broken frames is not supported for parallel analysis.");
+    }
+
+    if args.flip_flops.is_some() {
+        if args.flip_flop_frames == 0 {
+            anyhow::bail!(
+                "Number of flip-flop frames must be larger than 0, not {}",
+                args.flip_flop_frames
+            );
+        }
+
+        if args.trajectory.is_empty() {
+            anyhow::bail!("Detecting flip-flops requires a trajectory.");
+        }
+
+        if args.threads > 1 {
+            anyhow::bail!("Detecting flip-flops is not supported for parallel analysis.");
+        }
+
+        if args.follow {
+            anyhow::bail!("Detecting flip-flops cannot be combined with following the trajectory.");
+        }
+
+        if args.assign_once || args.upper.is_some() {
+            anyhow::bail!(
+                "Detecting flip-flops requires assigning lipids to leaflets in every frame."
+            );
+        }
+    }
+
+    if let Some(blocks) = args.blocks
+        && blocks < 2
+    {
+        anyhow::bail!("Number of blocks must be at least 2, not {}", blocks);
+    }
+
+    if args.block_length == Some(0) {
+        anyhow::bail!("Length of the blocks must be at least 1 frame.");
+    }
+
+    if args.error_method != ErrorMethod::default()
+        && args.blocks.is_none()
+        && args.block_length.is_none()
+    {
+        anyhow::bail!("Error method is only used with '--blocks' or '--block-length'.");
+    }
+
+    if args.block_errors.is_some() && args.blocks.is_none() && args.block_length.is_none() {
+        anyhow::bail!(
+            "Writing the map of the standard errors requires '--blocks' or '--block-length'."
+        );
+    }
+
+    if args.bootstrap.is_some() {
+        if args.blocks.is_none() && args.block_length.is_none() {
+            anyhow::bail!("Bootstrapping the thickness requires '--blocks' or '--block-length'.");
+        }
+
+        if args.bootstrap_samples == 0 {
+            anyhow::bail!("Number of bootstrap samples must be at least 1.");
+        }
+
+        if args.confidence <= 0.0 || args.confidence >= 1.0 {
+            anyhow::bail!(
+                "Confidence level must be between 0 and 1, not {}.",
+                args.confidence
+            );
+        }
+    }
+
+    if args.blocks.is_some() || args.block_length.is_some() {
+        if args.trajectory.is_empty() {
+            anyhow::bail!("Block averaging requires a trajectory.");
+        }
+
+        if args.trajectory[0] == stream::STDIN {
+            anyhow::bail!(
+                "Block averaging is not supported for trajectories read from the standard input."
+            );
+        }
+
+        if args.threads > 1 {
+            anyhow::bail!("Block averaging is not supported for parallel analysis.");
+        }
+
+        if args.follow {
+            anyhow::bail!("Block averaging cannot be combined with following the trajectory.");
+        }
+
+        if args.restart.is_some() {
+            anyhow::bail!("Block averaging cannot be combined with restarting the analysis.");
+        }
+    }
+
+    if args.convergence.is_some() {
+        if args.trajectory.is_empty() {
+            anyhow::bail!(
+                "Writing the convergence of the average thickness requires a trajectory."
+            );
+        }
+
+        if args.threads > 1 {
+            anyhow::bail!(
+                "Writing the convergence of the average thickness is not supported for parallel analysis."
+            );
+        }
+    }
+
+    if args.autocorrelation.is_some() {
+        if args.trajectory.is_empty() {
+            anyhow::bail!("Autocorrelation of the average thickness requires a trajectory.");
+        }
+
+        if args.state.is_some() || args.restart.is_some() {
+            anyhow::bail!(
+                "Autocorrelation of the average thickness cannot be combined with state files."
+            );
+        }
+    }
+
+    if args.effective_samples.is_some() {
+        if args.trajectory.is_empty() {
+            anyhow::bail!("Effective number of samples requires a trajectory.");
+        }
+
+        if args.state.is_some() || args.restart.is_some() {
+            anyhow::bail!("Effective number of samples cannot be calculated with state files.");
+        }
+    }
+
+    if args.convergence_freq == 0 {
+        anyhow::bail!(
+            "Convergence writing frequency must be larger than 0, not {}",
+            args.convergence_freq
+        );
+    }
+
+    if args.frame_maps.is_some() && args.threads > 1 {
+        anyhow::bail!("Writing maps for individual frames is not supported for parallel analysis.");
+    }
+
+    if args.assign_once && args.threads > 1 {
+        anyhow::bail!(
+            "Assigning lipids to leaflets only once is not supported for parallel analysis."
+        );
+    }
+
+    if args.assign_every == 0 {
+        anyhow::bail!(
+            "Leaflet assignment frequency must be larger than 0, not {}",
+            args.assign_every
+        );
+    }
+
+    if args.assign_every > 1 && args.threads > 1 {
+        anyhow::bail!(
+            "Assigning lipids to leaflets every N frames is not supported for parallel analysis."
+        );
+    }
+
+    if args.weights.is_some() && args.threads > 1 {
+        anyhow::bail!("Weighting frames is not supported for parallel analysis.");
+    }
+
+    if args.chunk.is_some() {
+        if args.trajectory.is_empty() {
+            anyhow::bail!("Analyzing a trajectory chunk requires a trajectory.");
+        }
+
+        if args.trajectory[0] == stream::STDIN {
+            anyhow::bail!("Trajectory read from the standard input cannot be split into chunks.");
+        }
+
+        if args.follow {
+            anyhow::bail!(
+                "Analyzing a trajectory chunk cannot be combined with following the trajectory."
+            );
+        }
+
+        if args.state.is_none() {
+            anyhow::bail!(
+                "Analyzing a trajectory chunk requires '--state' to store the raw accumulated grids."
+            );
+        }
+    }
+
+    if let Some(benchmark) = args.benchmark {
+        if benchmark == 0 {
+            anyhow::bail!(
+                "Number of benchmark frames must be larger than 0, not {}",
+                benchmark
+            );
+        }
+
+        if args.trajectory.is_empty() {
+            anyhow::bail!("Benchmarking the analysis requires a trajectory.");
+        }
+
+        if args.follow {
+            anyhow::bail!(
+                "Benchmarking the analysis cannot be combined with following the trajectory."
+            );
+        }
+    }
+
+    if args.state_freq == 0 {
+        anyhow::bail!(
+            "State file writing frequency must be larger than 0, not {}",
+            args.state_freq
+        );
+    }
+
+    if args.follow {
+        if args.trajectory.len() != 1 || trajectory::is_pattern(&args.trajectory[0]) {
+            anyhow::bail!("Following the trajectory requires exactly one trajectory file.");
+        }
+
+        if !matches!(
+            FileType::from_name(&args.trajectory[0]),
+            FileType::XTC | FileType::TRR
+        ) {
+            anyhow::bail!("Following the trajectory is only supported for xtc and trr files.");
+        }
+
+        if args.threads > 1 {
+            anyhow::bail!("Following the trajectory is not supported for parallel analysis.");
+        }
+
+        if args.skip_broken {
+            anyhow::bail!(
+                "Skipping broken frames cannot be combined with following the trajectory."
+            );
+        }
+
+        if args.follow_interval == 0 {
+            anyhow::bail!(
+                "Interval for checking new frames must be larger than 0, not {}",
+                args.follow_interval
+            );
+        }
+    }
+
+    if args.restart.is_some() && args.trajectory.is_empty() {
+        anyhow::bail!("Restarting the analysis requires a trajectory.");
+    }
+
+    if args.step == 0 {
+        anyhow::bail!("Step must be larger than 0, not {}", args.step);
+    }
+
+    if args.xmin > args.xmax {
+        anyhow::bail!("Minimum grid x-value cannot be higher than the maximum grid x-value.");
+    }
+
+    if args.ymin > args.ymax {
+        anyhow::bail!("Minimum grid y-value cannot be higher than the maximum grid y-value.");
+    }
+
+    Ok(())
+}
+
+/// Write the values of a map as `(x, y, value)` with the header and the average value into the output file.
+/// Returns the output file for writing further information.
+fn write_values(
+    output_name: impl AsRef<Path>,
+    grids: &ThicknessGrids,
+    quantity: &str,
+    unit: Option<&str>,
+    values: &[(f32, f32, f64)],
+    raw_arguments: &[String],
+) -> anyhow::Result<BufWriter<File>> {
+    let file = File::create(&output_name)?;
+    let mut output = BufWriter::new(file);
+
+    writeln!(
+        &mut output,
+        "# Generated with memthick v{}.",
+        env!("CARGO_PKG_VERSION")
+    )?;
+    writeln!(&mut output, "# Command line: {}", raw_arguments.join(" "))?;
+
+    writeln!(
+        &mut output,
+        "# See the average {} at the end of this file.",
+        quantity
+    )?;
+
+    let (label_x, label_y) = grids.axis_labels();
+    writeln!(&mut output, "@ xlabel {}", label_x)?;
+    writeln!(&mut output, "@ ylabel {}", label_y)?;
+
+    match unit {
+        Some(unit) => writeln!(&mut output, "@ zlabel {} [{}]", quantity, unit)?,
+        None => writeln!(&mut output, "@ zlabel {}", quantity)?,
+    }
+    writeln!(&mut output, "@ grid --")?;
+    writeln!(&mut output, "$ type colorbar")?;
+    writeln!(&mut output, "$ colormap rainbow")?;
+
+    for (x, y, value) in values.iter() {
+        writeln!(&mut output, "{:12.6} {:12.6} {:12.4}", x, y, value)?;
+    }
+
+    let (sum, n_bins) = values
+        .iter()
+        .filter(|(_, _, value)| value.is_finite())
+        .fold((0.0, 0), |(sum, n), (_, _, value)| (sum + value, n + 1));
+    writeln!(
+        &mut output,
+        "# Average {}: {:12.4}{}",
+        quantity,
+        sum / n_bins as f64,
+        unit.map(|unit| format!(" {}", unit)).unwrap_or_default()
+    )?;
+
+    Ok(output)
+}
+
+/// Processing of a map before it is written.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct MapFilters {
+    /// Radius for filling the undefined bins by interpolation.
+    fill_radius: Option<f32>,
+    /// Width of the Gaussian filter used to smooth the map.
+    smooth: Option<f32>,
+    /// Symmetry imposed on the map and its order.
+    symmetry: Option<(Symmetry, usize)>,
+    /// Center of the symmetry (`None` for the center of the map).
+    symmetry_center: Option<(f32, f32)>,
+}
+
+impl MapFilters {
+    /// Get the processing of the thickness map requested by the user.
+    fn from_args(args: &Args) -> MapFilters {
+        MapFilters {
+            fill_radius: args.fill_radius,
+            smooth: args.smooth,
+            symmetry: args
+                .symmetrize
+                .map(|symmetry| (symmetry, args.symmetry_order)),
+            symmetry_center: args.origin.as_deref().map(|origin| (origin[0], origin[1])),
+        }
+    }
+}
+
+fn write_map(
+    output_name: impl AsRef<Path>,
+    grids: &ThicknessGrids,
+    nan_limit: usize,
+    blocks: Option<&BlockAverage>,
+    filters: MapFilters,
+    raw_arguments: &[String],
+) -> anyhow::Result<BufWriter<File>> {
+    let (n_x, n_y) = (grids.upper_sum.n_tiles_x(), grids.upper_sum.n_tiles_y());
+    let tile = grids.upper_sum.tile_dim();
+
+    let mut values = grids.values(nan_limit);
+    if let Some((symmetry, order)) = filters.symmetry {
+        let (span_x, span_y) = (grids.upper_sum.span_x(), grids.upper_sum.span_y());
+        let center = filters
+            .symmetry_center
+            .unwrap_or(((span_x.0 + span_x.1) / 2.0, (span_y.0 + span_y.1) / 2.0));
+        values = symmetry::symmetrize(
+            &values,
+            n_x,
+            n_y,
+            tile,
+            (span_x.0, span_y.0),
+            symmetry,
+            order,
+            center,
+        );
+    }
+
+    if let Some(radius) = filters.fill_radius {
+        let (filled, interpolated) = interpolation::fill_undefined(&values, n_x, n_y, tile, radius);
+        let mask = filled
+            .iter()
+            .zip(interpolated.iter())
+            .map(|(&(x, y, value), &interpolated)| {
+                let flag = match (value.is_finite(), interpolated) {
+                    (false, _) => f64::NAN,
+                    (true, true) => 1.0,
+                    (true, false) => 0.0,
+                };
+                (x, y, flag)
+            })
+            .collect::<Vec<_>>();
+
+        let mask_name = suffixed_name(&output_name.as_ref().to_string_lossy(), "mask");
+        write_values(
+            mask_name,
+            grids,
+            "fraction of interpolated bins",
+            None,
+            &mask,
+            raw_arguments,
+        )?;
+        values = filled;
+    }
+
+    if let Some(sigma) = filters.smooth {
+        values = smoothing::gaussian_filter(&values, n_x, n_y, tile, sigma);
+    }
+
+    // leaflet interdigitation is dimensionless
+    let unit = if grids.distributions.is_some() {
+        None
+    } else {
+        Some("nm")
+    };
+    let mut output = write_values(
+        output_name,
+        grids,
+        grids.quantity,
+        unit,
+        &values,
+        raw_arguments,
+    )?;
+
+    if matches!(grids.geometry, Geometry::Vesicle | Geometry::Tube) {
+        let (upper, lower) = grids.geometry.leaflet_names();
+        let (upper_radius, lower_radius) = grids.leaflet_distances();
+        writeln!(
+            &mut output,
+            "# Average {} leaflet radius: {:12.4} nm",
+            upper, upper_radius
+        )?;
+        writeln!(
+            &mut output,
+            "# Average {} leaflet radius: {:12.4} nm",
+            lower, lower_radius
+        )?;
+    }
+
+    if let Some(footprint) = &grids.footprint {
+        let n_masked = footprint
+            .masked()
+            .into_iter()
+            .filter(|&masked| masked)
+            .count();
+        writeln!(
+            &mut output,
+            "# Bins masked by the protein footprint: {}",
+            n_masked
+        )?;
+    }
+
+    if let Some(blocks) = blocks {
+        match (blocks.estimate(grids, nan_limit), blocks.method) {
+            (Some((mean, error)), ErrorMethod::Blocks) => writeln!(
+                &mut output,
+                "# Block-averaged membrane thickness ({} blocks): {:.4} ± {:.4} nm (mean ± standard error)",
+                blocks.n_blocks(),
+                mean,
+                error
+            )?,
+            (Some((average, error)), ErrorMethod::Jackknife) => writeln!(
+                &mut output,
+                "# Jackknife estimate of the membrane thickness ({} blocks): {:.4} ± {:.4} nm (average ± standard error)",
+                blocks.n_blocks(),
+                average,
+                error
+            )?,
+            (None, _) => println!(
+                "WARNING: Not enough blocks contain data. Error of the average thickness could not be estimated."
+            ),
+        }
+    }
+
+    Ok(output)
+}
+
+/// Write the thickness maps of the individual sectors of the tilt direction of the helix into separate files.
+fn write_tilt_maps(
+    output_name: &str,
+    tilt: &TiltMaps,
+    nan_limit: usize,
+    raw_arguments: &[String],
+) -> anyhow::Result<()> {
+    let width = tilt.sector_width();
+
+    println!("\nThickness maps conditioned on the tilt direction of the helix:");
+    for (index, sector) in tilt.sectors().iter().enumerate() {
+        let (from, to) = ((index as f32 - 0.5) * width, (index as f32 + 0.5) * width);
+        let line = format!(
+            "Tilt azimuth {:7.1}° to {:7.1}°: {} frames",
+            from, to, sector.n_frames
+        );
+        println!("{}", line);
+
+        let mut file = write_map(
+            suffixed_name(output_name, index + 1),
+            sector,
+            nan_limit,
+            None,
+            MapFilters::default(),
+            raw_arguments,
+        )?;
+        writeln!(&mut file, "# {}", line)?;
+    }
+
+    Ok(())
+}
+
+/// Write the maps of the minimal and maximal instantaneous thickness into two files.
+fn write_extreme_maps(
+    output_name: &str,
+    grids: &ThicknessGrids,
+    extremes: &Extremes,
+    nan_limit: usize,
+    raw_arguments: &[String],
+) -> anyhow::Result<()> {
+    let (min, max): (Vec<_>, Vec<_>) = grids
+        .thickness(nan_limit)
+        .zip(extremes.values())
+        .map(|((x, y, thickness), (min, max))| {
+            if thickness.is_finite() {
+                ((x, y, min), (x, y, max))
+            } else {
+                ((x, y, f64::NAN), (x, y, f64::NAN))
+            }
+        })
+        .unzip();
+
+    for (extreme, name, values) in [("min", "minimal", min), ("max", "maximal", max)] {
+        write_values(
+            suffixed_name(output_name, extreme),
+            grids,
+            &format!("{} instantaneous membrane thickness", name),
+            Some("nm"),
+            &values,
+            raw_arguments,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Write the thickness map with adaptive resolution and the sizes of its bins.
+fn write_adaptive_map(
+    output_name: &str,
+    grids: &ThicknessGrids,
+    nan_limit: usize,
+    levels: u32,
+    raw_arguments: &[String],
+) -> anyhow::Result<()> {
+    let (thickness, sizes): (Vec<_>, Vec<_>) = adaptive::quadtree(grids, nan_limit, levels)
+        .into_iter()
+        .map(|(x, y, thickness, size)| ((x, y, thickness), (x, y, size as f64)))
+        .unzip();
+
+    write_values(
+        output_name,
+        grids,
+        "membrane thickness",
+        Some("nm"),
+        &thickness,
+        raw_arguments,
+    )?;
+    write_values(
+        suffixed_name(output_name, "size"),
+        grids,
+        "size of the adaptive bins",
+        Some("bins"),
+        &sizes,
+        raw_arguments,
+    )?;
+
+    Ok(())
+}
+
+/// Write the map of the thick and thin domains of the membrane and the properties of the individual domains.
+/// The threshold separating the domains is determined by Otsu's method if not provided.
+fn write_domain_map(
+    output_name: &str,
+    grids: &ThicknessGrids,
+    nan_limit: usize,
+    threshold: Option<f32>,
+    raw_arguments: &[String],
+) -> anyhow::Result<()> {
+    let thickness = grids.thickness(nan_limit).collect::<Vec<_>>();
+    let values = thickness
+        .iter()
+        .map(|&(_, _, value)| value)
+        .collect::<Vec<_>>();
+
+    let (threshold, source) = match threshold {
+        Some(threshold) => (threshold as f64, "user-defined"),
+        None => match domains::otsu_threshold(&values) {
+            Some(threshold) => (threshold, "Otsu's method"),
+            None => {
+                println!(
+                    "WARNING: Thickness map does not contain two different values. Domains were not identified."
+                );
+                return Ok(());
+            }
+        },
+    };
+
+    let areas = grids.bin_areas().collect::<Vec<_>>();
+    let (n_x, n_y) = (grids.upper_sum.n_tiles_x(), grids.upper_sum.n_tiles_y());
+    let (labels, domains) = domains::segment(&values, &areas, n_x, n_y, threshold);
+
+    let file = File::create(output_name)?;
+    let mut output = BufWriter::new(file);
+
+    writeln!(
+        &mut output,
+        "# Generated with memthick v{}.",
+        env!("CARGO_PKG_VERSION")
+    )?;
+    writeln!(&mut output, "# Command line: {}", raw_arguments.join(" "))?;
+    writeln!(
+        &mut output,
+        "# See the properties of the individual domains at the end of this file."
+    )?;
+
+    let (label_x, label_y) = grids.axis_labels();
+    writeln!(&mut output, "@ xlabel {}", label_x)?;
+    writeln!(&mut output, "@ ylabel {}", label_y)?;
+    writeln!(&mut output, "@ zlabel domain")?;
+    writeln!(&mut output, "@ grid --")?;
+    writeln!(&mut output, "$ type colorbar")?;
+    writeln!(&mut output, "$ colormap rainbow")?;
+
+    for ((x, y, _), label) in thickness.iter().zip(labels.iter()) {
+        let label = label.map(|label| (label + 1) as f64).unwrap_or(f64::NAN);
+        writeln!(&mut output, "{:12.6} {:12.6} {:12.4}", x, y, label)?;
+    }
+
+    writeln!(
+        &mut output,
+        "# Thickness threshold ({}): {:.4} nm",
+        source, threshold
+    )?;
+    writeln!(&mut output, "# domain type area [nm^2] thickness [nm] bins")?;
+    for (index, domain) in domains.iter().enumerate() {
+        writeln!(
+            &mut output,
+            "# {:6} {:>5} {:12.4} {:12.4} {:8}",
+            index + 1,
+            if domain.thick { "thick" } else { "thin" },
+            domain.area,
+            domain.thickness(),
+            domain.n_bins
+        )?;
+    }
+
+    output.flush()?;
+    Ok(())
+}
+
+/// Kind of a profile of the thickness around a point or selected atoms.
+#[derive(Debug, Clone, Copy)]
+enum ProfileKind {
+    /// Thickness by the distance from the center of the radial profile.
+    Radial,
+    /// Thickness by the azimuthal angle in an annulus around the center of the radial profile.
+    Azimuthal,
+    /// Thickness by the distance from the center of the radial profile and the azimuthal angle around it.
+    AzimuthalMap,
+    /// Thickness by the distance from the closest selected atoms.
+    Around,
+}
+
+/// Profiles of the thickness around points or selected atoms as `(kind, output file)`.
+fn radial_profiles(args: &Args) -> Vec<(ProfileKind, &str)> {
+    let mut profiles = Vec::new();
+    if let Some(radial) = &args.radial {
+        profiles.push((ProfileKind::Radial, radial.as_str()));
+    }
+
+    if let Some(azimuthal) = &args.azimuthal {
+        profiles.push((ProfileKind::Azimuthal, azimuthal.as_str()));
+    }
+
+    if let Some(azimuthal_map) = &args.azimuthal_map {
+        profiles.push((ProfileKind::AzimuthalMap, azimuthal_map.as_str()));
+    }
+
+    if args.around.is_some() {
+        profiles.push((ProfileKind::Around, args.around_output.as_str()));
+    }
+
+    profiles
+}
+
+/// Maps calculated from other atoms than the headgroups as `(group, query, quantity, output file)`.
+fn secondary_maps(args: &Args) -> Vec<(&'static str, &str, &'static str, &str)> {
+    let mut maps = Vec::new();
+    if let Some(hydrophobic) = &args.hydrophobic {
+        maps.push((
+            HYDROPHOBIC_GROUP,
+            hydrophobic.as_str(),
+            "hydrophobic thickness",
+            args.hydrophobic_output.as_str(),
+        ));
+    }
+
+    if let Some(interdigitation) = &args.interdigitation {
+        maps.push((
+            INTERDIGITATION_GROUP,
+            interdigitation.as_str(),
+            "leaflet interdigitation",
+            args.interdigitation_output.as_str(),
+        ));
+    }
+
+    if let (Some(sterols), Some(output)) = (&args.sterol_heads, &args.sterol_output) {
+        maps.push((
+            STEROL_GROUP,
+            sterols.as_str(),
+            "membrane thickness at sterols",
+            output.as_str(),
+        ));
+    }
+
+    maps
+}
+
+/// Write the thickness map into the output file and the maps calculated from other atoms
+/// and the lipid composition, environment, and table (if requested) into their own output files.
+fn write_maps(
+    args: &Args,
+    grids: &ThicknessGrids,
+    blocks: Option<&BlockAverage>,
+    raw_arguments: &[String],
+) -> anyhow::Result<()> {
+    write_map(
+        &args.output,
+        grids,
+        args.nan_limit,
+        blocks,
+        MapFilters::from_args(args),
+        raw_arguments,
+    )?;
+
+    if let (Some(block_errors), Some(blocks)) = (&args.block_errors, blocks) {
+        write_values(
+            block_errors,
+            grids,
+            "standard error of the membrane thickness",
+            Some("nm"),
+            &blocks.bin_errors(grids, args.nan_limit),
+            raw_arguments,
+        )?;
+    }
+
+    if let (Some(converge), Some(blocks)) = (&args.converge, blocks) {
+        converge::write_convergence(converge, grids, blocks, args.nan_limit, raw_arguments)?;
+    }
+
+    if let (Some(bootstrap), Some(blocks)) = (&args.bootstrap, blocks) {
+        let (lower, upper): (Vec<_>, Vec<_>) = blocks
+            .bootstrap(
+                grids,
+                args.nan_limit,
+                args.bootstrap_samples,
+                args.confidence,
+                args.seed,
+            )
+            .into_iter()
+            .map(|(x, y, lower, upper)| ((x, y, lower), (x, y, upper)))
+            .unzip();
+
+        for (bound, values) in [("lower", lower), ("upper", upper)] {
+            write_values(
+                suffixed_name(bootstrap, format!("{}_bound", bound)),
+                grids,
+                &format!("{} confidence bound of the membrane thickness", bound),
+                Some("nm"),
+                &values,
+                raw_arguments,
+            )?;
+        }
+    }
+
+    if let (Some(occupancy), Some(footprint)) = (&args.occupancy, &grids.footprint) {
+        write_values(
+            occupancy,
+            grids,
+            "fraction of frames with the bin covered by the protein",
+            None,
+            &footprint.occupancy(grids),
+            raw_arguments,
+        )?;
+    }
+
+    if let Some(stdev) = &args.stdev {
+        let values = grids
+            .thickness_deviation(args.nan_limit)
+            .collect::<Vec<_>>();
+        write_values(
+            stdev,
+            grids,
+            "standard deviation of the membrane thickness",
+            Some("nm"),
+            &values,
+            raw_arguments,
+        )?;
+    }
+
+    if let (Some(output), Some(reference)) = (&args.deviation, &grids.reference) {
+        let values = grids.thickness(args.nan_limit).collect::<Vec<_>>();
+        match reference.thickness(&values) {
+            Some((thickness, n_bins)) => {
+                let mut file = write_values(
+                    output,
+                    grids,
+                    "relative deviation of the membrane thickness",
+                    Some("%"),
+                    &deviation::relative_deviation(&values, thickness),
+                    raw_arguments,
+                )?;
+
+                match reference {
+                    Reference::Value(_) => {
+                        writeln!(&mut file, "# Reference thickness: {:12.4} nm", thickness)?
+                    }
+                    Reference::Bulk(_) | Reference::Average => writeln!(
+                        &mut file,
+                        "# Reference thickness: {:12.4} nm (average of {} bins)",
+                        thickness, n_bins
+                    )?,
+                }
+            }
+            None => println!(
+                "WARNING: No bins of the bulk membrane have defined thickness. Deviation map was not written."
+            ),
+        }
+    }
+
+    if let (Some(output), Some(deformation)) = (&args.deformation, &grids.deformation) {
+        let values = grids.thickness(args.nan_limit).collect::<Vec<_>>();
+        match deformation.far_field(&values) {
+            Some((far_field, n_bins)) => {
+                let map = Deformation::map(&values, far_field);
+                let mut file = write_values(
+                    output,
+                    grids,
+                    "deformation of the membrane thickness",
+                    Some("nm"),
+                    &map,
+                    raw_arguments,
+                )?;
+
+                let line = format!(
+                    "Far-field thickness: {:12.4} nm (average of {} bins)",
+                    far_field, n_bins
+                );
+                println!("\n{}", line);
+                writeln!(&mut file, "# {}", line)?;
+
+                match deformation.decay(&map) {
+                    Some(decay) => {
+                        let length = format!(
+                            "Decay length of the deformation: {:12.4} nm ({} shells)",
+                            decay.length, decay.n_shells
+                        );
+                        let amplitude =
+                            format!("Deformation at the inclusion: {:12.4} nm", decay.amplitude);
+                        for line in [length, amplitude] {
+                            println!("{}", line);
+                            writeln!(&mut file, "# {}", line)?;
+                        }
+                    }
+                    None => println!(
+                        "WARNING: Deformation does not decay with the distance from the inclusion. Decay length was not determined."
+                    ),
+                }
+            }
+            None => println!(
+                "WARNING: No bins of the bulk membrane have defined thickness. Deformation map was not written."
+            ),
+        }
+    }
+
+    if let Some(roi) = &grids.roi {
+        let values = grids.thickness(args.nan_limit).collect::<Vec<_>>();
+        roi.write(&args.roi_output, &values, raw_arguments)?;
+    }
+
+    if let (Some(output), Some(series)) = (&args.autocorrelation, &grids.series) {
+        series.write(output, raw_arguments)?;
+        if let Some(correlation) = series.correlation_time() {
+            println!(
+                "Integrated autocorrelation time of the average thickness: {:.3} ps ({:.1} independent samples).",
+                correlation.time, correlation.n_independent
+            );
+        }
+    }
+
+    if let (Some(output), Some(extremes)) = (&args.extremes, &grids.extremes) {
+        write_extreme_maps(output, grids, extremes, args.nan_limit, raw_arguments)?;
+    }
+
+    if let (Some(output), Some(bin_series)) = (&args.effective_samples, &grids.bin_series) {
+        let values = grids
+            .thickness(args.nan_limit)
+            .zip(bin_series.effective_samples())
+            .map(|((x, y, thickness), samples)| {
+                (
+                    x,
+                    y,
+                    if thickness.is_finite() {
+                        samples
+                    } else {
+                        f64::NAN
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+
+        write_values(
+            output,
+            grids,
+            "effective number of independent samples",
+            None,
+            &values,
+            raw_arguments,
+        )?;
+    }
+
+    if let Some(residues) = &grids.residues {
+        residues.write(&args.residues_output, grids, args.nan_limit, raw_arguments)?;
+    }
+
+    if let Some(tilt) = &grids.tilt {
+        write_tilt_maps(&args.tilt_output, tilt, args.nan_limit, raw_arguments)?;
+    }
+
+    if let Some(domains) = &args.domains {
+        write_domain_map(
+            domains,
+            grids,
+            args.nan_limit,
+            args.domain_threshold,
+            raw_arguments,
+        )?;
+    }
+
+    if let Some(projection) = &args.projection {
+        let values = grids.thickness(args.nan_limit).collect::<Vec<_>>();
+        let (n_x, n_y) = (grids.upper_sum.n_tiles_x(), grids.upper_sum.n_tiles_y());
+        let (label_x, label_y) = grids.axis_labels();
+        for (axis, along_x, label) in [("x", true, label_x), ("y", false, label_y)] {
+            projection::write(
+                suffixed_name(projection, axis),
+                &projection::project(&values, n_x, n_y, along_x),
+                &label,
+                raw_arguments,
+            )?;
+        }
+    }
+
+    if let Some(adaptive) = &args.adaptive {
+        write_adaptive_map(
+            adaptive,
+            grids,
+            args.nan_limit,
+            args.adaptive_levels,
+            raw_arguments,
+        )?;
+    }
+
+    if let Some(leaflet_maps) = &args.leaflet_maps {
+        write_leaflet_maps(leaflet_maps, grids, args.nan_limit, raw_arguments)?;
+    }
+
+    if let Some(midplane) = &args.midplane {
+        write_midplane_map(midplane, grids, args.nan_limit, raw_arguments)?;
+    }
+
+    if args.curvature.is_some() || args.gaussian_curvature.is_some() {
+        write_curvature_maps(args, grids, raw_arguments)?;
+    }
+
+    if let (Some(output), Some(spectrum)) = (&args.spectrum, &grids.spectrum) {
+        spectrum.write(output, raw_arguments)?;
+    }
+
+    for (profile, (_, output)) in grids.profiles.iter().zip(radial_profiles(args)) {
+        profile.write(output, args.nan_limit, raw_arguments)?;
+    }
+
+    let inclusions = grids
+        .profiles
+        .iter()
+        .skip(radial_profiles(args).len())
+        .collect::<Vec<_>>();
+    for (index, profile) in inclusions.iter().enumerate() {
+        profile.write(
+            suffixed_name(&args.inclusions_output, index + 1),
+            args.nan_limit,
+            raw_arguments,
+        )?;
+    }
+
+    if args.inclusions_average
+        && let Some((first, others)) = inclusions.split_first()
+    {
+        let mut average = (*first).clone();
+        for other in others {
+            average.merge(other);
+        }
+        average.write(
+            suffixed_name(&args.inclusions_output, "average"),
+            args.nan_limit,
+            raw_arguments,
+        )?;
+    }
+
+    let find = |sample: Sample| {
+        grids
+            .secondary
+            .iter()
+            .find(|secondary| secondary.sample == sample)
+    };
+    let secondary = grids
+        .secondary
+        .iter()
+        .filter(|secondary| secondary.sample == Sample::Distance);
+    for (secondary, (_, _, _, output)) in secondary.zip(secondary_maps(args)) {
+        write_map(
+            output,
+            secondary,
+            args.nan_limit,
+            None,
+            MapFilters::default(),
+            raw_arguments,
+        )?;
+    }
+
+    if let Some(roughness) = &args.roughness {
+        write_roughness_maps(roughness, grids, args.nan_limit, raw_arguments)?;
+    }
+
+    if let (Some(area_per_lipid), Some(areas)) = (&args.area_per_lipid, find(Sample::Area)) {
+        write_leaflet_values(
+            area_per_lipid,
+            areas,
+            areas.leaflet_heights(args.nan_limit),
+            |leaflet| format!("area per lipid in the {} leaflet", leaflet),
+            "nm^2",
+            raw_arguments,
+        )?;
+    }
+
+    if let Some(density) = &args.density {
+        write_leaflet_values(
+            density,
+            grids,
+            grids.leaflet_densities(),
+            |leaflet| format!("number density of headgroups in the {} leaflet", leaflet),
+            "nm^-2",
+            raw_arguments,
+        )?;
+    }
+
+    if let Some(asymmetry) = &args.asymmetry {
+        let mut file = write_values(
+            asymmetry,
+            grids,
+            "asymmetry of the number of headgroups between the leaflets",
+            None,
+            &grids.count_asymmetry().collect::<Vec<_>>(),
+            raw_arguments,
+        )?;
+
+        let upper = grids
+            .upper_count
+            .extract_raw()
+            .map(|(_, _, &count)| count)
+            .sum::<usize>();
+        let lower = grids
+            .lower_count
+            .extract_raw()
+            .map(|(_, _, &count)| count)
+            .sum::<usize>();
+        writeln!(
+            &mut file,
+            "# Asymmetry of all headgroups: {:12.4} ({} upper, {} lower)",
+            (upper as f64 - lower as f64) / (upper + lower) as f64,
+            upper,
+            lower
+        )?;
+    }
+
+    if let (Some(output), Some(composition)) = (&args.composition, &grids.composition) {
+        composition.write(output, raw_arguments)?;
+    }
+
+    if let (Some(_), Some(environment)) = (&args.environment, &grids.environment) {
+        environment.write(
+            &args.environment_output,
+            args.geometry.leaflet_names(),
+            raw_arguments,
+        )?;
+    }
+
+    if let (Some(output), Some(molecules)) = (&args.lipid_table, &grids.molecules) {
+        molecules.write(output, grids, args.nan_limit, raw_arguments)?;
+    }
+
+    Ok(())
+}
+
+/// Write the maps of the average distances of the headgroups of each leaflet from the membrane center
+/// into two files named after the leaflets.
+fn write_leaflet_maps(
+    output_name: &str,
+    grids: &ThicknessGrids,
+    nan_limit: usize,
+    raw_arguments: &[String],
+) -> anyhow::Result<()> {
+    let quantity = |leaflet: &str| match grids.geometry {
+        Geometry::Vesicle | Geometry::Tube => format!("radius of the {} leaflet", leaflet),
+        Geometry::Planar | Geometry::Buckle => {
+            format!(
+                "distance of the {} leaflet from the membrane center",
+                leaflet
+            )
+        }
+    };
+
+    write_leaflet_values(
+        output_name,
+        grids,
+        grids.leaflet_heights(nan_limit),
+        quantity,
+        "nm",
+        raw_arguments,
+    )
+}
+
+/// Write the maps of the roughness of both leaflets into two files named after the leaflets.
+/// The roughness of each leaflet over all its headgroups is written at the end of each file.
+fn write_roughness_maps(
+    output_name: &str,
+    grids: &ThicknessGrids,
+    nan_limit: usize,
+    raw_arguments: &[String],
+) -> anyhow::Result<()> {
+    let (upper_values, lower_values): (Vec<_>, Vec<_>) = grids
+        .leaflet_deviations(nan_limit)
+        .map(|(x, y, upper, lower)| ((x, y, upper), (x, y, lower)))
+        .unzip();
+
+    let (upper, lower) = grids.geometry.leaflet_names();
+    let (upper_global, lower_global) = grids.global_roughness();
+    for (leaflet, values, global) in [
+        (upper, upper_values, upper_global),
+        (lower, lower_values, lower_global),
+    ] {
+        let mut output = write_values(
+            suffixed_name(output_name, leaflet),
+            grids,
+            &format!("roughness of the {} leaflet", leaflet),
+            Some("nm"),
+            &values,
+            raw_arguments,
+        )?;
+
+        writeln!(
+            &mut output,
+            "# Global roughness of the {} leaflet: {:12.4} nm",
+            leaflet, global
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Write the values of the leaflets given as `(x, y, upper, lower)` into two files named after the leaflets.
+/// `quantity` provides the name of the mapped quantity for the name of each leaflet.
+fn write_leaflet_values(
+    output_name: &str,
+    grids: &ThicknessGrids,
+    values: impl Iterator<Item = (f32, f32, f64, f64)>,
+    quantity: impl Fn(&str) -> String,
+    unit: &str,
+    raw_arguments: &[String],
+) -> anyhow::Result<()> {
+    let (upper_values, lower_values): (Vec<_>, Vec<_>) = values
+        .map(|(x, y, upper, lower)| ((x, y, upper), (x, y, lower)))
+        .unzip();
+
+    let (upper, lower) = grids.geometry.leaflet_names();
+    for (leaflet, values) in [(upper, upper_values), (lower, lower_values)] {
+        write_values(
+            suffixed_name(output_name, leaflet),
+            grids,
+            &quantity(leaflet),
+            Some(unit),
+            &values,
+            raw_arguments,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Write the map of the membrane midplane, i.e., the mean of the average distances of the headgroups
+/// of both leaflets from the membrane center, into the output file.
+fn write_midplane_map(
+    output_name: &str,
+    grids: &ThicknessGrids,
+    nan_limit: usize,
+    raw_arguments: &[String],
+) -> anyhow::Result<()> {
+    let values = midplane_heights(grids, nan_limit);
+    let quantity = match grids.geometry {
+        Geometry::Vesicle | Geometry::Tube => "radius of the membrane midplane",
+        Geometry::Planar | Geometry::Buckle => "height of the membrane midplane",
+    };
+
+    write_values(
+        output_name,
+        grids,
+        quantity,
+        Some("nm"),
+        &values,
+        raw_arguments,
+    )?;
+    Ok(())
+}
+
+/// Write the maps of the mean and Gaussian curvature of the membrane midplane into their output files (if requested).
+fn write_curvature_maps(
+    args: &Args,
+    grids: &ThicknessGrids,
+    raw_arguments: &[String],
+) -> anyhow::Result<()> {
+    let heights = midplane_heights(grids, args.nan_limit);
+    let (n_x, n_y) = (grids.upper_sum.n_tiles_x(), grids.upper_sum.n_tiles_y());
+    let tile = grids.upper_sum.tile_dim();
+
+    if let Some(curvature) = &args.curvature {
+        let values = curvature::mean_curvature(&heights, n_x, n_y, tile);
+        write_values(
+            curvature,
+            grids,
+            "mean curvature",
+            Some("nm^-1"),
+            &values,
+            raw_arguments,
+        )?;
+    }
+
+    if let Some(gaussian) = &args.gaussian_curvature {
+        let values = curvature::gaussian_curvature(&heights, n_x, n_y, tile);
+        write_values(
+            gaussian,
+            grids,
+            "Gaussian curvature",
+            Some("nm^-2"),
+            &values,
+            raw_arguments,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Calculate the height of the membrane midplane in each grid bin as `(x, y, height)`.
+fn midplane_heights(grids: &ThicknessGrids, nan_limit: usize) -> Vec<(f32, f32, f64)> {
+    grids
+        .leaflet_heights(nan_limit)
+        .map(|(x, y, upper, lower)| (x, y, (upper + lower) / 2.0))
+        .collect()
+}
+
+/// Get the name of the file containing the map of the frame with the specified index.
+fn frame_name(frame_maps: &str, index: usize) -> PathBuf {
+    let path = Path::new(frame_maps);
+    let stem = path
+        .file_stem()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let name = match path.extension() {
+        Some(extension) => format!("{}_{:06}.{}", stem, index, extension.to_string_lossy()),
+        None => format!("{}_{:06}", stem, index),
+    };
+
+    path.with_file_name(name)
+}
+
+/// Get the name of the output file containing the map of the membrane with the specified index.
+fn membrane_name(output: &str, index: usize) -> String {
+    suffixed_name(output, index)
+}
+
+/// Get the file name created by appending the suffix to the stem of the output file name.
+fn suffixed_name(output: &str, suffix: impl std::fmt::Display) -> String {
+    let path = Path::new(output);
+    let stem = path
+        .file_stem()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let name = match path.extension() {
+        Some(extension) => format!("{}_{}.{}", stem, suffix, extension.to_string_lossy()),
+        None => format!("{}_{}", stem, suffix),
+    };
+
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// Maximal number of consecutive broken frames after which the reading of the trajectory is stopped.
+const MAX_CONSECUTIVE_BROKEN: usize = 100;
+
+/// Attach a progress printer to the trajectory reader and restrict it to the requested frames.
+fn setup_reader<'a, R>(
+    reader: TrajReader<'a, R>,
+    args: &Args,
+) -> Result<TrajRangeStepReader<'a, R>, ReadTrajError>
+where
+    R: TrajRangeRead<'a> + TrajStepRead<'a>,
+    R::FrameData: FrameDataTime,
+{
+    reader
+        .print_progress(ProgressPrinter::default())
+        .with_range(args.begin, args.end)?
+        .with_step(args.step)
+}
+
+/// Create a group from the query and check that it is not empty.
+fn create_group(system: &mut System, name: &str, query: &str) -> anyhow::Result<()> {
+    match system.group_create(name, query) {
+        Ok(_) | Err(GroupError::AlreadyExistsWarning(_)) => (),
+        Err(e) => return Err(e.into()),
+    }
+
+    if system.group_get_n_atoms(name).unwrap() == 0 {
+        anyhow::bail!("The query '{}' selects no atoms.", query);
+    }
+
+    Ok(())
+}
+
+/// Guess the masses of atoms with unknown masses from their names.
+/// Returns an error if the mass of any lipid atom remains unknown.
+fn guess_masses(system: &mut System) -> anyhow::Result<()> {
+    match system.guess_elements_unknown(Elements::default()) {
+        Ok(_) | Err(ElementError::ElementGuessWarning(_)) => (),
+        Err(e) => return Err(e.into()),
+    }
+
+    let n_unknown = system
+        .group_iter(LIPIDS_GROUP)?
+        .filter(|atom| atom.get_mass().is_none())
+        .count();
+    if n_unknown > 0 {
+        anyhow::bail!(
+            "Could not determine the masses of {} lipid atoms. Provide a tpr file to read the masses from.",
+            n_unknown
+        );
+    }
+
+    Ok(())
+}
+
+/// Remove the atoms excluded from the analysis from the group.
+/// Nothing is done if no atoms are excluded or the group does not exist.
+fn exclude_atoms(system: &mut System, group: &str) -> anyhow::Result<()> {
+    if !system.group_exists(EXCLUDE_GROUP) || !system.group_exists(group) {
+        return Ok(());
+    }
+
+    let kept = system
+        .group_iter(group)?
+        .map(|atom| atom.get_index())
+        .filter(|&index| !system.group_isin(EXCLUDE_GROUP, index).unwrap())
+        .collect::<Vec<_>>();
+
+    match system.group_create_from_indices(group, kept) {
+        Ok(_) | Err(GroupError::AlreadyExistsWarning(_)) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Check that the headgroup selections select only one atom of each lipid (identified by its residue number).
+/// Returns an error listing the offending lipids unless several atoms per lipid are allowed.
+fn check_single_heads(system: &System, args: &Args) -> anyhow::Result<()> {
+    let mut offending = Vec::new();
+    let mut previous: Option<(usize, usize)> = None;
+    for atom in system.group_iter(HEADS_GROUP)? {
+        let residue = atom.get_residue_number();
+        match &mut previous {
+            Some((number, count)) if *number == residue => {
+                *count += 1;
+                if *count == 2 {
+                    offending.push(format!("{} {}", residue, atom.get_residue_name()));
+                }
+            }
+            _ => previous = Some((residue, 1)),
+        }
+    }
+
+    if offending.is_empty() {
+        return Ok(());
+    }
+
+    const MAX_LISTED: usize = 5;
+    let mut listed = offending
+        .iter()
+        .take(MAX_LISTED)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(", ");
+    if offending.len() > MAX_LISTED {
+        listed.push_str(", ...");
+    }
+
+    let message = format!(
+        "The headgroup selection selects several atoms of {} lipid(s) (residues {}).",
+        offending.len(),
+        listed
+    );
+
+    if args.allow_multiple {
+        println!(
+            "WARNING: {} Each of these atoms is counted as a separate headgroup.\n",
+            message
+        );
+        return Ok(());
+    }
+
+    anyhow::bail!(
+        "{} Select only one atom per lipid, use '--head-com' to use the center of the selected atoms, or use '--allow-multiple'.",
+        message
+    );
+}
+
+/// Create all groups of atoms used in the analysis.
+fn create_groups(system: &mut System, args: &Args) -> anyhow::Result<()> {
+    create_group(system, LIPIDS_GROUP, &args.lipids)?;
+
+    if let Some(exclude) = &args.exclude {
+        create_group(system, EXCLUDE_GROUP, exclude)?;
+        exclude_atoms(system, LIPIDS_GROUP)?;
+        if system.group_get_n_atoms(LIPIDS_GROUP)? == 0 {
+            anyhow::bail!(
+                "No lipid atoms remain after excluding the atoms selected by '{}'.",
+                exclude
+            );
+        }
+    }
+
+    // the atoms defining the membrane center, the reference atoms for centering and fitting,
+    // the hydrophobic atoms, the tail ends, the environment atoms, and the water atoms must be read from the trajectory
+    // together with the lipids
+    system.group_union(LIPIDS_GROUP, LIPIDS_GROUP, READ_GROUP)?;
+    for (group, query) in [
+        (CENTER_GROUP, &args.center_selection),
+        (REFERENCE_GROUP, &args.center),
+        (FIT_GROUP, &args.fit),
+        (HYDROPHOBIC_GROUP, &args.hydrophobic),
+        (INTERDIGITATION_GROUP, &args.interdigitation),
+        (ENVIRONMENT_GROUP, &args.environment),
+        (WATER_GROUP, &args.water),
+        (PROFILE_GROUP, &args.radial_center),
+        (AROUND_GROUP, &args.around),
+        (PROTEIN_GROUP, &args.protein),
+        (HELIX_GROUP, &args.helix),
+        (RESIDUES_GROUP, &args.residues),
+        (INCLUSIONS_GROUP, &args.inclusions),
+    ] {
+        if let Some(query) = query {
+            create_group(system, group, query)?;
+            match system.group_union(READ_GROUP, group, READ_GROUP) {
+                Ok(_) | Err(GroupError::AlreadyExistsWarning(_)) => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    // the axis of the helix connects the centers of its halves
+    if args.helix.is_some() {
+        let atoms = system
+            .group_iter(HELIX_GROUP)?
+            .map(|atom| atom.get_index())
+            .collect::<Vec<_>>();
+        if atoms.len() < 2 {
+            anyhow::bail!(
+                "At least two atoms of the helix must be selected, not {}.",
+                atoms.len()
+            );
+        }
+
+        let (start, end) = atoms.split_at(atoms.len() / 2);
+        system.group_create_from_indices(HELIX_START_GROUP, start.to_vec())?;
+        system.group_create_from_indices(HELIX_END_GROUP, end.to_vec())?;
+    }
+
+    // the inclusion is only used in the input structure
+    if let Some(inclusion) = &args.inclusion {
+        create_group(system, INCLUSION_GROUP, inclusion)?;
+    }
+
+    if let (Some(upper), Some(lower)) = (&args.upper, &args.lower) {
+        create_group(system, UPPER_GROUP, upper)?;
+        create_group(system, LOWER_GROUP, lower)?;
+
+        if let Some(atom) = system
+            .group_iter(UPPER_GROUP)?
+            .find(|atom| system.group_isin(LOWER_GROUP, atom.get_index()).unwrap())
+        {
+            anyhow::bail!(
+                "Atom {} is selected both by the query '{}' and the query '{}'.",
+                atom.get_index() + 1,
+                upper,
+                lower
+            );
+        }
+
+        match system.group_union(UPPER_GROUP, LOWER_GROUP, HEADS_GROUP) {
+            Ok(_) | Err(GroupError::AlreadyExistsWarning(_)) => (),
+            Err(e) => return Err(e.into()),
+        }
+    } else if let (Some(upper), Some(lower)) = (&args.phosphates_upper, &args.phosphates_lower) {
+        // atoms of each lipid type are only counted in the leaflet they are selected for
+        create_group(system, UPPER_GROUP, upper)?;
+        create_group(system, LOWER_GROUP, lower)?;
+
+        match system.group_union(UPPER_GROUP, LOWER_GROUP, HEADS_GROUP) {
+            Ok(_) | Err(GroupError::AlreadyExistsWarning(_)) => (),
+            Err(e) => return Err(e.into()),
+        }
+    } else {
+        create_group(system, HEADS_GROUP, &args.phosphates)?;
+    }
+
+    // sterols can be counted in both leaflets
+    if let Some(sterols) = &args.sterol_heads {
+        create_group(system, STEROL_GROUP, sterols)?;
+        for group in [HEADS_GROUP, UPPER_GROUP, LOWER_GROUP] {
+            if !system.group_exists(group) {
+                continue;
+            }
+
+            match system.group_union(group, STEROL_GROUP, group) {
+                Ok(_) | Err(GroupError::AlreadyExistsWarning(_)) => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    for group in [HEADS_GROUP, UPPER_GROUP, LOWER_GROUP, STEROL_GROUP] {
+        exclude_atoms(system, group)?;
+    }
+
+    if system.group_get_n_atoms(HEADS_GROUP)? == 0 {
+        anyhow::bail!(
+            "No headgroups remain after excluding the atoms selected by '{}'.",
+            args.exclude.as_deref().unwrap_or_default()
+        );
+    }
+
+    if !args.head_com {
+        check_single_heads(system, args)?;
+    }
+
+    // each headgroup is represented by its first atom and positioned at the center of all its atoms
+    if args.head_com {
+        system.group_union(HEADS_GROUP, HEADS_GROUP, HEAD_ATOMS_GROUP)?;
+
+        let mut previous = None;
+        let mut first_atoms = Vec::new();
+        for atom in system.group_iter(HEADS_GROUP)? {
+            if previous != Some(atom.get_residue_number()) {
+                first_atoms.push(atom.get_index());
+                previous = Some(atom.get_residue_number());
+            }
+        }
+
+        match system.group_create_from_indices(HEADS_GROUP, first_atoms) {
+            Ok(_) | Err(GroupError::AlreadyExistsWarning(_)) => (),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    if let Some(tails) = &args.tails {
+        create_group(system, TAILS_GROUP, tails)?;
+        exclude_atoms(system, TAILS_GROUP)?;
+
+        let n_tails = system.group_get_n_atoms(TAILS_GROUP).unwrap();
+        let n_heads = system.group_get_n_atoms(HEADS_GROUP).unwrap();
+        if n_tails != n_heads {
+            let (heads, verb) = match (
+                &args.upper,
+                &args.lower,
+                &args.phosphates_upper,
+                &args.phosphates_lower,
+            ) {
+                (Some(upper), Some(lower), _, _) | (_, _, Some(upper), Some(lower)) => {
+                    (format!("the queries '{}' and '{}'", upper, lower), "select")
+                }
+                _ => (format!("the query '{}'", args.phosphates), "selects"),
+            };
+            let sterols = args.sterol_heads.as_ref().map_or(String::new(), |sterols| {
+                format!(" (together with the query '{}')", sterols)
+            });
+
+            anyhow::bail!(
+                "The query '{}' selects {} atoms, but {}{} {} {} headgroups. Select exactly one tail atom per lipid.",
+                tails,
+                n_tails,
+                heads,
+                sterols,
+                verb,
+                n_heads
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Iterate through the trajectory and accumulate the headgroup positions.
+#[allow(clippy::too_many_arguments)]
+fn analyze_trajectory<'a>(
+    trajectory: impl TrajMasterRead<'a>,
+    grids: &mut ThicknessGrids,
+    args: &Args,
+    classifier: &LeafletClassifier,
+    preprocessor: &FramePreprocessor,
+    weights: Option<&FrameWeights>,
+    mut blocks: Option<&mut BlockAverage>,
+    mut flip_flops: Option<&mut FlipFlopTracker>,
+    raw_arguments: &[String],
+) -> anyhow::Result<()> {
+    let restarted = grids.n_frames > 0;
+    let mut continuity = TimeContinuity::default();
+    let mut n_broken = 0;
+    let mut n_consecutive_broken = 0;
+
+    let mut convergence = args
+        .convergence
+        .as_ref()
+        .map(ConvergenceWriter::open)
+        .transpose()?;
+    let n_frames_start = grids.n_frames;
+
+    // grids for the calculation of instantaneous thickness maps
+    let mut instantaneous = args.frame_maps.as_ref().map(|_| {
+        let mut instantaneous = grids.clone();
+        instantaneous.clear();
+        instantaneous
+    });
+
+    for frame in trajectory {
+        let frame = match frame {
+            Ok(frame) => {
+                n_consecutive_broken = 0;
+                frame
+            }
+            // the last frame may still be being written by the simulation; it is read again in the next pass
+            // (incomplete xtc frames end the iteration, incomplete trr frames cannot be found)
+            Err(ReadTrajError::FrameNotFound) if args.follow => break,
+            Err(e) if args.skip_broken => {
+                n_broken += 1;
+                n_consecutive_broken += 1;
+
+                if n_consecutive_broken >= MAX_CONSECUTIVE_BROKEN {
+                    println!(
+                        "\nWARNING: {} consecutive frames could not be read. Stopping the analysis.",
+                        n_consecutive_broken
+                    );
+                    break;
+                }
+
+                println!("\nWARNING: Skipping a frame that could not be read. {}", e);
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        // skip the last frame analyzed before the restart
+        if restarted && frame.get_simulation_time() <= grids.last_time {
+            continue;
+        }
+
+        if args.trajectory.len() > 1 {
+            continuity.update(frame.get_simulation_time());
+        }
+
+        let weight = match weights {
+            Some(weights) => weights.get(grids.n_frames)?,
+            None => 1.0,
+        };
+
+        preprocessor.apply(frame)?;
+
+        let membrane_center = grids::membrane_center(frame, args.center_of_mass)?;
+        let upper = classifier.assign(frame, &membrane_center)?;
+
+        if let Some(flip_flops) = flip_flops.as_mut() {
+            flip_flops.update(frame.get_simulation_time(), &upper);
+        }
+
+        let upper = classifier.counted(&upper);
+
+        if let (Some(frame_maps), Some(instantaneous)) = (&args.frame_maps, &mut instantaneous) {
+            instantaneous.clear();
+            instantaneous.add_heads(frame, &membrane_center, &upper, 1.0)?;
+            write_map(
+                frame_name(frame_maps, grids.n_frames),
+                instantaneous,
+                1,
+                None,
+                MapFilters::default(),
+                raw_arguments,
+            )?;
+        }
+
+        if let Some(blocks) = blocks.as_mut() {
+            blocks.add_heads(grids.n_frames, frame, &membrane_center, &upper, weight)?;
+        }
+
+        grids.add_heads(frame, &membrane_center, &upper, weight)?;
+
+        if let Some(convergence) = convergence.as_mut()
+            && grids.n_frames.is_multiple_of(args.convergence_freq)
+        {
+            convergence.write(grids, args.nan_limit)?;
+        }
+
+        if let Some(state) = &args.state
+            && grids.n_frames.is_multiple_of(args.state_freq)
+        {
+            grids.write_state(state, raw_arguments)?;
+        }
+    }
+
+    if let Some(mut convergence) = convergence {
+        // always record the last analyzed frame
+        if grids.n_frames > n_frames_start && !grids.n_frames.is_multiple_of(args.convergence_freq)
+        {
+            convergence.write(grids, args.nan_limit)?;
+        }
+
+        convergence.finish()?;
+    }
+
+    println!();
+    continuity.report();
+
+    if n_broken > 0 {
+        println!(
+            "WARNING: {} trajectory frame(s) could not be read and were skipped.",
+            n_broken
+        );
+    }
+
+    Ok(())
+}
+
+/// Repeatedly analyze a trajectory that is being written by a running simulation.
+/// The output map is rewritten every time new frames have been analyzed.
+fn follow_trajectory(
+    system: &mut System,
+    grids: &mut ThicknessGrids,
+    args: &mut Args,
+    classifier: &LeafletClassifier,
+    weights: Option<&FrameWeights>,
+    raw_arguments: &[String],
+) -> anyhow::Result<()> {
+    let file = args.trajectory[0].clone();
+    let preprocessor = FramePreprocessor::new(args, system)?;
+
+    loop {
+        let n_frames = grids.n_frames;
+        if n_frames > 0 {
+            // continue reading from the last analyzed frame
+            args.begin = args.begin.max(grids.last_time);
+        }
+
+        match FileType::from_name(&file) {
+            FileType::XTC => analyze_trajectory(
+                setup_reader(system.group_xtc_iter(&file, READ_GROUP)?, args)?,
+                grids,
+                args,
+                classifier,
+                &preprocessor,
+                weights,
+                None,
+                None,
+                raw_arguments,
+            )?,
+            FileType::TRR => analyze_trajectory(
+                setup_reader(system.traj_iter::<TrrReader>(&file)?, args)?,
+                grids,
+                args,
+                classifier,
+                &preprocessor,
+                weights,
+                None,
+                None,
+                raw_arguments,
+            )?,
+            _ => unreachable!(
+                "FATAL MEMTHICK ERROR | follow_trajectory | Unsupported trajectory format should have been caught."
+            ),
+        }
+
+        if grids.n_frames > n_frames {
+            if let Some(state) = &args.state {
+                grids.write_state(state, raw_arguments)?;
+            }
+
+            write_maps(args, grids, None, raw_arguments)?;
+            println!(
+                "Analyzed {} frames (up to {} ps). Output written into '{}'.",
+                grids.n_frames, grids.last_time, args.output
+            );
+        }
+
+        if grids.n_frames > 0 && grids.last_time >= args.end {
+            return Ok(());
+        }
+
+        println!(
+            "Waiting for new frames (checking every {} s)...\n",
+            args.follow_interval
+        );
+        std::thread::sleep(Duration::from_secs(args.follow_interval));
+    }
+}
+
+/// Check that all the trajectory files are of the same supported format.
+fn sanity_check_trajectories(args: &Args) -> anyhow::Result<()> {
+    let Some(first) = args.trajectory.first() else {
+        // only the input structure is analyzed
+        if args.begin != 0.0 || args.end != f32::INFINITY || args.step != 1 {
+            anyhow::bail!(
+                "Selection of the analyzed frames ('--begin', '--end', and '--step') requires a trajectory."
+            );
+        }
+
+        return Ok(());
+    };
+
+    if first == stream::STDIN {
+        if args.trajectory.len() > 1 {
+            anyhow::bail!(
+                "Reading the trajectory from the standard input cannot be combined with other trajectory files."
+            );
+        }
+
+        if args.threads > 1 {
+            anyhow::bail!(
+                "Reading the trajectory from the standard input is not supported for parallel analysis."
+            );
+        }
+
+        return Ok(());
+    }
+
+    let format = FileType::from_name(first);
+    match format {
+        FileType::XTC | FileType::TRR => (),
+        #[cfg(feature = "chemfiles")]
+        FileType::DCD => (),
+        #[cfg(feature = "chemfiles")]
+        FileType::NC => {
+            // Amber NetCDF trajectories read through chemfiles carry no simulation time
+            if args.trajectory.len() > 1 {
+                anyhow::bail!(
+                    "Amber NetCDF trajectories do not contain simulation time and cannot be concatenated."
+                );
+            }
+
+            if args.begin != 0.0 || args.end != f32::INFINITY {
+                anyhow::bail!(
+                    "Amber NetCDF trajectories do not contain simulation time. Time range cannot be specified."
+                );
+            }
+
+            if args.restart.is_some() {
+                anyhow::bail!(
+                    "Amber NetCDF trajectories do not contain simulation time. Analysis cannot be restarted."
+                );
+            }
+
+            if args.chunk.is_some() {
+                anyhow::bail!(
+                    "Amber NetCDF trajectories do not contain simulation time and cannot be split into chunks."
+                );
+            }
+        }
+        #[cfg(not(feature = "chemfiles"))]
+        FileType::DCD | FileType::NC => anyhow::bail!(
+            "Reading dcd and nc trajectories requires memthick compiled with the 'chemfiles' feature."
+        ),
+        _ => anyhow::bail!(
+            "Unsupported format of the trajectory file '{}'. Provide an xtc, trr, dcd, or nc file.",
+            first
+        ),
+    }
+
+    if let Some(file) = args
+        .trajectory
+        .iter()
+        .find(|file| FileType::from_name(file) != format)
+    {
+        anyhow::bail!(
+            "All trajectory files must be of the same format, but '{}' differs from '{}'.",
+            file,
+            first
+        );
+    }
+
+    Ok(())
+}
+
+fn run() -> anyhow::Result<()> {
+    let raw_arguments = std::env::args().collect::<Vec<_>>();
+
+    let args = Args::parse();
+    println!("\n>> memthick {} <<\n", env!("CARGO_PKG_VERSION"));
+
+    match &args.command {
+        Some(Command::Merge(merge_args)) => return merge::run_merge(merge_args, &raw_arguments),
+        Some(Command::Diff(diff_args)) => return diff::run_diff(diff_args, &raw_arguments),
+        Some(Command::Converge(converge_args)) => {
+            return converge::run_converge(converge_args, &raw_arguments);
+        }
+        None => (),
+    }
+
+    run_analysis(args, &raw_arguments)
+}
+
+/// Run the analysis of all membranes in the system.
+fn run_analysis(args: Args, raw_arguments: &[String]) -> anyhow::Result<()> {
+    sanity_check_options(&args)?;
+
+    if args.membranes > 1 {
+        for membrane in 0..args.membranes {
+            let mut membrane_args = args.clone();
+            membrane_args.membrane = Some(membrane);
+            for output in membrane_args.outputs_mut() {
+                *output = membrane_name(output, membrane + 1);
+            }
+
+            analyze(membrane_args, raw_arguments)?;
+            println!();
+        }
+
+        return Ok(());
+    }
+
+    analyze(args, raw_arguments)
+}
+
+/// Run the analysis of a single membrane.
+fn analyze(mut args: Args, raw_arguments: &[String]) -> anyhow::Result<()> {
+    let mut system = System::from_file(args.structure()).map_err(anyhow::Error::from_boxed)?;
+    if let Some(ndx) = &args.index {
+        system.read_ndx(ndx)?;
+    }
+
+    create_groups(&mut system, &args)?;
+
+    if args.center_of_mass {
+        guess_masses(&mut system)?;
+    }
+
+    if args.normal_is_auto() {
+        args.normal = vec![Normal::guess(&system, args.center_of_mass)?.to_string()];
+        args.auto_normal = true;
+    }
+
+    let n_lipid_atoms = system.group_get_n_atoms(LIPIDS_GROUP)?;
+    if args.largest_aggregate {
+        let aggregate =
+            membranes::largest_aggregate(&system, args.normal(), args.aggregate_cutoff)?;
+        membranes::restrict_groups(&mut system, aggregate)?;
+    }
+
+    if let Some(membrane) = args.membrane {
+        let mut membranes = membranes::split_membranes(&system, &args.normal(), args.membranes)?;
+        membranes::restrict_groups(&mut system, membranes.swap_remove(membrane))?;
+    }
+
+    // the selected atoms are split into the individual inclusions
+    let mut n_inclusions = 0;
+    if args.inclusions.is_some() {
+        let inclusions = membranes::aggregates(
+            &system,
+            INCLUSIONS_GROUP,
+            args.normal(),
+            args.aggregate_cutoff,
+        )?;
+        if inclusions.is_empty() {
+            anyhow::bail!("No atoms of the inclusions selected.");
+        }
+
+        n_inclusions = inclusions.len();
+        for (index, atoms) in inclusions.into_iter().enumerate() {
+            system.group_create_from_indices(&inclusion_group(index), atoms)?;
+        }
+    }
+
+    let simbox = system.get_box().ok_or(SimBoxError::DoesNotExist)?;
+    if !simbox.is_orthogonal() || simbox.is_zero() {
+        return Err(SimBoxError::NotOrthogonal.into());
+    }
+
+    args.trajectory = trajectory::expand_patterns(&system, &args.trajectory)?;
+
+    // number of frames analyzed before the chunk and in all chunks together
+    let mut chunk_frames = None;
+    if let Some(chunk) = args.chunk {
+        let times = trajectory::frame_times(&system, &args.trajectory)?.expect(
+            "FATAL MEMTHICK ERROR | run | Chunks of trajectories read from the standard input should have been rejected.",
+        );
+
+        let n_analyzed = trajectory::analyzed_times(&times, args.begin, args.end, args.step).len();
+        chunk_frames = Some((chunk.n_preceding(n_analyzed), n_analyzed));
+
+        let Some((begin, end)) = chunk.time_range(&times, args.begin, args.end, args.step) else {
+            anyhow::bail!("Trajectory chunk {} contains no frames to analyze.", chunk);
+        };
+
+        args.begin = begin;
+        args.end = end;
+    }
+
+    print_options(&args, simbox);
+
+    if args.largest_aggregate {
+        println!(
+            "Analyzing the largest lipid aggregate containing {} of {} lipid atoms.\n",
+            system.group_get_n_atoms(LIPIDS_GROUP)?,
+            n_lipid_atoms
+        );
+    }
+
+    if args.inclusions.is_some() {
+        println!(
+            "Calculating the radial profiles around {} inclusions.\n",
+            n_inclusions
+        );
+    }
+
+    let normal = args.normal();
+    let (mut range_x, mut range_y) = args.geometry.map_range(&normal, simbox);
+    if args.scaled {
+        (range_x, range_y) = ((0.0, 1.0), (0.0, 1.0));
+    }
+    if args.grid == Grid::Polar {
+        let (box_x, box_y) = normal.lateral_box(simbox).expect(
+            "FATAL MEMTHICK ERROR | run | Polar grids should only be used with normals along box axes.",
+        );
+        (range_x, range_y) = ((0.0, box_x.min(box_y) / 2.0), (0.0, 360.0));
+    }
+    if args.geometry == Geometry::Buckle {
+        let center = grids::membrane_center(&system, args.center_of_mass)?;
+        range_x = (
+            0.0,
+            BuckleProfile::new(&system, &center, normal, args.buckle_modes)?.length(),
+        );
+    }
+    let xmin = args.xmin.unwrap_or(range_x.0);
+    let xmax = args.xmax.unwrap_or(range_x.1);
+    let ymin = args.ymin.unwrap_or(range_y.0);
+    let ymax = args.ymax.unwrap_or(range_y.1);
+
+    if matches!(args.geometry, Geometry::Planar | Geometry::Buckle) {
+        let guessed = Normal::guess(&system, args.center_of_mass)?;
+        if guessed != normal.dominant_axis() {
+            println!(
+                "WARNING: The membrane normal seems to be oriented along the {}-axis, but the normal '{}' is used. Consider using '--normal {}'.\n",
+                guessed, normal, guessed
+            );
+        }
+    }
+
+    let tile = match args.geometry {
+        Geometry::Planar if args.scaled => {
+            let (box_x, box_y) = normal.lateral_box(simbox).expect(
+                "FATAL MEMTHICK ERROR | run | Scaled grid coordinates should only be used with normals along box axes.",
+            );
+            (args.bin_size / box_x, args.bin_size / box_y)
+        }
+        Geometry::Planar if args.grid == Grid::Polar => (args.bin_size, args.angle_bin),
+        Geometry::Planar => (args.bin_size, args.bin_size),
+        Geometry::Vesicle => (args.angle_bin, args.angle_bin),
+        Geometry::Tube => (args.bin_size, args.angle_bin),
+        Geometry::Buckle => (args.bin_size, args.bin_size),
+    };
+    let mut grids = ThicknessGrids::new((xmin, xmax), (ymin, ymax), tile, normal, args.geometry)?;
+    grids.scaled = args.scaled;
+    grids.origin = args.polar_origin(simbox);
+    grids.method = args.thickness_method;
+    grids.fit_plane = args.fit_plane;
+    grids.kernel = args.kernel;
+    grids.pair_cutoff = args.pair_cutoff;
+    grids.exclude_shell = args.exclude_shell;
+    grids.region = args.region;
+    grids.kde_bandwidth = args.kde_bandwidth;
+    grids.center_of_mass = args.center_of_mass;
+    grids.buckle_modes = args.buckle_modes;
+    grids.heads = args.heads();
+    grids.leaflets = args.leaflets();
+
+    if let Some(restart) = &args.restart {
+        let restored = ThicknessGrids::read_state(restart)?;
+        if !restored.is_compatible(&grids) {
+            anyhow::bail!(
+                "The grid stored in the state file '{}' does not match the requested grid.",
+                restart
+            );
+        }
+
+        if !restored.has_same_settings(&grids) {
+            anyhow::bail!(
+                "The analysis settings stored in the state file '{}' do not match the requested analysis.",
+                restart
+            );
+        }
+
+        println!(
+            "Restarting from '{}': {} frames already analyzed, continuing after {} ps.\n",
+            restart, restored.n_frames, restored.last_time
+        );
+
+        // continue reading from the last analyzed frame
+        args.begin = args.begin.max(restored.last_time);
+        grids.merge(&restored);
+    }
+
+    for (group, query, quantity, _) in secondary_maps(&args) {
+        let mut secondary = grids.clone();
+        secondary.secondary.clear();
+        secondary.atoms = Some((group, grids::lipid_heads(&system, group, query)?));
+        secondary.quantity = quantity;
+        if group == INTERDIGITATION_GROUP {
+            secondary.distributions = Some(Distributions::new(&grids.upper_count));
+        }
+        grids.secondary.push(secondary);
+    }
+
+    // areas are calculated from the headgroups independently of the definition of the thickness
+    if args.area_per_lipid.is_some() {
+        let mut areas = grids.clone();
+        areas.secondary.clear();
+        areas.method = ThicknessMethod::Heads;
+        areas.sample = Sample::Area;
+        areas.quantity = "area per lipid";
+        grids.secondary.push(areas);
+    }
+
+    // created after the secondary grids, which are not conditioned on the tilt, and before the other
+    // additional data, which are not calculated for the individual sectors
+    if args.helix.is_some() {
+        grids.tilt = Some(TiltMaps::new(&grids, args.tilt_sectors));
+    }
+
+    if args.residues.is_some() {
+        grids.residues = Some(ResidueThickness::new(&system, RESIDUES_GROUP, &grids)?);
+    }
+
+    // created after the secondary grids, which do not map the composition
+    if args.composition.is_some() {
+        grids.composition = Some(Composition::new(&system, &grids.upper_count)?);
+    }
+
+    if args.environment.is_some() {
+        grids.environment = Some(Environment::new(&system, args.environment_cutoff)?);
+    }
+
+    if args.autocorrelation.is_some() {
+        grids.series = Some(ThicknessSeries::default());
+    }
+
+    if args.extremes.is_some() {
+        grids.extremes = Some(Extremes::new(grids.upper_count.n_tiles()));
+    }
+
+    if args.effective_samples.is_some() {
+        grids.bin_series = Some(BinSeries::default());
+    }
+
+    if args.spectrum.is_some() {
+        grids.spectrum = Some(Spectrum::new(&system, &grids.normal, args.bin_size)?);
+    }
+
+    let center = match args.radial_center {
+        Some(_) => ProfileCenter::Selection(PROFILE_GROUP),
+        None => {
+            let (x, y) = args.origin(simbox);
+            ProfileCenter::Point(x, y)
+        }
+    };
+    for (kind, _) in radial_profiles(&args) {
+        let profile = match kind {
+            ProfileKind::Radial => {
+                RadialProfile::new(&system, &grids.normal, args.radial_width, 360.0, center)?
+            }
+            ProfileKind::Azimuthal => {
+                let annulus = args.annulus.as_deref().expect(
+                    "FATAL MEMTHICK ERROR | analyze | Annulus of the azimuthal profile should have been validated.",
+                );
+                RadialProfile::annulus((annulus[0], annulus[1]), args.angle_bin, center)
+            }
+            ProfileKind::AzimuthalMap => RadialProfile::new(
+                &system,
+                &grids.normal,
+                args.radial_width,
+                args.angle_bin,
+                center,
+            )?,
+            ProfileKind::Around => RadialProfile::new(
+                &system,
+                &grids.normal,
+                args.radial_width,
+                360.0,
+                ProfileCenter::Closest(AROUND_GROUP),
+            )?,
+        };
+        grids.profiles.push(profile);
+    }
+
+    // profiles of the inclusions follow the other profiles
+    for index in 0..n_inclusions {
+        let center = ProfileCenter::Inclusion(index);
+        grids.profiles.push(RadialProfile::new(
+            &system,
+            &grids.normal,
+            args.radial_width,
+            360.0,
+            center,
+        )?);
+    }
+
+    if args.lipid_table.is_some() {
+        grids.molecules = Some(Molecules::new(&system, &grids.upper_count)?);
+    }
+
+    if args.protein.is_some() {
+        grids.footprint = Some(Footprint::new(
+            &grids,
+            PROTEIN_GROUP,
+            args.protein_radius,
+            args.protein_threshold,
+        ));
+    }
+
+    if args.deviation.is_some() {
+        grids.reference = Some(match (args.reference_thickness, &args.inclusion) {
+            (Some(thickness), _) => Reference::Value(thickness),
+            (None, Some(_)) => Reference::bulk(&system, &grids, INCLUSION_GROUP, args.bulk_cutoff)?,
+            (None, None) => Reference::Average,
+        });
+    }
+
+    if args.deformation.is_some() {
+        grids.deformation = Some(Deformation::new(
+            &system,
+            &grids,
+            INCLUSION_GROUP,
+            args.bulk_cutoff,
+            args.radial_width,
+        )?);
+    }
+
+    if let Some(roi) = &args.roi {
+        let roi = RegionOfInterest::read(roi, &grids)?;
+        if roi.n_inside() == 0 {
+            println!(
+                "WARNING: No bins of the thickness map are located inside the region of interest.\n"
+            );
+        }
+        grids.roi = Some(roi);
+    }
+
+    let classifier = LeafletClassifier::new(&args, &system)?;
+    let preprocessor = FramePreprocessor::new(&args, &system)?;
+    let mut weights = args.weights.as_ref().map(FrameWeights::read).transpose()?;
+    if let (Some(weights), Some((n_preceding, _))) = (&mut weights, chunk_frames) {
+        weights.skip(n_preceding);
+    }
+
+    // when restarting, the existing convergence file is extended
+    if let Some(convergence) = &args.convergence
+        && args.restart.is_none()
+        && args.benchmark.is_none()
+    {
+        ConvergenceWriter::create(convergence, raw_arguments)?;
+    }
+
+    let Some(first) = args.trajectory.first() else {
+        if args.nan_limit > 1 {
+            println!(
+                "WARNING: Analyzing a single structure but the NAN limit is {}. Consider using '-a 1'.\n",
+                args.nan_limit
+            );
+        }
+
+        let weight = match &weights {
+            Some(weights) => weights.get(0)?,
+            None => 1.0,
+        };
+
+        preprocessor.apply(&mut system)?;
+        grids.add_frame(&system, &classifier, weight)?;
+        if let Some(state) = &args.state {
+            grids.write_state(state, raw_arguments)?;
+        }
+
+        return write_maps(&args, &grids, None, raw_arguments);
+    };
+
+    let n_analyzed = || -> anyhow::Result<usize> {
+        let times = trajectory::frame_times(&system, &args.trajectory)?.expect(
+            "FATAL MEMTHICK ERROR | run | Block averaging of trajectories read from the standard input should have been rejected.",
+        );
+        Ok(trajectory::analyzed_times(&times, args.begin, args.end, args.step).len())
+    };
+
+    let mut blocks = match (args.blocks, args.block_length) {
+        (Some(n_blocks), _) => Some(BlockAverage::new(&grids, n_blocks, n_analyzed()?)),
+        (None, Some(block_length)) => Some(BlockAverage::with_length(
+            &grids,
+            block_length,
+            n_analyzed()?,
+        )?),
+        (None, None) => None,
+    };
+    if let Some(blocks) = blocks.as_mut() {
+        blocks.method = args.error_method;
+    }
+
+    let mut flip_flops = match args.flip_flops {
+        Some(_) => Some(FlipFlopTracker::new(&system, args.flip_flop_frames)?),
+        None => None,
+    };
+
+    if let Some(benchmark) = args.benchmark {
+        return benchmark::run_benchmark(&mut system, grids, &args, &classifier, benchmark);
+    }
+
+    if args.follow {
+        return follow_trajectory(
+            &mut system,
+            &mut grids,
+            &mut args,
+            &classifier,
+            weights.as_ref(),
+            raw_arguments,
+        );
+    }
+
+    if args.threads > 1 {
+        grids = parallel::analyze_parallel(&system, grids, &args, &classifier)?;
+        println!();
+
+        if let Some(state) = &args.state {
+            grids.write_state(state, raw_arguments)?;
+        }
+
+        return write_maps(&args, &grids, None, raw_arguments);
+    }
+
+    match FileType::from_name(first) {
+        _ if first == stream::STDIN => analyze_trajectory(
+            setup_reader(XtcStreamReader::new(&mut system), &args)?,
+            &mut grids,
+            &args,
+            &classifier,
+            &preprocessor,
+            weights.as_ref(),
+            blocks.as_mut(),
+            flip_flops.as_mut(),
+            raw_arguments,
+        )?,
+        FileType::XTC => analyze_trajectory(
+            setup_reader(
+                system.group_traj_cat_iter::<GroupXtcReader>(&args.trajectory, READ_GROUP)?,
+                &args,
+            )?,
+            &mut grids,
+            &args,
+            &classifier,
+            &preprocessor,
+            weights.as_ref(),
+            blocks.as_mut(),
+            flip_flops.as_mut(),
+            raw_arguments,
+        )?,
+        FileType::TRR => analyze_trajectory(
+            setup_reader(system.traj_cat_iter::<TrrReader>(&args.trajectory)?, &args)?,
+            &mut grids,
+            &args,
+            &classifier,
+            &preprocessor,
+            weights.as_ref(),
+            blocks.as_mut(),
+            flip_flops.as_mut(),
+            raw_arguments,
+        )?,
+        #[cfg(feature = "chemfiles")]
+        FileType::DCD => analyze_trajectory(
+            setup_reader(
+                system.traj_cat_iter::<ChemfilesReader>(&args.trajectory)?,
+                &args,
+            )?,
+            &mut grids,
+            &args,
+            &classifier,
+            &preprocessor,
+            weights.as_ref(),
+            blocks.as_mut(),
+            flip_flops.as_mut(),
+            raw_arguments,
+        )?,
+        #[cfg(feature = "chemfiles")]
+        FileType::NC => analyze_trajectory(
+            setup_reader(
+                system.traj_iter::<ChemfilesReader>(&args.trajectory[0])?,
+                &args,
+            )?,
+            &mut grids,
+            &args,
+            &classifier,
+            &preprocessor,
+            weights.as_ref(),
+            blocks.as_mut(),
+            flip_flops.as_mut(),
+            raw_arguments,
+        )?,
+        _ => unreachable!(
+            "FATAL MEMTHICK ERROR | run | Unsupported trajectory format should have been caught."
+        ),
+    }
+
+    // weights are provided for the frames of all chunks together
+    let n_analyzed = chunk_frames.map_or(grids.n_frames, |(_, n_analyzed)| n_analyzed);
+    if let Some(weights) = &weights
+        && weights.len() > n_analyzed
+    {
+        println!(
+            "WARNING: The weight file contains {} weights but only {} frames were analyzed.",
+            weights.len(),
+            n_analyzed
+        );
+    }
+
+    if let Some(state) = &args.state {
+        grids.write_state(state, raw_arguments)?;
+    }
+
+    if let (Some(filename), Some(flip_flops)) = (&args.flip_flops, &flip_flops) {
+        flip_flops.write(filename, raw_arguments)?;
+        println!(
+            "Detected {} flip-flop event(s). Written into '{}'.",
+            flip_flops.n_events(),
+            filename
+        );
+    }
+
+    write_maps(&args, &grids, blocks.as_ref(), raw_arguments)
 }
 
 fn main() {
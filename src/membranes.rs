@@ -0,0 +1,246 @@
+//! Selecting the lipids of the analyzed membrane, i.e., splitting systems with several stacked membranes
+//! into individual membranes and excluding lipids outside the main lipid aggregate.
+
+use groan_rs::{
+    errors::{GroupError, SimBoxError},
+    prelude::*,
+};
+
+use crate::{
+    CENTER_GROUP, ENVIRONMENT_GROUP, HEAD_ATOMS_GROUP, HEADS_GROUP, HYDROPHOBIC_GROUP,
+    INTERDIGITATION_GROUP, LIPIDS_GROUP, LOWER_GROUP, STEROL_GROUP, TAILS_GROUP, UPPER_GROUP,
+    leaflets::{LateralCells, find_root},
+    normal::Normal,
+};
+
+/// Name of the group containing the lipid atoms of the analyzed membrane.
+const MEMBRANE_GROUP: &str = "xxxMemthickReservedxxx-Membrane";
+
+/// Split the lipids into `n_membranes` membranes stacked along the membrane normal.
+///
+/// Lipids are identified by their residue numbers and positioned along the normal by their centers.
+/// The membranes are separated at the largest gaps between neighboring lipids along the normal.
+/// Returns the indices of the lipid atoms of each membrane, ordered along the normal.
+pub(crate) fn split_membranes(
+    system: &System,
+    normal: &Normal,
+    n_membranes: usize,
+) -> anyhow::Result<Vec<Vec<usize>>> {
+    let simbox = system.get_box().ok_or(SimBoxError::DoesNotExist)?;
+    // positions along the normal are only periodic if the normal is oriented along a box axis
+    let period = normal
+        .lateral_box(simbox)
+        .map(|_| normal.axial_range(simbox).1);
+
+    let mut lipids: Vec<(f32, Vec<usize>)> = Vec::new();
+    let mut current: Option<(usize, Vector3D, f32, Vec<usize>)> = None;
+    for atom in system.group_iter(LIPIDS_GROUP)? {
+        let position = atom
+            .get_position()
+            .ok_or_else(|| anyhow::anyhow!("Atom {} has no position.", atom.get_index() + 1))?;
+
+        match &mut current {
+            Some((residue, first, sum, atoms)) if *residue == atom.get_residue_number() => {
+                *sum += normal.distance(position, first, simbox);
+                atoms.push(atom.get_index());
+            }
+            _ => {
+                if let Some((_, first, sum, atoms)) = current.take() {
+                    lipids.push((
+                        lipid_coordinate(normal, &first, sum, atoms.len(), period),
+                        atoms,
+                    ));
+                }
+
+                current = Some((
+                    atom.get_residue_number(),
+                    position.clone(),
+                    0.0,
+                    vec![atom.get_index()],
+                ));
+            }
+        }
+    }
+
+    if let Some((_, first, sum, atoms)) = current {
+        lipids.push((
+            lipid_coordinate(normal, &first, sum, atoms.len(), period),
+            atoms,
+        ));
+    }
+
+    if lipids.len() < n_membranes {
+        anyhow::bail!(
+            "Cannot split {} lipids into {} membranes.",
+            lipids.len(),
+            n_membranes
+        );
+    }
+
+    lipids.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    // gap following each lipid along the normal; the gap after the last lipid is only considered for periodic normals
+    let n_lipids = lipids.len();
+    let mut gaps = (0..n_lipids - 1)
+        .map(|i| (lipids[i + 1].0 - lipids[i].0, i))
+        .collect::<Vec<_>>();
+    match period {
+        Some(period) => gaps.push((lipids[0].0 + period - lipids[n_lipids - 1].0, n_lipids - 1)),
+        None => gaps.push((f32::INFINITY, n_lipids - 1)),
+    }
+
+    gaps.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let mut cuts = gaps[..n_membranes]
+        .iter()
+        .map(|&(_, i)| i)
+        .collect::<Vec<_>>();
+    cuts.sort_unstable();
+
+    // start after the last cut so that a membrane crossing the periodic boundary is not split
+    let start = (cuts[n_membranes - 1] + 1) % n_lipids;
+    let mut membranes = vec![Vec::new()];
+    for i in (0..n_lipids).map(|i| (start + i) % n_lipids) {
+        membranes
+            .last_mut()
+            .expect("FATAL MEMTHICK ERROR | membranes::split_membranes | At least one membrane should exist.")
+            .extend_from_slice(&lipids[i].1);
+
+        if cuts.contains(&i) && membranes.len() < n_membranes {
+            membranes.push(Vec::new());
+        }
+    }
+
+    Ok(membranes)
+}
+
+/// Coordinate of the center of a lipid along the membrane normal.
+fn lipid_coordinate(
+    normal: &Normal,
+    first: &Vector3D,
+    sum: f32,
+    n_atoms: usize,
+    period: Option<f32>,
+) -> f32 {
+    let coordinate = normal.axial(first) + sum / n_atoms as f32;
+    match period {
+        Some(period) => coordinate.rem_euclid(period),
+        None => coordinate,
+    }
+}
+
+/// Find the largest aggregate of lipids, i.e., the largest group of lipids connected by atoms
+/// closer than `cutoff`. All atoms of a lipid (identified by its residue number) belong to the same aggregate.
+/// Returns the indices of the lipid atoms of the largest aggregate.
+pub(crate) fn largest_aggregate(
+    system: &System,
+    normal: Normal,
+    cutoff: f32,
+) -> anyhow::Result<Vec<usize>> {
+    Ok(aggregates(system, LIPIDS_GROUP, normal, cutoff)?
+        .into_iter()
+        .max_by_key(Vec::len)
+        .unwrap_or_default())
+}
+
+/// Split the atoms of the group into aggregates, i.e., groups of atoms connected by atoms closer than `cutoff`.
+/// All consecutive atoms with the same residue number belong to the same aggregate.
+/// Returns the indices of the atoms of each aggregate, ordered by the first atoms of the aggregates.
+pub(crate) fn aggregates(
+    system: &System,
+    group: &str,
+    normal: Normal,
+    cutoff: f32,
+) -> anyhow::Result<Vec<Vec<usize>>> {
+    let simbox = system.get_box().ok_or(SimBoxError::DoesNotExist)?;
+
+    let mut atoms = Vec::new();
+    let mut residues = Vec::new();
+    let mut cells = LateralCells::new(simbox, cutoff, normal);
+    for (i, atom) in system.group_iter(group)?.enumerate() {
+        let position = atom
+            .get_position()
+            .ok_or_else(|| anyhow::anyhow!("Atom {} has no position.", atom.get_index() + 1))?;
+
+        atoms.push(atom.get_index());
+        residues.push(atom.get_residue_number());
+        cells.insert(position, i);
+    }
+
+    // connected components of the atoms
+    let mut clusters = (0..atoms.len()).collect::<Vec<_>>();
+    let mut join = |i: usize, j: usize| {
+        let (root_i, root_j) = (find_root(&mut clusters, i), find_root(&mut clusters, j));
+        clusters[root_i.max(root_j)] = root_i.min(root_j);
+    };
+
+    for i in 1..atoms.len() {
+        if residues[i] == residues[i - 1] {
+            join(i - 1, i);
+        }
+    }
+
+    for (i, atom) in system.group_iter(group)?.enumerate() {
+        let position = atom
+            .get_position()
+            .expect("FATAL MEMTHICK ERROR | membranes::aggregates | Atom should have a position.");
+
+        for &(ref other, j) in cells.neighbors(position) {
+            if j > i && position.distance(other, Dimension::XYZ, simbox) <= cutoff {
+                join(i, j);
+            }
+        }
+    }
+
+    // the root of every cluster is its first atom
+    let mut aggregates: Vec<Vec<usize>> = Vec::new();
+    let mut aggregate_of = vec![usize::MAX; atoms.len()];
+    for (i, atom) in atoms.into_iter().enumerate() {
+        let root = find_root(&mut clusters, i);
+        if root == i {
+            aggregate_of[i] = aggregates.len();
+            aggregates.push(Vec::new());
+        }
+
+        aggregates[aggregate_of[root]].push(atom);
+    }
+
+    Ok(aggregates)
+}
+
+/// Restrict the lipids, headgroups, and tail ends used in the analysis to the specified atoms.
+pub(crate) fn restrict_groups(system: &mut System, atoms: Vec<usize>) -> anyhow::Result<()> {
+    system.group_create_from_indices(MEMBRANE_GROUP, atoms)?;
+
+    for group in [
+        LIPIDS_GROUP,
+        CENTER_GROUP,
+        ENVIRONMENT_GROUP,
+        HEADS_GROUP,
+        HEAD_ATOMS_GROUP,
+        HYDROPHOBIC_GROUP,
+        INTERDIGITATION_GROUP,
+        STEROL_GROUP,
+        UPPER_GROUP,
+        LOWER_GROUP,
+        TAILS_GROUP,
+    ] {
+        if !system.group_exists(group) {
+            continue;
+        }
+
+        match system.group_intersection(group, MEMBRANE_GROUP, group) {
+            Ok(_) | Err(GroupError::AlreadyExistsWarning(_)) => (),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    if system.group_get_n_atoms(HEADS_GROUP)? == 0 {
+        anyhow::bail!("The analyzed membrane contains no headgroups.");
+    }
+
+    if system.group_exists(CENTER_GROUP) && system.group_get_n_atoms(CENTER_GROUP)? == 0 {
+        anyhow::bail!("The analyzed membrane contains no atoms defining the membrane center.");
+    }
+
+    Ok(())
+}
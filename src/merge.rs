@@ -0,0 +1,105 @@
+//! Merging the results of separate analyses.
+
+use crate::{MapFilters, grids::ThicknessGrids, write_map};
+
+/// Arguments of the `merge` subcommand.
+#[derive(clap::Args, Debug, Clone)]
+pub(crate) struct MergeArgs {
+    #[arg(
+        help = "State files to merge",
+        long_help = "Paths to state files written by separate runs of memthick using the '--state' option.",
+        required = true,
+        num_args = 1..
+    )]
+    states: Vec<String>,
+
+    #[arg(
+        short = 'o',
+        long = "output",
+        help = "Path to the output file.",
+        long_help = "Path to the output file where the merged membrane thickness map will be written.",
+        default_value = "membrane_thickness.dat"
+    )]
+    output: String,
+
+    #[arg(
+        short = 'a',
+        long = "nan",
+        help = "Minimal required number of samples in a grid bin.",
+        long_help = "How many phosphates must be detected in a grid bin (in all the merged runs together) to calculate membrane thickness for this bin.",
+        default_value = "30"
+    )]
+    nan_limit: usize,
+
+    #[arg(
+        long = "state",
+        help = "Also write the merged state into this file.",
+        long_help = "Path to a file where the merged raw accumulated grids will be written. This state file can be merged further or used to restart the analysis."
+    )]
+    state: Option<String>,
+}
+
+/// Print the specified options of the `merge` subcommand.
+fn print_options(args: &MergeArgs) {
+    println!("[STATES]        {}", args.states.join(" "));
+    println!("[OUTPUT]        {}", args.output);
+    println!("[NAN LIMIT]     {}", args.nan_limit);
+    if let Some(state) = args.state.as_ref() {
+        println!("[STATE]         {}", state);
+    }
+    println!("\n");
+}
+
+/// Merge the grids from all the provided state files and write the final membrane thickness map.
+pub(crate) fn run_merge(args: &MergeArgs, raw_arguments: &[String]) -> anyhow::Result<()> {
+    if args.nan_limit == 0 {
+        anyhow::bail!("NAN limit must be larger than 0, not {}", args.nan_limit);
+    }
+
+    print_options(args);
+
+    let mut merged: Option<ThicknessGrids> = None;
+    for state in args.states.iter() {
+        let grids = ThicknessGrids::read_state(state)?;
+        println!(
+            "Read '{}': {} frames analyzed (last frame at {} ps).",
+            state, grids.n_frames, grids.last_time
+        );
+
+        match merged.as_mut() {
+            None => merged = Some(grids),
+            Some(merged) if !merged.is_compatible(&grids) => anyhow::bail!(
+                "The grid stored in the state file '{}' does not match the grid stored in the state file '{}'.",
+                state,
+                args.states[0]
+            ),
+            Some(merged) if !merged.has_same_settings(&grids) => anyhow::bail!(
+                "The analysis settings stored in the state file '{}' do not match the settings stored in the state file '{}'.",
+                state,
+                args.states[0]
+            ),
+            Some(merged) => merged.merge(&grids),
+        }
+    }
+
+    let merged = merged.expect(
+        "FATAL MEMTHICK ERROR | merge::run_merge | At least one state file should be provided.",
+    );
+
+    println!("\nMerged {} frames in total.", merged.n_frames);
+
+    if let Some(state) = &args.state {
+        merged.write_state(state, raw_arguments)?;
+    }
+
+    write_map(
+        &args.output,
+        &merged,
+        args.nan_limit,
+        None,
+        MapFilters::default(),
+        raw_arguments,
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,154 @@
+//! Local membrane thickness experienced by the individual lipid molecules.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use groan_rs::prelude::*;
+
+use crate::{
+    HEADS_GROUP,
+    grids::{CountMap, ThicknessGrids, tile_indices},
+};
+
+/// Grid bins visited by the headgroup of a single lipid.
+#[derive(Debug, Clone, Default)]
+struct Visits {
+    /// Summed weights of the samples of the headgroup in each visited grid bin.
+    bins: HashMap<usize, f64>,
+    upper: usize,
+    lower: usize,
+}
+
+/// Grid bins visited by the headgroup of each lipid during the trajectory.
+#[derive(Debug, Clone)]
+pub(crate) struct Molecules {
+    /// Residue number and residue name of the lipid of each headgroup.
+    lipids: Vec<(usize, String)>,
+    /// Index of each grid bin in the order of the thickness map.
+    indices: CountMap,
+    visits: Vec<Visits>,
+}
+
+impl Molecules {
+    /// Prepare empty records for the headgroups of the system using the grid bins of the `template` map.
+    pub(crate) fn new(system: &System, template: &CountMap) -> anyhow::Result<Molecules> {
+        let lipids = system
+            .group_iter(HEADS_GROUP)?
+            .map(|atom| {
+                (
+                    atom.get_residue_number(),
+                    atom.get_residue_name().to_owned(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Molecules {
+            visits: vec![Visits::default(); lipids.len()],
+            lipids,
+            indices: tile_indices(template),
+        })
+    }
+
+    /// Record a sample of the headgroup with the specified index in the grid bin at the map coordinates.
+    pub(crate) fn add(&mut self, head: usize, is_upper: bool, x: f32, y: f32, weight: f64) {
+        let Some(&bin) = self.indices.get_at(x, y) else {
+            return;
+        };
+
+        let visits = &mut self.visits[head];
+        *visits.bins.entry(bin).or_default() += weight;
+        if is_upper {
+            visits.upper += 1;
+        } else {
+            visits.lower += 1;
+        }
+    }
+
+    /// Remove all recorded samples.
+    pub(crate) fn clear(&mut self) {
+        for visits in self.visits.iter_mut() {
+            *visits = Visits::default();
+        }
+    }
+
+    /// Add the samples recorded in the other molecules into these molecules.
+    pub(crate) fn merge(&mut self, other: &Molecules) {
+        for (visits, other) in self.visits.iter_mut().zip(other.visits.iter()) {
+            for (&bin, &weight) in other.bins.iter() {
+                *visits.bins.entry(bin).or_default() += weight;
+            }
+
+            visits.upper += other.upper;
+            visits.lower += other.lower;
+        }
+    }
+
+    /// Write the average local thickness experienced by each lipid into a file.
+    /// The local thickness is the thickness of the final map in the bins visited by the headgroup of the lipid,
+    /// averaged over all samples of the headgroup in bins with defined thickness.
+    pub(crate) fn write(
+        &self,
+        filename: impl AsRef<Path>,
+        grids: &ThicknessGrids,
+        nan_limit: usize,
+        raw_arguments: &[String],
+    ) -> anyhow::Result<()> {
+        let filename = filename.as_ref();
+        let file = File::create(filename)
+            .with_context(|| format!("Could not create lipid table '{}'.", filename.display()))?;
+        let mut output = BufWriter::new(file);
+
+        writeln!(
+            &mut output,
+            "# Generated with memthick v{}.",
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(&mut output, "# Command line: {}", raw_arguments.join(" "))?;
+        writeln!(
+            &mut output,
+            "# Average local membrane thickness [nm] experienced by each lipid and the number of samples of its headgroup."
+        )?;
+        writeln!(&mut output, "# resid resname leaflet thickness samples")?;
+
+        let thickness = grids
+            .thickness(nan_limit)
+            .map(|(_, _, thickness)| thickness)
+            .collect::<Vec<_>>();
+        let (upper, lower) = grids.geometry.leaflet_names();
+
+        for ((residue, name), visits) in self.lipids.iter().zip(self.visits.iter()) {
+            let (sum, weight) = visits
+                .bins
+                .iter()
+                .filter(|&(&bin, _)| thickness[bin].is_finite())
+                .fold((0.0, 0.0), |(sum, total), (&bin, &weight)| {
+                    (sum + thickness[bin] * weight, total + weight)
+                });
+
+            let leaflet = match (visits.upper, visits.lower) {
+                (0, 0) => "none",
+                (_, 0) => upper,
+                (0, _) => lower,
+                _ => "both",
+            };
+
+            writeln!(
+                &mut output,
+                "{:8} {:>8} {:>8} {:12.4} {:10}",
+                residue,
+                name,
+                leaflet,
+                sum / weight,
+                visits.upper + visits.lower
+            )?;
+        }
+
+        output.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,533 @@
+//! Orientation of the membrane in the simulation box.
+
+use std::fmt::Display;
+
+use groan_rs::{errors::SimBoxError, prelude::*};
+
+use crate::grids;
+
+/// Axis of the simulation box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    /// Unit vector along the axis.
+    fn unit(self) -> [f32; 3] {
+        match self {
+            Axis::X => [1.0, 0.0, 0.0],
+            Axis::Y => [0.0, 1.0, 0.0],
+            Axis::Z => [0.0, 0.0, 1.0],
+        }
+    }
+}
+
+impl Display for Axis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Axis::X => write!(f, "x"),
+            Axis::Y => write!(f, "y"),
+            Axis::Z => write!(f, "z"),
+        }
+    }
+}
+
+/// Orientation of the membrane normal.
+///
+/// For a normal oriented along an axis of the simulation box, the grid maps are constructed in the plane
+/// formed by the two remaining axes, in the order x, y, z (i.e., y-z for the x-axis, x-z for the y-axis,
+/// x-y for the z-axis). For an arbitrary normal, the grid maps are constructed in the plane spanned
+/// by two unit vectors `u` and `v` perpendicular to the normal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Normal {
+    Axis(Axis),
+    Vector {
+        /// Unit vector along the membrane normal.
+        normal: [f32; 3],
+        /// First unit vector of the membrane plane.
+        u: [f32; 3],
+        /// Second unit vector of the membrane plane.
+        v: [f32; 3],
+    },
+}
+
+impl Default for Normal {
+    fn default() -> Self {
+        Normal::Axis(Axis::Z)
+    }
+}
+
+impl Normal {
+    /// Parse the membrane normal from an axis name (`x`, `y`, `z`) or from three components of a vector.
+    pub(crate) fn parse(values: &[impl AsRef<str>]) -> anyhow::Result<Normal> {
+        let invalid = || {
+            anyhow::anyhow!(
+                "Invalid membrane normal '{}'. Specify 'x', 'y', 'z', or three components of a vector.",
+                values
+                    .iter()
+                    .map(|x| x.as_ref())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            )
+        };
+
+        match values {
+            [axis] => match axis.as_ref().to_lowercase().as_str() {
+                "x" => Ok(Normal::Axis(Axis::X)),
+                "y" => Ok(Normal::Axis(Axis::Y)),
+                "z" => Ok(Normal::Axis(Axis::Z)),
+                _ => Err(invalid()),
+            },
+            [x, y, z] => {
+                let parse = |value: &str| value.parse::<f32>().map_err(|_| invalid());
+                let vector = [parse(x.as_ref())?, parse(y.as_ref())?, parse(z.as_ref())?];
+
+                let length = dot(vector, vector).sqrt();
+                if !length.is_finite() || length == 0.0 {
+                    anyhow::bail!("Membrane normal cannot be a zero vector.");
+                }
+
+                Ok(Normal::from_unit(vector.map(|c| c / length)))
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Construct the membrane normal from a unit vector.
+    /// Normals parallel to an axis of the simulation box are converted to that axis.
+    fn from_unit(normal: [f32; 3]) -> Normal {
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            if normal == axis.unit() {
+                return Normal::Axis(axis);
+            }
+        }
+
+        // the vectors of the plane are the box axes perpendicular to the dominant axis of the normal,
+        // orthogonalized against the normal and each other
+        let (first, second) = match dominant_axis(normal) {
+            Axis::X => (Axis::Y, Axis::Z),
+            Axis::Y => (Axis::X, Axis::Z),
+            Axis::Z => (Axis::X, Axis::Y),
+        };
+
+        let u = orthonormalize(first.unit(), &[normal]);
+        let v = orthonormalize(second.unit(), &[normal, u]);
+
+        Normal::Vector { normal, u, v }
+    }
+
+    /// Check whether the membrane normal has the same orientation as the other normal
+    /// (up to the precision of the state files).
+    pub(crate) fn matches(&self, other: &Normal) -> bool {
+        match (self, other) {
+            (Normal::Axis(a), Normal::Axis(b)) => a == b,
+            (Normal::Vector { normal: a, .. }, Normal::Vector { normal: b, .. }) => {
+                a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() < 1e-5)
+            }
+            _ => false,
+        }
+    }
+
+    /// Axis of the simulation box best aligned with the membrane normal.
+    pub(crate) fn dominant_axis(&self) -> Axis {
+        match self {
+            Normal::Axis(axis) => *axis,
+            Normal::Vector { normal, .. } => dominant_axis(*normal),
+        }
+    }
+
+    /// Signed distance of the position from the reference point along the membrane normal.
+    pub(crate) fn distance(
+        &self,
+        position: &Vector3D,
+        reference: &Vector3D,
+        simbox: &SimBox,
+    ) -> f32 {
+        match self {
+            Normal::Axis(Axis::X) => position.distance(reference, Dimension::X, simbox),
+            Normal::Axis(Axis::Y) => position.distance(reference, Dimension::Y, simbox),
+            Normal::Axis(Axis::Z) => position.distance(reference, Dimension::Z, simbox),
+            Normal::Vector { normal, .. } => {
+                dot(*normal, to_array(&reference.vector_to(position, simbox)))
+            }
+        }
+    }
+
+    /// Distance between the two positions in the membrane plane.
+    pub(crate) fn lateral_distance(&self, a: &Vector3D, b: &Vector3D, simbox: &SimBox) -> f32 {
+        match self {
+            Normal::Axis(Axis::X) => a.distance(b, Dimension::YZ, simbox),
+            Normal::Axis(Axis::Y) => a.distance(b, Dimension::XZ, simbox),
+            Normal::Axis(Axis::Z) => a.distance(b, Dimension::XY, simbox),
+            Normal::Vector { u, v, .. } => {
+                let vector = to_array(&b.vector_to(a, simbox));
+                dot(*u, vector).hypot(dot(*v, vector))
+            }
+        }
+    }
+
+    /// Unit vector along the membrane normal and unit vectors of the membrane plane.
+    pub(crate) fn basis(&self) -> ([f32; 3], [f32; 3], [f32; 3]) {
+        match self {
+            Normal::Axis(Axis::X) => ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+            Normal::Axis(Axis::Y) => ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+            Normal::Axis(Axis::Z) => ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+            Normal::Vector { normal, u, v } => (*normal, *u, *v),
+        }
+    }
+
+    /// Fit a plane to the headgroups of the frame and get its normal.
+    ///
+    /// The plane is fitted by least squares to the positions of the headgroups relative to the membrane center,
+    /// expressed as heights along this normal over the membrane plane. Both leaflets share the slopes of the plane
+    /// but have separate offsets. Headgroups not assigned to any leaflet (`None`) are ignored.
+    /// The fitted normal points to the same side of the membrane as this normal.
+    pub(crate) fn fit(
+        &self,
+        frame: &System,
+        membrane_center: &Vector3D,
+        upper: &[Option<bool>],
+    ) -> anyhow::Result<Normal> {
+        let simbox = frame.get_box().ok_or(SimBoxError::DoesNotExist)?;
+        let (normal, u, v) = self.basis();
+
+        // coordinates of the headgroups of the upper (index 0) and lower (index 1) leaflet
+        let mut leaflets: [Vec<(f32, f32, f32)>; 2] = [Vec::new(), Vec::new()];
+        for (position, &is_upper) in grids::head_positions(frame)?.iter().zip(upper.iter()) {
+            let Some(is_upper) = is_upper else {
+                continue;
+            };
+
+            let vector = to_array(&membrane_center.vector_to(position, simbox));
+            leaflets[usize::from(!is_upper)].push((
+                dot(u, vector),
+                dot(v, vector),
+                dot(normal, vector),
+            ));
+        }
+
+        let (mut saa, mut sab, mut sbb, mut sah, mut sbh) = (0.0, 0.0, 0.0, 0.0, 0.0);
+        for points in leaflets.iter().filter(|points| !points.is_empty()) {
+            let n = points.len() as f32;
+            let (mean_a, mean_b, mean_h) = points.iter().fold((0.0, 0.0, 0.0), |acc, p| {
+                (acc.0 + p.0 / n, acc.1 + p.1 / n, acc.2 + p.2 / n)
+            });
+
+            for (a, b, h) in points {
+                let (a, b, h) = (a - mean_a, b - mean_b, h - mean_h);
+                saa += a * a;
+                sab += a * b;
+                sbb += b * b;
+                sah += a * h;
+                sbh += b * h;
+            }
+        }
+
+        let determinant = saa * sbb - sab * sab;
+        if !determinant.is_normal() {
+            anyhow::bail!(
+                "Could not fit a plane to the headgroups at time {} ps. The headgroups do not span the membrane plane.",
+                frame.get_simulation_time()
+            );
+        }
+
+        // slopes of the plane along the two vectors of the membrane plane
+        let slope_u = (sah * sbb - sbh * sab) / determinant;
+        let slope_v = (sbh * saa - sah * sab) / determinant;
+
+        let fitted = [0, 1, 2].map(|i| normal[i] - slope_u * u[i] - slope_v * v[i]);
+        let length = dot(fitted, fitted).sqrt();
+
+        Ok(Normal::from_unit(fitted.map(|c| c / length)))
+    }
+
+    /// Names of the axes of the membrane plane.
+    pub(crate) fn plane_axes(&self) -> (&'static str, &'static str) {
+        match self {
+            Normal::Axis(Axis::X) => ("y", "z"),
+            Normal::Axis(Axis::Y) => ("x", "z"),
+            Normal::Axis(Axis::Z) => ("x", "y"),
+            Normal::Vector { .. } => ("u", "v"),
+        }
+    }
+
+    /// Coordinates of the position in the membrane plane.
+    pub(crate) fn lateral(&self, position: &Vector3D) -> (f32, f32) {
+        match self {
+            Normal::Axis(Axis::X) => (position.y, position.z),
+            Normal::Axis(Axis::Y) => (position.x, position.z),
+            Normal::Axis(Axis::Z) => (position.x, position.y),
+            Normal::Vector { u, v, .. } => {
+                let position = to_array(position);
+                (dot(*u, position), dot(*v, position))
+            }
+        }
+    }
+
+    /// Coordinate of the position along the membrane normal.
+    pub(crate) fn axial(&self, position: &Vector3D) -> f32 {
+        match self {
+            Normal::Axis(Axis::X) => position.x,
+            Normal::Axis(Axis::Y) => position.y,
+            Normal::Axis(Axis::Z) => position.z,
+            Normal::Vector { normal, .. } => dot(*normal, to_array(position)),
+        }
+    }
+
+    /// Range of coordinates along the membrane normal covered by the simulation box.
+    pub(crate) fn axial_range(&self, simbox: &SimBox) -> (f32, f32) {
+        match self {
+            Normal::Axis(Axis::X) => (0.0, simbox.x),
+            Normal::Axis(Axis::Y) => (0.0, simbox.y),
+            Normal::Axis(Axis::Z) => (0.0, simbox.z),
+            Normal::Vector { normal, .. } => {
+                let mut range = (f32::INFINITY, f32::NEG_INFINITY);
+                for x in [0.0, simbox.x] {
+                    for y in [0.0, simbox.y] {
+                        for z in [0.0, simbox.z] {
+                            let coordinate = dot(*normal, [x, y, z]);
+                            range = (range.0.min(coordinate), range.1.max(coordinate));
+                        }
+                    }
+                }
+
+                range
+            }
+        }
+    }
+
+    /// Size of the simulation box in the membrane plane.
+    /// Returns `None` if the membrane plane is not periodic, i.e., for a normal not oriented along a box axis.
+    pub(crate) fn lateral_box(&self, simbox: &SimBox) -> Option<(f32, f32)> {
+        match self {
+            Normal::Axis(Axis::X) => Some((simbox.y, simbox.z)),
+            Normal::Axis(Axis::Y) => Some((simbox.x, simbox.z)),
+            Normal::Axis(Axis::Z) => Some((simbox.x, simbox.y)),
+            Normal::Vector { .. } => None,
+        }
+    }
+
+    /// Range of coordinates in the membrane plane covered by the simulation box.
+    pub(crate) fn lateral_range(&self, simbox: &SimBox) -> ((f32, f32), (f32, f32)) {
+        if let Some((x, y)) = self.lateral_box(simbox) {
+            return ((0.0, x), (0.0, y));
+        }
+
+        let mut range_x = (f32::INFINITY, f32::NEG_INFINITY);
+        let mut range_y = (f32::INFINITY, f32::NEG_INFINITY);
+        for x in [0.0, simbox.x] {
+            for y in [0.0, simbox.y] {
+                for z in [0.0, simbox.z] {
+                    let (u, v) = self.lateral(&Vector3D::new(x, y, z));
+                    range_x = (range_x.0.min(u), range_x.1.max(u));
+                    range_y = (range_y.0.min(v), range_y.1.max(v));
+                }
+            }
+        }
+
+        (range_x, range_y)
+    }
+
+    /// Guess the axis of the simulation box along which the membrane normal is oriented
+    /// from the positions of the headgroups.
+    ///
+    /// Along the membrane normal, all headgroups are located at a similar distance from the membrane center,
+    /// while in the membrane plane their distances from the center are spread uniformly.
+    /// The normal is therefore guessed as the axis with the lowest relative spread of these distances.
+    pub(crate) fn guess(system: &System, center_of_mass: bool) -> anyhow::Result<Axis> {
+        let simbox = system.get_box().ok_or(SimBoxError::DoesNotExist)?;
+        let center = grids::membrane_center(system, center_of_mass)?;
+        let heads = grids::head_positions(system)?;
+
+        let relative_spread = |axis: Axis| -> anyhow::Result<f32> {
+            let normal = Normal::Axis(axis);
+            let distances = heads
+                .iter()
+                .map(|position| normal.distance(position, &center, simbox).abs())
+                .collect::<Vec<f32>>();
+
+            let n = distances.len() as f32;
+            let mean = distances.iter().sum::<f32>() / n;
+            let variance = distances.iter().map(|d| (d - mean).powi(2)).sum::<f32>() / n;
+
+            Ok(variance.sqrt() / mean)
+        };
+
+        let mut best = (Axis::Z, relative_spread(Axis::Z)?);
+        for axis in [Axis::X, Axis::Y] {
+            let spread = relative_spread(axis)?;
+            if spread < best.1 {
+                best = (axis, spread);
+            }
+        }
+
+        Ok(best.0)
+    }
+}
+
+impl Display for Normal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Normal::Axis(axis) => write!(f, "{}", axis),
+            Normal::Vector { normal, .. } => {
+                write!(f, "{} {} {}", normal[0], normal[1], normal[2])
+            }
+        }
+    }
+}
+
+/// Convert the vector into an array of its components.
+pub(crate) fn to_array(vector: &Vector3D) -> [f32; 3] {
+    [vector.x, vector.y, vector.z]
+}
+
+/// Calculate the dot product of two vectors.
+pub(crate) fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// Get the axis of the simulation box best aligned with the vector.
+fn dominant_axis(vector: [f32; 3]) -> Axis {
+    [Axis::X, Axis::Y, Axis::Z]
+        .into_iter()
+        .max_by(|a, b| {
+            dot(a.unit(), vector)
+                .abs()
+                .total_cmp(&dot(b.unit(), vector).abs())
+        })
+        .expect("FATAL MEMTHICK ERROR | normal::dominant_axis | There should be three axes.")
+}
+
+/// Remove the components along the provided unit vectors from the vector and normalize it.
+fn orthonormalize(mut vector: [f32; 3], basis: &[[f32; 3]]) -> [f32; 3] {
+    for unit in basis {
+        let projection = dot(vector, *unit);
+        vector = [
+            vector[0] - projection * unit[0],
+            vector[1] - projection * unit[1],
+            vector[2] - projection * unit[2],
+        ];
+    }
+
+    let length = dot(vector, vector).sqrt();
+    vector.map(|c| c / length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HEADS_GROUP, LIPIDS_GROUP};
+
+    /// Create a system containing only the specified headgroups, which also form the lipids.
+    fn heads_system(positions: &[Vector3D]) -> System {
+        let atoms = positions
+            .iter()
+            .enumerate()
+            .map(|(i, position)| {
+                Atom::new(i + 1, "LIP", i + 1, "PO4").with_position(position.clone())
+            })
+            .collect();
+
+        let mut system = System::new("heads", atoms, Some(SimBox::from([20.0, 20.0, 20.0])));
+        let indices = (0..positions.len()).collect::<Vec<_>>();
+        system
+            .group_create_from_indices(HEADS_GROUP, indices.clone())
+            .unwrap();
+        system
+            .group_create_from_indices(LIPIDS_GROUP, indices)
+            .unwrap();
+        system
+    }
+
+    /// Headgroups of both leaflets on a plane with the specified slopes along x and y.
+    fn tilted_membrane(slope_x: f32, slope_y: f32) -> (Vec<Vector3D>, Vec<Option<bool>>) {
+        let mut positions = Vec::new();
+        let mut upper = Vec::new();
+        for i in -3..=3 {
+            for j in -3..=3 {
+                let (a, b) = (i as f32, j as f32);
+                let height = slope_x * a + slope_y * b;
+                positions.push(Vector3D::new(10.0 + a, 10.0 + b, 12.0 + height));
+                positions.push(Vector3D::new(10.0 + a, 10.0 + b, 8.0 + height));
+                upper.extend([Some(true), Some(false)]);
+            }
+        }
+
+        (positions, upper)
+    }
+
+    #[test]
+    fn fit_tilted_plane() {
+        let (positions, upper) = tilted_membrane(0.1, -0.2);
+        let system = heads_system(&positions);
+        let center = Vector3D::new(10.0, 10.0, 10.0);
+
+        let fitted = Normal::default().fit(&system, &center, &upper).unwrap();
+        let Normal::Vector { normal, .. } = fitted else {
+            panic!("fitted normal should not be parallel to an axis");
+        };
+
+        let length = (0.1f32 * 0.1 + 0.2 * 0.2 + 1.0).sqrt();
+        let expected = [-0.1 / length, 0.2 / length, 1.0 / length];
+        for (component, expected) in normal.iter().zip(expected.iter()) {
+            assert!((component - expected).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn fit_flat_plane() {
+        let (positions, upper) = tilted_membrane(0.0, 0.0);
+        let system = heads_system(&positions);
+        let center = Vector3D::new(10.0, 10.0, 10.0);
+
+        let fitted = Normal::default().fit(&system, &center, &upper).unwrap();
+        assert_eq!(fitted, Normal::Axis(Axis::Z));
+    }
+
+    #[test]
+    fn fit_degenerate() {
+        let center = Vector3D::new(10.0, 10.0, 10.0);
+
+        // headgroups on a single line do not span the membrane plane
+        let positions = (0..5)
+            .map(|i| Vector3D::new(8.0 + i as f32, 10.0, 12.0))
+            .collect::<Vec<_>>();
+        let system = heads_system(&positions);
+        assert!(
+            Normal::default()
+                .fit(&system, &center, &[Some(true); 5])
+                .is_err()
+        );
+
+        // single headgroup and no assigned headgroups
+        let system = heads_system(&[Vector3D::new(10.0, 10.0, 12.0)]);
+        assert!(
+            Normal::default()
+                .fit(&system, &center, &[Some(true)])
+                .is_err()
+        );
+        assert!(Normal::default().fit(&system, &center, &[None]).is_err());
+    }
+
+    #[test]
+    fn guess_axis() {
+        // membrane with the headgroups at 2 nm from its center along x, spread over the y-z plane
+        let (positions, _) = tilted_membrane(0.0, 0.0);
+        let rotated = positions
+            .iter()
+            .map(|position| Vector3D::new(position.z, position.x, position.y))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            Normal::guess(&heads_system(&positions), false).unwrap(),
+            Axis::Z
+        );
+        assert_eq!(
+            Normal::guess(&heads_system(&rotated), false).unwrap(),
+            Axis::X
+        );
+    }
+}
@@ -0,0 +1,117 @@
+//! Parallel analysis of trajectories.
+
+use std::fmt::Display;
+
+use groan_rs::prelude::*;
+
+use crate::{
+    Args, READ_GROUP, grids::ThicknessGrids, leaflets::LeafletClassifier,
+    preprocess::FramePreprocessor,
+};
+
+/// Error occuring while analyzing a trajectory frame in a worker thread.
+#[derive(Debug)]
+struct FrameError(anyhow::Error);
+
+impl Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+impl ParallelTrajData for ThicknessGrids {
+    fn reduce(data: Vec<Self>) -> Self {
+        let mut iter = data.into_iter();
+        let mut reduced = iter.next().expect(
+            "FATAL MEMTHICK ERROR | ThicknessGrids::reduce | At least one thread should exist.",
+        );
+
+        for grids in iter {
+            reduced.merge(&grids);
+        }
+
+        reduced
+    }
+}
+
+/// Analyze the trajectory using multiple threads.
+/// Each thread accumulates its own grids which are summed at the end.
+pub(crate) fn analyze_trajectory_parallel<'a, R>(
+    system: &System,
+    grids: ThicknessGrids,
+    args: &Args,
+    classifier: &LeafletClassifier,
+    group: Option<&str>,
+) -> anyhow::Result<ThicknessGrids>
+where
+    R: TrajReadOpen<'a> + TrajRangeRead<'a> + TrajStepRead<'a> + TrajStepTimeRead<'a> + 'a,
+    R::FrameData: FrameDataTime,
+{
+    // frames up to this time have already been analyzed before the restart
+    let restart_time = (grids.n_frames > 0).then_some(grids.last_time);
+    let preprocessor = FramePreprocessor::new(args, system)?;
+
+    let body = move |frame: &System, grids: &mut ThicknessGrids| {
+        if restart_time.is_some_and(|time| frame.get_simulation_time() <= time) {
+            return Ok(());
+        }
+
+        // the frames are shared by the threads, so they must be copied before being modified
+        if preprocessor.is_active() {
+            let mut frame = frame.clone();
+            preprocessor.apply(&mut frame).map_err(FrameError)?;
+            return grids.add_frame(&frame, classifier, 1.0).map_err(FrameError);
+        }
+
+        grids.add_frame(frame, classifier, 1.0).map_err(FrameError)
+    };
+
+    // every thread starts with empty grids; previously accumulated data are added at the end
+    let mut empty = grids.clone();
+    empty.clear();
+
+    let mut result = system
+        .traj_iter_cat_map_reduce::<R, ThicknessGrids, FrameError>(
+            &args.trajectory,
+            args.threads,
+            body,
+            empty,
+            group,
+            Some(args.begin),
+            Some(args.end),
+            Some(args.step),
+            Some(ProgressPrinter::default()),
+        )
+        .map_err(anyhow::Error::from_boxed)?;
+
+    result.merge(&grids);
+    Ok(result)
+}
+
+/// Dispatch the parallel analysis based on the format of the trajectory files.
+pub(crate) fn analyze_parallel(
+    system: &System,
+    grids: ThicknessGrids,
+    args: &Args,
+    classifier: &LeafletClassifier,
+) -> anyhow::Result<ThicknessGrids> {
+    match FileType::from_name(&args.trajectory[0]) {
+        FileType::XTC => analyze_trajectory_parallel::<GroupXtcReader>(
+            system,
+            grids,
+            args,
+            classifier,
+            Some(READ_GROUP),
+        ),
+        FileType::TRR => {
+            analyze_trajectory_parallel::<TrrReader>(system, grids, args, classifier, None)
+        }
+        #[cfg(feature = "chemfiles")]
+        FileType::DCD | FileType::NC => {
+            analyze_trajectory_parallel::<ChemfilesReader>(system, grids, args, classifier, None)
+        }
+        _ => anyhow::bail!("Parallel analysis is not supported for this trajectory format."),
+    }
+}
@@ -0,0 +1,258 @@
+//! Modifying trajectory frames before the analysis.
+
+use groan_rs::{errors::SimBoxError, prelude::*};
+
+use crate::{
+    Args, FIT_GROUP, LIPIDS_GROUP, READ_GROUP, REFERENCE_GROUP, grids,
+    normal::{Normal, dot, to_array},
+};
+
+/// Modifications applied to every frame before the headgroups are assigned to leaflets.
+#[derive(Debug, Clone)]
+pub(crate) struct FramePreprocessor {
+    normal: Normal,
+    /// Place the center of the membrane into the center of the simulation box along the membrane normal.
+    center_membrane: bool,
+    /// Place the center of the reference atoms into the center of the simulation box in the membrane plane.
+    center_reference: bool,
+    /// The membrane center is the center of mass of the lipids.
+    center_of_mass: bool,
+    /// Positions of the fitting atoms in the input structure.
+    fit: Option<FitReference>,
+    /// Make lipid molecules broken across periodic boundaries whole.
+    make_whole: bool,
+}
+
+impl FramePreprocessor {
+    /// Create the preprocessor from the provided options.
+    /// The positions of the fitting atoms are taken from the input structure `system`.
+    pub(crate) fn new(args: &Args, system: &System) -> anyhow::Result<Self> {
+        let normal = args.normal();
+        let fit = match &args.fit {
+            Some(query) => Some(FitReference::new(system, &normal, query)?),
+            None => None,
+        };
+
+        Ok(FramePreprocessor {
+            normal,
+            center_membrane: args.center_membrane,
+            center_reference: args.center.is_some(),
+            center_of_mass: args.center_of_mass,
+            fit,
+            make_whole: args.make_whole,
+        })
+    }
+
+    /// Check whether the frames have to be modified at all.
+    pub(crate) fn is_active(&self) -> bool {
+        self.center_membrane || self.center_reference || self.fit.is_some() || self.make_whole
+    }
+
+    /// Apply the modifications to the frame.
+    pub(crate) fn apply(&self, frame: &mut System) -> anyhow::Result<()> {
+        if let Some(fit) = &self.fit {
+            fit.apply(frame, &self.normal)?;
+        }
+
+        if self.center_reference {
+            let shift = self.reference_shift(frame)?;
+            translate(frame, &shift)?;
+        }
+
+        if self.center_membrane {
+            let shift = self.membrane_shift(frame)?;
+            translate(frame, &shift)?;
+        }
+
+        // translations wrap the atoms into the box, so the molecules are made whole afterwards
+        if self.make_whole {
+            make_lipids_whole(frame)?;
+        }
+
+        Ok(())
+    }
+
+    /// Calculate the translation in the membrane plane placing the reference atoms into the center of the box.
+    fn reference_shift(&self, frame: &System) -> anyhow::Result<Vector3D> {
+        let simbox = frame.get_box().ok_or(SimBoxError::DoesNotExist)?;
+        let box_center = Vector3D::new(simbox.x / 2.0, simbox.y / 2.0, simbox.z / 2.0);
+        let reference = frame.group_get_center(REFERENCE_GROUP)?;
+
+        let vector = reference.vector_to(&box_center, simbox);
+        let distance = self.normal.distance(&box_center, &reference, simbox);
+        let (normal, _, _) = self.normal.basis();
+
+        Ok(Vector3D::new(
+            vector.x - normal[0] * distance,
+            vector.y - normal[1] * distance,
+            vector.z - normal[2] * distance,
+        ))
+    }
+
+    /// Calculate the translation along the membrane normal placing the membrane center into the center of the box.
+    fn membrane_shift(&self, frame: &System) -> anyhow::Result<Vector3D> {
+        let simbox = frame.get_box().ok_or(SimBoxError::DoesNotExist)?;
+        let box_center = Vector3D::new(simbox.x / 2.0, simbox.y / 2.0, simbox.z / 2.0);
+        let membrane_center = grids::membrane_center(frame, self.center_of_mass)?;
+
+        let distance = self.normal.distance(&box_center, &membrane_center, simbox);
+        let (normal, _, _) = self.normal.basis();
+
+        Ok(Vector3D::new(
+            normal[0] * distance,
+            normal[1] * distance,
+            normal[2] * distance,
+        ))
+    }
+}
+
+/// Translate all atoms read from the trajectory (i.e., all atoms used in the analysis) by the specified vector.
+fn translate(frame: &mut System, shift: &Vector3D) -> anyhow::Result<()> {
+    frame.group_translate(READ_GROUP, shift)?;
+    Ok(())
+}
+
+/// Make lipid molecules broken across periodic boundaries whole.
+///
+/// Lipids are identified by their residue numbers. All atoms of a lipid are placed at the periodic images
+/// closest to the first atom of the lipid, so no bonds are required.
+fn make_lipids_whole(frame: &mut System) -> anyhow::Result<()> {
+    let simbox = frame.get_box().ok_or(SimBoxError::DoesNotExist)?.clone();
+
+    let mut anchor: Option<(usize, Vector3D)> = None;
+    for atom in frame.group_iter_mut(LIPIDS_GROUP)? {
+        let position = atom
+            .get_position()
+            .ok_or_else(|| anyhow::anyhow!("Atom {} has no position.", atom.get_index() + 1))?
+            .clone();
+
+        match &anchor {
+            Some((residue, reference)) if *residue == atom.get_residue_number() => {
+                let vector = reference.vector_to(&position, &simbox);
+                atom.set_position(Vector3D::new(
+                    reference.x + vector.x,
+                    reference.y + vector.y,
+                    reference.z + vector.z,
+                ));
+            }
+            _ => anchor = Some((atom.get_residue_number(), position)),
+        }
+    }
+
+    Ok(())
+}
+
+/// Positions of the fitting atoms in the membrane plane of the input structure.
+#[derive(Debug, Clone)]
+struct FitReference {
+    /// Center of geometry of the fitting atoms.
+    center: Vector3D,
+    /// Coordinates of the fitting atoms in the membrane plane relative to their center.
+    positions: Vec<(f32, f32)>,
+}
+
+impl FitReference {
+    /// Get the positions of the fitting atoms in the input structure.
+    fn new(system: &System, normal: &Normal, query: &str) -> anyhow::Result<FitReference> {
+        let (center, positions) = fit_positions(system, normal)?;
+        if positions.len() < 2 {
+            anyhow::bail!(
+                "Fitting the frames requires at least 2 atoms, but the query '{}' selects {} atom(s).",
+                query,
+                positions.len()
+            );
+        }
+
+        Ok(FitReference { center, positions })
+    }
+
+    /// Rotate all atoms read from the trajectory around the membrane normal and translate them in the membrane plane
+    /// so that the fitting atoms overlap with their positions in the input structure.
+    fn apply(&self, frame: &mut System, normal: &Normal) -> anyhow::Result<()> {
+        let (center, positions) = fit_positions(frame, normal)?;
+
+        // optimal rotation in the membrane plane (two-dimensional Kabsch algorithm)
+        let (sin, cos) = positions.iter().zip(self.positions.iter()).fold(
+            (0.0, 0.0),
+            |(sin, cos), ((x, y), (x_ref, y_ref))| {
+                (sin + x * y_ref - y * x_ref, cos + x * x_ref + y * y_ref)
+            },
+        );
+        let angle = f32::atan2(sin, cos);
+        let (sin, cos) = angle.sin_cos();
+
+        let simbox = frame.get_box().ok_or(SimBoxError::DoesNotExist)?.clone();
+        let (axis, u, v) = normal.basis();
+        let height = normal.axial(&center);
+        let target = to_array(&self.center);
+        let target_height = normal.axial(&self.center);
+
+        for atom in frame.group_iter_mut(READ_GROUP)? {
+            let position = atom
+                .get_position()
+                .ok_or_else(|| anyhow::anyhow!("Atom {} has no position.", atom.get_index() + 1))?;
+
+            let vector = center.vector_to(position, &simbox);
+            let vector = to_array(&vector);
+            let (a, b) = (dot(u, vector), dot(v, vector));
+            let (a, b) = (cos * a - sin * b, sin * a + cos * b);
+            let shift = height - target_height + dot(axis, vector);
+
+            atom.set_position(Vector3D::new(
+                target[0] + a * u[0] + b * v[0] + shift * axis[0],
+                target[1] + a * u[1] + b * v[1] + shift * axis[1],
+                target[2] + a * u[2] + b * v[2] + shift * axis[2],
+            ));
+        }
+
+        frame.group_wrap(READ_GROUP)?;
+
+        Ok(())
+    }
+}
+
+/// Calculate the center of the fitting atoms and their coordinates in the membrane plane relative to this center.
+/// The fitting atoms are made whole using the first of them as the reference.
+fn fit_positions(system: &System, normal: &Normal) -> anyhow::Result<(Vector3D, Vec<(f32, f32)>)> {
+    let simbox = system.get_box().ok_or(SimBoxError::DoesNotExist)?;
+    let (_, u, v) = normal.basis();
+
+    let mut first = None;
+    let mut vectors = Vec::new();
+    for atom in system.group_iter(FIT_GROUP)? {
+        let position = atom
+            .get_position()
+            .ok_or_else(|| anyhow::anyhow!("Atom {} has no position.", atom.get_index() + 1))?;
+
+        let first = first.get_or_insert_with(|| position.clone());
+        vectors.push(to_array(&first.vector_to(position, simbox)));
+    }
+
+    let Some(first) = first else {
+        return Ok((Vector3D::default(), Vec::new()));
+    };
+
+    let n_atoms = vectors.len() as f32;
+    let mean = vectors.iter().fold([0.0; 3], |sum, vector| {
+        [
+            sum[0] + vector[0] / n_atoms,
+            sum[1] + vector[1] / n_atoms,
+            sum[2] + vector[2] / n_atoms,
+        ]
+    });
+
+    let positions = vectors
+        .iter()
+        .map(|vector| {
+            let relative = [
+                vector[0] - mean[0],
+                vector[1] - mean[1],
+                vector[2] - mean[2],
+            ];
+            (dot(u, relative), dot(v, relative))
+        })
+        .collect();
+
+    let center = Vector3D::new(first.x + mean[0], first.y + mean[1], first.z + mean[2]);
+    Ok((center, positions))
+}
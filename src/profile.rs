@@ -0,0 +1,364 @@
+//! Radial profiles of the membrane thickness around a point or selected atoms in the membrane plane.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use groan_rs::prelude::*;
+
+use crate::{geometry, grids::LeafletTotals, normal::Normal};
+
+/// Accumulated samples of one bin of a profile.
+#[derive(Debug, Clone, Default)]
+struct Shell {
+    totals: LeafletTotals,
+    /// Number of samples in the upper and the lower leaflet.
+    counts: (usize, usize),
+    /// Weighted sum of the instantaneous thickness of the bin in the individual frames.
+    frame_sum: f64,
+    /// Weighted sum of the squared instantaneous thickness of the bin in the individual frames.
+    frame_squares: f64,
+    /// Sum of the weights of the frames with samples in both leaflets of the bin.
+    frame_weight: f64,
+    /// Number of frames with samples in both leaflets of the bin.
+    n_frames: usize,
+}
+
+impl Shell {
+    /// Standard error of the thickness of the bin estimated from the spread of its instantaneous thickness,
+    /// assuming independent frames. NaN for bins sampled in fewer than two frames.
+    fn error(&self) -> f64 {
+        if self.n_frames < 2 {
+            return f64::NAN;
+        }
+
+        let n = self.n_frames as f64;
+        let mean = self.frame_sum / self.frame_weight;
+        let variance =
+            (self.frame_squares / self.frame_weight - mean * mean).max(0.0) * n / (n - 1.0);
+        (variance / n).sqrt()
+    }
+
+    /// Thickness of the bin calculated from all its samples and its standard error.
+    /// Both are NaN for bins with fewer than `nan_limit` samples in any of the leaflets.
+    fn thickness(&self, nan_limit: usize) -> (f64, f64) {
+        if self.counts.0 < nan_limit || self.counts.1 < nan_limit {
+            return (f64::NAN, f64::NAN);
+        }
+
+        (
+            self.totals
+                .thickness_since(&LeafletTotals::default())
+                .unwrap_or(f64::NAN),
+            self.error(),
+        )
+    }
+}
+
+/// Reference from which the lateral distances of the headgroups are measured.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ProfileCenter {
+    /// Fixed point in the membrane plane.
+    Point(f32, f32),
+    /// Center of the atoms of the group in every frame.
+    Selection(&'static str),
+    /// Laterally closest atom of the group in every frame.
+    Closest(&'static str),
+    /// Center of the atoms of the inclusion with the specified index in every frame.
+    Inclusion(usize),
+}
+
+/// Membrane thickness in bins given by the lateral distance of the headgroups from the center of the profile
+/// (shells) and by their azimuthal angle around it (sectors).
+#[derive(Debug, Clone)]
+pub(crate) struct RadialProfile {
+    center: ProfileCenter,
+    /// Inner radius of the first shell (in nm).
+    inner: f32,
+    /// Width of the shells (in nm).
+    width: f32,
+    n_shells: usize,
+    /// Angular width of the sectors (in degrees).
+    sector: f32,
+    n_sectors: usize,
+    /// Bins of the profile indexed by `shell * n_sectors + sector`.
+    bins: Vec<Shell>,
+}
+
+impl RadialProfile {
+    /// Prepare an empty profile with shells of the specified width reaching to half of the shorter side
+    /// of the simulation box in the membrane plane, each shell split into sectors of the specified angle
+    /// (in degrees, 360 for a purely radial profile).
+    pub(crate) fn new(
+        system: &System,
+        normal: &Normal,
+        width: f32,
+        sector: f32,
+        center: ProfileCenter,
+    ) -> anyhow::Result<RadialProfile> {
+        let simbox = system
+            .get_box()
+            .ok_or_else(|| anyhow::anyhow!("Radial profile requires a simulation box."))?;
+        let (box_x, box_y) = normal.lateral_box(simbox).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Radial profile requires the membrane normal to be oriented along an axis of the simulation box."
+            )
+        })?;
+
+        let n_shells = ((box_x.min(box_y) / 2.0 / width).ceil() as usize).max(1);
+        Ok(RadialProfile::with_bins(
+            center, 0.0, width, n_shells, sector,
+        ))
+    }
+
+    /// Prepare an empty profile of the annulus between the specified radii (in nm) split into sectors
+    /// of the specified angle (in degrees).
+    pub(crate) fn annulus(
+        annulus: (f32, f32),
+        sector: f32,
+        center: ProfileCenter,
+    ) -> RadialProfile {
+        RadialProfile::with_bins(center, annulus.0, annulus.1 - annulus.0, 1, sector)
+    }
+
+    fn with_bins(
+        center: ProfileCenter,
+        inner: f32,
+        width: f32,
+        n_shells: usize,
+        sector: f32,
+    ) -> RadialProfile {
+        let n_sectors = ((360.0 / sector).ceil() as usize).max(1);
+        RadialProfile {
+            center,
+            inner,
+            width,
+            n_shells,
+            sector,
+            n_sectors,
+            bins: vec![Shell::default(); n_shells * n_sectors],
+        }
+    }
+
+    /// Add the distances of the headgroups of the frame from the membrane center to the bins
+    /// given by their lateral positions relative to the center of the profile. `upper` specifies the leaflet of each headgroup.
+    /// Distances are calculated using the periodic images closest to each other.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn add(
+        &mut self,
+        frame: &System,
+        positions: &[Vector3D],
+        membrane_center: &Vector3D,
+        upper: &[Option<bool>],
+        normal: &Normal,
+        simbox: &SimBox,
+        weight: f64,
+    ) -> anyhow::Result<()> {
+        let periods = normal.lateral_box(simbox);
+        let references = match self.center {
+            ProfileCenter::Point(x, y) => vec![(x, y)],
+            ProfileCenter::Selection(group) => {
+                vec![normal.lateral(&frame.group_get_center(group)?)]
+            }
+            ProfileCenter::Inclusion(index) => {
+                vec![normal.lateral(&frame.group_get_center(&crate::inclusion_group(index))?)]
+            }
+            ProfileCenter::Closest(group) => frame
+                .group_iter(group)?
+                .map(|atom| {
+                    atom.get_position()
+                        .map(|position| normal.lateral(position))
+                        .ok_or_else(|| {
+                            anyhow::anyhow!("Atom {} has no position.", atom.get_index() + 1)
+                        })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?,
+        };
+
+        let mut totals = vec![LeafletTotals::default(); self.bins.len()];
+        for (position, &is_upper) in positions.iter().zip(upper.iter()) {
+            let Some(is_upper) = is_upper else {
+                continue;
+            };
+
+            let lateral = normal.lateral(position);
+            let (distance, angle) = references
+                .iter()
+                .map(|&reference| geometry::polar_coordinates(lateral, reference, periods))
+                .fold((f32::INFINITY, 0.0), |closest, polar| {
+                    if polar.0 < closest.0 { polar } else { closest }
+                });
+
+            if distance < self.inner {
+                continue;
+            }
+
+            let shell = ((distance - self.inner) / self.width) as usize;
+            if shell >= self.n_shells {
+                continue;
+            }
+
+            let sector = ((angle / self.sector) as usize).min(self.n_sectors - 1);
+            let index = shell * self.n_sectors + sector;
+
+            let value = normal.distance(position, membrane_center, simbox) as f64;
+            let bin = &mut self.bins[index];
+            if is_upper {
+                totals[index].upper_sum += weight * value;
+                totals[index].upper_weight += weight;
+                bin.counts.0 += 1;
+            } else {
+                totals[index].lower_sum += weight * value;
+                totals[index].lower_weight += weight;
+                bin.counts.1 += 1;
+            }
+        }
+
+        for (bin, totals) in self.bins.iter_mut().zip(totals) {
+            if let Some(thickness) = totals.thickness_since(&LeafletTotals::default()) {
+                bin.frame_sum += weight * thickness;
+                bin.frame_squares += weight * thickness * thickness;
+                bin.frame_weight += weight;
+                bin.n_frames += 1;
+            }
+
+            bin.totals += totals;
+        }
+
+        Ok(())
+    }
+
+    /// Remove all accumulated samples.
+    pub(crate) fn clear(&mut self) {
+        self.bins.fill(Shell::default());
+    }
+
+    /// Add the samples accumulated in the other profile into this profile.
+    pub(crate) fn merge(&mut self, other: &RadialProfile) {
+        for (bin, other) in self.bins.iter_mut().zip(other.bins.iter()) {
+            bin.totals += other.totals;
+            bin.counts.0 += other.counts.0;
+            bin.counts.1 += other.counts.1;
+            bin.frame_sum += other.frame_sum;
+            bin.frame_squares += other.frame_squares;
+            bin.frame_weight += other.frame_weight;
+            bin.n_frames += other.n_frames;
+        }
+    }
+
+    /// Write the thickness of every bin into a file. Profiles with one sector are written as a function
+    /// of the distance and profiles with one shell as a function of the angle, both with the standard errors
+    /// of the thickness. Other profiles are written as maps in the distance and the angle.
+    /// Thickness is NaN for bins with fewer than `nan_limit` samples in any of the leaflets.
+    pub(crate) fn write(
+        &self,
+        filename: impl AsRef<Path>,
+        nan_limit: usize,
+        raw_arguments: &[String],
+    ) -> anyhow::Result<()> {
+        let filename = filename.as_ref();
+        let file = File::create(filename).with_context(|| {
+            format!(
+                "Could not create radial profile file '{}'.",
+                filename.display()
+            )
+        })?;
+        let mut output = BufWriter::new(file);
+
+        writeln!(
+            &mut output,
+            "# Generated with memthick v{}.",
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(&mut output, "# Command line: {}", raw_arguments.join(" "))?;
+
+        let reference = match self.center {
+            ProfileCenter::Closest(_) => "the closest selected atom",
+            ProfileCenter::Point(..) | ProfileCenter::Selection(_) => "the center of the profile",
+            ProfileCenter::Inclusion(_) => "the center of the inclusion",
+        };
+        let radius = |shell: usize| self.inner + (shell as f32 + 0.5) * self.width;
+        let angle = |sector: usize| (sector as f32 + 0.5) * self.sector;
+
+        if self.n_sectors > 1 && self.n_shells > 1 {
+            writeln!(
+                &mut output,
+                "# Membrane thickness in shells of {} nm and sectors of {} deg around {}.",
+                self.width, self.sector, reference
+            )?;
+            writeln!(&mut output, "@ xlabel lateral distance [nm]")?;
+            writeln!(&mut output, "@ ylabel azimuthal angle [deg]")?;
+            writeln!(&mut output, "@ zlabel membrane thickness [nm]")?;
+            writeln!(&mut output, "@ grid --")?;
+            writeln!(&mut output, "$ type colorbar")?;
+            writeln!(&mut output, "$ colormap rainbow")?;
+
+            for shell in 0..self.n_shells {
+                for sector in 0..self.n_sectors {
+                    let (thickness, _) =
+                        self.bins[shell * self.n_sectors + sector].thickness(nan_limit);
+                    writeln!(
+                        &mut output,
+                        "{:12.6} {:12.6} {:12.4}",
+                        radius(shell),
+                        angle(sector),
+                        thickness
+                    )?;
+                }
+            }
+
+            output.flush()?;
+            return Ok(());
+        }
+
+        if self.n_sectors > 1 {
+            writeln!(
+                &mut output,
+                "# Membrane thickness in sectors of {} deg of the annulus between {} and {} nm around {}.",
+                self.sector,
+                self.inner,
+                self.inner + self.width,
+                reference
+            )?;
+        } else {
+            writeln!(
+                &mut output,
+                "# Membrane thickness in shells of {} nm by the lateral distance from {}.",
+                self.width, reference
+            )?;
+        }
+        writeln!(
+            &mut output,
+            "# Errors are standard errors of the instantaneous thickness of the bins assuming independent frames."
+        )?;
+
+        let label = if self.n_sectors > 1 {
+            "azimuthal angle [deg]"
+        } else {
+            "lateral distance [nm]"
+        };
+        writeln!(&mut output, "@ xlabel {}", label)?;
+        writeln!(&mut output, "@ ylabel membrane thickness [nm]")?;
+        writeln!(&mut output, "# {} thickness [nm] error [nm] frames", label)?;
+
+        for (index, bin) in self.bins.iter().enumerate() {
+            let (thickness, error) = bin.thickness(nan_limit);
+            let coordinate = if self.n_sectors > 1 {
+                angle(index)
+            } else {
+                radius(index)
+            };
+            writeln!(
+                &mut output,
+                "{:12.4} {:12.4} {:12.4} {:8}",
+                coordinate, thickness, error, bin.n_frames
+            )?;
+        }
+
+        output.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+//! Projections of the thickness map onto its axes.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+
+/// Average of the map over the bins sharing one coordinate.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Projection {
+    /// Shared coordinate of the bins.
+    coordinate: f32,
+    /// Average value of the bins with defined values.
+    mean: f64,
+    /// Standard error of the average value.
+    error: f64,
+    /// Number of bins with defined values.
+    n_bins: usize,
+}
+
+/// Average the map given as `(x, y, value)` for every bin of a grid with `n_x` x `n_y` bins in row-major order
+/// over the second coordinate (`along_x`) or over the first coordinate (otherwise). Undefined values are ignored.
+/// The error is the standard error of the mean of the averaged bins.
+pub(crate) fn project(
+    values: &[(f32, f32, f64)],
+    n_x: usize,
+    n_y: usize,
+    along_x: bool,
+) -> Vec<Projection> {
+    let (n_lines, n_points) = if along_x { (n_x, n_y) } else { (n_y, n_x) };
+    let bin = |line: usize, point: usize| {
+        if along_x {
+            line * n_y + point
+        } else {
+            point * n_y + line
+        }
+    };
+
+    (0..n_lines)
+        .map(|line| {
+            let (x, y, _) = values[bin(line, 0)];
+            let defined = (0..n_points)
+                .map(|point| values[bin(line, point)].2)
+                .filter(|value| value.is_finite())
+                .collect::<Vec<_>>();
+
+            let n = defined.len() as f64;
+            let mean = defined.iter().sum::<f64>() / n;
+            let error = if defined.len() < 2 {
+                f64::NAN
+            } else {
+                let variance = defined
+                    .iter()
+                    .map(|value| (value - mean).powi(2))
+                    .sum::<f64>()
+                    / (n - 1.0);
+                (variance / n).sqrt()
+            };
+
+            Projection {
+                coordinate: if along_x { x } else { y },
+                mean,
+                error,
+                n_bins: defined.len(),
+            }
+        })
+        .collect()
+}
+
+/// Write the projection of the thickness map along the axis with the specified label into a file.
+pub(crate) fn write(
+    filename: impl AsRef<Path>,
+    projection: &[Projection],
+    label: &str,
+    raw_arguments: &[String],
+) -> anyhow::Result<()> {
+    let filename = filename.as_ref();
+    let file = File::create(filename)
+        .with_context(|| format!("Could not create projection file '{}'.", filename.display()))?;
+    let mut output = BufWriter::new(file);
+
+    writeln!(
+        &mut output,
+        "# Generated with memthick v{}.",
+        env!("CARGO_PKG_VERSION")
+    )?;
+    writeln!(&mut output, "# Command line: {}", raw_arguments.join(" "))?;
+    writeln!(
+        &mut output,
+        "# Membrane thickness averaged over the bins of the thickness map with the same {}.",
+        label
+    )?;
+    writeln!(
+        &mut output,
+        "# Errors are standard errors of the mean over the averaged bins."
+    )?;
+    writeln!(&mut output, "@ xlabel {}", label)?;
+    writeln!(&mut output, "@ ylabel membrane thickness [nm]")?;
+    writeln!(&mut output, "# {} thickness [nm] error [nm] bins", label)?;
+
+    for line in projection {
+        writeln!(
+            &mut output,
+            "{:12.6} {:12.4} {:12.4} {:8}",
+            line.coordinate, line.mean, line.error, line.n_bins
+        )?;
+    }
+
+    output.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Map of `n_x` x `n_y` bins of 1 x 1 with the specified values of the bins `(i, j)`.
+    fn map(n_x: usize, n_y: usize, value: impl Fn(usize, usize) -> f64) -> Vec<(f32, f32, f64)> {
+        (0..n_x * n_y)
+            .map(|index| {
+                let (i, j) = (index / n_y, index % n_y);
+                (i as f32, j as f32, value(i, j))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn constant_map() {
+        let values = map(4, 3, |_, _| 2.0);
+
+        let projection = project(&values, 4, 3, true);
+        assert_eq!(projection.len(), 4);
+        for (i, line) in projection.iter().enumerate() {
+            assert_eq!(line.coordinate, i as f32);
+            assert_eq!(line.mean, 2.0);
+            assert_eq!(line.error, 0.0);
+            assert_eq!(line.n_bins, 3);
+        }
+
+        let projection = project(&values, 4, 3, false);
+        assert_eq!(projection.len(), 3);
+        assert!(projection.iter().all(|line| line.mean == 2.0));
+    }
+
+    #[test]
+    fn mean_and_error() {
+        let values = map(2, 3, |i, j| (i + 2 * j) as f64);
+
+        // the second line contains the values 1, 3, and 5
+        let line = project(&values, 2, 3, true)[1];
+        assert_eq!(line.mean, 3.0);
+        assert!((line.error - (4.0f64 / 3.0).sqrt()).abs() < 1e-12);
+
+        // the third line contains the values 4 and 5
+        let line = project(&values, 2, 3, false)[2];
+        assert_eq!(line.coordinate, 2.0);
+        assert_eq!(line.mean, 4.5);
+        assert!((line.error - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn undefined_bins_ignored() {
+        let values = map(2, 3, |i, j| match (i, j) {
+            (0, 0) => f64::NAN,
+            (1, _) => f64::NAN,
+            _ => 1.0,
+        });
+        let projection = project(&values, 2, 3, true);
+
+        assert_eq!(projection[0].mean, 1.0);
+        assert_eq!(projection[0].n_bins, 2);
+        assert!(projection[1].mean.is_nan());
+        assert!(projection[1].error.is_nan());
+        assert_eq!(projection[1].n_bins, 0);
+
+        // a single defined bin has no error
+        let projection = project(&values, 2, 3, false);
+        assert_eq!(projection[1].mean, 1.0);
+        assert!(projection[1].error.is_nan());
+    }
+}
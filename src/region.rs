@@ -0,0 +1,97 @@
+//! Restriction of the analyzed headgroups to a region of the simulation box.
+
+use std::{fmt::Display, str::FromStr};
+
+use groan_rs::prelude::*;
+
+use crate::grids;
+
+/// Cuboid region of the simulation box, specified as `xmin:xmax,ymin:ymax[,zmin:zmax]` (in nm).
+/// Bounds that are not provided (e.g., `5:,:`) are unlimited.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Region {
+    /// Minimal and maximal coordinate along the x, y, and z axis.
+    ranges: [(f32, f32); 3],
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "expected region in the format 'xmin:xmax,ymin:ymax[,zmin:zmax]', got '{}'",
+                string
+            )
+        };
+
+        let bounds = string.split(',').collect::<Vec<_>>();
+        if bounds.len() != 2 && bounds.len() != 3 {
+            return Err(invalid());
+        }
+
+        let bound = |value: &str, unlimited: f32| match value.trim() {
+            "" => Ok(unlimited),
+            value => value.parse::<f32>().map_err(|_| invalid()),
+        };
+
+        let mut ranges = [(f32::NEG_INFINITY, f32::INFINITY); 3];
+        for (range, bounds) in ranges.iter_mut().zip(bounds) {
+            let (min, max) = bounds.split_once(':').ok_or_else(invalid)?;
+            *range = (bound(min, f32::NEG_INFINITY)?, bound(max, f32::INFINITY)?);
+
+            if range.0 >= range.1 {
+                return Err(format!(
+                    "minimum of the region must be lower than its maximum, got '{}'",
+                    bounds
+                ));
+            }
+        }
+
+        Ok(Region { ranges })
+    }
+}
+
+impl Display for Region {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bound = |value: f32| {
+            if value.is_finite() {
+                value.to_string()
+            } else {
+                String::new()
+            }
+        };
+
+        let ranges = self
+            .ranges
+            .iter()
+            .map(|&(min, max)| format!("{}:{}", bound(min), bound(max)))
+            .collect::<Vec<_>>();
+
+        write!(f, "{}", ranges.join(","))
+    }
+}
+
+impl Region {
+    /// Check whether the point lies inside the region.
+    fn contains(&self, point: &Vector3D) -> bool {
+        [point.x, point.y, point.z]
+            .iter()
+            .zip(self.ranges.iter())
+            .all(|(&coordinate, &(min, max))| coordinate >= min && coordinate <= max)
+    }
+
+    /// Remove the headgroups located outside the region in the frame from the leaflets specified by `upper`
+    /// (see [`ThicknessGrids::add_heads`](crate::grids::ThicknessGrids::add_heads)).
+    pub(crate) fn restrict(
+        &self,
+        frame: &System,
+        upper: &[Option<bool>],
+    ) -> anyhow::Result<Vec<Option<bool>>> {
+        Ok(grids::head_positions(frame)?
+            .iter()
+            .zip(upper.iter())
+            .map(|(head, &upper)| if self.contains(head) { upper } else { None })
+            .collect())
+    }
+}
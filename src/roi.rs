@@ -0,0 +1,320 @@
+//! Comparison of the membrane thickness inside and outside a region of interest in the membrane plane.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+
+use crate::grids::ThicknessGrids;
+
+/// Width of the bins of the thickness histograms (in nm).
+const HISTOGRAM_BIN: f64 = 0.05;
+/// Maximal difference between the coordinates of the bins of the mask and of the grids.
+const COORDINATE_TOLERANCE: f32 = 1e-4;
+
+/// Grid bins located inside the region of interest.
+#[derive(Debug, Clone)]
+pub(crate) struct RegionOfInterest {
+    /// Whether each bin is inside the region in the order of the grid maps.
+    inside: Vec<bool>,
+}
+
+impl RegionOfInterest {
+    /// Read the region of interest from a file. Lines with two columns are the vertices of a polygon
+    /// in the coordinates of the map, bins with centers inside the polygon or on its edges are inside the region.
+    /// Lines with three columns form a mask map with the same bins as the grids (e.g., written by memthick),
+    /// bins with defined non-zero values are inside the region. Lines starting with '#', '@', or '$' are ignored.
+    pub(crate) fn read(
+        filename: impl AsRef<Path>,
+        grids: &ThicknessGrids,
+    ) -> anyhow::Result<RegionOfInterest> {
+        let filename = filename.as_ref();
+        let file = File::open(filename).with_context(|| {
+            format!(
+                "Could not open region of interest file '{}'.",
+                filename.display()
+            )
+        })?;
+
+        let mut rows = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty()
+                || line.starts_with('#')
+                || line.starts_with('@')
+                || line.starts_with('$')
+            {
+                continue;
+            }
+
+            let row = line
+                .split_whitespace()
+                .map(|value| value.parse::<f64>())
+                .collect::<Result<Vec<_>, _>>()
+                .ok()
+                .filter(|row| row.len() == 2 || row.len() == 3)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Could not parse line '{}' in region of interest file '{}'.",
+                        line,
+                        filename.display()
+                    )
+                })?;
+
+            if rows
+                .first()
+                .is_some_and(|first: &Vec<f64>| first.len() != row.len())
+            {
+                anyhow::bail!(
+                    "Region of interest file '{}' mixes polygon vertices and bins of a mask.",
+                    filename.display()
+                );
+            }
+            rows.push(row);
+        }
+
+        let bins = grids
+            .upper_sum
+            .extract_raw()
+            .map(|(x, y, _)| (x, y))
+            .collect::<Vec<_>>();
+        let inside = match rows.first().map(Vec::len) {
+            None => anyhow::bail!("Region of interest file '{}' is empty.", filename.display()),
+            Some(2) => {
+                if rows.len() < 3 {
+                    anyhow::bail!(
+                        "Polygon in region of interest file '{}' must have at least 3 vertices.",
+                        filename.display()
+                    );
+                }
+
+                let polygon = rows
+                    .iter()
+                    .map(|row| (row[0] as f32, row[1] as f32))
+                    .collect::<Vec<_>>();
+                bins.iter()
+                    .map(|&point| contains(&polygon, point))
+                    .collect()
+            }
+            Some(_) => {
+                let compatible = rows.len() == bins.len()
+                    && rows.iter().zip(bins.iter()).all(|(row, &(x, y))| {
+                        (row[0] as f32 - x).abs() <= COORDINATE_TOLERANCE
+                            && (row[1] as f32 - y).abs() <= COORDINATE_TOLERANCE
+                    });
+
+                if !compatible {
+                    anyhow::bail!(
+                        "Mask in region of interest file '{}' does not have the same bins as the thickness map.",
+                        filename.display()
+                    );
+                }
+
+                rows.iter()
+                    .map(|row| row[2].is_finite() && row[2] != 0.0)
+                    .collect()
+            }
+        };
+
+        Ok(RegionOfInterest { inside })
+    }
+
+    /// Number of bins inside the region.
+    pub(crate) fn n_inside(&self) -> usize {
+        self.inside.iter().filter(|&&inside| inside).count()
+    }
+
+    /// Write the histograms of the thickness of the bins inside and outside the region of interest
+    /// and the average thickness of both parts. Bins with undefined thickness are ignored.
+    pub(crate) fn write(
+        &self,
+        filename: impl AsRef<Path>,
+        values: &[(f32, f32, f64)],
+        raw_arguments: &[String],
+    ) -> anyhow::Result<()> {
+        let filename = filename.as_ref();
+        let part = |inside: bool| {
+            values
+                .iter()
+                .zip(self.inside.iter())
+                .filter(|&(&(_, _, value), &is_inside)| value.is_finite() && is_inside == inside)
+                .map(|(&(_, _, value), _)| value)
+                .collect::<Vec<_>>()
+        };
+        let (inside, outside) = (part(true), part(false));
+
+        let bin = |value: f64| (value / HISTOGRAM_BIN).floor() as i64;
+        let (first, last) = inside
+            .iter()
+            .chain(outside.iter())
+            .fold((i64::MAX, i64::MIN), |(first, last), &value| {
+                (first.min(bin(value)), last.max(bin(value)))
+            });
+
+        let n_bins = if first <= last {
+            (last - first + 1) as usize
+        } else {
+            0
+        };
+        let histogram = |values: &[f64]| {
+            let mut histogram = vec![0usize; n_bins];
+            for &value in values {
+                histogram[(bin(value) - first) as usize] += 1;
+            }
+            histogram
+        };
+        let (inside_histogram, outside_histogram) = (histogram(&inside), histogram(&outside));
+
+        let file = File::create(filename).with_context(|| {
+            format!(
+                "Could not create region of interest file '{}'.",
+                filename.display()
+            )
+        })?;
+        let mut output = BufWriter::new(file);
+
+        writeln!(
+            &mut output,
+            "# Generated with memthick v{}.",
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(&mut output, "# Command line: {}", raw_arguments.join(" "))?;
+        writeln!(
+            &mut output,
+            "# Histograms of the membrane thickness of the bins inside and outside the region of interest."
+        )?;
+        writeln!(
+            &mut output,
+            "# See the average thickness of both parts at the end of this file."
+        )?;
+        writeln!(&mut output, "@ xlabel membrane thickness [nm]")?;
+        writeln!(&mut output, "@ ylabel fraction of bins")?;
+        writeln!(&mut output, "# thickness [nm] inside outside")?;
+
+        let fraction = |count: usize, total: usize| count as f64 / total as f64;
+        for (index, (&count_inside, &count_outside)) in inside_histogram
+            .iter()
+            .zip(outside_histogram.iter())
+            .enumerate()
+        {
+            writeln!(
+                &mut output,
+                "{:12.4} {:12.6} {:12.6}",
+                (first + index as i64) as f64 * HISTOGRAM_BIN + HISTOGRAM_BIN / 2.0,
+                fraction(count_inside, inside.len()),
+                fraction(count_outside, outside.len())
+            )?;
+        }
+
+        println!("\nThickness of the region of interest:");
+        for (label, values) in [("inside", &inside), ("outside", &outside)] {
+            let line = format!(
+                "Average thickness {:>7} the region of interest: {:12.4} nm ({} bins)",
+                label,
+                values.iter().sum::<f64>() / values.len() as f64,
+                values.len()
+            );
+            println!("{}", line);
+            writeln!(&mut output, "# {}", line)?;
+        }
+
+        output.flush()?;
+        Ok(())
+    }
+}
+
+/// Check whether the point lies inside the polygon using the even-odd rule.
+/// Points on the edges of the polygon (within `COORDINATE_TOLERANCE`) are inside.
+fn contains(polygon: &[(f32, f32)], (x, y): (f32, f32)) -> bool {
+    let mut inside = false;
+    for (index, &(x1, y1)) in polygon.iter().enumerate() {
+        let (x2, y2) = polygon[(index + 1) % polygon.len()];
+        if on_segment((x1, y1), (x2, y2), (x, y)) {
+            return true;
+        }
+
+        if (y1 > y) != (y2 > y) && x < x1 + (y - y1) / (y2 - y1) * (x2 - x1) {
+            inside = !inside;
+        }
+    }
+
+    inside
+}
+
+/// Check whether the point lies on the segment between `start` and `end` within `COORDINATE_TOLERANCE`.
+fn on_segment(start: (f32, f32), end: (f32, f32), (x, y): (f32, f32)) -> bool {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let squared = dx * dx + dy * dy;
+    let t = if squared > 0.0 {
+        (((x - start.0) * dx + (y - start.1) * dy) / squared).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+
+    let (px, py) = (start.0 + t * dx - x, start.1 + t * dy - y);
+    px * px + py * py <= COORDINATE_TOLERANCE * COORDINATE_TOLERANCE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQUARE: [(f32, f32); 4] = [(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)];
+
+    #[test]
+    fn inside_and_outside() {
+        assert!(contains(&SQUARE, (1.0, 1.0)));
+        assert!(contains(&SQUARE, (0.5, 1.9)));
+        assert!(!contains(&SQUARE, (3.0, 1.0)));
+        assert!(!contains(&SQUARE, (-0.5, 1.0)));
+        assert!(!contains(&SQUARE, (1.0, 2.5)));
+    }
+
+    #[test]
+    fn boundary_inside() {
+        for point in [
+            (0.0, 1.0),
+            (2.0, 1.0),
+            (1.0, 0.0),
+            (1.0, 2.0),
+            (0.0, 0.0),
+            (2.0, 2.0),
+            (2.00005, 1.0),
+        ] {
+            assert!(contains(&SQUARE, point), "{:?}", point);
+        }
+        assert!(!contains(&SQUARE, (2.001, 1.0)));
+    }
+
+    #[test]
+    fn concave_polygon() {
+        // U-shaped polygon open at the top between x = 1 and x = 2
+        let polygon = [
+            (0.0, 0.0),
+            (3.0, 0.0),
+            (3.0, 3.0),
+            (2.0, 3.0),
+            (2.0, 1.0),
+            (1.0, 1.0),
+            (1.0, 3.0),
+            (0.0, 3.0),
+        ];
+        assert!(contains(&polygon, (0.5, 2.5)));
+        assert!(contains(&polygon, (2.5, 2.5)));
+        assert!(contains(&polygon, (1.5, 0.5)));
+        assert!(contains(&polygon, (1.5, 1.0)));
+        assert!(!contains(&polygon, (1.5, 2.0)));
+    }
+
+    #[test]
+    fn orientation_independent() {
+        let reversed = SQUARE.iter().rev().copied().collect::<Vec<_>>();
+        for point in [(1.0, 1.0), (0.0, 1.0), (2.0, 2.0), (3.0, 1.0)] {
+            assert_eq!(contains(&SQUARE, point), contains(&reversed, point));
+        }
+    }
+}
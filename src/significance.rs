@@ -0,0 +1,211 @@
+//! Statistical comparison of the thickness maps of two analyses.
+
+use crate::grids::{ThicknessGrids, ThicknessMethod};
+
+/// Maximal number of iterations of the continued fraction of the incomplete beta function.
+const MAX_ITERATIONS: usize = 200;
+/// Relative precision of the continued fraction of the incomplete beta function.
+const EPSILON: f64 = 1e-12;
+
+/// Thickness of a grid bin and the sampled means it is calculated from.
+#[derive(Debug, Clone)]
+pub(crate) struct Estimate {
+    thickness: f64,
+    /// Sample variance and number of samples of every mean contributing to the thickness.
+    terms: Vec<(f64, usize)>,
+}
+
+/// Calculate the thickness of each grid bin together with the variances of the means it is calculated from
+/// in the order of the grid maps. The samples of both leaflets are independent, except for the Luzzati thickness,
+/// where each sample contributes half of the same thickness to both leaflets.
+/// Estimates are `None` for bins with fewer than `nan_limit` samples in any of the leaflets.
+pub(crate) fn estimates(grids: &ThicknessGrids, nan_limit: usize) -> Vec<Option<Estimate>> {
+    // unbiased variance of the samples from their standard deviation
+    let variance = |deviation: f64, n: usize| deviation * deviation * n as f64 / (n as f64 - 1.0);
+
+    grids
+        .thickness(nan_limit)
+        .zip(grids.leaflet_deviations(nan_limit))
+        .zip(grids.upper_count.extract_raw())
+        .zip(grids.lower_count.extract_raw())
+        .map(
+            |((((_, _, thickness), (_, _, upper, lower)), (_, _, &n_upper)), (_, _, &n_lower))| {
+                if !thickness.is_finite() || n_upper < 2 || n_lower < 2 {
+                    return None;
+                }
+
+                let terms = match grids.method {
+                    ThicknessMethod::Luzzati => vec![(variance(upper + lower, n_upper), n_upper)],
+                    _ => vec![
+                        (variance(upper, n_upper), n_upper),
+                        (variance(lower, n_lower), n_lower),
+                    ],
+                };
+
+                Some(Estimate { thickness, terms })
+            },
+        )
+        .collect()
+}
+
+/// Compare the thickness of a bin in two analyses using Welch's t-test, assuming independent samples.
+/// Returns the two-sided p-value of the null hypothesis that the thickness is the same in both analyses.
+/// The p-value is NaN if none of the analyses shows any fluctuations of the thickness.
+pub(crate) fn welch_test(a: &Estimate, b: &Estimate) -> f64 {
+    let terms = a.terms.iter().chain(b.terms.iter());
+    let error = terms
+        .clone()
+        .map(|&(variance, n)| variance / n as f64)
+        .sum::<f64>();
+    if error <= 0.0 {
+        return f64::NAN;
+    }
+
+    // Welch-Satterthwaite approximation of the degrees of freedom
+    let dof = error * error
+        / terms
+            .map(|&(variance, n)| (variance / n as f64).powi(2) / (n as f64 - 1.0))
+            .sum::<f64>();
+
+    let t = (a.thickness - b.thickness) / error.sqrt();
+    incomplete_beta(dof / (dof + t * t), dof / 2.0, 0.5)
+}
+
+/// Regularized incomplete beta function I_x(a, b).
+fn incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+
+    if x >= 1.0 {
+        return 1.0;
+    }
+
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+
+    // the continued fraction converges quickly only below this point, use the symmetry relation above it
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_fraction(x, a, b) / a
+    } else {
+        1.0 - front * beta_fraction(1.0 - x, b, a) / b
+    }
+}
+
+/// Evaluate the continued fraction of the incomplete beta function using the modified Lentz's method.
+fn beta_fraction(x: f64, a: f64, b: f64) -> f64 {
+    let tiny = f64::MIN_POSITIVE / EPSILON;
+    let clamp = |value: f64| if value.abs() < tiny { tiny } else { value };
+
+    let mut c = 1.0;
+    let mut d = 1.0 / clamp(1.0 - (a + b) * x / (a + 1.0));
+    let mut fraction = d;
+
+    for m in 1..=MAX_ITERATIONS {
+        let m = m as f64;
+
+        // even step
+        let numerator = m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m));
+        d = 1.0 / clamp(1.0 + numerator * d);
+        c = clamp(1.0 + numerator / c);
+        fraction *= d * c;
+
+        // odd step
+        let numerator = -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0));
+        d = 1.0 / clamp(1.0 + numerator * d);
+        c = clamp(1.0 + numerator / c);
+        let delta = d * c;
+        fraction *= delta;
+
+        if (delta - 1.0).abs() < EPSILON {
+            break;
+        }
+    }
+
+    fraction
+}
+
+/// Natural logarithm of the gamma function for positive arguments (Lanczos approximation).
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let series = COEFFICIENTS
+        .iter()
+        .enumerate()
+        .fold(1.000000000190015, |sum, (i, coefficient)| {
+            sum + coefficient / (x + 1.0 + i as f64)
+        });
+
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn estimate(thickness: f64, terms: Vec<(f64, usize)>) -> Estimate {
+        Estimate { thickness, terms }
+    }
+
+    #[test]
+    fn ln_gamma_known_values() {
+        assert!(ln_gamma(1.0).abs() < 1e-10);
+        assert!((ln_gamma(5.0) - 24.0f64.ln()).abs() < 1e-10);
+        assert!((ln_gamma(0.5) - std::f64::consts::PI.sqrt().ln()).abs() < 1e-10);
+    }
+
+    #[test]
+    fn incomplete_beta_known_values() {
+        assert_eq!(incomplete_beta(0.0, 2.0, 3.0), 0.0);
+        assert_eq!(incomplete_beta(1.0, 2.0, 3.0), 1.0);
+
+        for &x in &[0.1, 0.5, 0.9] {
+            // I_x(1, 1) = x and I_x(a, 1) = x^a
+            assert!((incomplete_beta(x, 1.0, 1.0) - x).abs() < 1e-10);
+            assert!((incomplete_beta(x, 3.0, 1.0) - x.powi(3)).abs() < 1e-10);
+        }
+
+        // symmetric distribution
+        assert!((incomplete_beta(0.5, 4.5, 4.5) - 0.5).abs() < 1e-10);
+    }
+
+    #[test]
+    fn welch_test_one_degree_of_freedom() {
+        // error = 1, dof = 1, t = 1; the two-sided p-value of the Cauchy distribution is 0.5
+        let a = estimate(4.0, vec![(2.0, 2)]);
+        let b = estimate(3.0, vec![]);
+        assert!((welch_test(&a, &b) - 0.5).abs() < 1e-10);
+    }
+
+    #[test]
+    fn welch_test_two_degrees_of_freedom() {
+        // error = 2, dof = 2, t = sqrt(2); p = 1 - |t| / sqrt(2 + t^2) = 1 - 1 / sqrt(2)
+        let a = estimate(5.0, vec![(2.0, 2)]);
+        let b = estimate(3.0, vec![(2.0, 2)]);
+        let expected = 1.0 - 1.0 / 2.0f64.sqrt();
+        assert!((welch_test(&a, &b) - expected).abs() < 1e-10);
+        assert!((welch_test(&b, &a) - expected).abs() < 1e-10);
+    }
+
+    #[test]
+    fn welch_test_same_thickness() {
+        let a = estimate(4.0, vec![(0.3, 10), (0.2, 12)]);
+        let b = estimate(4.0, vec![(0.5, 8), (0.1, 20)]);
+        assert!((welch_test(&a, &b) - 1.0).abs() < 1e-10);
+    }
+
+    #[test]
+    fn welch_test_no_fluctuations() {
+        let a = estimate(4.0, vec![(0.0, 10)]);
+        let b = estimate(3.0, vec![(0.0, 10)]);
+        assert!(welch_test(&a, &b).is_nan());
+    }
+}
@@ -0,0 +1,130 @@
+//! Gaussian smoothing of the maps.
+
+/// Distance from the center of the Gaussian kernel (in multiples of its width) at which the kernel is truncated.
+pub(crate) const KERNEL_CUTOFF: f32 = 3.0;
+
+/// Smooth the map given as `(x, y, value)` for every bin of a grid with `n_x` x `n_y` bins of size `tile`
+/// in row-major order using a Gaussian filter of width `sigma` (in the units of the map coordinates).
+///
+/// Bins with undefined values are ignored when smoothing their neighbors and remain undefined,
+/// i.e., the smoothed value of each bin is the average of the defined values weighted by the Gaussian kernel.
+pub(crate) fn gaussian_filter(
+    values: &[(f32, f32, f64)],
+    n_x: usize,
+    n_y: usize,
+    tile: (f32, f32),
+    sigma: f32,
+) -> Vec<(f32, f32, f64)> {
+    let reach_x = (KERNEL_CUTOFF * sigma / tile.0).ceil() as isize;
+    let reach_y = (KERNEL_CUTOFF * sigma / tile.1).ceil() as isize;
+    let kernel = |di: isize, dj: isize| {
+        let (dx, dy) = (di as f32 * tile.0, dj as f32 * tile.1);
+        (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp() as f64
+    };
+
+    values
+        .iter()
+        .enumerate()
+        .map(|(index, &(x, y, value))| {
+            if !value.is_finite() {
+                return (x, y, value);
+            }
+
+            let (i, j) = ((index / n_y) as isize, (index % n_y) as isize);
+            let (mut sum, mut total) = (0.0, 0.0);
+            for di in -reach_x..=reach_x {
+                for dj in -reach_y..=reach_y {
+                    let (ni, nj) = (i + di, j + dj);
+                    if ni < 0 || nj < 0 || ni >= n_x as isize || nj >= n_y as isize {
+                        continue;
+                    }
+
+                    let neighbor = values[ni as usize * n_y + nj as usize].2;
+                    if neighbor.is_finite() {
+                        let weight = kernel(di, dj);
+                        sum += weight * neighbor;
+                        total += weight;
+                    }
+                }
+            }
+
+            (x, y, sum / total)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Map of `n_x` x `n_y` bins of 1 x 1 with the specified values of the bins `(i, j)`.
+    fn map(n_x: usize, n_y: usize, value: impl Fn(usize, usize) -> f64) -> Vec<(f32, f32, f64)> {
+        (0..n_x * n_y)
+            .map(|index| {
+                let (i, j) = (index / n_y, index % n_y);
+                (i as f32, j as f32, value(i, j))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn constant_map_unchanged() {
+        let mut values = map(5, 4, |_, _| 3.5);
+        values[6].2 = f64::NAN;
+
+        let smoothed = gaussian_filter(&values, 5, 4, (1.0, 1.0), 1.0);
+        for (index, &(x, y, value)) in smoothed.iter().enumerate() {
+            assert_eq!((x, y), (values[index].0, values[index].1));
+            if index == 6 {
+                assert!(value.is_nan());
+            } else {
+                assert!((value - 3.5).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn single_peak() {
+        let values = map(9, 9, |i, j| if (i, j) == (4, 4) { 1.0 } else { 0.0 });
+        let smoothed = gaussian_filter(&values, 9, 9, (1.0, 1.0), 1.0);
+
+        // the kernel is truncated at three widths, i.e., three bins
+        let row = (-3..=3)
+            .map(|d: i32| (-(d * d) as f64 / 2.0).exp())
+            .sum::<f64>();
+        assert!((smoothed[4 * 9 + 4].2 - 1.0 / (row * row)).abs() < 1e-6);
+        assert!((smoothed[4 * 9 + 5].2 - (-0.5f64).exp() / (row * row)).abs() < 1e-6);
+        assert_eq!(smoothed[8].2, 0.0);
+    }
+
+    #[test]
+    fn linear_map_preserved_inside() {
+        let values = map(9, 9, |i, j| i as f64 + 2.0 * j as f64);
+        let smoothed = gaussian_filter(&values, 9, 9, (1.0, 1.0), 1.0);
+        assert!((smoothed[4 * 9 + 4].2 - 12.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn edges_not_wrapped() {
+        // bins of the first row have no neighbors in the last row
+        let values = map(6, 3, |i, _| if i == 5 { 100.0 } else { 0.0 });
+        let smoothed = gaussian_filter(&values, 6, 3, (1.0, 1.0), 0.3);
+        assert!(smoothed[..3].iter().all(|&(_, _, value)| value == 0.0));
+        assert!(smoothed[4 * 3].2 > 0.0);
+    }
+
+    #[test]
+    fn undefined_neighbors_ignored() {
+        let values = map(3, 1, |i, _| match i {
+            0 => 1.0,
+            1 => f64::NAN,
+            _ => 5.0,
+        });
+        let smoothed = gaussian_filter(&values, 3, 1, (1.0, 1.0), 0.5);
+
+        // the neighbors at a distance of two bins have weight exp(-8)
+        let weight = (-8.0f64).exp();
+        assert!((smoothed[0].2 - (1.0 + 5.0 * weight) / (1.0 + weight)).abs() < 1e-6);
+        assert!(smoothed[1].2.is_nan());
+    }
+}
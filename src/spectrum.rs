@@ -0,0 +1,348 @@
+//! Undulation spectrum of the membrane midplane.
+
+use std::{
+    f64::consts::PI,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use groan_rs::prelude::*;
+
+use crate::normal::Normal;
+
+/// Maximal difference between the wave vector magnitudes (in nm^-1) of modes averaged together.
+const Q_TOLERANCE: f64 = 1e-3;
+
+/// Fourier spectrum of the instantaneous height of the membrane midplane accumulated over frames.
+#[derive(Debug, Clone)]
+pub(crate) struct Spectrum {
+    n_x: usize,
+    n_y: usize,
+    /// Weighted sums of the magnitudes of the wave vectors of all modes (in nm^-1).
+    q_sum: Vec<f64>,
+    /// Weighted sums of the squared Fourier amplitudes of all modes multiplied by the area of the membrane (in nm^4).
+    power_sum: Vec<f64>,
+    weight: f64,
+}
+
+impl Spectrum {
+    /// Prepare an empty spectrum for midplanes discretized into bins of approximately `bin_size` (in nm)
+    /// covering the membrane plane of the simulation box.
+    pub(crate) fn new(system: &System, normal: &Normal, bin_size: f32) -> anyhow::Result<Spectrum> {
+        let simbox = system
+            .get_box()
+            .ok_or_else(|| anyhow::anyhow!("Undulation spectrum requires a simulation box."))?;
+        let (box_x, box_y) = normal.lateral_box(simbox).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Undulation spectrum requires the membrane normal to be oriented along an axis of the simulation box."
+            )
+        })?;
+
+        let n_x = ((box_x / bin_size).round() as usize).max(1);
+        let n_y = ((box_y / bin_size).round() as usize).max(1);
+
+        Ok(Spectrum {
+            n_x,
+            n_y,
+            q_sum: vec![0.0; n_x * n_y],
+            power_sum: vec![0.0; n_x * n_y],
+            weight: 0.0,
+        })
+    }
+
+    /// Add the spectrum of the midplane of the frame. `upper` specifies the leaflet of each headgroup.
+    ///
+    /// The heights of the headgroups of each leaflet are averaged in the bins covering the membrane plane
+    /// and bins without any headgroups are assigned the average height of the leaflet.
+    /// The midplane is the mean of the heights of both leaflets.
+    pub(crate) fn add(
+        &mut self,
+        positions: &[Vector3D],
+        membrane_center: &Vector3D,
+        upper: &[Option<bool>],
+        normal: &Normal,
+        simbox: &SimBox,
+        weight: f64,
+    ) {
+        let Some((box_x, box_y)) = normal.lateral_box(simbox) else {
+            return;
+        };
+
+        let n_bins = self.n_x * self.n_y;
+        let mut sums = [vec![0.0; n_bins], vec![0.0; n_bins]];
+        let mut counts = [vec![0usize; n_bins], vec![0usize; n_bins]];
+        for (position, &is_upper) in positions.iter().zip(upper.iter()) {
+            let Some(is_upper) = is_upper else {
+                continue;
+            };
+
+            let (x, y) = normal.lateral(position);
+            let ix = ((x.rem_euclid(box_x) / box_x * self.n_x as f32) as usize).min(self.n_x - 1);
+            let iy = ((y.rem_euclid(box_y) / box_y * self.n_y as f32) as usize).min(self.n_y - 1);
+            let leaflet = if is_upper { 0 } else { 1 };
+            sums[leaflet][ix * self.n_y + iy] +=
+                normal.distance(position, membrane_center, simbox) as f64;
+            counts[leaflet][ix * self.n_y + iy] += 1;
+        }
+
+        let mut heights = vec![0.0; n_bins];
+        for (sums, counts) in sums.iter().zip(counts.iter()) {
+            let total = counts.iter().sum::<usize>();
+            if total == 0 {
+                return;
+            }
+
+            let average = sums.iter().sum::<f64>() / total as f64;
+            for (height, (&sum, &count)) in heights.iter_mut().zip(sums.iter().zip(counts.iter())) {
+                *height += if count > 0 {
+                    sum / count as f64
+                } else {
+                    average
+                } / 2.0;
+            }
+        }
+
+        let amplitudes = fourier_transform(&heights, self.n_x, self.n_y);
+        let area = box_x as f64 * box_y as f64;
+        for nx in 0..self.n_x {
+            for ny in 0..self.n_y {
+                let index = nx * self.n_y + ny;
+                let qx = 2.0 * PI * frequency(nx, self.n_x) / box_x as f64;
+                let qy = 2.0 * PI * frequency(ny, self.n_y) / box_y as f64;
+                let (re, im) = amplitudes[index];
+
+                self.q_sum[index] += weight * qx.hypot(qy);
+                self.power_sum[index] += weight * area * (re * re + im * im);
+            }
+        }
+
+        self.weight += weight;
+    }
+
+    /// Remove all accumulated frames.
+    pub(crate) fn clear(&mut self) {
+        self.q_sum.fill(0.0);
+        self.power_sum.fill(0.0);
+        self.weight = 0.0;
+    }
+
+    /// Add the frames accumulated in the other spectrum into this spectrum.
+    pub(crate) fn merge(&mut self, other: &Spectrum) {
+        for (value, other) in self.q_sum.iter_mut().zip(other.q_sum.iter()) {
+            *value += other;
+        }
+
+        for (value, other) in self.power_sum.iter_mut().zip(other.power_sum.iter()) {
+            *value += other;
+        }
+
+        self.weight += other.weight;
+    }
+
+    /// Write the radially averaged spectrum into a file. Modes with the same magnitude of the wave vector
+    /// are averaged together. The mode with zero wave vector is not written.
+    pub(crate) fn write(
+        &self,
+        filename: impl AsRef<Path>,
+        raw_arguments: &[String],
+    ) -> anyhow::Result<()> {
+        let filename = filename.as_ref();
+        let file = File::create(filename)
+            .with_context(|| format!("Could not create spectrum file '{}'.", filename.display()))?;
+        let mut output = BufWriter::new(file);
+
+        writeln!(
+            &mut output,
+            "# Generated with memthick v{}.",
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(&mut output, "# Command line: {}", raw_arguments.join(" "))?;
+        writeln!(
+            &mut output,
+            "# Radially averaged undulation spectrum of the membrane midplane. For a tensionless membrane, A<|h(q)|^2> = kT / (kappa q^4)."
+        )?;
+        writeln!(&mut output, "# q [nm^-1] A<|h(q)|^2> [nm^4] modes")?;
+
+        let mut modes = self
+            .q_sum
+            .iter()
+            .zip(self.power_sum.iter())
+            .skip(1)
+            .map(|(q, power)| (q / self.weight, power / self.weight))
+            .collect::<Vec<_>>();
+        modes.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut start = 0;
+        while start < modes.len() {
+            let end = modes[start..]
+                .iter()
+                .position(|&(q, _)| q - modes[start].0 > Q_TOLERANCE)
+                .map(|offset| start + offset)
+                .unwrap_or(modes.len());
+
+            let group = &modes[start..end];
+            let n_modes = group.len() as f64;
+            let q = group.iter().map(|&(q, _)| q).sum::<f64>() / n_modes;
+            let power = group.iter().map(|&(_, power)| power).sum::<f64>() / n_modes;
+            writeln!(&mut output, "{:12.6} {:16.8e} {:8}", q, power, group.len())?;
+
+            start = end;
+        }
+
+        output.flush()?;
+        Ok(())
+    }
+}
+
+/// Signed frequency of the Fourier mode with the specified index for a transform of `n` values.
+fn frequency(index: usize, n: usize) -> f64 {
+    if index <= n / 2 {
+        index as f64
+    } else {
+        index as f64 - n as f64
+    }
+}
+
+/// Calculate the normalized two-dimensional discrete Fourier transform of the values given in row-major order.
+/// Returns the complex amplitudes `(re, im)` of all modes in the same order.
+fn fourier_transform(values: &[f64], n_x: usize, n_y: usize) -> Vec<(f64, f64)> {
+    let transform = |input: &[(f64, f64)]| {
+        let n = input.len();
+        (0..n)
+            .map(|k| {
+                input
+                    .iter()
+                    .enumerate()
+                    .fold((0.0, 0.0), |(re, im), (j, &(value_re, value_im))| {
+                        let (sin, cos) = (-2.0 * PI * (k * j % n) as f64 / n as f64).sin_cos();
+                        (
+                            re + value_re * cos - value_im * sin,
+                            im + value_re * sin + value_im * cos,
+                        )
+                    })
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // transform along y for every x, then along x for every y
+    let mut amplitudes = values.iter().map(|&value| (value, 0.0)).collect::<Vec<_>>();
+    for row in amplitudes.chunks_mut(n_y) {
+        row.copy_from_slice(&transform(row));
+    }
+
+    for iy in 0..n_y {
+        let column = (0..n_x)
+            .map(|ix| amplitudes[ix * n_y + iy])
+            .collect::<Vec<_>>();
+        for (ix, value) in transform(&column).into_iter().enumerate() {
+            amplitudes[ix * n_y + iy] = value;
+        }
+    }
+
+    let n_values = (n_x * n_y) as f64;
+    amplitudes
+        .into_iter()
+        .map(|(re, im)| (re / n_values, im / n_values))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frequencies() {
+        let even = (0..6).map(|index| frequency(index, 6)).collect::<Vec<_>>();
+        assert_eq!(even, [0.0, 1.0, 2.0, 3.0, -2.0, -1.0]);
+
+        let odd = (0..5).map(|index| frequency(index, 5)).collect::<Vec<_>>();
+        assert_eq!(odd, [0.0, 1.0, 2.0, -2.0, -1.0]);
+    }
+
+    #[test]
+    fn fourier_transform_constant() {
+        let amplitudes = fourier_transform(&[1.5; 12], 4, 3);
+        assert!((amplitudes[0].0 - 1.5).abs() < 1e-12 && amplitudes[0].1.abs() < 1e-12);
+        assert!(
+            amplitudes[1..]
+                .iter()
+                .all(|&(re, im)| re.abs() < 1e-12 && im.abs() < 1e-12)
+        );
+    }
+
+    #[test]
+    fn fourier_transform_sine() {
+        // sin(2 pi 2 x / 8) has the amplitudes -i/2 and i/2 at the frequencies 2 and -2 along x
+        let (n_x, n_y) = (8, 4);
+        let values = (0..n_x * n_y)
+            .map(|index| (2.0 * PI * 2.0 * (index / n_y) as f64 / n_x as f64).sin())
+            .collect::<Vec<_>>();
+
+        let amplitudes = fourier_transform(&values, n_x, n_y);
+        for (index, &(re, im)) in amplitudes.iter().enumerate() {
+            let expected = match index {
+                index if index == 2 * n_y => -0.5,
+                index if index == 6 * n_y => 0.5,
+                _ => 0.0,
+            };
+            assert!(re.abs() < 1e-12 && (im - expected).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn spectrum_single_mode() {
+        // midplane h(x) = A sin(2 pi x / 8) in a box of 8 x 4 nm discretized into bins of 1 nm
+        let simbox = SimBox::from([8.0, 4.0, 10.0]);
+        let amplitude = 0.5;
+        let mut spectrum = Spectrum {
+            n_x: 8,
+            n_y: 4,
+            q_sum: vec![0.0; 32],
+            power_sum: vec![0.0; 32],
+            weight: 0.0,
+        };
+
+        let mut positions = Vec::new();
+        let mut upper = Vec::new();
+        for ix in 0..8 {
+            for iy in 0..4 {
+                let (x, y) = (ix as f32 + 0.5, iy as f32 + 0.5);
+                let height = amplitude * (2.0 * std::f32::consts::PI * x / 8.0).sin();
+                positions.push(Vector3D::new(x, y, 5.0 + height + 2.0));
+                positions.push(Vector3D::new(x, y, 5.0 + height - 2.0));
+                upper.extend([Some(true), Some(false)]);
+            }
+        }
+
+        let center = Vector3D::new(4.0, 2.0, 5.0);
+        spectrum.add(
+            &positions,
+            &center,
+            &upper,
+            &Normal::default(),
+            &simbox,
+            1.0,
+        );
+
+        let peak = spectrum
+            .power_sum
+            .iter()
+            .enumerate()
+            .skip(1)
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(index, _)| index)
+            .unwrap();
+        assert!(peak == 4 || peak == 7 * 4);
+        assert!((spectrum.q_sum[peak] - 2.0 * PI / 8.0).abs() < 1e-6);
+
+        // area * |A / 2|^2 in both modes with frequency 1 and -1 along x
+        let expected = 32.0 * (amplitude as f64 / 2.0).powi(2);
+        assert!((spectrum.power_sum[4] - expected).abs() < 1e-4);
+        assert!((spectrum.power_sum[7 * 4] - expected).abs() < 1e-4);
+
+        let total = spectrum.power_sum.iter().sum::<f64>();
+        assert!((total - 2.0 * expected).abs() < 1e-4);
+    }
+}
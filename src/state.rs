@@ -0,0 +1,478 @@
+//! Reading and writing the state of the analysis, i.e., the raw accumulated grid maps.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use groan_rs::{prelude::GridMap, structures::gridmap::DataOrder};
+
+use crate::{
+    geometry::Geometry,
+    grids::{Sample, ThicknessGrids, ThicknessMethod},
+    normal::Normal,
+    region::Region,
+};
+
+/// Version of the format of the state files. Only state files of the same version can be read.
+const STATE_VERSION: u32 = 1;
+
+impl ThicknessGrids {
+    /// Write the raw accumulated grid maps into a state file.
+    ///
+    /// The state is first written into a temporary file which is then renamed
+    /// so that an interrupted write never corrupts an existing state file.
+    pub(crate) fn write_state(
+        &self,
+        filename: impl AsRef<Path>,
+        raw_arguments: &[String],
+    ) -> anyhow::Result<()> {
+        let filename = filename.as_ref();
+        let mut temporary = filename.as_os_str().to_owned();
+        temporary.push(".tmp");
+
+        let file = File::create(&temporary)
+            .with_context(|| format!("Could not create state file '{}'.", filename.display()))?;
+        let mut output = BufWriter::new(file);
+
+        writeln!(
+            &mut output,
+            "# State file generated with memthick v{}.",
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(&mut output, "# Command line: {}", raw_arguments.join(" "))?;
+        writeln!(&mut output, "@ version {}", STATE_VERSION)?;
+
+        let span_x = self.upper_sum.span_x();
+        let span_y = self.upper_sum.span_y();
+        let tile = self.upper_sum.tile_dim();
+
+        writeln!(&mut output, "@ frames {}", self.n_frames)?;
+        writeln!(&mut output, "@ time {}", self.last_time)?;
+        writeln!(&mut output, "@ xrange {} {}", span_x.0, span_x.1)?;
+        writeln!(&mut output, "@ yrange {} {}", span_y.0, span_y.1)?;
+        writeln!(&mut output, "@ bin {} {}", tile.0, tile.1)?;
+        writeln!(&mut output, "@ normal {}", self.normal)?;
+        writeln!(&mut output, "@ geometry {}", self.geometry)?;
+        writeln!(&mut output, "@ scaled {}", self.scaled)?;
+        writeln!(&mut output, "@ method {}", self.method)?;
+
+        // settings of the analysis affecting the accumulated samples
+        let optional =
+            |value: Option<f32>| value.map_or(String::from("none"), |value| value.to_string());
+        writeln!(&mut output, "@ kernel {}", optional(self.kernel))?;
+        writeln!(&mut output, "@ kde-bandwidth {}", self.kde_bandwidth)?;
+        writeln!(&mut output, "@ pair-cutoff {}", self.pair_cutoff)?;
+        writeln!(
+            &mut output,
+            "@ exclude-shell {}",
+            optional(self.exclude_shell)
+        )?;
+        writeln!(
+            &mut output,
+            "@ region {}",
+            self.region
+                .map_or(String::from("none"), |region| region.to_string())
+        )?;
+        writeln!(&mut output, "@ buckle-modes {}", self.buckle_modes)?;
+        writeln!(&mut output, "@ fit-plane {}", self.fit_plane)?;
+        writeln!(&mut output, "@ center-of-mass {}", self.center_of_mass)?;
+        writeln!(&mut output, "@ heads {}", self.heads)?;
+        writeln!(&mut output, "@ leaflets {}", self.leaflets)?;
+        writeln!(
+            &mut output,
+            "# x y upper_sum upper_count lower_sum lower_count upper_weight lower_weight upper_squares lower_squares"
+        )?;
+
+        for (
+            (
+                (
+                    ((((upper_sum, upper_count), lower_sum), lower_count), upper_weight),
+                    lower_weight,
+                ),
+                upper_squares,
+            ),
+            lower_squares,
+        ) in self
+            .upper_sum
+            .extract_raw()
+            .zip(self.upper_count.extract_raw())
+            .zip(self.lower_sum.extract_raw())
+            .zip(self.lower_count.extract_raw())
+            .zip(self.upper_weight.extract_raw())
+            .zip(self.lower_weight.extract_raw())
+            .zip(self.upper_squares.extract_raw())
+            .zip(self.lower_squares.extract_raw())
+        {
+            writeln!(
+                &mut output,
+                "{} {} {} {} {} {} {} {} {} {}",
+                upper_sum.0,
+                upper_sum.1,
+                upper_sum.2,
+                upper_count.2,
+                lower_sum.2,
+                lower_count.2,
+                upper_weight.2,
+                lower_weight.2,
+                upper_squares.2,
+                lower_squares.2
+            )?;
+        }
+
+        output.flush()?;
+        drop(output);
+
+        std::fs::rename(&temporary, filename)
+            .with_context(|| format!("Could not write state file '{}'.", filename.display()))?;
+
+        Ok(())
+    }
+
+    /// Read the raw accumulated grid maps from a state file.
+    pub(crate) fn read_state(filename: impl AsRef<Path>) -> anyhow::Result<ThicknessGrids> {
+        let filename = filename.as_ref();
+        let file = File::open(filename)
+            .with_context(|| format!("Could not open state file '{}'.", filename.display()))?;
+
+        let mut n_frames = None;
+        let mut last_time = None;
+        let mut span_x = None;
+        let mut span_y = None;
+        let mut tile = None;
+        let mut version = None;
+        let mut normal = None;
+        let mut geometry = None;
+        let mut scaled = None;
+        let mut method = None;
+        let mut kernel = None;
+        let mut kde_bandwidth = None;
+        let mut pair_cutoff = None;
+        let mut exclude_shell = None;
+        let mut region = None;
+        let mut buckle_modes = None;
+        let mut fit_plane = None;
+        let mut center_of_mass = None;
+        let mut heads = None;
+        let mut leaflets = None;
+
+        let mut upper_sum = Vec::new();
+        let mut upper_count = Vec::new();
+        let mut lower_sum = Vec::new();
+        let mut lower_count = Vec::new();
+        let mut upper_weight = Vec::new();
+        let mut lower_weight = Vec::new();
+        let mut upper_squares = Vec::new();
+        let mut lower_squares = Vec::new();
+
+        let invalid = |line: &str| {
+            anyhow::anyhow!(
+                "Could not parse line '{}' in state file '{}'.",
+                line,
+                filename.display()
+            )
+        };
+
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('@') {
+                let mut split = header.split_whitespace();
+                let key = split.next().ok_or_else(|| invalid(line))?;
+
+                if key == "version" {
+                    let value = split.next().ok_or_else(|| invalid(line))?;
+                    let value = value.parse::<u32>().map_err(|_| invalid(line))?;
+                    if value != STATE_VERSION {
+                        anyhow::bail!(
+                            "State file '{}' has format version {}, but this version of memthick only reads version {}.",
+                            filename.display(),
+                            value,
+                            STATE_VERSION
+                        );
+                    }
+                    version = Some(value);
+                    continue;
+                }
+
+                // settings stored as text
+                let text = || split.clone().collect::<Vec<_>>().join(" ");
+                let optional = |text: String| match text.as_str() {
+                    "none" => Ok(None),
+                    value => value.parse::<f32>().map(Some).map_err(|_| invalid(line)),
+                };
+
+                match key {
+                    "kernel" => {
+                        kernel = Some(optional(text())?);
+                        continue;
+                    }
+                    "exclude-shell" => {
+                        exclude_shell = Some(optional(text())?);
+                        continue;
+                    }
+                    "region" => {
+                        region = Some(match text().as_str() {
+                            "none" => None,
+                            value => Some(value.parse::<Region>().map_err(|_| invalid(line))?),
+                        });
+                        continue;
+                    }
+                    "kde-bandwidth" => {
+                        kde_bandwidth = Some(text().parse::<f32>().map_err(|_| invalid(line))?);
+                        continue;
+                    }
+                    "pair-cutoff" => {
+                        pair_cutoff = Some(text().parse::<f32>().map_err(|_| invalid(line))?);
+                        continue;
+                    }
+                    "fit-plane" => {
+                        fit_plane = Some(text().parse::<bool>().map_err(|_| invalid(line))?);
+                        continue;
+                    }
+                    "center-of-mass" => {
+                        center_of_mass = Some(text().parse::<bool>().map_err(|_| invalid(line))?);
+                        continue;
+                    }
+                    "buckle-modes" => {
+                        buckle_modes = Some(text().parse::<usize>().map_err(|_| invalid(line))?);
+                        continue;
+                    }
+                    "heads" => {
+                        heads = Some(text());
+                        continue;
+                    }
+                    "leaflets" => {
+                        leaflets = Some(text());
+                        continue;
+                    }
+                    _ => (),
+                }
+
+                if key == "normal" {
+                    normal =
+                        Some(Normal::parse(&split.collect::<Vec<_>>()).map_err(|_| invalid(line))?);
+                    continue;
+                }
+
+                if key == "geometry" {
+                    let name = split.next().ok_or_else(|| invalid(line))?;
+                    geometry = Some(Geometry::parse(name).map_err(|_| invalid(line))?);
+                    continue;
+                }
+
+                if key == "scaled" {
+                    let value = split.next().ok_or_else(|| invalid(line))?;
+                    scaled = Some(value.parse::<bool>().map_err(|_| invalid(line))?);
+                    continue;
+                }
+
+                if key == "method" {
+                    let name = split.next().ok_or_else(|| invalid(line))?;
+                    method = Some(ThicknessMethod::parse(name).map_err(|_| invalid(line))?);
+                    continue;
+                }
+
+                let values = split
+                    .map(|x| x.parse::<f64>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| invalid(line))?;
+
+                match (key, values.as_slice()) {
+                    ("frames", [n]) => n_frames = Some(*n as usize),
+                    ("time", [t]) => last_time = Some(*t as f32),
+                    ("xrange", [min, max]) => span_x = Some((*min as f32, *max as f32)),
+                    ("yrange", [min, max]) => span_y = Some((*min as f32, *max as f32)),
+                    ("bin", [x, y]) => tile = Some((*x as f32, *y as f32)),
+                    _ => return Err(invalid(line)),
+                }
+
+                continue;
+            }
+
+            let values = line.split_whitespace().collect::<Vec<_>>();
+            if values.len() != 10 {
+                return Err(invalid(line));
+            }
+
+            let float = |index: usize| values[index].parse::<f64>().map_err(|_| invalid(line));
+            let count = |index: usize| values[index].parse::<usize>().map_err(|_| invalid(line));
+
+            upper_sum.push(float(2)?);
+            upper_count.push(count(3)?);
+            lower_sum.push(float(4)?);
+            lower_count.push(count(5)?);
+            upper_weight.push(float(6)?);
+            lower_weight.push(float(7)?);
+            upper_squares.push(float(8)?);
+            lower_squares.push(float(9)?);
+        }
+
+        let missing = |what: &str| {
+            anyhow::anyhow!(
+                "State file '{}' does not specify {}.",
+                filename.display(),
+                what
+            )
+        };
+
+        version.ok_or_else(|| missing("the version of its format"))?;
+        let n_frames = n_frames.ok_or_else(|| missing("the number of frames"))?;
+        let last_time = last_time.ok_or_else(|| missing("the time of the last frame"))?;
+        let span_x = span_x.ok_or_else(|| missing("the x-range of the grid"))?;
+        let span_y = span_y.ok_or_else(|| missing("the y-range of the grid"))?;
+        let tile = tile.ok_or_else(|| missing("the size of the grid bin"))?;
+        let normal = normal.ok_or_else(|| missing("the membrane normal"))?;
+        let geometry = geometry.ok_or_else(|| missing("the membrane geometry"))?;
+        let scaled = scaled.ok_or_else(|| missing("whether the grid coordinates are scaled"))?;
+        let method = method.ok_or_else(|| missing("the thickness method"))?;
+        let kernel = kernel.ok_or_else(|| missing("the deposition kernel"))?;
+        let kde_bandwidth = kde_bandwidth.ok_or_else(|| missing("the kernel bandwidth"))?;
+        let pair_cutoff = pair_cutoff.ok_or_else(|| missing("the pairing cutoff"))?;
+        let exclude_shell = exclude_shell.ok_or_else(|| missing("the excluded shell"))?;
+        let region = region.ok_or_else(|| missing("the analyzed region"))?;
+        let buckle_modes = buckle_modes.ok_or_else(|| missing("the number of buckle modes"))?;
+        let fit_plane = fit_plane.ok_or_else(|| missing("whether the plane was fitted"))?;
+        let center_of_mass =
+            center_of_mass.ok_or_else(|| missing("the definition of the membrane center"))?;
+        let heads = heads.ok_or_else(|| missing("the selection of the headgroups"))?;
+        let leaflets = leaflets.ok_or_else(|| missing("the assignment of the leaflets"))?;
+
+        Ok(ThicknessGrids {
+            upper_sum: GridMap::from_vec(
+                span_x,
+                span_y,
+                tile,
+                upper_sum,
+                DataOrder::RowMajor,
+                f64::clone as fn(&f64) -> f64,
+            )?,
+            upper_count: GridMap::from_vec(
+                span_x,
+                span_y,
+                tile,
+                upper_count,
+                DataOrder::RowMajor,
+                usize::clone as fn(&usize) -> usize,
+            )?,
+            upper_weight: GridMap::from_vec(
+                span_x,
+                span_y,
+                tile,
+                upper_weight,
+                DataOrder::RowMajor,
+                f64::clone as fn(&f64) -> f64,
+            )?,
+            upper_squares: GridMap::from_vec(
+                span_x,
+                span_y,
+                tile,
+                upper_squares,
+                DataOrder::RowMajor,
+                f64::clone as fn(&f64) -> f64,
+            )?,
+            lower_sum: GridMap::from_vec(
+                span_x,
+                span_y,
+                tile,
+                lower_sum,
+                DataOrder::RowMajor,
+                f64::clone as fn(&f64) -> f64,
+            )?,
+            lower_count: GridMap::from_vec(
+                span_x,
+                span_y,
+                tile,
+                lower_count,
+                DataOrder::RowMajor,
+                usize::clone as fn(&usize) -> usize,
+            )?,
+            lower_weight: GridMap::from_vec(
+                span_x,
+                span_y,
+                tile,
+                lower_weight,
+                DataOrder::RowMajor,
+                f64::clone as fn(&f64) -> f64,
+            )?,
+            lower_squares: GridMap::from_vec(
+                span_x,
+                span_y,
+                tile,
+                lower_squares,
+                DataOrder::RowMajor,
+                f64::clone as fn(&f64) -> f64,
+            )?,
+            n_frames,
+            last_time,
+            normal,
+            geometry,
+            method,
+            pair_cutoff,
+            kde_bandwidth,
+            exclude_shell,
+            region,
+            buckle_modes,
+            fit_plane,
+            scaled,
+            origin: None,
+            center_of_mass,
+            heads,
+            leaflets,
+            secondary: Vec::new(),
+            atoms: None,
+            quantity: "membrane thickness",
+            sample: Sample::default(),
+            kernel,
+            composition: None,
+            environment: None,
+            molecules: None,
+            distributions: None,
+            spectrum: None,
+            profiles: Vec::new(),
+            reference: None,
+            roi: None,
+            deformation: None,
+            extremes: None,
+            series: None,
+            bin_series: None,
+            footprint: None,
+            tilt: None,
+            residues: None,
+        })
+    }
+
+    /// Check that the grid maps span the same area with the same bin size
+    /// and the same membrane normal, geometry, coordinates, and thickness definition as the other grid maps.
+    pub(crate) fn is_compatible(&self, other: &ThicknessGrids) -> bool {
+        self.normal.matches(&other.normal)
+            && self.geometry == other.geometry
+            && self.scaled == other.scaled
+            && self.method == other.method
+            && self.upper_sum.span_x() == other.upper_sum.span_x()
+            && self.upper_sum.span_y() == other.upper_sum.span_y()
+            && self.upper_sum.tile_dim() == other.upper_sum.tile_dim()
+    }
+
+    /// Check that the samples of the grid maps were accumulated using the same settings of the analysis
+    /// (headgroups, leaflet assignment, and the calculation of the samples) as the samples of the other grid maps,
+    /// so that the grid maps can be combined.
+    pub(crate) fn has_same_settings(&self, other: &ThicknessGrids) -> bool {
+        self.kernel == other.kernel
+            && self.kde_bandwidth == other.kde_bandwidth
+            && self.pair_cutoff == other.pair_cutoff
+            && self.exclude_shell == other.exclude_shell
+            && self.region == other.region
+            && self.buckle_modes == other.buckle_modes
+            && self.fit_plane == other.fit_plane
+            && self.center_of_mass == other.center_of_mass
+            && self.heads == other.heads
+            && self.leaflets == other.leaflets
+    }
+}
@@ -0,0 +1,161 @@
+//! Reading xtc trajectories streamed through the standard input.
+
+use std::{
+    io::{BufReader, ErrorKind, Stdin},
+    marker::PhantomData,
+    path::Path,
+};
+
+use groan_rs::{errors::ReadTrajError, prelude::*};
+use molly::{Frame, XTCReader};
+
+/// Trajectory "file name" requesting the trajectory to be read from the standard input.
+pub(crate) const STDIN: &str = "-";
+
+/// Precision used when searching for the start of the time range (same as in `groan_rs`).
+const TIME_PRECISION: f32 = 0.001;
+
+/// Xtc trajectory read from the standard input.
+///
+/// The standard input cannot be seeked, so frames are never jumped over,
+/// they are always fully read and possibly discarded.
+pub(crate) struct XtcStream {
+    reader: XTCReader<BufReader<Stdin>>,
+    /// Number of atoms in the system.
+    n_atoms: usize,
+    /// Frame that has already been read from the stream while searching for
+    /// the start of the time range, but has not been provided to the iterator yet.
+    pending: Option<Frame>,
+}
+
+impl TrajFile for XtcStream {}
+
+impl XtcStream {
+    /// Read the next frame from the stream. Returns `None` if the stream has ended.
+    fn read_frame(&mut self) -> Option<Result<Frame, ReadTrajError>> {
+        if let Some(frame) = self.pending.take() {
+            return Some(Ok(frame));
+        }
+
+        let mut frame = Frame::default();
+        match self.reader.read_frame(&mut frame) {
+            Ok(_) if frame.natoms() != self.n_atoms => Some(Err(
+                ReadTrajError::AtomsNumberMismatch(Box::from(Path::new(STDIN))),
+            )),
+            Ok(_) => Some(Ok(frame)),
+            // expecting that this is not an error but the end of the stream was just reached
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => None,
+            Err(e) => Some(Err(ReadTrajError::MollyXtcError(e.to_string()))),
+        }
+    }
+}
+
+/// Data of a single frame read from the xtc stream.
+pub(crate) struct XtcStreamFrameData {
+    frame: Frame,
+}
+
+impl FrameData for XtcStreamFrameData {
+    type TrajFile = XtcStream;
+
+    fn from_frame(stream: &mut XtcStream, _system: &System) -> Option<Result<Self, ReadTrajError>> {
+        stream
+            .read_frame()
+            .map(|frame| frame.map(|frame| XtcStreamFrameData { frame }))
+    }
+
+    fn update_system(self, system: &mut System) {
+        for (atom, pos) in system.atoms_iter_mut().zip(self.frame.coords()) {
+            atom.set_position(Vector3D::new(pos.x, pos.y, pos.z));
+            atom.reset_velocity();
+            atom.reset_force();
+        }
+
+        system.set_simulation_step(self.frame.step as u64);
+        system.set_simulation_time(self.frame.time);
+        let b = self.frame.boxvec;
+        system.set_box(
+            [
+                b.col(0).x,
+                b.col(1).y,
+                b.col(2).z,
+                b.col(0).y,
+                b.col(0).z,
+                b.col(1).x,
+                b.col(1).z,
+                b.col(2).x,
+                b.col(2).y,
+            ]
+            .into(),
+        );
+        system.set_precision(self.frame.precision as u64);
+    }
+}
+
+impl FrameDataTime for XtcStreamFrameData {
+    fn get_time(&self) -> f32 {
+        self.frame.time
+    }
+}
+
+/// Reader of an xtc trajectory streamed through the standard input.
+pub(crate) struct XtcStreamReader<'a> {
+    system: *mut System,
+    stream: XtcStream,
+    phantom: PhantomData<&'a mut System>,
+}
+
+impl<'a> XtcStreamReader<'a> {
+    /// Start reading an xtc trajectory from the standard input.
+    pub(crate) fn new(system: &'a mut System) -> TrajReader<'a, XtcStreamReader<'a>> {
+        let n_atoms = system.get_n_atoms();
+
+        TrajReader::wrap_traj(XtcStreamReader {
+            system: system as *mut System,
+            stream: XtcStream {
+                reader: XTCReader::new(BufReader::new(std::io::stdin())),
+                n_atoms,
+                pending: None,
+            },
+            phantom: PhantomData,
+        })
+    }
+}
+
+impl<'a> TrajRead<'a> for XtcStreamReader<'a> {
+    type FrameData = XtcStreamFrameData;
+
+    fn get_system(&mut self) -> *mut System {
+        self.system
+    }
+
+    fn get_file_handle(&mut self) -> &mut XtcStream {
+        &mut self.stream
+    }
+}
+
+impl<'a> TrajRangeRead<'a> for XtcStreamReader<'a> {
+    fn jump_to_start(&mut self, start_time: f32) -> Result<(), ReadTrajError> {
+        loop {
+            match self.stream.read_frame() {
+                None => return Err(ReadTrajError::StartNotFound(start_time.to_string())),
+                Some(Err(e)) => return Err(e),
+                Some(Ok(frame)) if frame.time >= start_time - TIME_PRECISION => {
+                    self.stream.pending = Some(frame);
+                    return Ok(());
+                }
+                Some(Ok(_)) => (),
+            }
+        }
+    }
+}
+
+impl<'a> TrajStepRead<'a> for XtcStreamReader<'a> {
+    fn skip_frame(&mut self) -> Result<bool, ReadTrajError> {
+        match self.stream.read_frame() {
+            None => Ok(false),
+            Some(Ok(_)) => Ok(true),
+            Some(Err(_)) => Err(ReadTrajError::SkipFailed),
+        }
+    }
+}
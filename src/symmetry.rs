@@ -0,0 +1,221 @@
+//! Symmetrization of the thickness map.
+
+use std::fmt::Display;
+
+use clap::ValueEnum;
+
+/// Symmetry imposed on the thickness map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Symmetry {
+    /// Mirror symmetry with respect to the line through the center parallel to the second map axis.
+    MirrorX,
+    /// Mirror symmetry with respect to the line through the center parallel to the first map axis.
+    MirrorY,
+    /// Rotational symmetry of the specified order around the center.
+    Rotation,
+}
+
+impl Display for Symmetry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Symmetry::MirrorX => write!(f, "mirror-x"),
+            Symmetry::MirrorY => write!(f, "mirror-y"),
+            Symmetry::Rotation => write!(f, "rotation"),
+        }
+    }
+}
+
+impl Symmetry {
+    /// Positions of all images of the point under the symmetry operations (including the point itself).
+    fn images(&self, (x, y): (f32, f32), center: (f32, f32), order: usize) -> Vec<(f32, f32)> {
+        match self {
+            Symmetry::MirrorX => vec![(x, y), (2.0 * center.0 - x, y)],
+            Symmetry::MirrorY => vec![(x, y), (x, 2.0 * center.1 - y)],
+            Symmetry::Rotation => (0..order)
+                .map(|k| {
+                    let (sin, cos) =
+                        (2.0 * std::f32::consts::PI * k as f32 / order as f32).sin_cos();
+                    let (dx, dy) = (x - center.0, y - center.1);
+                    (
+                        center.0 + cos * dx - sin * dy,
+                        center.1 + sin * dx + cos * dy,
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Symmetrize the map given as `(x, y, value)` for every bin of a grid with `n_x` x `n_y` bins of size `tile`
+/// in row-major order, with the first bin centered at `start`. The center of each bin is mapped by all operations
+/// of the symmetry around `center` and the bin is assigned the average of the defined values of the bins containing
+/// its images. Images outside the grid are ignored. Bins remain undefined only if none of their images is defined.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn symmetrize(
+    values: &[(f32, f32, f64)],
+    n_x: usize,
+    n_y: usize,
+    tile: (f32, f32),
+    start: (f32, f32),
+    symmetry: Symmetry,
+    order: usize,
+    center: (f32, f32),
+) -> Vec<(f32, f32, f64)> {
+    let bin = |(x, y): (f32, f32)| {
+        let (i, j) = (
+            ((x - start.0) / tile.0).round(),
+            ((y - start.1) / tile.1).round(),
+        );
+        (i >= 0.0 && j >= 0.0 && (i as usize) < n_x && (j as usize) < n_y)
+            .then(|| i as usize * n_y + j as usize)
+    };
+
+    values
+        .iter()
+        .map(|&(x, y, _)| {
+            let defined = symmetry
+                .images((x, y), center, order)
+                .into_iter()
+                .filter_map(bin)
+                .map(|index| values[index].2)
+                .filter(|value| value.is_finite())
+                .collect::<Vec<_>>();
+
+            (x, y, defined.iter().sum::<f64>() / defined.len() as f64)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Map of `n_x` x `n_y` bins of 1 x 1 with the specified values of the bins `(i, j)`.
+    fn map(n_x: usize, n_y: usize, value: impl Fn(usize, usize) -> f64) -> Vec<(f32, f32, f64)> {
+        (0..n_x * n_y)
+            .map(|index| {
+                let (i, j) = (index / n_y, index % n_y);
+                (i as f32, j as f32, value(i, j))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn constant_map_unchanged() {
+        let values = map(4, 3, |_, _| 1.5);
+        for symmetry in [Symmetry::MirrorX, Symmetry::MirrorY, Symmetry::Rotation] {
+            let symmetrized = symmetrize(
+                &values,
+                4,
+                3,
+                (1.0, 1.0),
+                (0.0, 0.0),
+                symmetry,
+                4,
+                (1.5, 1.0),
+            );
+            assert!(
+                symmetrized
+                    .iter()
+                    .all(|&(_, _, value)| (value - 1.5).abs() < 1e-12)
+            );
+        }
+    }
+
+    #[test]
+    fn mirror() {
+        let values = map(4, 3, |i, j| (10 * i + j) as f64);
+
+        let mirrored = symmetrize(
+            &values,
+            4,
+            3,
+            (1.0, 1.0),
+            (0.0, 0.0),
+            Symmetry::MirrorX,
+            1,
+            (1.5, 1.0),
+        );
+        assert_eq!(mirrored[0].2, (0.0 + 30.0) / 2.0);
+        assert_eq!(mirrored[3 * 3 + 2].2, (2.0 + 32.0) / 2.0);
+        assert_eq!(mirrored[3 + 1].2, (11.0 + 21.0) / 2.0);
+
+        let mirrored = symmetrize(
+            &values,
+            4,
+            3,
+            (1.0, 1.0),
+            (0.0, 0.0),
+            Symmetry::MirrorY,
+            1,
+            (1.5, 1.0),
+        );
+        assert_eq!(mirrored[0].2, (0.0 + 2.0) / 2.0);
+        assert_eq!(mirrored[3 + 1].2, 11.0);
+    }
+
+    #[test]
+    fn rotation() {
+        let values = map(3, 3, |i, j| (3 * i + j) as f64);
+        let rotated = symmetrize(
+            &values,
+            3,
+            3,
+            (1.0, 1.0),
+            (0.0, 0.0),
+            Symmetry::Rotation,
+            4,
+            (1.0, 1.0),
+        );
+
+        // corners and edges are averaged separately, the central bin is its own image
+        assert!((rotated[0].2 - (0.0 + 2.0 + 6.0 + 8.0) / 4.0).abs() < 1e-12);
+        assert!((rotated[1].2 - (1.0 + 3.0 + 5.0 + 7.0) / 4.0).abs() < 1e-12);
+        assert_eq!(rotated[4].2, 4.0);
+    }
+
+    #[test]
+    fn images_outside_ignored() {
+        let values = map(3, 1, |i, _| i as f64);
+
+        // the mirror images of the first two bins lie outside the grid
+        let mirrored = symmetrize(
+            &values,
+            3,
+            1,
+            (1.0, 1.0),
+            (0.0, 0.0),
+            Symmetry::MirrorX,
+            1,
+            (2.0, 0.0),
+        );
+        assert_eq!(
+            mirrored.iter().map(|x| x.2).collect::<Vec<_>>(),
+            vec![0.0, 1.0, 2.0]
+        );
+    }
+
+    #[test]
+    fn undefined_bins_filled_from_images() {
+        let values = map(2, 2, |i, j| match (i, j) {
+            (0, 0) => f64::NAN,
+            (0, 1) => f64::NAN,
+            (1, 0) => 3.0,
+            _ => f64::NAN,
+        });
+        let mirrored = symmetrize(
+            &values,
+            2,
+            2,
+            (1.0, 1.0),
+            (0.0, 0.0),
+            Symmetry::MirrorX,
+            1,
+            (0.5, 0.5),
+        );
+        assert_eq!(mirrored[0].2, 3.0);
+        assert_eq!(mirrored[2].2, 3.0);
+        assert!(mirrored[1].2.is_nan());
+        assert!(mirrored[3].2.is_nan());
+    }
+}
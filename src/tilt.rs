@@ -0,0 +1,80 @@
+//! Thickness maps conditioned on the direction of the tilt of a transmembrane helix.
+
+use groan_rs::{errors::SimBoxError, prelude::*};
+
+use crate::{HELIX_END_GROUP, HELIX_START_GROUP, grids::ThicknessGrids, normal::Normal};
+
+/// Thickness grids accumulated separately for the sectors of the azimuthal angle of the helix tilt.
+#[derive(Debug, Clone)]
+pub(crate) struct TiltMaps {
+    /// Grids of the individual sectors. The first sector is centered at the azimuthal angle of 0°.
+    sectors: Vec<ThicknessGrids>,
+}
+
+impl TiltMaps {
+    /// Prepare `n_sectors` empty grids with the same layout as the template.
+    pub(crate) fn new(template: &ThicknessGrids, n_sectors: usize) -> TiltMaps {
+        let mut empty = template.clone();
+        empty.clear();
+
+        TiltMaps {
+            sectors: vec![empty; n_sectors],
+        }
+    }
+
+    /// Get the grids accumulated for the individual sectors.
+    pub(crate) fn sectors(&self) -> &[ThicknessGrids] {
+        &self.sectors
+    }
+
+    /// Width of one sector (in degrees).
+    pub(crate) fn sector_width(&self) -> f32 {
+        360.0 / self.sectors.len() as f32
+    }
+
+    /// Calculate the azimuthal angle (0-360°) of the tilt of the helix in the membrane plane, i.e., the direction
+    /// of the helix axis pointing towards the upper leaflet projected onto the membrane plane. The helix axis
+    /// connects the centers of the atoms of the first and the second half of the helix.
+    fn azimuth(frame: &System, normal: &Normal) -> anyhow::Result<f32> {
+        let simbox = frame.get_box().ok_or(SimBoxError::DoesNotExist)?;
+        let start = frame.group_get_center(HELIX_START_GROUP)?;
+        let end = frame.group_get_center(HELIX_END_GROUP)?;
+
+        let axis = start.vector_to(&end, simbox);
+        let sign = if normal.axial(&axis) < 0.0 { -1.0 } else { 1.0 };
+        let (x, y) = normal.lateral(&axis);
+
+        Ok((sign * y).atan2(sign * x).to_degrees().rem_euclid(360.0))
+    }
+
+    /// Add positions of headgroups relative to the provided membrane center to the grids of the sector
+    /// containing the current tilt azimuth of the helix. See [`ThicknessGrids::add_heads`].
+    pub(crate) fn add_heads(
+        &mut self,
+        frame: &System,
+        normal: &Normal,
+        membrane_center: &Vector3D,
+        upper: &[Option<bool>],
+        weight: f64,
+    ) -> anyhow::Result<()> {
+        let azimuth = TiltMaps::azimuth(frame, normal)?;
+        let width = self.sector_width();
+        let sector = ((azimuth + width / 2.0) / width) as usize % self.sectors.len();
+
+        self.sectors[sector].add_heads(frame, membrane_center, upper, weight)
+    }
+
+    /// Remove all accumulated data from the grids of all sectors.
+    pub(crate) fn clear(&mut self) {
+        for sector in self.sectors.iter_mut() {
+            sector.clear();
+        }
+    }
+
+    /// Add the data accumulated in the other tilt maps into these tilt maps.
+    pub(crate) fn merge(&mut self, other: &TiltMaps) {
+        for (sector, other) in self.sectors.iter_mut().zip(other.sectors.iter()) {
+            sector.merge(other);
+        }
+    }
+}
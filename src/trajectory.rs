@@ -0,0 +1,276 @@
+//! Handling of input trajectory files.
+
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::Context;
+use groan_rs::prelude::*;
+
+/// Check whether the string contains any wildcard characters.
+pub(crate) fn is_pattern(string: &str) -> bool {
+    string.contains(['*', '?'])
+}
+
+/// Check whether the name matches the pattern. `*` matches any sequence of characters, `?` matches any single character.
+fn wildcard_match(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some('*'), _) => {
+            wildcard_match(&pattern[1..], name)
+                || (!name.is_empty() && wildcard_match(pattern, &name[1..]))
+        }
+        (Some('?'), Some(_)) => wildcard_match(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => wildcard_match(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+/// Get the simulation time of the first frame of a trajectory file.
+fn first_frame_time(system: &System, file: &str) -> anyhow::Result<f32> {
+    let mut system = system.clone();
+    let frame = match FileType::from_name(file) {
+        FileType::XTC => system.traj_iter::<XtcReader>(file)?.next(),
+        FileType::TRR => system.traj_iter::<TrrReader>(file)?.next(),
+        #[cfg(feature = "chemfiles")]
+        FileType::DCD | FileType::NC => system.traj_iter::<ChemfilesReader>(file)?.next(),
+        _ => anyhow::bail!("Unsupported format of the trajectory file '{}'.", file),
+    };
+
+    match frame {
+        None => anyhow::bail!("Trajectory file '{}' contains no frames.", file),
+        Some(frame) => Ok(frame?.get_simulation_time()),
+    }
+}
+
+/// Collect simulation times of all frames of a trajectory file without reading their coordinates.
+fn skip_frames<'a>(mut reader: impl TrajStepTimeRead<'a>) -> anyhow::Result<Vec<f32>> {
+    let mut times = Vec::new();
+    while let Some(time) = reader.skip_frame_time()? {
+        times.push(time);
+    }
+
+    Ok(times)
+}
+
+/// Get the simulation times of all frames of the trajectory files analyzed as one continuous trajectory.
+/// Frames are only skipped over, not read, so this is much faster than reading the trajectory.
+/// Returns `None` if the trajectory is read from the standard input and therefore cannot be pre-scanned.
+pub(crate) fn frame_times(system: &System, files: &[String]) -> anyhow::Result<Option<Vec<f32>>> {
+    let mut all_times: Vec<f32> = Vec::new();
+
+    for file in files {
+        if file == crate::stream::STDIN {
+            return Ok(None);
+        }
+
+        let mut system = system.clone();
+        let times = match FileType::from_name(file) {
+            FileType::XTC => skip_frames(XtcReader::new(&mut system, file)?)?,
+            FileType::TRR => skip_frames(TrrReader::new(&mut system, file)?)?,
+            #[cfg(feature = "chemfiles")]
+            FileType::DCD | FileType::NC => skip_frames(ChemfilesReader::new(&mut system, file)?)?,
+            _ => anyhow::bail!("Unsupported format of the trajectory file '{}'.", file),
+        };
+
+        // duplicate frames at the boundaries between trajectory files are not analyzed
+        let duplicate = match (all_times.last(), times.first()) {
+            (Some(last), Some(first)) => (first - last).abs() < 0.001,
+            _ => false,
+        };
+
+        all_times.extend(times.into_iter().skip(duplicate as usize));
+    }
+
+    Ok(Some(all_times))
+}
+
+/// Select the times of the frames that are analyzed for the specified time range and step.
+pub(crate) fn analyzed_times(times: &[f32], begin: f32, end: f32, step: usize) -> Vec<f32> {
+    times
+        .iter()
+        .copied()
+        .filter(|&time| time >= begin - 0.001 && time <= end)
+        .step_by(step)
+        .collect()
+}
+
+/// Expand all trajectory file patterns (e.g., `md.part*.xtc`) into matching files.
+/// Files matching a pattern are sorted by the simulation time of their first frame.
+/// Files specified without wildcards are kept in the specified order.
+pub(crate) fn expand_patterns(system: &System, files: &[String]) -> anyhow::Result<Vec<String>> {
+    let mut expanded = Vec::new();
+
+    for file in files {
+        if !is_pattern(file) {
+            expanded.push(file.clone());
+            continue;
+        }
+
+        let path = Path::new(file);
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+
+        if is_pattern(&directory.to_string_lossy()) {
+            anyhow::bail!(
+                "Wildcards are only supported in the file name, not in the directory ('{}').",
+                file
+            );
+        }
+
+        let pattern = path
+            .file_name()
+            .map(|x| x.to_string_lossy().chars().collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        let mut matching = Vec::new();
+        for entry in std::fs::read_dir(&directory)
+            .with_context(|| format!("Could not read directory '{}'.", directory.display()))?
+        {
+            let entry = entry?;
+            let name = entry
+                .file_name()
+                .to_string_lossy()
+                .chars()
+                .collect::<Vec<_>>();
+            if entry.path().is_file() && wildcard_match(&pattern, &name) {
+                let matched = match path.parent() {
+                    Some(parent) if !parent.as_os_str().is_empty() => entry.path(),
+                    _ => PathBuf::from(entry.file_name()),
+                };
+
+                matching.push(matched.to_string_lossy().into_owned());
+            }
+        }
+
+        if matching.is_empty() {
+            anyhow::bail!("No trajectory files match the pattern '{}'.", file);
+        }
+
+        let mut timed = matching
+            .into_iter()
+            .map(|file| first_frame_time(system, &file).map(|time| (time, file)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        timed.sort_by(|(t1, f1), (t2, f2)| t1.total_cmp(t2).then_with(|| f1.cmp(f2)));
+        expanded.extend(timed.into_iter().map(|(_, file)| file));
+    }
+
+    Ok(expanded)
+}
+
+/// Checks the continuity of simulation time across the analyzed frames.
+#[derive(Debug, Default)]
+pub(crate) struct TimeContinuity {
+    /// Simulation time of the previously analyzed frame.
+    previous: Option<f32>,
+    /// Expected time difference between consecutive analyzed frames.
+    expected_dt: Option<f32>,
+    /// Discontinuities found in the trajectory as (previous time, current time).
+    discontinuities: Vec<(f32, f32)>,
+}
+
+impl TimeContinuity {
+    /// Record the time of the next analyzed frame.
+    pub(crate) fn update(&mut self, time: f32) {
+        if let Some(previous) = self.previous {
+            let dt = time - previous;
+            match self.expected_dt {
+                None if dt > 0.0 => self.expected_dt = Some(dt),
+                None => self.discontinuities.push((previous, time)),
+                Some(expected) => {
+                    // allow for some imprecision in the stored times
+                    if dt <= 0.0 || dt > 1.5 * expected {
+                        self.discontinuities.push((previous, time));
+                    }
+                }
+            }
+        }
+
+        self.previous = Some(time);
+    }
+
+    /// Print a warning for every discontinuity found in the trajectory.
+    pub(crate) fn report(&self) {
+        for (previous, current) in self.discontinuities.iter() {
+            if current <= previous {
+                println!(
+                    "WARNING: Simulation time goes backwards from {} ps to {} ps. Trajectory parts are likely overlapping.",
+                    previous, current
+                );
+            } else {
+                println!(
+                    "WARNING: Gap in the simulation time between {} ps and {} ps. Some trajectory part may be missing.",
+                    previous, current
+                );
+            }
+        }
+    }
+}
+
+/// Slice of the trajectory analyzed in a single run, specified as `i/N` (i-th of N slices, counted from 1).
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Chunk {
+    index: usize,
+    total: usize,
+}
+
+impl FromStr for Chunk {
+    type Err = String;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected chunk in the format 'i/N', got '{}'", string);
+
+        let (index, total) = string.split_once('/').ok_or_else(invalid)?;
+        let index = index.trim().parse::<usize>().map_err(|_| invalid())?;
+        let total = total.trim().parse::<usize>().map_err(|_| invalid())?;
+
+        if index == 0 || index > total {
+            return Err(format!(
+                "chunk index must be between 1 and {}, not {}",
+                total, index
+            ));
+        }
+
+        Ok(Chunk { index, total })
+    }
+}
+
+impl Display for Chunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.index, self.total)
+    }
+}
+
+impl Chunk {
+    /// Get the time range covering the frames of this chunk.
+    /// Frames selected using the time range and the step are split into equally sized contiguous chunks.
+    /// Returns `None` if the chunk contains no frames.
+    pub(crate) fn time_range(
+        &self,
+        times: &[f32],
+        begin: f32,
+        end: f32,
+        step: usize,
+    ) -> Option<(f32, f32)> {
+        let analyzed = analyzed_times(times, begin, end, step);
+
+        let first = self.n_preceding(analyzed.len());
+        let last = self.index * analyzed.len() / self.total;
+
+        if first == last {
+            return None;
+        }
+
+        Some((analyzed[first], analyzed[last - 1]))
+    }
+
+    /// Get the number of frames preceding this chunk if `n_analyzed` frames are analyzed in all chunks together.
+    pub(crate) fn n_preceding(&self, n_analyzed: usize) -> usize {
+        (self.index - 1) * n_analyzed / self.total
+    }
+}
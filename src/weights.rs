@@ -0,0 +1,84 @@
+//! Reading weights of the analyzed trajectory frames.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use anyhow::Context;
+
+/// Weights of the individual analyzed trajectory frames.
+#[derive(Debug)]
+pub(crate) struct FrameWeights {
+    weights: Vec<f64>,
+    /// Number of weights belonging to frames analyzed in a different run (e.g., of a preceding trajectory chunk).
+    offset: usize,
+}
+
+impl FrameWeights {
+    /// Read frame weights from a file containing one weight per line.
+    /// Empty lines and lines starting with '#' or '@' are ignored.
+    pub(crate) fn read(filename: impl AsRef<Path>) -> anyhow::Result<FrameWeights> {
+        let filename = filename.as_ref();
+        let file = File::open(filename)
+            .with_context(|| format!("Could not open weight file '{}'.", filename.display()))?;
+
+        let mut weights = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with('@') {
+                continue;
+            }
+
+            let weight = line.parse::<f64>().map_err(|_| {
+                anyhow::anyhow!(
+                    "Could not parse line '{}' in weight file '{}'.",
+                    line,
+                    filename.display()
+                )
+            })?;
+
+            if !weight.is_finite() || weight < 0.0 {
+                anyhow::bail!(
+                    "Invalid frame weight '{}' in weight file '{}'. Weights must be non-negative.",
+                    line,
+                    filename.display()
+                );
+            }
+
+            weights.push(weight);
+        }
+
+        if weights.is_empty() {
+            anyhow::bail!("Weight file '{}' contains no weights.", filename.display());
+        }
+
+        Ok(FrameWeights { weights, offset: 0 })
+    }
+
+    /// Skip the weights of the specified number of frames analyzed in a different run,
+    /// so that the frame indices are counted from the first frame analyzed in this run.
+    pub(crate) fn skip(&mut self, n_frames: usize) {
+        self.offset = n_frames;
+    }
+
+    /// Get the weight of the frame with the specified index (counted from the first analyzed frame).
+    pub(crate) fn get(&self, index: usize) -> anyhow::Result<f64> {
+        self.weights
+            .get(self.offset + index)
+            .copied()
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "The weight file contains only {} weights but more frames are being analyzed.",
+                    self.weights.len()
+                )
+            })
+    }
+
+    /// Get the number of weights.
+    pub(crate) fn len(&self) -> usize {
+        self.weights.len()
+    }
+}